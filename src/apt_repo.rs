@@ -0,0 +1,214 @@
+//! `--repo <dir>` maintains a simple apt repository in a local dir, without `reprepro` or `aptly`.
+//!
+//! Packages are copied to `pool/main/`, and `dists/<suite>/` gets `Packages`, `Packages.gz` and `Release` indexes
+//! of all the packages in the pool. The `Release` file isn't signed.
+
+use crate::deb::read::{control_field, read_deb_members, read_tar_entries};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::publish::Publisher;
+use crate::util::compress::gzipped;
+use crate::util::rfc2822_date;
+use crate::util::sha::sha256_hex;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const COMPONENT: &str = "main";
+
+/// Apt repository in a local dir, which can be served by any web server
+pub struct AptRepo {
+    pub dir: PathBuf,
+    /// Name of the `dists/` subdir, used in `sources.list`
+    pub suite: String,
+}
+
+/// A `.deb` in the pool
+#[derive(Debug)]
+struct PoolPackage {
+    /// The control file, without the trailing newline
+    control: String,
+    name: String,
+    version: String,
+    architecture: String,
+    /// Relative to the repo's root, with `/` separators
+    filename: String,
+    size: usize,
+    md5: String,
+    sha256: String,
+    /// Of the `debian-binary` member
+    mtime: u64,
+}
+
+impl AptRepo {
+    pub const DEFAULT_SUITE: &'static str = "stable";
+
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), suite: Self::DEFAULT_SUITE.into() }
+    }
+
+    /// Copies the `.deb` to the pool. Returns the path in the pool.
+    pub fn add_package(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<PathBuf> {
+        let package = read_package(deb_path, String::new())?;
+        let source = control_field(&package.control, "Source")
+            .and_then(|s| s.split_ascii_whitespace().next())
+            .unwrap_or(&package.name);
+        // the .deb may have been written with a custom name
+        let version = package.version.split_once(':').map_or(package.version.as_str(), |(_, v)| v);
+        let pool_dir = self.dir.join("pool").join(COMPONENT).join(pool_prefix(source)).join(source);
+        let dest = pool_dir.join(format!("{}_{version}_{}.deb", package.name, package.architecture));
+        if dest.exists() && fs::read(&dest)? != fs::read(deb_path)? {
+            // apt caches packages by their version, and won't notice a changed file
+            listener.warning(format!("{} was already in the repository, and has been replaced. Change the version when the package changes", dest.display()));
+        }
+        fs::create_dir_all(&pool_dir)?;
+        fs::copy(deb_path, &dest).map_err(|e| CargoDebError::IoFile("unable to copy the package to the repository", e, dest.clone()))?;
+        Ok(dest)
+    }
+
+    /// Rewrites the `Packages` and `Release` files from all `.deb` files in the pool
+    pub fn update_indexes(&self) -> CDResult<()> {
+        let mut packages = Vec::new();
+        find_debs(&self.dir, &self.dir.join("pool"), &mut packages)?;
+        packages.sort_by(|a, b| (&a.name, &a.version, &a.architecture, &a.filename).cmp(&(&b.name, &b.version, &b.architecture, &b.filename)));
+
+        let suite_dir = self.dir.join("dists").join(&self.suite);
+        // architectures that are gone would still be listed
+        let _ = fs::remove_dir_all(suite_dir.join(COMPONENT));
+        let mut index_files = Vec::new();
+        let architectures = architectures(&packages);
+        for arch in &architectures {
+            let index = packages_index(&packages, arch);
+            let rel_dir = format!("{COMPONENT}/binary-{arch}");
+            fs::create_dir_all(suite_dir.join(&rel_dir))?;
+            let gz = gzipped(index.as_bytes(), true)?;
+            fs::write(suite_dir.join(&rel_dir).join("Packages"), &index)?;
+            fs::write(suite_dir.join(&rel_dir).join("Packages.gz"), &gz)?;
+            index_files.push((format!("{rel_dir}/Packages"), index.into_bytes()));
+            index_files.push((format!("{rel_dir}/Packages.gz"), gz));
+        }
+        // the newest package's timestamp, so that the same packages make the same file
+        let date = packages.iter().map(|p| p.mtime).max().unwrap_or(0);
+        fs::write(suite_dir.join("Release"), release_file(&self.suite, &architectures, date, &index_files))?;
+        Ok(())
+    }
+}
+
+impl Publisher for AptRepo {
+    fn publish(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<()> {
+        let dest = self.add_package(deb_path, listener)?;
+        self.update_indexes()?;
+        listener.info(format!("Added {} to the repository in {}", dest.display(), self.dir.display()));
+        Ok(())
+    }
+}
+
+/// Debian's convention, e.g. `pool/main/f/foo` and `pool/main/libf/libfoo`
+fn pool_prefix(source: &str) -> &str {
+    let len = if source.starts_with("lib") && source.len() > 3 { 4 } else { 1 };
+    source.get(..len).unwrap_or(source)
+}
+
+fn find_debs(repo_dir: &Path, dir: &Path, packages: &mut Vec<PoolPackage>) -> CDResult<()> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_debs(repo_dir, &path, packages)?;
+        } else if path.extension().is_some_and(|ext| ext == "deb") {
+            let rel_path = path.strip_prefix(repo_dir).unwrap_or(&path);
+            let filename = rel_path.iter().map(|c| c.to_string_lossy()).collect::<Vec<_>>().join("/");
+            packages.push(read_package(&path, filename)?);
+        }
+    }
+    Ok(())
+}
+
+fn read_package(deb_path: &Path, filename: String) -> CDResult<PoolPackage> {
+    let invalid = |reason: &str| CargoDebError::CorruptedDeb(deb_path.to_owned(), reason.into());
+    let members = read_deb_members(deb_path)?;
+    let control_tar = members.iter().find(|m| m.is_control()).ok_or_else(|| invalid("control archive is missing"))?.decompressed()?;
+    let control = read_tar_entries(&control_tar)?.into_iter()
+        .find(|e| e.path.file_name().is_some_and(|name| name == "control"))
+        .ok_or_else(|| invalid("control file is missing"))?;
+    let control = String::from_utf8(control.data).map_err(|_| invalid("control file isn't UTF-8"))?;
+    let control = control.trim_end().to_owned();
+    let field = |name| control_field(&control, name).map(str::to_owned).ok_or_else(|| invalid("control file has no Package, Version, or Architecture"));
+    let data = fs::read(deb_path)?;
+    Ok(PoolPackage {
+        name: field("Package")?,
+        version: field("Version")?,
+        architecture: field("Architecture")?,
+        control,
+        filename,
+        size: data.len(),
+        md5: format!("{:x}", md5::compute(&data)),
+        sha256: sha256_hex(&data),
+        mtime: members[0].mtime,
+    })
+}
+
+/// `all` packages are in the index of every architecture, and have their own only if there are no others
+fn architectures(packages: &[PoolPackage]) -> BTreeSet<&str> {
+    let mut architectures: BTreeSet<_> = packages.iter().map(|p| p.architecture.as_str()).filter(|&a| a != "all").collect();
+    if architectures.is_empty() {
+        architectures.insert("all");
+    }
+    architectures
+}
+
+fn packages_index(packages: &[PoolPackage], arch: &str) -> String {
+    let mut out = String::new();
+    for p in packages.iter().filter(|p| p.architecture == arch || p.architecture == "all") {
+        let _ = writeln!(out, "{}\nFilename: {}\nSize: {}\nMD5sum: {}\nSHA256: {}\n", p.control, p.filename, p.size, p.md5, p.sha256);
+    }
+    out
+}
+
+fn release_file(suite: &str, architectures: &BTreeSet<&str>, date: u64, index_files: &[(String, Vec<u8>)]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Suite: {suite}\nCodename: {suite}\nDate: {}", rfc2822_date(date));
+    let _ = writeln!(out, "Architectures: {}", architectures.iter().copied().collect::<Vec<_>>().join(" "));
+    let _ = writeln!(out, "Components: {COMPONENT}");
+    let _ = writeln!(out, "MD5Sum:");
+    for (path, data) in index_files {
+        let _ = writeln!(out, " {:x} {} {path}", md5::compute(data), data.len());
+    }
+    let _ = writeln!(out, "SHA256:");
+    for (path, data) in index_files {
+        let _ = writeln!(out, " {} {} {path}", sha256_hex(data), data.len());
+    }
+    out
+}
+
+#[test]
+fn apt_repo_indexes() {
+    assert_eq!(pool_prefix("foo"), "f");
+    assert_eq!(pool_prefix("libfoo"), "libf");
+    assert_eq!(pool_prefix("lib"), "l");
+
+    let package = |name: &str, architecture: &str| PoolPackage {
+        control: format!("Package: {name}\nVersion: 1.0-1\nArchitecture: {architecture}"),
+        name: name.into(),
+        version: "1.0-1".into(),
+        architecture: architecture.into(),
+        filename: format!("pool/main/{}/{name}/{name}_1.0-1_{architecture}.deb", pool_prefix(name)),
+        size: 10,
+        md5: "m".into(),
+        sha256: "s".into(),
+        mtime: 0,
+    };
+    let packages = [package("a", "amd64"), package("b", "all"), package("c", "arm64")];
+    assert_eq!(architectures(&packages).into_iter().collect::<Vec<_>>(), ["amd64", "arm64"]);
+    assert_eq!(architectures(&packages[1..2]).into_iter().collect::<Vec<_>>(), ["all"]);
+    assert_eq!(packages_index(&packages, "amd64"), "Package: a\nVersion: 1.0-1\nArchitecture: amd64\nFilename: pool/main/a/a/a_1.0-1_amd64.deb\nSize: 10\nMD5sum: m\nSHA256: s\n\n\
+        Package: b\nVersion: 1.0-1\nArchitecture: all\nFilename: pool/main/b/b/b_1.0-1_all.deb\nSize: 10\nMD5sum: m\nSHA256: s\n\n");
+
+    let release = release_file("stable", &architectures(&packages), 0, &[("main/binary-amd64/Packages".into(), b"x".to_vec())]);
+    assert!(release.starts_with("Suite: stable\nCodename: stable\nDate: Thu, 01 Jan 1970 00:00:00 +0000\nArchitectures: amd64 arm64\nComponents: main\n"), "{release}");
+    assert!(release.contains("\nSHA256:\n 2d711642b726b04401627ca9fbac32f5c8530fb1903cc4db02258717921a4881 1 main/binary-amd64/Packages\n"), "{release}");
+}
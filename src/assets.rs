@@ -2,12 +2,20 @@ use crate::config::{is_glob_pattern, PackageConfig};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
 use crate::parse::manifest::CargoDebAssetArrayOrTable;
-use crate::util::compress::gzipped;
+use crate::util::compress::GzipConfig;
 use crate::util::read_file_to_bytes;
+use rayon::prelude::*;
 use std::borrow::Cow;
 use std::env::consts::DLL_SUFFIX;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+
+/// `PATH_MAX` on Linux, including the terminating NUL
+const MAX_PATH_LEN: usize = 4095;
+/// `NAME_MAX` on Linux filesystems
+const MAX_PATH_COMPONENT_LEN: usize = 255;
+/// Deeper paths are almost certainly a mistake, like a glob following a symlink loop
+const MAX_PATH_DEPTH: usize = 64;
 
 #[derive(Debug, Clone)]
 pub enum AssetSource {
@@ -17,6 +25,16 @@ pub enum AssetSource {
     Symlink(PathBuf),
     /// Write data to destination as-is.
     Data(Vec<u8>),
+    /// A FIFO or a device node, without any data
+    Special(SpecialFile),
+}
+
+/// File types that need `allow-special-files`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFile {
+    Fifo,
+    CharDevice { major: u32, minor: u32 },
+    BlockDevice { major: u32, minor: u32 },
 }
 
 impl AssetSource {
@@ -39,7 +57,7 @@ impl AssetSource {
         match self {
             Self::Symlink(ref p) |
             Self::Path(ref p) => Some(p),
-            Self::Data(_) => None,
+            Self::Data(_) | Self::Special(_) => None,
         }
     }
 
@@ -48,13 +66,14 @@ impl AssetSource {
         match self {
             Self::Symlink(p) |
             Self::Path(p) => Some(p),
-            Self::Data(_) => None,
+            Self::Data(_) | Self::Special(_) => None,
         }
     }
 
+    /// Symlinks and special files are archived without any data
     #[must_use]
     pub fn archive_as_symlink_only(&self) -> bool {
-        matches!(self, Self::Symlink(_))
+        matches!(self, Self::Symlink(_) | Self::Special(_))
     }
 
     #[must_use]
@@ -63,7 +82,7 @@ impl AssetSource {
             // FIXME: may not be accurate if the executable is not stripped yet?
             Self::Path(ref p) => fs::metadata(p).ok().map(|m| m.len()),
             Self::Data(ref d) => Some(d.len() as u64),
-            Self::Symlink(_) => None,
+            Self::Symlink(_) | Self::Special(_) => None,
         }
     }
 
@@ -75,6 +94,7 @@ impl AssetSource {
                 Cow::Owned(data)
             },
             Self::Data(d) => Cow::Borrowed(d),
+            Self::Special(_) => Cow::Borrowed(&[]),
             Self::Symlink(p) => {
                 let data = read_file_to_bytes(p)
                     .map_err(|e| CargoDebError::IoFile("Symlink unexpectedly used to read file data", e, p.clone()))?;
@@ -96,6 +116,105 @@ pub(crate) struct RawAsset {
     pub source_path: PathBuf,
     pub target_path: PathBuf,
     pub chmod: u32,
+    /// Include the asset only in some builds
+    pub when: Option<AssetCondition>,
+    /// FIFO or device, which has no `source_path`
+    pub special: Option<SpecialFile>,
+    /// Whether to move it from `usr/lib` to `usr/lib/$triple` with `--multiarch`, if not the default
+    pub multiarch: Option<bool>,
+    /// Owner in the data archive, instead of root
+    pub owner: Option<AssetOwner>,
+    /// Skip compression, stripping and multiarch paths
+    pub verbatim: bool,
+}
+
+/// `owner = "user:group"` of an asset. Names are used by dpkg if the user exists on the system, otherwise ids are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AssetOwner {
+    pub user: OwnerId,
+    pub group: OwnerId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OwnerId {
+    Name(String),
+    Id(u64),
+}
+
+impl OwnerId {
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Ok(id) = s.parse() {
+            return Ok(Self::Id(id));
+        }
+        // Debian's adduser rules, and the 32-byte limit of tar
+        let valid = s.len() <= 31 && s.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && s.bytes().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, b'_' | b'-' | b'.'));
+        if !valid {
+            return Err(format!("`{s}` is not a valid user or group name"));
+        }
+        Ok(Self::Name(s.into()))
+    }
+}
+
+impl std::str::FromStr for AssetOwner {
+    type Err = String;
+
+    /// `user`, `user:group`, or numeric ids. The group is root if not specified, like in `chown`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (user, group) = s.split_once(':').unwrap_or((s, "0"));
+        Ok(Self { user: OwnerId::parse(user)?, group: OwnerId::parse(group)? })
+    }
+}
+
+/// Empty directory from `dirs = [["var/lib/app", "750", "user:group"]]`. The mode and owner are optional.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub(crate) struct PackageDir {
+    /// Relative to the root of the package
+    pub target_path: PathBuf,
+    pub chmod: u32,
+    pub owner: Option<AssetOwner>,
+}
+
+impl TryFrom<Vec<String>> for PackageDir {
+    type Error = String;
+
+    fn try_from(args: Vec<String>) -> Result<Self, String> {
+        let [path, rest @ ..] = &args[..] else {
+            return Err("Expected `dirs` to contain arrays of [path, mode, owner], but found an empty array".into());
+        };
+        if rest.len() > 2 {
+            return Err(format!("Too many elements in `dirs` entry for {path}. Expected [path, mode, owner]"));
+        }
+        let target_path = Path::new(path.trim_start_matches('/'));
+        if target_path.as_os_str().is_empty() || !target_path.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+            return Err(format!("Invalid path in `dirs`: `{path}`"));
+        }
+        let chmod = match rest.first() {
+            Some(mode) => u32::from_str_radix(mode, 8).map_err(|e| format!("Unable to parse mode of {path} in `dirs` as an octal number: {e}"))?,
+            None => 0o755,
+        };
+        let owner = rest.get(1).map(|o| o.parse()).transpose().map_err(|e| format!("Invalid owner of {path} in `dirs`: {e}"))?;
+        Ok(Self { target_path: target_path.components().collect(), chmod, owner })
+    }
+}
+
+/// `when` key of an asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum AssetCondition {
+    /// `--target` is different than the host
+    CrossCompiling,
+    Native,
+}
+
+impl AssetCondition {
+    pub(crate) fn matches(self, is_cross_compiling: bool) -> bool {
+        match self {
+            Self::CrossCompiling => is_cross_compiling,
+            Self::Native => !is_cross_compiling,
+        }
+    }
 }
 
 impl Assets {
@@ -133,6 +252,71 @@ pub enum IsBuilt {
     Workspace,
 }
 
+/// Source path of an asset, which can be a glob pattern.
+///
+/// Files matched by a glob keep their path relative to the part of the pattern before the first wildcard,
+/// e.g. `assets/**/*.png` installed to `usr/share/app/` puts `assets/icons/a.png` at `usr/share/app/icons/a.png`.
+/// A plain path is installed at the target path itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetGlob {
+    pattern: PathBuf,
+    /// Leading components without wildcards, stripped from matched paths. `None` if it's not a glob.
+    source_prefix: Option<PathBuf>,
+}
+
+impl From<&Path> for AssetGlob {
+    fn from(pattern: &Path) -> Self {
+        let source_prefix = is_glob_pattern(pattern).then(|| {
+            pattern.iter()
+                .take_while(|&part| !is_glob_pattern(part.as_ref()))
+                .collect::<PathBuf>()
+        });
+        Self { pattern: pattern.to_owned(), source_prefix }
+    }
+}
+
+impl AssetGlob {
+    #[must_use]
+    pub fn pattern(&self) -> &Path {
+        &self.pattern
+    }
+
+    #[must_use]
+    pub fn is_glob(&self) -> bool {
+        self.source_prefix.is_some()
+    }
+
+    /// Existing files matching the pattern. Directories are skipped.
+    pub fn matched_files(&self) -> CDResult<Vec<PathBuf>> {
+        let found = glob::glob(self.pattern.to_str().ok_or("utf8 path")?)?.collect::<Vec<_>>();
+        // files that exist only in the test harness' memory
+        #[cfg(feature = "testing")]
+        let found = if found.is_empty() && !self.is_glob() && crate::testing::fake_file(&self.pattern).is_some() {
+            vec![Ok(self.pattern.clone())]
+        } else {
+            found
+        };
+        found.into_iter()
+            // Remove dirs from globs without throwing away errors
+            .filter_map(|entry| match entry {
+                Ok(source_file) if source_file.is_dir() => None,
+                Ok(source_file) => Some(Ok(source_file)),
+                Err(e) => Some(Err(e.into())),
+            })
+            .collect()
+    }
+
+    /// Where a file matched by this pattern goes, if the asset's destination is `target_path`
+    #[must_use]
+    pub fn target_path(&self, source_file: &Path, target_path: &Path) -> PathBuf {
+        match self.source_prefix.as_deref().and_then(|prefix| source_file.strip_prefix(prefix).ok()) {
+            Some(rel_path) => target_path.join(rel_path),
+            None => target_path.to_owned(),
+        }
+    }
+}
+
+/// An asset with a source path that may be a glob, which becomes [`Asset`]s after the files are built
 #[derive(Debug, Clone)]
 pub struct UnresolvedAsset {
     pub source_path: PathBuf,
@@ -140,48 +324,38 @@ pub struct UnresolvedAsset {
 }
 
 impl UnresolvedAsset {
-    pub(crate) fn new(source_path: PathBuf, target_path: PathBuf, chmod: u32, is_built: IsBuilt, is_example: bool) -> Self {
+    /// `target_path` ending with `/` is a directory. `source_path` can be a glob pattern (see [`AssetGlob`]).
+    #[must_use]
+    pub fn new(source_path: PathBuf, target_path: PathBuf, chmod: u32, is_built: IsBuilt, is_example: bool) -> Self {
         Self {
             source_path,
-            c: AssetCommon { target_path, chmod, is_example, is_built },
+            c: AssetCommon { target_path, chmod, owner: None, verbatim: false, is_example, is_built },
         }
     }
 
     /// Convert `source_path` (with glob or dir) to actual path
     pub fn resolve(self, preserve_symlinks: bool) -> CDResult<Vec<Asset>> {
-        let Self { source_path, c: AssetCommon { target_path, chmod, is_built, is_example } } = self;
-        let source_prefix = is_glob_pattern(&source_path).then(|| {
-            source_path.iter()
-                .take_while(|&part| !is_glob_pattern(part.as_ref()))
-                .collect::<PathBuf>()
-        });
-        let matched_assets = glob::glob(source_path.to_str().ok_or("utf8 path")?)?
-            // Remove dirs from globs without throwing away errors
-            .map(|entry| {
-                let source_file = entry?;
-                Ok(if source_file.is_dir() { None } else { Some(source_file) })
-            })
-            .filter_map(|res| {
-                Some(res.transpose()?.map(|source_file| {
-                    let target_file = if let Some(source_prefix) = &source_prefix {
-                        target_path.join(source_file.strip_prefix(source_prefix).unwrap())
-                    } else {
-                        target_path.clone()
-                    };
-                    log::debug!("asset {} -> {} {} {:o}", source_file.display(), target_file.display(), if is_built != IsBuilt::No {"copy"} else {"build"}, chmod);
-                    let asset = Asset::new(
-                        AssetSource::from_path(source_file, preserve_symlinks),
-                        target_file,
-                        chmod,
-                        is_built,
-                        is_example,
-                    );
-                    if source_prefix.is_some() {
-                        asset.processed("glob", None)
-                    } else {
-                        asset
-                    }
-                }))
+        let Self { source_path, c: AssetCommon { target_path, chmod, owner, verbatim, is_built, is_example } } = self;
+        let source_glob = AssetGlob::from(source_path.as_path());
+        let matched_assets = source_glob.matched_files()?.into_iter()
+            .map(|source_file| {
+                let target_file = source_glob.target_path(&source_file, &target_path);
+                log::debug!("asset {} -> {} {} {:o}", source_file.display(), target_file.display(), if is_built != IsBuilt::No {"copy"} else {"build"}, chmod);
+                let mut asset = Asset::new(
+                    AssetSource::from_path(source_file, preserve_symlinks),
+                    target_file,
+                    chmod,
+                    is_built,
+                    is_example,
+                );
+                asset.c.target_path = Asset::normalized_target_path(&asset.c.target_path)?;
+                asset.c.owner.clone_from(&owner);
+                asset.c.verbatim = verbatim;
+                Ok(if source_glob.is_glob() {
+                    asset.processed("glob", None)
+                } else {
+                    asset
+                })
             })
             .collect::<CDResult<Vec<_>>>()?;
 
@@ -196,6 +370,10 @@ impl UnresolvedAsset {
 pub struct AssetCommon {
     pub target_path: PathBuf,
     pub chmod: u32,
+    /// `None` is root
+    pub(crate) owner: Option<AssetOwner>,
+    /// Packaged exactly as it is in the source
+    pub(crate) verbatim: bool,
     pub(crate) is_example: bool,
     is_built: IsBuilt,
 }
@@ -230,8 +408,48 @@ impl Asset {
         Self {
             source,
             processed_from: None,
-            c: AssetCommon { target_path, chmod, is_example, is_built },
+            c: AssetCommon { target_path, chmod, owner: None, verbatim: false, is_example, is_built },
+        }
+    }
+
+    /// Makes the path relative to the root of the package, without `.` components.
+    /// Rejects paths that dpkg can't install or that would escape the root.
+    ///
+    /// `\` and trailing dots or spaces are valid in Linux file names, so they're kept as they are,
+    /// rather than treated as Windows path separators or names mangled by Windows.
+    pub(crate) fn normalized_target_path(target_path: &Path) -> CDResult<PathBuf> {
+        let invalid = |reason: String| CargoDebError::InvalidAssetPath(target_path.to_path_buf(), reason);
+        let mut normalized = PathBuf::new();
+        let mut depth = 0;
+        for component in target_path.components() {
+            match component {
+                Component::CurDir => {},
+                Component::ParentDir => return Err(invalid("`..` is not allowed in installation paths".into())),
+                Component::RootDir | Component::Prefix(_) => return Err(invalid("it must be relative to the root of the package".into())),
+                Component::Normal(c) => {
+                    if c.len() > MAX_PATH_COMPONENT_LEN {
+                        return Err(invalid(format!("a component is {} bytes long, and the limit is {MAX_PATH_COMPONENT_LEN}", c.len())));
+                    }
+                    if depth == 0 && c == "DEBIAN" {
+                        return Err(invalid("DEBIAN dir at the root is reserved for dpkg's control files".into()));
+                    }
+                    normalized.push(c);
+                    depth += 1;
+                },
+            }
+        }
+        if depth == 0 {
+            return Err(invalid("it's empty".into()));
+        }
+        if depth > MAX_PATH_DEPTH {
+            return Err(invalid(format!("it's {depth} dirs deep, and the limit is {MAX_PATH_DEPTH}")));
+        }
+        // dpkg adds the leading `/`
+        let len = normalized.as_os_str().len() + 1;
+        if len > MAX_PATH_LEN {
+            return Err(invalid(format!("it's {len} bytes long, and the limit is {MAX_PATH_LEN}")));
         }
+        Ok(normalized)
     }
 
     #[must_use]
@@ -289,7 +507,7 @@ fn debug_filename(path: &Path) -> PathBuf {
 pub(crate) fn is_dynamic_library_filename(path: &Path) -> bool {
     path.file_name()
         .and_then(|f| f.to_str())
-        .map_or(false, |f| f.ends_with(DLL_SUFFIX))
+        .is_some_and(|f| f.ends_with(DLL_SUFFIX))
 }
 
 /// Compress man pages and other assets per Debian Policy.
@@ -299,9 +517,11 @@ pub(crate) fn is_dynamic_library_filename(path: &Path) -> bool {
 /// <https://www.debian.org/doc/debian-policy/ch-docs.html>
 /// <https://lintian.debian.org/tags/manpage-not-compressed.html>
 pub fn compress_assets(package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
-    let mut indices_to_remove = Vec::new();
-    let mut new_assets = Vec::new();
+    compress_assets_with_gzip(package_deb, &GzipConfig::default(), listener)
+}
 
+/// Like [`compress_assets`], with Zopfli or flate2, and the cache of compressed files set in `gzip`
+pub fn compress_assets_with_gzip(package_deb: &mut PackageConfig, gzip: &GzipConfig, listener: &dyn Listener) -> CDResult<()> {
     fn needs_compression(path: &str) -> bool {
         !path.ends_with(".gz") &&
             (path.starts_with("usr/share/man/") ||
@@ -309,46 +529,35 @@ pub fn compress_assets(package_deb: &mut PackageConfig, listener: &dyn Listener)
                 (path.starts_with("usr/share/info/") && path.ends_with(".info")))
     }
 
-    for (idx, orig_asset) in package_deb.assets.resolved.iter().enumerate() {
-        if !orig_asset.c.target_path.starts_with("usr") {
-            continue;
-        }
-        let target_path_str = orig_asset.c.target_path.to_string_lossy();
-        if needs_compression(&target_path_str) {
-            debug_assert!(!orig_asset.c.is_built());
-
-            let mut new_path = target_path_str.into_owned();
-            new_path.push_str(".gz");
-            listener.info(format!("Compressing '{new_path}'"));
-            new_assets.push(Asset::new(
-                crate::assets::AssetSource::Data(gzipped(&orig_asset.source.data()?)?),
-                new_path.into(),
-                orig_asset.c.chmod,
-                IsBuilt::No,
-                false,
-            ).processed("compressed",
-                orig_asset.source.path().unwrap_or(&orig_asset.c.target_path).to_path_buf()
-            ));
-            indices_to_remove.push(idx);
-        }
-    }
+    let (to_compress, mut assets): (Vec<_>, Vec<_>) = std::mem::take(&mut package_deb.assets.resolved).into_iter()
+        .partition(|asset| !asset.c.verbatim && asset.c.target_path.starts_with("usr") && needs_compression(&asset.c.target_path.to_string_lossy()));
 
-    for idx in indices_to_remove.iter().rev() {
-        package_deb.assets.resolved.swap_remove(*idx);
-    }
+    let compressed = to_compress.into_par_iter().map(|orig_asset| {
+        debug_assert!(!orig_asset.c.is_built());
 
-    package_deb.assets.resolved.append(&mut new_assets);
+        let new_path = format!("{}.gz", orig_asset.c.target_path.to_string_lossy());
+        let (data, reused) = gzip.gzipped(&orig_asset.source.data()?)?;
+        listener.info(if reused { format!("Reused compressed '{new_path}'") } else { format!("Compressing '{new_path}'") });
+        let original_path = orig_asset.source.path().unwrap_or(&orig_asset.c.target_path).to_path_buf();
+        // keeps owner and other properties of the original
+        Ok(Asset {
+            source: crate::assets::AssetSource::Data(data),
+            processed_from: None,
+            c: AssetCommon { target_path: new_path.into(), ..orig_asset.c },
+        }.processed(if reused { "reused" } else { "compressed" }, original_path))
+    }).collect::<CDResult<Vec<_>>>()?;
 
+    assets.extend(compressed);
+    package_deb.assets.resolved = assets;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{Config, DebConfigOverrides};
+    use crate::config::tests::own_manifest_config;
     use crate::parse::manifest::SystemdUnitsConfig;
     use crate::util::tests::add_test_fs_paths;
-    use crate::CargoLockingFlags;
 
     #[test]
     fn assets() {
@@ -373,6 +582,49 @@ mod tests {
         assert!(a.c.is_built == IsBuilt::No);
     }
 
+    #[test]
+    fn normalized_target_paths() {
+        let norm = |p: &str| Asset::normalized_target_path(Path::new(p)).map(|p| p.to_str().unwrap().to_owned());
+        assert_eq!(norm("./usr//bin/./foo").unwrap(), "usr/bin/foo");
+        assert_eq!(norm("usr/share/DEBIAN/ok").unwrap(), "usr/share/DEBIAN/ok");
+        assert_eq!(norm("usr/share/foo/notes.").unwrap(), "usr/share/foo/notes.");
+        assert!(norm("DEBIAN/postinst").is_err());
+        assert!(norm("./DEBIAN/control").is_err());
+        assert!(norm("usr/../../etc/passwd").is_err());
+        assert!(norm("/usr/bin/foo").is_err());
+        assert!(norm("./").is_err());
+        assert!(norm(&format!("usr/{}", "x".repeat(256))).is_err());
+        assert!(norm(&"a/".repeat(65)).is_err());
+        let long_dir = format!("{}/", "x".repeat(200));
+        assert!(norm(&long_dir.repeat(20)).is_ok());
+        assert!(norm(&long_dir.repeat(21)).unwrap_err().to_string().contains("bytes long"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            assert_eq!(norm("usr\\share\\foo").unwrap(), "usr\\share\\foo");
+            let non_utf8 = Path::new(std::ffi::OsStr::from_bytes(b"usr/share/\xff"));
+            assert_eq!(Asset::normalized_target_path(non_utf8).unwrap(), non_utf8);
+        }
+    }
+
+    #[test]
+    fn asset_globs() {
+        let glob = AssetGlob::from(Path::new("src/**/dh_lib.rs"));
+        assert!(glob.is_glob());
+        assert_eq!(glob.matched_files().unwrap(), [Path::new("src/dh/dh_lib.rs")]);
+        assert_eq!(glob.target_path(Path::new("src/dh/dh_lib.rs"), Path::new("usr/share/foo")), Path::new("usr/share/foo/dh/dh_lib.rs"));
+
+        let glob = AssetGlob::from(Path::new("src/dh/*_lib.rs"));
+        assert_eq!(glob.matched_files().unwrap(), [Path::new("src/dh/dh_lib.rs")]);
+        assert_eq!(glob.target_path(Path::new("src/dh/dh_lib.rs"), Path::new("usr/share/foo/")), Path::new("usr/share/foo/dh_lib.rs"));
+
+        let plain = AssetGlob::from(Path::new("src"));
+        assert!(!plain.is_glob());
+        assert!(plain.matched_files().unwrap().is_empty());
+        assert_eq!(plain.target_path(Path::new("src"), Path::new("usr/share/foo/bar")), Path::new("usr/share/foo/bar"));
+    }
+
     /// Tests that getting the debug filename from a path returns the same path
     /// with ".debug" appended
     #[test]
@@ -411,6 +663,23 @@ mod tests {
         assert_eq!(debug_target, Path::new("/usr/lib/debug/baz/bar.debug"));
     }
 
+    #[test]
+    fn verbatim_assets_are_not_compressed() {
+        let (_, mut package_deb) = own_manifest_config(&crate::listener::NoOpListener);
+        let mut verbatim = Asset::new(AssetSource::Data(b"verbatim".to_vec()), "usr/share/man/man1/a.1".into(), 0o600, IsBuilt::No, false);
+        verbatim.c.verbatim = true;
+        let mut other = Asset::new(AssetSource::Data(b"other".to_vec()), "usr/share/man/man1/b.1".into(), 0o644, IsBuilt::No, false);
+        other.c.owner = Some("man:man".parse().unwrap());
+        package_deb.assets.resolved = vec![verbatim, other];
+
+        compress_assets(&mut package_deb, &crate::listener::NoOpListener).unwrap();
+        let paths = package_deb.assets.resolved.iter().map(|a| a.c.target_path.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(paths, ["usr/share/man/man1/a.1", "usr/share/man/man1/b.1.gz"]);
+        assert_eq!(&*package_deb.assets.resolved[0].source.data().unwrap(), b"verbatim");
+        assert_eq!(package_deb.assets.resolved[0].c.chmod, 0o600);
+        assert_eq!(package_deb.assets.resolved[1].c.owner, Some("man:man".parse().unwrap()));
+    }
+
     fn to_canon_static_str(s: &str) -> &'static str {
         let cwd = std::env::current_dir().unwrap();
         let abs_path = cwd.join(s);
@@ -426,7 +695,7 @@ mod tests {
         // supply a systemd unit file as if it were available on disk
         let _g = add_test_fs_paths(&[to_canon_static_str("cargo-deb.service")]);
 
-        let (config, mut package_deb) = Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, DebConfigOverrides::default(), None, None, None, CargoLockingFlags::default(), &mock_listener).unwrap();
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
         config.prepare_assets_before_build(&mut package_deb, &mock_listener).unwrap();
 
         let num_unit_assets = package_deb.assets.resolved.iter()
@@ -440,11 +709,12 @@ mod tests {
     fn add_systemd_assets_with_config_adds_unit_assets() {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().return_const(());
+        mock_listener.expect_wants_diagnostics().return_const(false);
 
         // supply a systemd unit file as if it were available on disk
         let _g = add_test_fs_paths(&[to_canon_static_str("cargo-deb.service")]);
 
-        let (config, mut package_deb) = Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, DebConfigOverrides::default(), None, None, None, CargoLockingFlags::default(), &mock_listener).unwrap();
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
 
         package_deb.systemd_units.get_or_insert(vec![SystemdUnitsConfig::default()]);
         package_deb.maintainer_scripts_rel_path.get_or_insert(PathBuf::new());
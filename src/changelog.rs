@@ -0,0 +1,181 @@
+//! Converts [Keep a Changelog](https://keepachangelog.com) style `CHANGELOG.md` to a Debian changelog
+
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::parse::manifest::debian_version_string;
+use crate::util::{rfc2822_date, timestamp_from_date};
+use regex::Regex;
+use std::fmt::Write;
+
+struct Release<'a> {
+    version: &'a str,
+    timestamp: Option<u64>,
+    /// Entries under `### Added`, etc. The first one is for entries before any `###` heading
+    sections: Vec<(Option<&'a str>, Vec<Entry>)>,
+}
+
+struct Entry {
+    nested: bool,
+    text: String,
+}
+
+/// Releases are `## [1.2.3] - 2024-12-18` sections. `## [Unreleased]` is skipped.
+pub(crate) fn markdown_to_debian(markdown: &str, package_deb: &PackageConfig) -> CDResult<String> {
+    let release_heading = Regex::new(r"^##[ \t]+\[?v?([0-9][^\] \t]*)\]?(?:[ \t]*[-–(][ \t]*([0-9]{4})-([0-9]{2})-([0-9]{2}))?").unwrap();
+    let link_definition = Regex::new(r"^\[[^\]]+\]:[ \t]").unwrap();
+
+    let mut releases = Vec::new();
+    let mut in_release = false;
+    let mut after_blank_line = true;
+    for line in markdown.lines() {
+        let content = line.trim();
+        if line.starts_with("## ") {
+            in_release = false;
+            if let Some(c) = release_heading.captures(line) {
+                let date = c.get(2).zip(c.get(3)).zip(c.get(4))
+                    .and_then(|((y, m), d)| timestamp_from_date(y.as_str().parse().ok()?, m.as_str().parse().ok()?, d.as_str().parse().ok()?));
+                releases.push(Release {
+                    version: c.get(1).unwrap().as_str(),
+                    timestamp: date,
+                    sections: vec![(None, Vec::new())],
+                });
+                in_release = true;
+            }
+            continue;
+        }
+        let Some(release) = releases.last_mut().filter(|_| in_release) else { continue };
+        if let Some(name) = line.strip_prefix("### ") {
+            release.sections.push((Some(name.trim()), Vec::new()));
+            after_blank_line = true;
+            continue;
+        }
+        if content.is_empty() || line.starts_with('#') || link_definition.is_match(line) {
+            after_blank_line = true;
+            continue;
+        }
+        let entries = &mut release.sections.last_mut().unwrap().1;
+        let bullet = ["- ", "* ", "+ "].iter().find_map(|b| content.strip_prefix(b));
+        match (bullet, entries.last_mut()) {
+            (Some(text), _) => entries.push(Entry { nested: line.starts_with([' ', '\t']), text: text.trim().into() }),
+            // continuation of a wrapped bullet point
+            (None, Some(last)) if !after_blank_line || line.starts_with([' ', '\t']) => {
+                last.text.push(' ');
+                last.text.push_str(content);
+            },
+            (None, _) => entries.push(Entry { nested: false, text: content.into() }),
+        }
+        after_blank_line = false;
+    }
+
+    if releases.is_empty() {
+        return Err(CargoDebError::Str("The Markdown changelog has no release headings like `## [1.0.0] - 2024-12-31`"));
+    }
+
+    let mut out = String::new();
+    for release in releases {
+        let version = if release.version == package_deb.cargo_version {
+            package_deb.deb_version.as_str().into()
+        } else {
+            debian_version_string(release.version, None)
+        };
+        let _ = writeln!(out, "{} ({version}) unstable; urgency=medium\n", package_deb.deb_name);
+        let mut has_entries = false;
+        for (name, entries) in &release.sections {
+            if entries.is_empty() {
+                continue;
+            }
+            has_entries = true;
+            let indent = if let Some(name) = name {
+                let _ = writeln!(out, "  * {name}:");
+                "  "
+            } else {
+                ""
+            };
+            for entry in entries {
+                let bullet = match (name.is_some(), entry.nested) {
+                    (false, false) => "*",
+                    (false, true) | (true, false) => "-",
+                    (true, true) => "+",
+                };
+                let indent = if entry.nested { format!("{indent}    ") } else { format!("{indent}  ") };
+                write_wrapped(&mut out, &format!("{indent}{bullet} "), &entry.text);
+            }
+        }
+        if !has_entries {
+            out.push_str("  * New upstream release.\n");
+        }
+        let timestamp = release.timestamp.unwrap_or(package_deb.default_timestamp);
+        let _ = writeln!(out, "\n -- {}  {}\n", package_deb.maintainer, rfc2822_date(timestamp));
+    }
+    Ok(out)
+}
+
+/// Wraps the text at 80 columns, aligning continuation lines after the bullet
+fn write_wrapped(out: &mut String, first_line_prefix: &str, text: &str) {
+    let indent = " ".repeat(first_line_prefix.len());
+    let mut line_len = 0;
+    for word in text.split_whitespace() {
+        if line_len == 0 {
+            out.push_str(first_line_prefix);
+            line_len = first_line_prefix.len();
+        } else if line_len + 1 + word.len() > 80 {
+            out.push('\n');
+            out.push_str(&indent);
+            line_len = indent.len();
+        } else {
+            out.push(' ');
+            line_len += 1;
+        }
+        out.push_str(word);
+        line_len += word.len();
+    }
+    out.push('\n');
+}
+
+#[test]
+fn keepachangelog() {
+    let package_deb = crate::config::tests::example_package_config(Default::default(), Default::default()).unwrap();
+
+    let debian = markdown_to_debian("# Changelog
+
+All notable changes to this project will be documented in this file.
+
+## [Unreleased]
+### Added
+- Not yet
+
+## [1.1.0-beta.2] - 2024-12-18
+### Added
+- New visual identity, which is very nice, and this line is so long that it will need to be wrapped
+- Translations:
+  - Spanish
+### Fixed
+* The bug
+  that was wrapped
+
+## 1.0.0 - 2000-02-29
+
+[1.1.0-beta.2]: https://example.com/compare/v1.0.0...v1.1.0-beta.2
+", &package_deb).unwrap();
+    assert_eq!(debian, "\
+example (1.1.0~beta.2-1) unstable; urgency=medium
+
+  * Added:
+    - New visual identity, which is very nice, and this line is so long that it
+      will need to be wrapped
+    - Translations:
+      + Spanish
+  * Fixed:
+    - The bug that was wrapped
+
+ -- Me <me@example.com>  Wed, 18 Dec 2024 00:00:00 +0000
+
+example (1.0.0-1) unstable; urgency=medium
+
+  * New upstream release.
+
+ -- Me <me@example.com>  Tue, 29 Feb 2000 00:00:00 +0000
+
+");
+    assert!(markdown_to_debian("# Changelog\n\n## [Unreleased]\n", &package_deb).is_err());
+}
@@ -0,0 +1,269 @@
+//! Definitions of command-line options, and generators of shell completions and the man page for them
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArgKind {
+    Flag,
+    /// Flag with an optional `=value`
+    FlagOpt,
+    Opt,
+    Multi,
+}
+
+/// A getopts option, in a form that can be inspected
+pub(crate) struct CliOption {
+    short: &'static str,
+    long: &'static str,
+    desc: &'static str,
+    hint: &'static str,
+    kind: ArgKind,
+}
+
+impl CliOption {
+    const fn flag(short: &'static str, long: &'static str, desc: &'static str) -> Self {
+        Self { short, long, desc, hint: "", kind: ArgKind::Flag }
+    }
+
+    const fn flag_opt(short: &'static str, long: &'static str, desc: &'static str, hint: &'static str) -> Self {
+        Self { short, long, desc, hint, kind: ArgKind::FlagOpt }
+    }
+
+    const fn opt(short: &'static str, long: &'static str, desc: &'static str, hint: &'static str) -> Self {
+        Self { short, long, desc, hint, kind: ArgKind::Opt }
+    }
+
+    const fn multi(short: &'static str, long: &'static str, desc: &'static str, hint: &'static str) -> Self {
+        Self { short, long, desc, hint, kind: ArgKind::Multi }
+    }
+
+    pub fn register(&self, opts: &mut getopts::Options) {
+        match self.kind {
+            ArgKind::Flag => opts.optflag(self.short, self.long, self.desc),
+            ArgKind::FlagOpt => opts.optflagopt(self.short, self.long, self.desc, self.hint),
+            ArgKind::Opt => opts.optopt(self.short, self.long, self.desc, self.hint),
+            ArgKind::Multi => opts.optmulti(self.short, self.long, self.desc, self.hint),
+        };
+    }
+
+    /// Deprecated options that aren't shown in the help
+    fn is_hidden(&self) -> bool {
+        matches!(self.long, "system-xz" | "no-separate-debug-symbols")
+    }
+
+    fn takes_path(&self) -> bool {
+        self.hint.contains("path") || self.hint.ends_with(".toml")
+    }
+
+    /// `gz|xz` hint lists the possible values
+    fn choices(&self) -> Option<Vec<&'static str>> {
+        if !self.hint.contains('|') {
+            return None;
+        }
+        Some(self.hint.split('|').filter(|c| !c.starts_with('<')).collect())
+    }
+}
+
+pub(crate) const CLI_OPTIONS: &[CliOption] = &[
+    CliOption::flag("", "no-strip", "Do not strip debug symbols from the binary"),
+    CliOption::flag("", "strip", "Always try to strip debug symbols"),
+    CliOption::flag("", "no-separate-debug-symbols", "Do not strip debug symbols into a separate .debug file"),
+    CliOption::flag("", "separate-debug-symbols", "Strip debug symbols into a separate .debug file"),
+    CliOption::flag("", "compress-debug-symbols", "Apply objcopy --compress-debug-sections"),
+    CliOption::opt("o", "output", "Write .deb to this file or directory", "path"),
+    CliOption::opt("p", "package", "Select which Cargo workspace package to use", "name"),
+    CliOption::flag_opt("", "install", "Immediately install the created deb package. With multiple packages, install the first one, all (for the host's architecture), or none", "main|all|none"),
+    CliOption::flag("", "allow-downgrade", "Let --install replace a newer version of the package"),
+    CliOption::flag("", "verify", "Before --install, re-read the package and check its files against md5sums"),
+    CliOption::opt("", "add-to-repo", "Add the package to a local aptly or reprepro repository", "aptly:repo|reprepro:basedir:codename"),
+    CliOption::opt("", "repo", "Copy the package to a simple apt repository in this dir, and update its Packages and Release files", "dir"),
+    CliOption::flag("", "sign", "Sign the package with cosign, as configured in [package.metadata.deb.sign.sigstore]"),
+    CliOption::flag_opt("", "publish", "Upload the package via HTTP PUT or to S3, as configured in [package.metadata.deb.publish], or to the given URL", "url"),
+    CliOption::flag("q", "quiet", "Don't print warnings"),
+    CliOption::flag("v", "verbose", "Print progress"),
+    CliOption::opt("", "message-format", "Print paths of generated packages and other messages as JSON lines to stdout", "human|json"),
+    CliOption::flag("", "version", "Show version of the cargo-deb tool"),
+    CliOption::opt("", "deb-version", "Override version string for the package", "version"),
+    CliOption::flag("", "no-auto-doc", "Don't add the readme to /usr/share/doc"),
+    CliOption::opt("", "set-version-from", "Read the version from a file, or from the latest tag if 'git'", "file|git"),
+    CliOption::opt("", "deb-revision", "Override revision suffix string for the package", "num"),
+    CliOption::opt("", "maintainer", "Override Maintainer field", "name"),
+    CliOption::opt("", "crate", "Download a crate from crates.io, and package it into the current directory", "name@version"),
+    CliOption::multi("", "manifest-path", "Cargo project file location. Can be repeated to package several crates", "./Cargo.toml"),
+    CliOption::flag("", "offline", "Passed to Cargo"),
+    CliOption::flag("", "offline-strict", "Run Cargo offline, refuse to run anything that could use the network, and list all commands run"),
+    CliOption::flag("", "locked", "Passed to Cargo"),
+    CliOption::flag("", "frozen", "Passed to Cargo"),
+    CliOption::flag("", "no-metadata-cache", "Don't reuse `cargo metadata` output saved in target/debian/.cache"),
+    CliOption::multi("", "variant", "Alternative Cargo.toml configuration section to use. Can be repeated or comma-separated to make a package for each", "name"),
+    CliOption::opt("", "target", "Rust target for cross-compilation", "triple"),
+    CliOption::multi("", "target-distro", "Make a package for each of these releases", "debian12,ubuntu24.04"),
+    CliOption::opt("", "flavor", "Debian derivative with a different CPU baseline. Warns if the --target doesn't match it", "debian|raspbian"),
+    CliOption::opt("", "multiarch", "Put libs in /usr/lib/$arch-linux-gnu/", "none|same|foreign"),
+    CliOption::opt("", "profile", "Select which Cargo build profile to use", "release|<custom>"),
+    CliOption::flag("", "no-build", "Assume the project is already built"),
+    CliOption::flag("", "explain-deps", "Print which binaries and libraries caused each dependency in Depends"),
+    CliOption::flag("", "check-config", "Only validate the configuration, assets and scripts, without building. Reports all problems found"),
+    CliOption::opt("", "print", "Only print the resolved target, profile and output directories as name=path lines, without building", "paths"),
+    CliOption::opt("", "cargo-build", "Override cargo build subcommand", "subcommand"),
+    CliOption::flag("", "keep-old-debs", "Don't delete other versions of the package from target/debian"),
+    CliOption::flag("", "changes", "Also write a .changes file for uploading the package"),
+    CliOption::flag("", "fail-on-missing-systemd-unit", "Fail if systemd-units are configured, but no unit files have been found"),
+    CliOption::flag("", "keep-temp", "Keep stripped binaries and control files in target/debian/<name>/"),
+    CliOption::flag("", "fast", "Use faster compression, which makes a larger deb file"),
+    CliOption::flag("", "best", "Use the slowest compression (xz -9e), which makes the smallest deb file"),
+    CliOption::flag("", "deny-build-paths", "Fail if binaries contain absolute paths of the build directories"),
+    CliOption::opt("", "compat-level", "Make the package installable with this (old) version of dpkg", "dpkg-version"),
+    CliOption::opt("Z", "compress-type", "Compress with the given compression format", "gz|xz"),
+    CliOption::flag("", "compress-system", "Use the corresponding command-line tool for compression"),
+    CliOption::flag("", "system-xz", "Compress using command-line xz command instead of built-in. Deprecated, use --compress-system instead"),
+    CliOption::opt("", "previous-deb", "Previous version of the package. Changed conffiles will be documented in NEWS.Debian", "path.deb"),
+    CliOption::opt("", "delta-against", "Also write a delta with only files changed since this older version of the package", "old.deb"),
+    CliOption::opt("", "apply-delta", "Don't build, only rebuild the newer .deb from --delta-against and this delta", "file.deb-delta"),
+    CliOption::flag_opt("", "version-metadata", "Add X-Vcs-Revision field with the git commit hash, or the given revision", "revision"),
+    CliOption::flag("", "upstream-metadata", "Also write debian/watch and debian/upstream/metadata for a source package to target/debian/<name>-source/"),
+    CliOption::flag("", "emit-debhelper-shim", "Don't build, only write debian/ dir for dpkg-buildpackage, with debian/rules that runs cargo-deb"),
+    CliOption::flag("", "source", "Don't build, only make a source package (.dsc, .orig.tar.xz, .debian.tar.xz) with vendored dependencies"),
+    CliOption::flag("", "crate-source", "Package the library crate's source as librust-<name>-dev for /usr/share/cargo/registry, like debcargo does"),
+    CliOption::flag("", "i-know-what-im-doing", "Allow essential = true, which makes the package impossible to uninstall"),
+    CliOption::flag("", "sandbox", "Run strip, objcopy, xz and dpkg-shlibdeps in a Landlock sandbox"),
+    CliOption::flag("", "rsyncable", "Use worse compression, but reduce differences between versions of packages"),
+    CliOption::flag("", "dpkg-compat", "Write the archive byte-for-byte the same as dpkg-deb --root-owner-group would"),
+    CliOption::opt("", "edit-metadata", "Don't build, only change control fields of an existing .deb file", "path.deb"),
+    CliOption::opt("", "version-suffix", "With --edit-metadata, append this to the package version", "suffix"),
+    CliOption::multi("", "field", "With --edit-metadata, set a control field. Empty value removes it", "Name:value"),
+    CliOption::opt("", "generate-completions", "Print a shell completion script for cargo-deb", "bash|zsh|fish"),
+    CliOption::flag("", "generate-man", "Print a man page for cargo-deb"),
+    CliOption::flag("h", "help", "Print this help menu"),
+];
+
+fn visible_options() -> impl Iterator<Item = &'static CliOption> {
+    CLI_OPTIONS.iter().filter(|o| !o.is_hidden())
+}
+
+pub(crate) fn completions(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(bash_completions()),
+        "zsh" => Some(zsh_completions()),
+        "fish" => Some(fish_completions()),
+        _ => None,
+    }
+}
+
+fn bash_completions() -> String {
+    let mut out = String::from("_cargo_deb() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n    case \"$prev\" in\n");
+    // the optional value of FlagOpt can't be a separate word
+    for opt in visible_options().filter(|o| !matches!(o.kind, ArgKind::Flag | ArgKind::FlagOpt)) {
+        let names = if opt.short.is_empty() { format!("--{}", opt.long) } else { format!("-{}|--{}", opt.short, opt.long) };
+        let action = if let Some(choices) = opt.choices() {
+            format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", choices.join(" "))
+        } else if opt.takes_path() {
+            "COMPREPLY=($(compgen -f -- \"$cur\"))".into()
+        } else {
+            "COMPREPLY=()".into()
+        };
+        let _ = writeln!(out, "        {names}) {action}; return;;");
+    }
+    out.push_str("    esac\n    COMPREPLY=($(compgen -W \"");
+    let all = visible_options().flat_map(|o| {
+        let short = (!o.short.is_empty()).then(|| format!("-{}", o.short));
+        short.into_iter().chain(Some(format!("--{}", o.long)))
+    }).collect::<Vec<_>>();
+    out.push_str(&all.join(" "));
+    out.push_str("\" -- \"$cur\"))\n}\ncomplete -F _cargo_deb cargo-deb\n");
+    out
+}
+
+fn zsh_completions() -> String {
+    let escape = |s: &str| s.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:");
+    let mut out = String::from("#compdef cargo-deb\n\n_arguments -s \\\n");
+    for opt in visible_options() {
+        let repeat = if opt.kind == ArgKind::Multi { "*" } else { "" };
+        let value = match opt.kind {
+            ArgKind::Flag => String::new(),
+            _ => {
+                let action = match opt.choices() {
+                    Some(choices) => format!("({})", choices.join(" ")),
+                    None if opt.takes_path() => "_files".into(),
+                    None => " ".into(),
+                };
+                let optional = if opt.kind == ArgKind::FlagOpt { ":" } else { "" };
+                format!(":{optional}{}:{action}", escape(opt.hint))
+            },
+        };
+        let (short_suffix, long_suffix) = match opt.kind {
+            ArgKind::Flag => ("", ""),
+            ArgKind::FlagOpt => ("-", "=-"),
+            ArgKind::Opt | ArgKind::Multi => ("+", "="),
+        };
+        let desc = escape(opt.desc);
+        if !opt.short.is_empty() {
+            let _ = writeln!(out, "  '{repeat}-{}{short_suffix}[{desc}]{value}' \\", opt.short);
+        }
+        let _ = writeln!(out, "  '{repeat}--{}{long_suffix}[{desc}]{value}' \\", opt.long);
+    }
+    out.push_str("  '*::cargo build flags:'\n");
+    out
+}
+
+fn fish_completions() -> String {
+    let mut out = String::new();
+    for opt in visible_options() {
+        let _ = write!(out, "complete -c cargo-deb -l {}", opt.long);
+        if !opt.short.is_empty() {
+            let _ = write!(out, " -s {}", opt.short);
+        }
+        let _ = write!(out, " -d '{}'", opt.desc.replace('\'', "\\'"));
+        if opt.kind == ArgKind::FlagOpt {
+            if let Some(choices) = opt.choices() {
+                let _ = write!(out, " -f -a '{}'", choices.join(" "));
+            }
+        } else if opt.kind != ArgKind::Flag {
+            match opt.choices() {
+                Some(choices) => { let _ = write!(out, " -x -a '{}'", choices.join(" ")); },
+                None if opt.takes_path() => out.push_str(" -r -F"),
+                None => out.push_str(" -x"),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub(crate) fn man_page() -> String {
+    let roff = |s: &str| s.replace('\\', "\\\\").replace('-', "\\-");
+    let mut out = format!(".TH CARGO\\-DEB 1 \"\" \"cargo\\-deb {}\"\n", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, ".SH NAME\ncargo\\-deb \\- {}", roff(env!("CARGO_PKG_DESCRIPTION")));
+    out.push_str(".SH SYNOPSIS\n\\fBcargo deb\\fR [\\fIoptions\\fR] [\\fB\\-\\-\\fR \\fIcargo build flags\\fR]\n");
+    out.push_str(".SH DESCRIPTION\nBuilds a Rust project with Cargo, and makes a Debian package from it, \
+        using metadata from \\fBCargo.toml\\fR and its \\fB[package.metadata.deb]\\fR section.\n");
+    out.push_str(".SH OPTIONS\n");
+    for opt in visible_options() {
+        out.push_str(".TP\n");
+        if !opt.short.is_empty() {
+            let _ = write!(out, "\\fB\\-{}\\fR, ", roff(opt.short));
+        }
+        let _ = write!(out, "\\fB\\-\\-{}\\fR", roff(opt.long));
+        match opt.kind {
+            ArgKind::Flag => {},
+            ArgKind::FlagOpt => { let _ = write!(out, "[=\\fI{}\\fR]", roff(opt.hint)); },
+            ArgKind::Opt | ArgKind::Multi => { let _ = write!(out, " \\fI{}\\fR", roff(opt.hint)); },
+        }
+        let _ = writeln!(out, "\n{}", roff(opt.desc));
+    }
+    let _ = writeln!(out, ".SH SEE ALSO\n{}", roff(env!("CARGO_PKG_HOMEPAGE")));
+    out
+}
+
+#[test]
+fn generates_completions() {
+    for shell in ["bash", "zsh", "fish"] {
+        let script = completions(shell).unwrap();
+        assert!(script.contains("compress-type"), "{script}");
+        assert!(!script.contains("system-xz"));
+    }
+    assert!(completions("bash").unwrap().contains("--multiarch) COMPREPLY=($(compgen -W \"none same foreign\""));
+    assert!(man_page().contains("\\fB\\-o\\fR, \\fB\\-\\-output\\fR \\fIpath\\fR\n"));
+    assert!(man_page().contains("\\fB\\-\\-install\\fR[=\\fImain|all|none\\fR]\n"));
+    assert!(!completions("bash").unwrap().contains("--install)"));
+}
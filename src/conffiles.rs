@@ -0,0 +1,86 @@
+//! Helps admins upgrading packages whose default configuration files have changed
+
+use crate::assets::{Asset, AssetSource, IsBuilt};
+use crate::config::PackageConfig;
+use crate::deb::read::{read_deb_members, read_tar_entries};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::compress::GzipConfig;
+use crate::util::rfc2822_date;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Compares conffiles with the ones in the previous version of the package.
+///
+/// For every changed conffile, installs a copy of the new default in `/usr/share/<pkg>/default/`,
+/// and adds a `NEWS.Debian` entry about it, which `apt-listchanges` shows during upgrades.
+pub(crate) fn add_changed_conffile_defaults(package_deb: &mut PackageConfig, previous_deb_path: &Path, gzip: &GzipConfig, listener: &dyn Listener) -> CDResult<()> {
+    if package_deb.conf_files.is_empty() {
+        return Ok(());
+    }
+    let previous_files = previous_data_files(previous_deb_path)?;
+
+    let mut changed = Vec::new();
+    for conf_file in &package_deb.conf_files {
+        let target_path = Path::new(conf_file.trim_start_matches('/'));
+        let Some(asset) = package_deb.assets.resolved.iter().find(|a| a.c.target_path == target_path) else { continue };
+        let Some(previous) = previous_files.get(target_path) else {
+            log::debug!("{conf_file} is new in this version");
+            continue;
+        };
+        let data = asset.source.data()?;
+        if previous.as_slice() != &*data {
+            changed.push((target_path.to_owned(), data.into_owned()));
+        }
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let default_dir = Path::new("usr/share").join(&package_deb.deb_name).join("default");
+    let mut news = format!("{} ({}) unstable; urgency=medium\n\n  * The default configuration has changed in:\n", package_deb.deb_name, package_deb.deb_version);
+    for (target_path, _) in &changed {
+        let _ = writeln!(news, "    /{}", target_path.display());
+    }
+    let _ = writeln!(news, "\n    If you have modified these files, dpkg will ask what to do with them during the upgrade.\n    \
+        The new defaults are in /{} for comparison.\n    \
+        To review the changes even if you haven't modified the files, upgrade with dpkg --force-confask.\n", default_dir.display());
+    let _ = writeln!(news, " -- {}  {}", package_deb.maintainer, rfc2822_date(package_deb.default_timestamp));
+
+    for (target_path, data) in changed {
+        listener.info(format!("/{} has changed since {}", target_path.display(), previous_deb_path.display()));
+        package_deb.assets.resolved.push(Asset::new(
+            AssetSource::Data(data),
+            default_dir.join(&target_path),
+            0o644,
+            IsBuilt::No,
+            false,
+        ));
+    }
+
+    let news_path = Path::new("usr/share/doc").join(&package_deb.deb_name).join("NEWS.Debian.gz");
+    if package_deb.assets.resolved.iter().any(|a| a.c.target_path == news_path) {
+        listener.warning(format!("/{} already exists in assets. Add the conffile changes to it yourself:\n{news}", news_path.display()));
+    } else {
+        package_deb.assets.resolved.push(Asset::new(
+            AssetSource::Data(gzip.gzipped(news.as_bytes())?.0),
+            news_path,
+            0o644,
+            IsBuilt::No,
+            false,
+        ).processed("generated", previous_deb_path.to_owned()));
+    }
+    Ok(())
+}
+
+/// Regular files in the data archive, with paths relative to the root
+fn previous_data_files(deb_path: &Path) -> CDResult<HashMap<PathBuf, Vec<u8>>> {
+    let members = read_deb_members(deb_path)?;
+    let data = members.iter().find(|m| m.is_data())
+        .ok_or(CargoDebError::Str("previous deb doesn't have data.tar"))?;
+    Ok(read_tar_entries(&data.decompressed()?)?.into_iter()
+        .filter(|e| e.entry_type.is_file())
+        .map(|e| (e.path.strip_prefix(".").unwrap_or(&e.path).to_owned(), e.data))
+        .collect())
+}
@@ -1,21 +1,26 @@
 use crate::assets::is_dynamic_library_filename;
-use crate::assets::{Asset, AssetSource, Assets, IsBuilt, UnresolvedAsset, RawAsset};
-use crate::util::compress::gzipped;
-use crate::dependencies::resolve_with_dpkg;
-use crate::dh::dh_installsystemd;
+use crate::assets::{Asset, AssetSource, Assets, IsBuilt, PackageDir, UnresolvedAsset, RawAsset, SpecialFile};
+use crate::util::compress::{self, GzipConfig};
+use crate::dependencies::{self, resolve_with_dpkg};
+use crate::changelog;
+use crate::desktop;
+use crate::icons;
+use crate::dh::{dh_installsystemd, dh_lib};
+use crate::distro::{DistroFlavor, TargetDistro};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
 use crate::parse::cargo::CargoConfig;
-use crate::parse::manifest::{cargo_metadata, manifest_debug_flag, manifest_version_string, LicenseFile};
-use crate::parse::manifest::{CargoDeb, CargoDebAssetArrayOrTable, CargoMetadataTarget, CargoPackageMetadata, ManifestFound};
-use crate::parse::manifest::{DependencyList, SystemUnitsSingleOrMultiple, SystemdUnitsConfig};
+use crate::relations::{RelationList, VersionOp};
+use crate::parse::manifest::{cargo_metadata, manifest_debug_flag, manifest_version_string, Copyright, CopyrightStanza, LicenseFile};
+use crate::parse::manifest::{CargoDeb, CargoDebAssetArrayOrTable, CargoMetadataTarget, CargoPackageMetadata, DependencyFeatures, ManifestFound, SpecialFileType};
+use crate::parse::manifest::{ChangelogFormat, DependencyList, DuplicateAssets, PublishConfig, SignConfig, SystemUnitsSingleOrMultiple, SystemdUnitsConfig};
 use crate::util::ok_or::OkOrThen;
 use crate::util::pathbytes::AsUnixPathBytes;
-use crate::util::wordsplit::WordSplit;
+use crate::util::{is_path_file, offline, sandbox};
 use crate::{debian_architecture_from_rust_triple, debian_triple_from_rust_triple, CargoLockingFlags, DEFAULT_TARGET};
 use rayon::prelude::*;
 use std::borrow::Cow;
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env::consts::{DLL_PREFIX, DLL_SUFFIX, EXE_SUFFIX};
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -27,6 +32,30 @@ pub(crate) fn is_glob_pattern(s: &Path) -> bool {
     s.to_bytes().iter().any(|&c| c == b'*' || c == b'[' || c == b']' || c == b'!')
 }
 
+/// Checks a `dpkg-maintscript-helper` command and its arguments, which are pasted into shell scripts unquoted
+fn maintscript_command(command: &str) -> CDResult<String> {
+    let err = |reason| CargoDebError::InvalidMaintscript(command.to_owned(), reason);
+    let args = command.split_ascii_whitespace().collect::<Vec<_>>();
+    // number of paths after the command, and max number of args including the command
+    let (paths, max_args) = match args.first().copied() {
+        Some("rm_conffile") => (1, 4),
+        Some("mv_conffile" | "symlink_to_dir" | "dir_to_symlink") => (2, 5),
+        _ => return Err(err("it must start with rm_conffile, mv_conffile, symlink_to_dir, or dir_to_symlink")),
+    };
+    if args.len() <= paths || args.len() > max_args {
+        return Err(err("wrong number of arguments"));
+    }
+    if args.iter().any(|arg| arg.bytes().any(|c| !c.is_ascii_alphanumeric() && !b"/._+~:@%=,-".contains(&c))) {
+        return Err(err("arguments can't contain quotes or other special characters"));
+    }
+    // symlink targets can be relative
+    let path_args = if args[0].ends_with("conffile") { &args[1..=paths] } else { &args[1..2] };
+    if path_args.iter().any(|path| !path.starts_with('/')) {
+        return Err(err("paths must be absolute"));
+    }
+    Ok(args.join(" "))
+}
+
 /// Match the official `dh_installsystemd` defaults and rename the confusing
 /// `dh_installsystemd` option names to be consistently positive rather than
 /// mostly, but not always, negative.
@@ -37,28 +66,36 @@ impl From<&SystemdUnitsConfig> for dh_installsystemd::Options {
             no_start: !config.start.unwrap_or(true),
             restart_after_upgrade: config.restart_after_upgrade.unwrap_or(true),
             no_stop_on_upgrade: !config.stop_on_upgrade.unwrap_or(true),
+            user: config.user_unit.unwrap_or(false),
+            restart_policy: config.restart_policy.unwrap_or_default(),
+            reload_on_upgrade: config.reload_on_upgrade.unwrap_or(false),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum ArchSpec {
-    /// e.g. [armhf]
-    Require(String),
-    /// e.g. [!armhf]
-    NegRequire(String),
+    /// e.g. [armhf arm64]
+    Require(Vec<String>),
+    /// e.g. [!armhf !arm64]
+    NegRequire(Vec<String>),
 }
 
 fn get_architecture_specification(depend: &str) -> CDResult<(String, Option<ArchSpec>)> {
     use ArchSpec::{NegRequire, Require};
-    let re = regex::Regex::new(r#"(.*)\[(!?)(.*)\]"#).unwrap();
+    let re = regex::Regex::new(r#"(.*)\[(.*)\]"#).unwrap();
     match re.captures(depend) {
         Some(caps) => {
-            let spec = if &caps[2] == "!" {
-                NegRequire(caps[3].to_string())
+            let archs: Vec<_> = caps[2].split_ascii_whitespace().collect();
+            let negated = archs.iter().filter(|a| a.starts_with('!')).count();
+            let names = archs.iter().map(|a| a.trim_start_matches('!').to_owned()).collect();
+            // Debian policy doesn't allow mixing them
+            let spec = if archs.is_empty() || (negated != 0 && negated != archs.len()) {
+                return Err(CargoDebError::InvalidRelation(depend.trim().to_owned()));
+            } else if negated == 0 {
+                Require(names)
             } else {
-                assert_eq!(&caps[2], "");
-                Require(caps[3].to_string())
+                NegRequire(names)
             };
             Ok((caps[1].trim().to_string(), Some(spec)))
         },
@@ -69,19 +106,21 @@ fn get_architecture_specification(depend: &str) -> CDResult<(String, Option<Arch
 /// Architecture specification strings
 /// <https://www.debian.org/doc/debian-policy/ch-customized-programs.html#s-arch-spec>
 fn match_architecture(spec: ArchSpec, target_arch: &str) -> CDResult<bool> {
-    let (neg, spec) = match spec {
-        ArchSpec::NegRequire(pkg) => (true, pkg),
-        ArchSpec::Require(pkg) => (false, pkg),
+    let (neg, archs) = match spec {
+        ArchSpec::NegRequire(archs) => (true, archs),
+        ArchSpec::Require(archs) => (false, archs),
     };
-    let output = Command::new("dpkg-architecture")
-        .args(["-a", target_arch, "-i", &spec])
-        .output()
-        .map_err(|e| CargoDebError::CommandFailed(e, "dpkg-architecture"))?;
-    if neg {
-        Ok(!output.status.success())
-    } else {
-        Ok(output.status.success())
+    // dpkg-architecture checks only one architecture or wildcard at a time
+    for arch in &archs {
+        let output = sandbox::apply(&mut Command::new("dpkg-architecture"))?
+            .args(["-a", target_arch, "-i", arch])
+            .output()
+            .map_err(|e| CargoDebError::CommandFailed(e, "dpkg-architecture"))?;
+        if output.status.success() {
+            return Ok(!neg);
+        }
     }
+    Ok(neg)
 }
 
 #[derive(Debug)]
@@ -92,15 +131,21 @@ pub struct Config {
     pub package_manifest_dir: PathBuf,
     /// Run `cargo` commands from this dir, or things may subtly break
     pub cargo_run_current_dir: PathBuf,
+    /// Approximate number of crates to compile, for progress of the build
+    pub(crate) build_crate_count: usize,
     /// User-configured output path for *.deb
     pub deb_output_path: Option<String>,
     /// Triple. `None` means current machine architecture.
     pub rust_target_triple: Option<String>,
     /// `CARGO_TARGET_DIR`
     pub target_dir: PathBuf,
+    /// `CARGO_TARGET_DIR` to set for Cargo, if it's been overridden
+    pub(crate) cargo_target_dir_override: Option<PathBuf>,
     /// List of Cargo features to use during build
     pub features: Vec<String>,
     pub default_features: bool,
+    /// `[features]` table from the manifest
+    feature_table: BTreeMap<String, Vec<String>>,
     /// Should the binary be stripped from debug symbols?
     pub debug_symbols: DebugSymbols,
 
@@ -129,22 +174,32 @@ pub struct PackageConfig {
     pub deb_name: String,
     /// The version to give the Debian package; usually the same as the Cargo version
     pub deb_version: String,
+    /// The version in `Cargo.toml`, ignoring `--deb-version` and other overrides
+    pub(crate) cargo_version: String,
     /// The software license of the project (SPDX format).
     pub license: Option<String>,
     /// The location of the license file
     pub license_file_rel_path: Option<PathBuf>,
     /// number of lines to skip when reading `license_file`
     pub license_file_skip_lines: usize,
+    /// Multiple license files, each written as a separate `License:` paragraph
+    pub(crate) license_files_rel_paths: Vec<PathBuf>,
     /// The copyright of the project
     /// (Debian's `copyright` file contents).
     pub copyright: Option<String>,
+    /// Explicit `Files:` stanzas of the copyright file
+    pub(crate) copyright_stanzas: Vec<CopyrightStanza>,
     pub changelog: Option<String>,
+    /// Markdown changelogs are converted to the Debian format, only if set to `Markdown`
+    pub(crate) changelog_format: Option<ChangelogFormat>,
     /// The homepage URL of the project.
     pub homepage: Option<String>,
     /// Documentation URL from `Cargo.toml`. Fallback if `homepage` is missing.
     pub documentation: Option<String>,
     /// The URL of the software repository. Fallback if both `homepage` and `documentation` are missing.
     pub repository: Option<String>,
+    /// Not `publish = false`
+    pub(crate) published_on_crates_io: bool,
     /// A short description of the project.
     pub description: String,
     /// An extended description of the project.
@@ -154,10 +209,24 @@ pub struct PackageConfig {
     pub maintainer: String,
     /// Deps including `$auto`
     pub wildcard_depends: String,
+    /// Debian packages of libraries, when `$auto` can't use dpkg
+    pub soname_packages: BTreeMap<String, String>,
+    /// Removed from the resolved deps
+    pub depends_remove: Vec<String>,
+    /// Version constraints replacing the resolved ones
+    pub depends_pin: BTreeMap<String, String>,
     /// The Debian dependencies required to run the project.
     pub resolved_depends: Option<String>,
     /// The Debian pre-dependencies.
     pub pre_depends: Option<String>,
+    /// Renames of dependencies for `--target-distro`, keyed by distro name
+    pub dependency_aliases: BTreeMap<String, BTreeMap<String, String>>,
+    /// Add `Depends` implied by crates in the dependency tree, see `hints.rs`
+    pub dependency_hints: bool,
+    /// Release the package is tailored for
+    pub target_distro: Option<TargetDistro>,
+    /// Derivative distro with a different baseline for the architecture, e.g. Raspbian's ARMv6 armhf
+    pub flavor: DistroFlavor,
     /// The Debian recommended dependencies.
     pub recommends: Option<String>,
     /// The Debian suggested dependencies.
@@ -168,6 +237,10 @@ pub struct PackageConfig {
     pub section: Option<String>,
     /// The Debian priority of the project. Typically 'optional'.
     pub priority: String,
+    /// `Essential: yes` makes the package impossible to remove without force
+    pub essential: bool,
+    /// `Protected: yes` makes dpkg refuse to remove the package unless forced
+    pub protected: bool,
 
     /// `Conflicts` Debian control field.
     ///
@@ -185,6 +258,8 @@ pub struct PackageConfig {
     ///
     /// See [PackageTransition](https://wiki.debian.org/PackageTransition).
     pub provides: Option<String>,
+    /// Relationship fields longer than this are folded, one relation per line. 0 (the default) never folds.
+    pub(crate) relations_line_width: usize,
 
     /// The Debian architecture of the target system.
     pub architecture: String,
@@ -193,20 +268,57 @@ pub struct PackageConfig {
     /// A list of configuration files installed by the package.
     /// Automatically includes all files in `/etc`
     pub conf_files: Vec<String>,
+    /// Files in `/etc` that must not be automatically marked as conf files
+    pub non_conf_files: Vec<String>,
+    /// Absolute paths of files the program creates, which aren't in the package, but are deleted on purge.
+    /// Dirs end with `/`, and are deleted only if empty.
+    pub runtime_files: Vec<String>,
+    /// Validated `dpkg-maintscript-helper` arguments, e.g. `rm_conffile /etc/foo.conf 1.2~`
+    pub maintscripts: Vec<String>,
+    /// Empty directories created in the package, with their own mode and owner
+    pub(crate) dirs: Vec<PackageDir>,
     /// All of the files that are to be packaged.
     pub(crate) assets: Assets,
     pub(crate) raw_assets: Option<Vec<RawAsset>>,
+    /// `None` keeps the first asset with a warning
+    pub(crate) duplicate_assets: Option<DuplicateAssets>,
 
     /// Added to usr/share/doc as a fallback
     pub readme_rel_path: Option<PathBuf>,
     /// The location of the triggers file
     pub triggers_file_rel_path: Option<PathBuf>,
+    /// AppStream metainfo XML to install in `/usr/share/metainfo`
+    pub metainfo_rel_path: Option<PathBuf>,
+    /// SVG or PNG to install in `/usr/share/icons/hicolor`
+    pub icon_rel_path: Option<PathBuf>,
+    /// `sysusers.d` config that creates system users in `postinst`
+    pub sysusers_rel_path: Option<PathBuf>,
+    /// `tmpfiles.d` config that creates files and dirs in `postinst`
+    pub tmpfiles_rel_path: Option<PathBuf>,
     /// The path where possible maintainer scripts live
     pub maintainer_scripts_rel_path: Option<PathBuf>,
     /// Should symlinks be preserved in the assets
     pub preserve_symlinks: bool,
+    /// Opt-in for FIFO and device assets
+    pub allow_special_files: bool,
+    /// Don't delete other versions of this package from `target/debian`
+    pub keep_old_debs: bool,
+    /// Write a `.changes` file next to the `.deb`
+    pub changes_file: bool,
+    /// Error instead of a warning when `systemd-units` finds no unit files
+    pub fail_on_missing_systemd_unit: bool,
+    /// Add maintainer script fragments that assets need, e.g. updating icon caches
+    pub implicit_triggers: bool,
     /// Details of how to install any systemd units
     pub(crate) systemd_units: Option<Vec<SystemdUnitsConfig>>,
+    /// Commit the package has been built from, for the `X-Vcs-Revision` field
+    pub vcs_revision: Option<String>,
+    /// Also install it as `/usr/share/doc/<name>/REVISION`
+    pub(crate) vcs_revision_file: bool,
+    /// Where `--publish` uploads the package
+    pub(crate) publish: Option<PublishConfig>,
+    /// How `--sign` signs the package
+    pub(crate) sign: Option<SignConfig>,
     /// unix timestamp for generated files
     pub default_timestamp: u64,
 }
@@ -226,8 +338,26 @@ pub enum DebugSymbols {
 #[derive(Debug, Clone, Default)]
 pub struct DebConfigOverrides {
     pub deb_version: Option<String>,
+    /// Replaces the version from `Cargo.toml`, but still gets the revision appended
+    pub upstream_version: Option<String>,
+    /// `Some(false)` doesn't add the readme to `/usr/share/doc`
+    pub auto_doc: Option<bool>,
     pub deb_revision: Option<String>,
     pub maintainer: Option<String>,
+    /// `X-Vcs-Revision` field. `Some(None)` uses the commit hash from git.
+    pub vcs_revision: Option<Option<String>>,
+    /// `--i-know-what-im-doing` allows `essential = true`
+    pub allow_essential: bool,
+    /// `--flavor`
+    pub flavor: DistroFlavor,
+    /// `--keep-old-debs`
+    pub keep_old_debs: bool,
+    /// `--changes`
+    pub changes_file: bool,
+    /// `--fail-on-missing-systemd-unit`
+    pub fail_on_missing_systemd_unit: bool,
+    /// Build in this dir instead of `CARGO_TARGET_DIR` or Cargo's default
+    pub target_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -244,6 +374,7 @@ impl Config {
     /// Makes a new config from `Cargo.toml` in the `manifest_path`
     ///
     /// `None` target means the host machine's architecture.
+    #[allow(clippy::too_many_arguments)]
     pub fn from_manifest(
         root_manifest_path: Option<&Path>,
         selected_package_name: Option<&str>,
@@ -263,11 +394,13 @@ impl Config {
         let ManifestFound {
             build_targets,
             root_manifest,
+            workspace_root,
             mut manifest_path,
             mut target_dir,
             mut manifest,
             cargo_run_current_dir,
-        } = cargo_metadata(root_manifest_path, selected_package_name, cargo_locking_flags)?;
+            build_crate_count,
+        } = cargo_metadata(root_manifest_path, selected_package_name, overrides.target_dir.as_deref(), cargo_locking_flags)?;
 
         let default_timestamp = if let Ok(source_date_epoch) = std::env::var("SOURCE_DATE_EPOCH") {
             source_date_epoch.parse().map_err(|e| CargoDebError::NumParse("SOURCE_DATE_EPOCH", e))?
@@ -286,28 +419,49 @@ impl Config {
             target_dir.push(rust_target_triple);
         };
 
-        let selected_profile = build_profile_override.as_deref().unwrap_or("release");
-
-        let debug_enabled = manifest_debug_flag(&manifest, selected_profile)
-            .or_else(move || manifest_debug_flag(root_manifest.as_ref()?, selected_profile))
-            .unwrap_or(false);
-
+        let package_name = manifest.package.as_ref().map_or("", |p| p.name()).to_owned();
+        let feature_table = std::mem::take(&mut manifest.features);
         let cargo_package = manifest.package.as_mut().ok_or("bad package")?;
 
+        let mut deb = cargo_package.metadata.take().and_then(|m| m.deb).unwrap_or_default();
+        // [workspace.metadata.deb] has defaults for all packages in the workspace
+        if let Some(workspace_deb) = root_manifest.as_ref().and_then(|m| m.workspace.as_ref()?.metadata.as_ref()?.deb.clone()) {
+            deb = deb.inherit_from(workspace_deb.into_workspace_defaults(&workspace_root, listener));
+        }
+
         // If we build against a variant use that config and change the package name
         let mut deb = if let Some(variant) = config_variant {
             // Use dash as underscore is not allowed in package names
             cargo_package.name = format!("{}-{variant}", cargo_package.name);
-            let mut deb = cargo_package.metadata.take()
-                .and_then(|m| m.deb).unwrap_or_default();
             let variant = deb.variants
                 .as_mut()
                 .and_then(|v| v.remove(variant))
                 .ok_or_else(|| CargoDebError::VariantNotFound(variant.to_string()))?;
             variant.inherit_from(deb)
         } else {
-            cargo_package.metadata.take().and_then(|m| m.deb).unwrap_or_default()
+            deb
         };
+        deb = deb.with_target_overrides(rust_target_triple.unwrap_or(DEFAULT_TARGET))
+            .with_workspace_root_paths(&workspace_root);
+
+        // --profile takes precedence over the variant's profile, which takes precedence over the package's
+        let build_profile_override = match (build_profile_override, deb.profile.take()) {
+            (Some(cli), Some(manifest)) if cli != manifest => {
+                listener.info(format!("Using --profile={cli} instead of profile = \"{manifest}\" from [package.metadata.deb]"));
+                Some(cli)
+            },
+            (Some(profile), _) => Some(profile),
+            (None, Some(profile)) => {
+                listener.info(format!("Using profile \"{profile}\" from [package.metadata.deb]"));
+                Some(profile)
+            },
+            (None, None) => None,
+        };
+        let selected_profile = build_profile_override.as_deref().unwrap_or("release");
+        let debug_enabled = manifest_debug_flag(&manifest, selected_profile, &package_name)
+            .or_else(|| manifest_debug_flag(root_manifest.as_ref()?, selected_profile, &package_name))
+            .unwrap_or(false);
+        let cargo_package = manifest.package.as_mut().ok_or("bad package")?;
 
         let separate_debug_symbols = separate_debug_symbols.unwrap_or_else(|| deb.separate_debug_symbols.unwrap_or(false));
         let compress_debug_symbols = compress_debug_symbols.unwrap_or_else(|| deb.compress_debug_symbols.unwrap_or(false));
@@ -331,26 +485,40 @@ impl Config {
             deb_output_path,
             rust_target_triple: rust_target_triple.map(|t| t.to_string()),
             target_dir,
+            cargo_target_dir_override: overrides.target_dir.clone(),
             features: deb.features.take().unwrap_or_default(),
             default_features: deb.default_features.unwrap_or(true),
+            feature_table,
             debug_symbols,
             build_profile_override,
             build_targets,
             cargo_locking_flags,
             cargo_run_current_dir,
+            build_crate_count,
         };
 
+        let mut overrides = overrides;
+        if let Some(revision @ None) = &mut overrides.vcs_revision {
+            *revision = Some(git_revision(&config.package_manifest_dir)?);
+        }
+        if let Some(Some(revision)) = &overrides.vcs_revision {
+            // it goes into a single-line control field
+            if revision.is_empty() || revision.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err(CargoDebError::InvalidVcsRevision(revision.clone()));
+            }
+        }
         let package_deb = PackageConfig::new(deb, cargo_package, listener, default_timestamp, overrides, config.rust_target_triple())?;
 
         Ok((config, package_deb))
     }
 
     pub fn prepare_assets_before_build(&self, package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
-        package_deb.assets = if let Some(raw_assets) = package_deb.raw_assets.take() {
-            self.explicit_assets(raw_assets, package_deb, listener)?
-        } else {
-            self.implicit_assets(package_deb)?
-        };
+        self.prepare_assets_before_build_with_gzip(package_deb, &GzipConfig::default(), listener)
+    }
+
+    /// `gzip` is for generated files, like the changelog
+    pub fn prepare_assets_before_build_with_gzip(&self, package_deb: &mut PackageConfig, gzip: &GzipConfig, listener: &dyn Listener) -> CDResult<()> {
+        package_deb.assets = self.configured_assets(package_deb, listener)?;
 
         // https://wiki.debian.org/Multiarch/Implementation
         if package_deb.multiarch != Multiarch::None {
@@ -372,13 +540,79 @@ impl Config {
         }
 
         self.add_copyright_asset(package_deb)?;
-        self.add_changelog_asset(package_deb)?;
-        self.add_systemd_assets(package_deb)?;
+        self.add_changelog_asset(package_deb, gzip)?;
+        Self::add_revision_asset(package_deb);
+        self.add_metainfo_asset(package_deb)?;
+        self.add_icon_assets(package_deb, listener)?;
+        self.add_sysusers_asset(package_deb)?;
+        self.add_tmpfiles_asset(package_deb)?;
+        self.add_systemd_assets(package_deb, listener)?;
+        self.resolve_extended_description(package_deb)?;
 
         self.reset_deb_temp_directory(package_deb)?;
         Ok(())
     }
 
+    /// Reads `extended-description-file` (or the readme used instead), so that the control file can be made without reading files
+    pub fn resolve_extended_description(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        if let Some(desc) = self.read_extended_description_file(&package_deb.extended_description)? {
+            package_deb.extended_description = ExtendedDescription::String(desc);
+        }
+        Ok(())
+    }
+
+    fn read_extended_description_file(&self, extended_description: &ExtendedDescription) -> CDResult<Option<String>> {
+        let path = match extended_description {
+            ExtendedDescription::None | ExtendedDescription::String(_) => return Ok(None),
+            ExtendedDescription::File(p) => p.clone(),
+            ExtendedDescription::ReadmeFallback(p) => self.path_in_package(p),
+        };
+        let desc = fs::read_to_string(&path)
+            .map_err(|err| CargoDebError::IoFile("unable to read extended description from file", err, path))?;
+        Ok(Some(desc))
+    }
+
+    /// Checks everything that can be checked without building, for `--check-config`.
+    /// Returns all the problems found, instead of stopping at the first one.
+    pub(crate) fn check_package(&self, package_deb: &mut PackageConfig, listener: &dyn Listener) -> Vec<CargoDebError> {
+        let mut problems = Vec::new();
+        match self.configured_assets(package_deb, listener) {
+            Ok(assets) => package_deb.assets = assets,
+            Err(e) => problems.push(e),
+        }
+        // built assets don't exist yet
+        for asset in package_deb.assets.unresolved.iter().filter(|a| !a.c.is_built()) {
+            problems.extend(asset.clone().resolve(package_deb.preserve_symlinks).err());
+        }
+        problems.extend(self.add_copyright_asset(package_deb).err());
+        problems.extend(self.add_changelog_asset(package_deb, &GzipConfig { fast: true, cache_dir: None }).err());
+        problems.extend(self.add_metainfo_asset(package_deb).err());
+        problems.extend(self.add_icon_assets(package_deb, listener).err());
+        problems.extend(self.add_sysusers_asset(package_deb).err());
+        problems.extend(self.add_tmpfiles_asset(package_deb).err());
+        problems.extend(self.resolve_extended_description(package_deb).err());
+        problems.extend(package_deb.check_priority_policy(listener).err());
+
+        if let Some(dir) = &package_deb.maintainer_scripts_rel_path {
+            let path = self.path_in_package(dir);
+            if !path.is_dir() {
+                problems.push(CargoDebError::IoFile("maintainer-scripts directory not found", io::ErrorKind::NotFound.into(), path));
+            }
+        }
+        for unit in package_deb.systemd_units.iter().flatten() {
+            let Some(unit_dir) = unit.unit_scripts.as_ref().or(package_deb.maintainer_scripts_rel_path.as_ref()) else {
+                problems.push(CargoDebError::Str("systemd-units need `unit-scripts` or `maintainer-scripts` to be set"));
+                continue;
+            };
+            let search_path = self.path_in_package(unit_dir);
+            if dh_installsystemd::find_units(&search_path, &package_deb.name, unit.unit_name.as_deref()).is_empty() {
+                let searched = dh_installsystemd::unit_file_names_searched(&search_path, &package_deb.name, unit.unit_name.as_deref()).join(", ");
+                problems.push(CargoDebError::SystemdUnitsNotFound(search_path, searched));
+            }
+        }
+        problems
+    }
+
     pub fn set_cargo_build_flags_for_package(&self, package_deb: &PackageConfig, flags: &mut Vec<String>) {
         flags.push(self.build_profile_override.as_deref().map(|p| format!("--profile={p}")).unwrap_or("--release".into()));
         flags.extend(self.cargo_locking_flags.flags().map(String::from));
@@ -406,7 +640,7 @@ impl Config {
                 log::debug!("building workspace because {} is from another package", source_path.unwrap_or(&asset_target.target_path).display());
                 same_package = false;
             }
-            if asset_target.is_dynamic_library() || source_path.map_or(false, is_dynamic_library_filename) {
+            if asset_target.is_dynamic_library() || source_path.is_some_and(is_dynamic_library_filename) {
                 log::debug!("building libs for {}", source_path.unwrap_or(&asset_target.target_path).display());
                 build_libs = true;
             } else if asset_target.is_executable() {
@@ -452,14 +686,18 @@ impl Config {
     }
 
     /// Generates the copyright file from the license file and adds that to the tar archive.
-    fn generate_copyright_asset(&self, package_deb: &PackageConfig) -> CDResult<(PathBuf, Vec<u8>)> {
+    pub(crate) fn generate_copyright_asset(&self, package_deb: &PackageConfig) -> CDResult<(PathBuf, Vec<u8>)> {
         let mut copyright: Vec<u8> = Vec::new();
         let source_path;
         if let Some(path) = &package_deb.license_file_rel_path {
             source_path = self.path_in_package(path);
             let license_string = fs::read_to_string(&source_path)
                 .map_err(|e| CargoDebError::IoFile("unable to read license file", e, path.clone()))?;
-            if !has_copyright_metadata(&license_string) {
+            if !package_deb.copyright_stanzas.is_empty() {
+                // the license text follows the stanzas as a separate paragraph
+                package_deb.append_copyright_metadata(&mut copyright)?;
+                copyright.push(b'\n');
+            } else if !has_copyright_metadata(&license_string) {
                 package_deb.append_copyright_metadata(&mut copyright)?;
             }
 
@@ -473,6 +711,18 @@ impl Config {
                     copyright.write_all(b"\n")?;
                 }
             }
+        } else if let Some(first_path) = package_deb.license_files_rel_paths.first() {
+            source_path = self.path_in_package(first_path);
+            package_deb.append_copyright_metadata(&mut copyright)?;
+            for path in &package_deb.license_files_rel_paths {
+                let license_string = fs::read_to_string(self.path_in_package(path))
+                    .map_err(|e| CargoDebError::IoFile("unable to read license file", e, path.clone()))?;
+                writeln!(copyright, "\nLicense: {}", license_name_for_file(path, package_deb.license.as_deref()))?;
+                for line in license_string.trim_matches('\n').lines() {
+                    let line = line.trim_end();
+                    writeln!(copyright, " {}", if line.is_empty() { "." } else { line })?;
+                }
+            }
         } else {
             source_path = "Cargo.toml".into();
             package_deb.append_copyright_metadata(&mut copyright)?;
@@ -481,9 +731,9 @@ impl Config {
         Ok((source_path, copyright))
     }
 
-    fn add_changelog_asset(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+    fn add_changelog_asset(&self, package_deb: &mut PackageConfig, gzip: &GzipConfig) -> CDResult<()> {
         if package_deb.changelog.is_some() {
-            if let Some((source_path, changelog_file)) = self.generate_changelog_asset(package_deb)? {
+            if let Some((source_path, changelog_file)) = self.generate_changelog_asset(package_deb, gzip)? {
                 log::debug!("added changelog via {}", source_path.display());
                 package_deb.assets.resolved.push(Asset::new(
                     AssetSource::Data(changelog_file),
@@ -497,27 +747,112 @@ impl Config {
         Ok(())
     }
 
+    fn add_revision_asset(package_deb: &mut PackageConfig) {
+        if let Some(revision) = package_deb.vcs_revision.as_ref().filter(|_| package_deb.vcs_revision_file) {
+            package_deb.assets.resolved.push(Asset::new(
+                AssetSource::Data(format!("{revision}\n").into_bytes()),
+                Path::new("usr/share/doc").join(&package_deb.deb_name).join("REVISION"),
+                0o644,
+                IsBuilt::No,
+                false,
+            ).processed("generated", None));
+        }
+    }
+
     /// Generates compressed changelog file
-    fn generate_changelog_asset(&self, package_deb: &PackageConfig) -> CDResult<Option<(PathBuf, Vec<u8>)>> {
+    fn generate_changelog_asset(&self, package_deb: &PackageConfig, gzip: &GzipConfig) -> CDResult<Option<(PathBuf, Vec<u8>)>> {
         if let Some(ref path) = package_deb.changelog {
             let source_path = self.path_in_package(path);
-            let changelog = fs::read(&source_path)
-                .and_then(|content| {
-                    // allow pre-compressed
-                    if source_path.extension().is_some_and(|e| e == "gz") {
-                        return Ok(content);
-                    }
-                    // The input is plaintext, but the debian package should contain gzipped one.
-                    gzipped(&content)
-                })
-                .map_err(|e| CargoDebError::IoFile("unable to read changelog file", e, source_path.clone()))?;
+            let read_error = |e| CargoDebError::IoFile("unable to read changelog file", e, source_path.clone());
+            // allow pre-compressed
+            let changelog = if source_path.extension().is_some_and(|e| e == "gz") {
+                fs::read(&source_path).map_err(read_error)?
+            } else {
+                // The input is plaintext, but the debian package should contain gzipped one.
+                let changelog = self.changelog_text(package_deb)?.unwrap_or_default();
+                gzip.gzipped(changelog.as_bytes()).map_err(read_error)?.0
+            };
             Ok(Some((source_path, changelog)))
         } else {
             Ok(None)
         }
     }
 
-    fn add_systemd_assets(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+    /// The changelog in the Debian format, converted from Markdown if needed
+    pub(crate) fn changelog_text(&self, package_deb: &PackageConfig) -> CDResult<Option<String>> {
+        let Some(path) = &package_deb.changelog else {
+            return Ok(None);
+        };
+        let source_path = self.path_in_package(path);
+        let changelog = fs::read(&source_path).map_err(|e| CargoDebError::IoFile("unable to read changelog file", e, source_path.clone()))?;
+        if source_path.extension().is_some_and(|e| e == "gz") {
+            return Ok(Some(String::from_utf8(compress::decompress("gz", &changelog)?).map_err(|_| "changelog isn't UTF-8")?));
+        }
+        let changelog = String::from_utf8(changelog).map_err(|_| "changelog isn't UTF-8")?;
+        // packages that ship CHANGELOG.md as-is must stay the same, so conversion is opt-in
+        let format = package_deb.changelog_format.unwrap_or(ChangelogFormat::Debian);
+        Ok(Some(if format == ChangelogFormat::Markdown { changelog::markdown_to_debian(&changelog, package_deb)? } else { changelog }))
+    }
+
+    fn add_metainfo_asset(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        if let Some(rel_path) = &package_deb.metainfo_rel_path {
+            let source_path = self.path_in_package(rel_path);
+            let target_path = desktop::metainfo_target_path(&source_path)?;
+            log::debug!("added metainfo {} as {}", source_path.display(), target_path.display());
+            package_deb.assets.resolved.push(Asset::new(
+                AssetSource::Path(source_path),
+                target_path,
+                0o644,
+                IsBuilt::No,
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_icon_assets(&self, package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+        if let Some(rel_path) = &package_deb.icon_rel_path {
+            let assets = icons::icon_assets(&self.path_in_package(rel_path), &package_deb.deb_name, listener)?;
+            package_deb.assets.resolved.extend(assets);
+        }
+        Ok(())
+    }
+
+    /// Like `dh_installsysusers`. Without the `sysusers` option, `<package>.sysusers` in `maintainer-scripts` is used if it exists.
+    fn add_sysusers_asset(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        let rel_path = package_deb.sysusers_rel_path.clone();
+        self.add_config_snippet_asset(package_deb, rel_path.as_deref(), "sysusers", SYSUSERS_DIR)
+    }
+
+    /// Like `dh_installtmpfiles`. Without the `tmpfiles` option, `<package>.tmpfiles` in `maintainer-scripts` is used if it exists.
+    fn add_tmpfiles_asset(&self, package_deb: &mut PackageConfig) -> CDResult<()> {
+        let rel_path = package_deb.tmpfiles_rel_path.clone();
+        self.add_config_snippet_asset(package_deb, rel_path.as_deref(), "tmpfiles", TMPFILES_DIR)
+    }
+
+    /// Installs the file as `<dir>/<package>.conf`
+    fn add_config_snippet_asset(&self, package_deb: &mut PackageConfig, rel_path: Option<&Path>, suffix: &str, dir: &str) -> CDResult<()> {
+        let source_path = match (rel_path, &package_deb.maintainer_scripts_rel_path) {
+            (Some(rel_path), _) => {
+                let path = self.path_in_package(rel_path);
+                if !is_path_file(&path) {
+                    return Err(CargoDebError::IoFile("config file not found", io::ErrorKind::NotFound.into(), path));
+                }
+                path
+            },
+            (None, Some(dir)) => match dh_lib::pkgfile(&self.path_in_package(dir), &package_deb.name, &package_deb.name, suffix, None) {
+                Some(path) => path,
+                None => return Ok(()),
+            },
+            (None, None) => return Ok(()),
+        };
+        let target_path = PathBuf::from(format!("{dir}/{}.conf", package_deb.deb_name));
+        log::debug!("added {suffix} {} as {}", source_path.display(), target_path.display());
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Path(source_path), target_path, 0o644, IsBuilt::No, false));
+        Ok(())
+    }
+
+    fn add_systemd_assets(&self, package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
         if let Some(ref config_vec) = package_deb.systemd_units {
             for config in config_vec {
                 let units_dir_option = config.unit_scripts.as_ref()
@@ -527,9 +862,37 @@ impl Config {
                     let package = &package_deb.name;
                     let unit_name = config.unit_name.as_deref();
 
-                    let units = dh_installsystemd::find_units(&search_path, package, unit_name);
+                    if listener.wants_diagnostics() {
+                        for candidate in dh_installsystemd::trace_find_units(&search_path, package, unit_name) {
+                            listener.systemd_unit_candidate(&candidate.info(), candidate.to_string());
+                        }
+                    }
+                    let mut units = dh_installsystemd::find_units(&search_path, package, unit_name);
+                    if config.user_unit.unwrap_or(false) {
+                        units = dh_installsystemd::into_user_units(units);
+                    }
+                    if units.is_empty() {
+                        let searched = dh_installsystemd::unit_file_names_searched(&search_path, package, unit_name).join(", ");
+                        let err = CargoDebError::SystemdUnitsNotFound(search_path, searched);
+                        if package_deb.fail_on_missing_systemd_unit {
+                            return Err(err);
+                        }
+                        listener.warning(format!("{err}\nUse --fail-on-missing-systemd-unit to make it an error"));
+                    }
 
                     for (source, target) in units {
+                        // systemd kills the service after TimeoutStopSec=, so prerm's stop job can't hang
+                        let stop_timeout = config.stop_timeout.as_ref().filter(|_| !config.user_unit.unwrap_or(false))
+                            .zip(target.path.file_name().and_then(|n| n.to_str()).filter(|n| n.ends_with(".service")));
+                        if let Some((timeout, unit_name)) = stop_timeout {
+                            package_deb.assets.resolved.push(Asset::new(
+                                AssetSource::Data(format!("[Service]\nTimeoutStopSec={}\n", timeout.0).into_bytes()),
+                                target.path.with_file_name(format!("{unit_name}.d/stop-timeout.conf")),
+                                0o644,
+                                IsBuilt::No,
+                                false,
+                            ).processed("stop-timeout", None));
+                        }
                         package_deb.assets.resolved.push(Asset::new(
                             AssetSource::from_path(source, package_deb.preserve_symlinks), // should this even support symlinks at all?
                             target.path,
@@ -546,33 +909,109 @@ impl Config {
         Ok(())
     }
 
+    /// Run-time dependencies of the package for the target platform, with features they'll have in the build, for `dependency-hints`
+    ///
+    /// `extra_flags` are arguments for `cargo build`, which may contain `--features`
+    pub(crate) fn dependency_features(&self, extra_flags: &[String]) -> CDResult<DependencyFeatures> {
+        let mut feature_flags = Vec::new();
+        if !self.default_features {
+            feature_flags.push("--no-default-features".into());
+        }
+        if !self.features.is_empty() {
+            feature_flags.push(format!("--features={}", self.features.join(",")));
+        }
+        let mut flags = extra_flags.iter();
+        while let Some(flag) = flags.next() {
+            match flag.as_str() {
+                "--all-features" | "--no-default-features" => feature_flags.push(flag.clone()),
+                "--features" | "-F" => feature_flags.extend(flags.next().map(|f| format!("--features={f}"))),
+                f if f.starts_with("--features=") => feature_flags.push(flag.clone()),
+                _ => {},
+            }
+        }
+        crate::parse::manifest::dependency_features(&self.package_manifest_dir.join("Cargo.toml"), self.rust_target_triple(),
+            &feature_flags, self.cargo_target_dir_override.as_deref(), self.cargo_locking_flags)
+    }
+
+    /// Features that the build will have, including ones enabled by other features
+    ///
+    /// `extra_flags` are arguments for `cargo build`, which may contain `--features`
+    pub(crate) fn enabled_features(&self, extra_flags: &[String]) -> BTreeSet<String> {
+        let mut requested = self.features.clone();
+        let mut default_features = self.default_features;
+        let mut flags = extra_flags.iter().map(String::as_str);
+        while let Some(flag) = flags.next() {
+            match flag {
+                "--all-features" => requested.extend(self.feature_table.keys().cloned()),
+                "--no-default-features" => default_features = false,
+                "--features" | "-F" => requested.extend(flags.next().map(String::from)),
+                _ => requested.extend(flag.strip_prefix("--features=").map(String::from)),
+            }
+        }
+        if default_features {
+            requested.push("default".into());
+        }
+
+        let mut enabled = BTreeSet::new();
+        let mut queue = requested.iter()
+            .flat_map(|f| f.split([',', ' ']))
+            .filter(|f| !f.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        while let Some(feature) = queue.pop() {
+            if let Some(implied) = self.feature_table.get(&feature) {
+                // dependencies' features don't matter here
+                queue.extend(implied.iter().filter(|f| !f.contains(['/', ':'])).cloned());
+            }
+            enabled.insert(feature);
+        }
+        enabled
+    }
+
     pub(crate) fn path_in_build<P: AsRef<Path>>(&self, rel_path: P) -> PathBuf {
         self.path_in_build_(rel_path.as_ref())
     }
 
     pub(crate) fn path_in_build_(&self, rel_path: &Path) -> PathBuf {
+        self.profile_dir().join(rel_path)
+    }
+
+    /// `CARGO_TARGET_DIR` or `target/` as Cargo uses it, i.e. without the target triple subdirectory of `target_dir`
+    pub fn cargo_target_dir(&self) -> &Path {
+        if self.rust_target_triple.is_some() {
+            self.target_dir.parent().unwrap_or(&self.target_dir)
+        } else {
+            &self.target_dir
+        }
+    }
+
+    /// Where Cargo puts built binaries, e.g. `target/release` or `target/<triple>/<profile>`
+    pub fn profile_dir(&self) -> PathBuf {
         let profile = match self.build_profile_override.as_deref() {
             None => "release",
             Some("dev") => "debug",
             Some(p) => p,
         };
+        self.target_dir.join(profile)
+    }
 
-        let mut path = self.target_dir.join(profile);
-        path.push(rel_path);
-        path
+    /// The dev profile is used with `--profile=dev` or `profile = "dev"`
+    pub(crate) fn is_dev_profile(&self) -> bool {
+        self.build_profile_override.as_deref() == Some("dev")
     }
 
     pub(crate) fn path_in_package<P: AsRef<Path>>(&self, rel_path: P) -> PathBuf {
         self.package_manifest_dir.join(rel_path)
     }
 
-    /// Store intermediate files here
-    pub(crate) fn deb_temp_dir(&self, package_deb: &PackageConfig) -> PathBuf {
+    /// Store intermediate files here: `data/` has stripped binaries and debug symbols at their install paths,
+    /// and `control/` has a copy of the control files (only with `--keep-temp`)
+    pub fn deb_temp_dir(&self, package_deb: &PackageConfig) -> PathBuf {
         self.target_dir.join("debian").join(&package_deb.name)
     }
 
     /// Save final .deb here
-    pub(crate) fn deb_output_path(&self, package_deb: &PackageConfig) -> PathBuf {
+    pub fn deb_output_path(&self, package_deb: &PackageConfig) -> PathBuf {
         let filename = format!("{}_{}_{}.deb", package_deb.deb_name, package_deb.deb_version, package_deb.architecture);
 
         if let Some(ref path_str) = self.deb_output_path {
@@ -587,7 +1026,8 @@ impl Config {
         }
     }
 
-    pub(crate) fn default_deb_output_dir(&self) -> PathBuf {
+    /// `target/debian`, or `target/<triple>/debian` when cross-compiling. `--output` may override it.
+    pub fn default_deb_output_dir(&self) -> PathBuf {
         self.target_dir.join("debian")
     }
 
@@ -598,30 +1038,60 @@ impl Config {
     /// Creates empty (removes files if needed) target/debian/foo directory so that we can start fresh.
     fn reset_deb_temp_directory(&self, package_deb: &PackageConfig) -> io::Result<()> {
         let deb_temp_dir = self.deb_temp_dir(package_deb);
-        let _ = fs::remove_dir(&deb_temp_dir);
-        // Delete previous .deb from target/debian, but only other versions of the same package
-        let mut deb_dir = self.default_deb_output_dir();
-        deb_dir.push(format!("{}_*_{}.deb", package_deb.deb_name, package_deb.architecture));
-        if let Ok(old_files) = glob::glob(deb_dir.to_str().ok_or(io::ErrorKind::InvalidInput)?) {
-            for old_file in old_files.flatten() {
-                let _ = fs::remove_file(old_file);
-            }
+        let _ = fs::remove_dir_all(&deb_temp_dir);
+        if !package_deb.keep_old_debs {
+            self.remove_old_debs(package_deb, &[]);
         }
         fs::create_dir_all(deb_temp_dir)
     }
 
+    /// Deletes previous .deb from target/debian, but only other versions of the same package.
+    /// Debs in `keep` have been made in the same run, e.g. for other target distros.
+    pub(crate) fn remove_old_debs(&self, package_deb: &PackageConfig, keep: &[&Path]) {
+        let Ok(entries) = fs::read_dir(self.default_deb_output_dir()) else { return };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if is_old_deb_of(&entry.file_name().to_string_lossy(), &package_deb.deb_name, &package_deb.architecture) && !keep.contains(&path.as_path()) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
     #[must_use]
     pub fn rust_target_triple(&self) -> &str{
         self.rust_target_triple.as_deref().unwrap_or(DEFAULT_TARGET)
     }
 }
 
+/// `name_version_arch.deb`, or a partial `.deb.tmp` left by an interrupted build.
+/// Names and versions can't contain `_`, so other packages can't match.
+fn is_old_deb_of(file_name: &str, deb_name: &str, architecture: &str) -> bool {
+    let Some(rest) = file_name.strip_suffix(".deb").or_else(|| file_name.strip_suffix(".deb.tmp")) else { return false };
+    let mut parts = rest.split('_');
+    parts.next() == Some(deb_name)
+        && parts.next().is_some_and(|version| !version.is_empty())
+        && parts.next() == Some(architecture)
+        && parts.next().is_none()
+}
+
 impl PackageConfig {
     pub(crate) fn new(mut deb: CargoDeb, cargo_package: &mut cargo_toml::Package<CargoPackageMetadata>, listener: &dyn Listener, default_timestamp: u64, overrides: DebConfigOverrides, target: &str) -> Result<Self, CargoDebError> {
-        let (license_file_rel_path, license_file_skip_lines) = parse_license_file(cargo_package, deb.license_file.as_ref())?;
+        let (license_file_rel_path, license_file_skip_lines, license_files_rel_paths) = parse_license_file(cargo_package, deb.license_file.as_ref())?;
+        let (copyright, copyright_stanzas) = match deb.copyright.take() {
+            Some(Copyright::String(s)) => (Some(s), vec![]),
+            Some(Copyright::Stanza(stanza)) => (None, vec![stanza]),
+            Some(Copyright::Stanzas(stanzas)) => (None, stanzas),
+            None => (None, vec![]),
+        };
         let mut license = cargo_package.license.take().map(|v| v.unwrap());
+        // with a synopsis, Cargo's description becomes the extended description
+        let synopsis = deb.synopsis.take();
+        if let Some(synopsis) = &synopsis {
+            check_synopsis(synopsis).map_err(|why| CargoDebError::InvalidSynopsis(synopsis.clone(), why))?;
+        }
+        let mut cargo_description = cargo_package.description.take().map(|v| v.unwrap());
 
-        if license.is_none() && license_file_rel_path.is_none() {
+        if license.is_none() && license_file_rel_path.is_none() && license_files_rel_paths.is_empty() {
             if cargo_package.publish() == false {
                 license = Some("UNLICENSED".into());
                 listener.info("license field defaulted to UNLICENSED".into());
@@ -631,24 +1101,32 @@ impl PackageConfig {
         }
 
         let has_maintainer_override = overrides.maintainer.is_some();
+        let auto_doc = overrides.auto_doc.or(deb.auto_doc).unwrap_or(true);
+        let cargo_version = cargo_package.version().to_owned();
+        if let Some(upstream_version) = overrides.upstream_version {
+            cargo_package.version.set(upstream_version);
+        }
         let deb_version = overrides.deb_version.unwrap_or_else(|| manifest_version_string(cargo_package, overrides.deb_revision.or(deb.revision.take()).as_deref()).into_owned());
         if let Err(why) = check_debian_version(&deb_version) {
             return Err(CargoDebError::InvalidVersion(why, deb_version));
         }
         Ok(Self {
             deb_version,
+            cargo_version,
             default_timestamp,
             raw_assets: deb.assets.take(),
+            duplicate_assets: deb.duplicate_assets.take(),
             name: cargo_package.name.clone(),
             deb_name: deb.name.take().unwrap_or_else(|| debian_package_name(&cargo_package.name)),
             license,
             license_file_rel_path,
             license_file_skip_lines,
+            license_files_rel_paths,
             maintainer: overrides.maintainer.or_else(|| deb.maintainer.take()).ok_or_then(|| {
                 Ok(cargo_package.authors().first()
                     .ok_or("The package must have a maintainer specified (--maintainer works too) or have the authors property")?.to_owned())
             })?,
-            copyright: match deb.copyright.take() {
+            copyright: match copyright {
                 ok @ Some(_) => ok,
                 _ if !cargo_package.authors().is_empty() => Some(cargo_package.authors().join(", ")),
                 _ if has_maintainer_override => {
@@ -656,21 +1134,24 @@ impl PackageConfig {
                     // As a compromise if the maintainer is set on the command-line, assume they can't fix the metadata, and let it be missing.
                     None
                 },
+                _ if !copyright_stanzas.is_empty() && copyright_stanzas.iter().all(|s| s.copyright.is_some()) => None,
                 _ => return Err("The package must have a copyright or authors property".into()),
             },
+            copyright_stanzas,
             homepage: cargo_package.homepage().map(From::from),
             documentation: cargo_package.documentation().map(From::from),
             repository: cargo_package.repository.take().map(|v| v.unwrap()),
-            description: cargo_package.description.take().map_or_else(|| {
+            published_on_crates_io: cargo_package.publish() != false,
+            description: synopsis.or_else(|| cargo_description.take()).unwrap_or_else(|| {
                 listener.warning("description field is missing in Cargo.toml".to_owned());
                 format!("[generated from Rust crate {}]", cargo_package.name)
-            }, |v| v.unwrap()),
+            }),
             extended_description: if let Some(path) = deb.extended_description_file.take() {
                 if deb.extended_description.is_some() {
                     listener.warning("extended-description and extended-description-file are both set".into());
                 }
                 ExtendedDescription::File(path.into())
-            } else if let Some(desc) = deb.extended_description.take() {
+            } else if let Some(desc) = deb.extended_description.take().or(cargo_description) {
                 ExtendedDescription::String(desc)
             } else if let Some(readme_rel_path) = cargo_package.readme().as_path() {
                 if readme_rel_path.extension().is_some_and(|ext| ext == "md" || ext == "markdown") {
@@ -680,31 +1161,85 @@ impl PackageConfig {
             } else {
                 ExtendedDescription::None
             },
-            readme_rel_path: cargo_package.readme().as_path().map(|p| p.to_path_buf()),
-            wildcard_depends: deb.depends.take().map_or_else(|| "$auto".to_owned(), DependencyList::into_depends_string),
+            readme_rel_path: cargo_package.readme().as_path().filter(|_| auto_doc).map(|p| p.to_path_buf()),
+            wildcard_depends: deb.depends.take().map_or_else(|| Ok("$auto".to_owned()), DependencyList::into_depends_string)?,
+            soname_packages: deb.soname_packages.take().unwrap_or_default(),
+            depends_remove: deb.depends_remove.take().unwrap_or_default(),
+            depends_pin: deb.depends_pin.take().unwrap_or_default(),
             resolved_depends: None,
-            pre_depends: deb.pre_depends.take().map(DependencyList::into_depends_string),
-            recommends: deb.recommends.take().map(DependencyList::into_depends_string),
-            suggests: deb.suggests.take().map(DependencyList::into_depends_string),
-            enhances: deb.enhances.take(),
-            conflicts: deb.conflicts.take(),
-            breaks: deb.breaks.take(),
-            replaces: deb.replaces.take(),
-            provides: deb.provides.take(),
+            pre_depends: deb.pre_depends.take().map(|d| d.into_relations_string("pre-depends")).transpose()?,
+            dependency_aliases: deb.dependency_aliases.take().unwrap_or_default(),
+            dependency_hints: deb.dependency_hints.unwrap_or(false),
+            target_distro: None,
+            flavor: overrides.flavor,
+            recommends: deb.recommends.take().map(|d| d.into_relations_string("recommends")).transpose()?,
+            suggests: deb.suggests.take().map(|d| d.into_relations_string("suggests")).transpose()?,
+            enhances: deb.enhances.take().map(|d| d.into_relations_string("enhances")).transpose()?,
+            conflicts: deb.conflicts.take().map(|d| d.into_relations_string("conflicts")).transpose()?,
+            breaks: deb.breaks.take().map(|d| d.into_relations_string("breaks")).transpose()?,
+            replaces: deb.replaces.take().map(|d| d.into_relations_string("replaces")).transpose()?,
+            provides: deb.provides.take().map(|d| d.into_relations_string("provides")).transpose()?,
+            relations_line_width: deb.relations_line_width.unwrap_or(0),
             section: deb.section.take(),
-            priority: deb.priority.take().unwrap_or_else(|| "optional".to_owned()),
-            architecture: debian_architecture_from_rust_triple(target).to_owned(),
+            priority: match deb.priority.take() {
+                Some(p) if p == "extra" => {
+                    listener.warning("Priority 'extra' is deprecated. Use 'optional' instead".into());
+                    p
+                },
+                Some(p) if priority_rank(&p).is_none() => {
+                    listener.warning(format!("Priority '{p}' is unknown. It should be one of: required, important, standard, optional"));
+                    p
+                },
+                Some(p) => p,
+                None => "optional".to_owned(),
+            },
+            essential: match deb.essential {
+                Some(true) if !overrides.allow_essential => return Err(CargoDebError::EssentialNotAllowed(cargo_package.name.clone())),
+                Some(true) => {
+                    listener.warning("The package is Essential, so it can't be removed without --force options. Consider `protected = true` instead".into());
+                    true
+                },
+                _ => false,
+            },
+            protected: deb.protected.unwrap_or(false),
+            architecture: {
+                if let Some(warning) = overrides.flavor.check_target(target) {
+                    listener.warning(warning);
+                }
+                debian_architecture_from_rust_triple(target).to_owned()
+            },
             conf_files: deb.conf_files.take().unwrap_or_default(),
+            non_conf_files: deb.non_conf_files.take().unwrap_or_default(),
+            runtime_files: deb.runtime_files.take().unwrap_or_default().iter().map(|path| {
+                let normalized = Asset::normalized_target_path(Path::new(path.trim_start_matches('/')))?;
+                Ok(format!("/{}{}", normalized.display(), if path.ends_with('/') { "/" } else { "" }))
+            }).collect::<CDResult<_>>()?,
+            maintscripts: deb.maintscripts.take().unwrap_or_default().iter().map(|command| maintscript_command(command)).collect::<CDResult<_>>()?,
+            dirs: deb.dirs.take().unwrap_or_default(),
             assets: Assets::new(),
             triggers_file_rel_path: deb.triggers_file.take().map(PathBuf::from),
+            metainfo_rel_path: deb.metainfo.take().map(PathBuf::from),
+            icon_rel_path: deb.icon.take().map(PathBuf::from),
+            sysusers_rel_path: deb.sysusers.take().map(PathBuf::from),
+            tmpfiles_rel_path: deb.tmpfiles.take().map(PathBuf::from),
             changelog: deb.changelog.take(),
+            changelog_format: deb.changelog_format.take(),
             maintainer_scripts_rel_path: deb.maintainer_scripts.take().map(PathBuf::from),
             preserve_symlinks: deb.preserve_symlinks.unwrap_or(false),
+            keep_old_debs: overrides.keep_old_debs || deb.keep_old_debs.unwrap_or(false),
+            changes_file: overrides.changes_file,
+            fail_on_missing_systemd_unit: overrides.fail_on_missing_systemd_unit,
+            implicit_triggers: deb.implicit_triggers.unwrap_or(true),
+            allow_special_files: deb.allow_special_files.unwrap_or(false),
             systemd_units: match deb.systemd_units.take() {
                 None => None,
                 Some(SystemUnitsSingleOrMultiple::Single(s)) => Some(vec![s]),
                 Some(SystemUnitsSingleOrMultiple::Multi(v)) => Some(v),
             },
+            publish: deb.publish.take(),
+            sign: deb.sign.take(),
+            vcs_revision: overrides.vcs_revision.flatten(),
+            vcs_revision_file: deb.vcs_revision_file.unwrap_or(false),
             multiarch: Multiarch::None,
         })
     }
@@ -722,52 +1257,246 @@ impl PackageConfig {
         }
     }
 
+    /// Whether any of these packages is already in `Depends`, `Pre-Depends` or `Recommends`
+    pub(crate) fn has_dependency_on(&self, names: &[&str]) -> bool {
+        [&self.resolved_depends, &self.pre_depends, &self.recommends].into_iter().flatten()
+            .any(|deps| RelationList::package_names(deps).any(|name| names.contains(&name.as_str())))
+    }
+
+    /// Appends `relation` to `Depends`, like [`Self::add_recommends`]
+    pub(crate) fn add_depends(&mut self, relation: &str, listener: &dyn Listener) -> bool {
+        if self.has_any_dependency_in(relation) {
+            return false;
+        }
+        self.resolved_depends = Some(appended_relation(self.resolved_depends.as_deref(), relation, "Depends", listener));
+        true
+    }
+
+    /// Appends `relation` to `Recommends`. Packages already in `Depends`, `Pre-Depends` or `Recommends` are left alone.
+    /// Returns whether it has been added.
+    pub(crate) fn add_recommends(&mut self, relation: &str, listener: &dyn Listener) -> bool {
+        if self.has_any_dependency_in(relation) {
+            return false;
+        }
+        self.recommends = Some(appended_relation(self.recommends.as_deref(), relation, "Recommends", listener));
+        true
+    }
+
+    fn has_any_dependency_in(&self, relation: &str) -> bool {
+        let names = RelationList::package_names(relation).collect::<Vec<_>>();
+        self.has_dependency_on(&names.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
     pub(crate) fn multiarch_lib_dir(&self, rust_target_triple: &str) -> PathBuf {
         PathBuf::from(format!("usr/lib/{}", debian_triple_from_rust_triple(rust_target_triple)))
     }
 
+    /// Adds a file to the package, e.g. one generated by a library user after `Config::from_manifest`.
+    ///
+    /// The target path is normalized like paths of `assets` in the manifest, an asset with the same
+    /// target path is handled according to the `duplicate-assets` setting, and files in `/etc` become conffiles.
+    pub fn add_asset(&mut self, mut asset: Asset, listener: &dyn Listener) -> CDResult<()> {
+        asset.c.target_path = Asset::normalized_target_path(&asset.c.target_path)?;
+        let runtime_path = format!("/{}", asset.c.target_path.display());
+        if self.runtime_files.contains(&runtime_path) {
+            return Err(CargoDebError::RuntimeFileIsAsset(asset.c.target_path));
+        }
+        self.assets.resolved.push(asset);
+        if let Err(e) = self.remove_duplicate_assets(listener) {
+            self.assets.resolved.pop();
+            return Err(e);
+        }
+        self.add_conf_files()
+    }
+
+    /// Adds a file with the given contents at `target_path` (a file path, not a dir). See `add_asset`.
+    pub fn add_data_asset(&mut self, data: Vec<u8>, target_path: impl Into<PathBuf>, chmod: u32, listener: &dyn Listener) -> CDResult<()> {
+        let target_path = target_path.into();
+        if target_path.to_string_lossy().ends_with('/') {
+            return Err(CargoDebError::InvalidAssetPath(target_path, "it needs a file name, because there's no source file to take it from".into()));
+        }
+        self.add_asset(Asset::new(AssetSource::Data(data), target_path, chmod, IsBuilt::No, false), listener)
+    }
+
     pub fn resolve_assets(&mut self) -> CDResult<()> {
+        self.resolve_assets_with_listener(&crate::listener::NoOpListener)
+    }
+
+    /// Like [`Self::resolve_assets`], but reports duplicate assets and conffiles to the listener
+    pub fn resolve_assets_with_listener(&mut self, listener: &dyn Listener) -> CDResult<()> {
         for u in self.assets.unresolved.drain(..) {
             let matched = u.resolve(self.preserve_symlinks)?;
             self.assets.resolved.extend(matched);
         }
-        self.add_conf_files();
+        self.remove_duplicate_assets(listener)?;
+        self.add_conf_files()?;
+        // likely a typo, which would make the file a conffile after all
+        if let Some(missing) = self.non_conf_files.iter().find(|f| {
+            let path = Path::new(f.trim_start_matches('/'));
+            !self.assets.resolved.iter().any(|a| a.c.target_path == path)
+        }) {
+            return Err(CargoDebError::NonConfFileNotInPackage(missing.clone()));
+        }
+        if !self.conf_files.is_empty() && listener.wants_diagnostics() {
+            listener.info(format!("conffiles: {}", self.conf_files.join(", ")));
+        }
+        let runtime_paths = self.runtime_files.iter().map(|f| Path::new(f.trim_matches('/'))).collect::<Vec<_>>();
+        if let Some(asset) = self.assets.resolved.iter().find(|a| runtime_paths.contains(&a.c.target_path.as_path())) {
+            return Err(CargoDebError::RuntimeFileIsAsset(asset.c.target_path.clone()));
+        }
+        if !self.runtime_files.is_empty() {
+            listener.info(format!("runtime files (deleted on purge): {}", self.runtime_files.join(", ")));
+        }
+        Ok(())
+    }
+
+    /// Applies the `duplicate-assets` policy to assets with the same target path
+    fn remove_duplicate_assets(&mut self, listener: &dyn Listener) -> CDResult<()> {
+        let mut kept = HashMap::with_capacity(self.assets.resolved.len());
+        let mut remove = Vec::new();
+        for (i, asset) in self.assets.resolved.iter().enumerate() {
+            let Some(prev) = kept.insert(&asset.c.target_path, i) else { continue };
+            let path = asset.c.target_path.display();
+            match self.duplicate_assets {
+                Some(DuplicateAssets::Error) => return Err(CargoDebError::DuplicateAsset(asset.c.target_path.clone())),
+                Some(DuplicateAssets::Last) => remove.push(prev),
+                policy @ (Some(DuplicateAssets::First) | None) => {
+                    if policy.is_none() {
+                        listener.warning(format!("Duplicate asset /{path}. Only the first one is used. Set `duplicate-assets` in [package.metadata.deb] to choose"));
+                    }
+                    kept.insert(&asset.c.target_path, prev);
+                    remove.push(i);
+                },
+            }
+        }
+        if !remove.is_empty() {
+            remove.sort_unstable();
+            let mut i = 0;
+            self.assets.resolved.retain(|_| {
+                let keep = remove.binary_search(&i).is_err();
+                i += 1;
+                keep
+            });
+        }
         Ok(())
     }
 
     /// Debian defaults all /etc files to be conf files
     /// <https://www.debian.org/doc/manuals/maint-guide/dother.en.html#conffiles>
-    fn add_conf_files(&mut self) {
+    ///
+    /// Files listed in `non-conf-files` are exempt, since they're usually generated or rewritten at run time.
+    /// Only regular files are added, since dpkg checksums their contents.
+    fn add_conf_files(&mut self) -> CDResult<()> {
+        self.normalize_conf_files();
         let existing_conf_files = self.conf_files.iter()
             .map(|c| c.trim_start_matches('/')).collect::<HashSet<_>>();
+        let non_conf_files = self.non_conf_files.iter()
+            .map(|c| c.trim_start_matches('/')).collect::<HashSet<_>>();
+
+        if let Some(both) = non_conf_files.iter().find(|n| existing_conf_files.contains(*n)) {
+            return Err(CargoDebError::ConfFileConflict(format!("/{both}")));
+        }
 
         let mut new_conf = Vec::new();
         for a in &self.assets.resolved {
-            if a.c.target_path.starts_with("etc") {
+            let is_regular_file = matches!(a.source, AssetSource::Path(_) | AssetSource::Data(_));
+            if is_regular_file && a.c.target_path.starts_with("etc") {
                 let Some(path_str) = a.c.target_path.to_str() else { continue };
                 if existing_conf_files.contains(path_str) {
                     continue;
                 }
+                if non_conf_files.contains(path_str) {
+                    log::debug!("/{path_str} is in non-conf-files, so it won't be a conffile");
+                    continue;
+                }
                 log::debug!("automatically adding /{path_str} to conffiles");
                 new_conf.push(format!("/{path_str}"));
             }
         }
         self.conf_files.append(&mut new_conf);
+        self.normalize_conf_files();
+        Ok(())
+    }
+
+    /// Sorts and deduplicates conffiles, so that `etc/a`, `/etc/a` and `/etc/./a` are one entry.
+    /// Symlinks to other conffiles are dropped, because dpkg would track the same file twice.
+    fn normalize_conf_files(&mut self) {
+        let mut conf_files = self.conf_files.iter().map(|f| normalized_conffile_path(f)).collect::<BTreeSet<_>>();
+        let symlinks_to_conf_files = self.assets.resolved.iter().filter_map(|a| {
+            let AssetSource::Symlink(source_path) = &a.source else { return None };
+            let link = fs::read_link(source_path).ok()?;
+            let path = normalized_conffile_path(a.c.target_path.to_str()?);
+            let link_target = if link.is_absolute() { link } else { a.c.target_path.parent()?.join(link) };
+            conf_files.contains(&normalized_conffile_path(link_target.to_str()?)).then_some(path)
+        }).collect::<Vec<_>>();
+        for path in symlinks_to_conf_files {
+            log::debug!("{path} is a symlink to another conffile");
+            conf_files.remove(&path);
+        }
+        self.conf_files = conf_files.into_iter().collect();
+    }
+
+    /// Drops systemd units that have `required-features` which are not enabled
+    pub(crate) fn select_systemd_units(&mut self, enabled_features: &BTreeSet<String>) {
+        if let Some(units) = &mut self.systemd_units {
+            units.retain(|unit| {
+                let Some(missing) = unit.required_features.iter().flatten().find(|f| !enabled_features.contains(*f)) else { return true };
+                log::debug!("skipping systemd unit {:?}, because feature '{missing}' is not enabled", unit.unit_name);
+                false
+            });
+        }
+    }
+
+    /// File names of `sysusers.d` configs in the package, for `postinst`
+    pub(crate) fn sysusers_files(&self) -> Vec<&str> {
+        self.config_snippet_files(SYSUSERS_DIR)
+    }
+
+    /// File names of `tmpfiles.d` configs in the package, for `postinst`
+    pub(crate) fn tmpfiles_files(&self) -> Vec<&str> {
+        self.config_snippet_files(TMPFILES_DIR)
+    }
+
+    fn config_snippet_files(&self, dir: &str) -> Vec<&str> {
+        let mut files: Vec<_> = self.assets.resolved.iter().filter_map(|asset| {
+            let name = asset.c.target_path.strip_prefix(dir).ok()?.to_str()?;
+            Some(name).filter(|name| name.ends_with(".conf") && !name.contains('/'))
+        }).collect();
+        files.sort_unstable();
+        files.dedup();
+        files
     }
 
     /// run dpkg/ldd to check deps of libs
     pub fn resolve_binary_dependencies(&mut self, lib_dir_search_path: Option<&Path>, listener: &dyn Listener) -> CDResult<()> {
+        self.resolve_binary_dependencies_with_explain(lib_dir_search_path, false, listener)
+    }
+
+    /// `explain` reports which binaries and libraries caused each dependency
+    pub fn resolve_binary_dependencies_with_explain(&mut self, lib_dir_search_path: Option<&Path>, explain: bool, listener: &dyn Listener) -> CDResult<()> {
         let mut deps = BTreeSet::new();
+        let mut explicit_deps = Vec::new();
+        let mut binaries_deps = Vec::new();
         for word in self.wildcard_depends.split(',') {
             let word = word.trim();
+            if word.is_empty() {
+                continue;
+            }
             if word == "$auto" {
                 let bin = self.all_binaries();
                 let resolved = bin.par_iter()
-                    .filter(|bin| !bin.archive_as_symlink_only())
+                    .filter(|bin| !bin.source.archive_as_symlink_only())
                     .filter_map(|&p| {
-                        let bname = p.path()?;
-                        match resolve_with_dpkg(bname, lib_dir_search_path) {
-                            Ok(bindeps) => Some(bindeps),
+                        let bname = p.source.path()?;
+                        let resolved = match resolve_with_dpkg(bname, lib_dir_search_path, explain) {
+                            Err(CargoDebError::CommandFailed(e, _)) if e.kind() == io::ErrorKind::NotFound => {
+                                listener.info(format!("dpkg-shlibdeps not found, reading libraries of {} from the ELF file", bname.display()));
+                                dependencies::resolve_from_elf(bname, &self.soname_packages, listener)
+                            },
+                            res => res,
+                        };
+                        match resolved {
+                            Ok(bindeps) => Some((p.c.target_path.as_path(), bindeps)),
                             Err(err) => {
                                 listener.warning(format!("{err}\nNo $auto deps for {}", bname.display()));
                                 None
@@ -775,32 +1504,152 @@ impl PackageConfig {
                         }
                     })
                     .collect::<Vec<_>>();
-                for dep in resolved.into_iter().flat_map(|s| s.into_iter()) {
-                    deps.insert(dep);
+                for (_, bindeps) in &resolved {
+                    deps.extend(bindeps.depends.iter().cloned());
                 }
+                binaries_deps.extend(resolved);
             } else {
                 let (dep, arch_spec) = get_architecture_specification(word)?;
                 if let Some(spec) = arch_spec {
                     if match_architecture(spec, &self.architecture)? {
+                        explicit_deps.push(dep.clone());
                         deps.insert(dep);
                     }
                 } else {
+                    explicit_deps.push(dep.clone());
                     deps.insert(dep);
                 }
             }
         }
-        self.resolved_depends = Some(itertools::Itertools::join(&mut deps.into_iter(), ", "));
+        if explain {
+            let binaries_deps = binaries_deps.iter().map(|(path, deps)| (*path, deps)).collect::<Vec<_>>();
+            listener.report(dependencies::explain(&binaries_deps, &explicit_deps));
+        }
+        // postinst runs systemd-sysusers, which is also available without systemd
+        if !self.sysusers_files().is_empty() {
+            deps.insert("systemd | systemd-standalone-sysusers".into());
+        }
+        let mut resolved_depends = RelationList::normalize_str(&itertools::Itertools::join(&mut deps.into_iter(), ", "), "Depends", listener);
+        if let Some(distro) = &self.target_distro {
+            resolved_depends = distro.alias_dependencies(&resolved_depends, &self.dependency_aliases);
+            self.pre_depends = self.pre_depends.as_deref().map(|deps| distro.alias_dependencies(deps, &self.dependency_aliases));
+        }
+        resolved_depends = self.flavor.alias_dependencies(&resolved_depends, &self.dependency_aliases);
+        self.pre_depends = self.pre_depends.as_deref().map(|deps| self.flavor.alias_dependencies(deps, &self.dependency_aliases));
+
+        // overrides apply to final package names, after aliasing
+        if !self.depends_remove.is_empty() || !self.depends_pin.is_empty() {
+            match RelationList::parse(&resolved_depends) {
+                Ok(mut relations) => {
+                    for name in relations.remove_packages(&self.depends_remove) {
+                        listener.warning(format!("depends-remove has '{name}', but it's not in Depends"));
+                    }
+                    for name in relations.pin_versions(&self.depends_pin)? {
+                        listener.warning(format!("depends-pin has '{name}', but it's not in Depends"));
+                    }
+                    resolved_depends = relations.to_string();
+                },
+                Err(e) => listener.warning(format!("{e}. depends-remove and depends-pin haven't been applied")),
+            }
+        }
+        self.resolved_depends = Some(resolved_depends);
+        Ok(())
+    }
+
+    /// Packages with `required` or `important` priority must follow stricter rules.
+    /// This runs after dependencies have been resolved.
+    pub fn check_priority_policy(&self, listener: &dyn Listener) -> CDResult<()> {
+        if !matches!(self.priority.as_str(), "required" | "important") {
+            return Ok(());
+        }
+        let own_rank = priority_rank(&self.priority).unwrap_or_default();
+        if let Some(asset) = self.assets.resolved.iter().find(|a| a.c.target_path.starts_with("usr/local")) {
+            return Err(CargoDebError::PriorityPolicy(self.priority.clone(), format!("must not install files in /usr/local, but /{} is", asset.c.target_path.display())));
+        }
+
+        let deps = self.resolved_depends.iter().chain(&self.pre_depends)
+            .filter_map(|d| RelationList::parse(d).map_err(|e| listener.warning(format!("{e}. Its priority can't be checked"))).ok())
+            .collect::<Vec<_>>();
+        for group in deps.iter().flat_map(|d| &d.groups) {
+            let mut unknown = None;
+            let satisfied = group.iter().map(|rel| rel.name.as_str()).any(|name| match installed_package_priority(name).as_deref().and_then(priority_rank) {
+                Some(rank) => rank <= own_rank,
+                None => {
+                    unknown = Some(name);
+                    false
+                },
+            });
+            if satisfied {
+                continue;
+            }
+            if let Some(name) = unknown {
+                listener.warning(format!("Unable to check priority of dependency '{name}', because it's not installed on this system, or has an unknown priority. \
+                    Priority: {} packages must not depend on packages with a lower priority", self.priority));
+            } else {
+                let dep = RelationList { groups: vec![group.clone()] };
+                return Err(CargoDebError::PriorityPolicy(self.priority.clone(), format!("must not depend on packages with a lower priority, but it depends on '{dep}'")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks for absolute paths of the build machine (e.g. in panic messages) in the built binaries,
+    /// which leak infrastructure details and make the build unreproducible.
+    /// This runs after binaries have been stripped.
+    pub(crate) fn check_build_paths(&self, config: &Config, deny: bool, listener: &dyn Listener) -> CDResult<()> {
+        let mut prefixes = vec![
+            (config.package_manifest_dir.clone(), "."),
+            (config.cargo_run_current_dir.clone(), "."),
+            (config.target_dir.clone(), "target"),
+        ];
+        if let Some(cargo_home) = std::env::var_os("CARGO_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo"))) {
+            prefixes.push((cargo_home, "/cargo"));
+        }
+        // a short path like `/` would match everything
+        prefixes.retain(|(path, _)| path.is_absolute() && path.components().count() > 2);
+        prefixes.sort_by(|a, b| a.0.cmp(&b.0));
+        prefixes.dedup_by(|a, b| a.0.starts_with(&b.0));
+        if prefixes.is_empty() {
+            return Ok(());
+        }
+        let pattern = prefixes.iter().map(|(path, _)| regex::escape(&path.to_string_lossy())).collect::<Vec<_>>().join("|");
+        let pattern = regex::bytes::Regex::new(&pattern).map_err(|_| "invalid build path")?;
+
+        for asset in &self.assets.resolved {
+            if !(asset.c.is_built() && (asset.c.is_dynamic_library() || asset.c.is_executable())) || asset.c.target_path.starts_with("usr/lib/debug") {
+                continue;
+            }
+            let data = asset.source.data()?;
+            let found: BTreeSet<_> = pattern.find_iter(&data).map(|m| m.as_bytes()).collect();
+            if found.is_empty() {
+                continue;
+            }
+            let suggestion = format!("RUSTFLAGS=\"{}\"", prefixes.iter()
+                .filter(|(path, _)| found.contains(path.to_string_lossy().as_bytes()))
+                .map(|(path, replacement)| format!("--remap-path-prefix={}={replacement}", path.display()))
+                .collect::<Vec<_>>().join(" "));
+            if deny {
+                return Err(CargoDebError::BuildPaths(asset.c.target_path.clone(), suggestion));
+            }
+            listener.info(format!("/{} contains absolute paths of the build machine. To avoid that, build with {suggestion}", asset.c.target_path.display()));
+        }
         Ok(())
     }
 
+    /// Appends the distro's suffix to the version, and renames dependencies for it
+    pub fn set_target_distro(&mut self, distro: TargetDistro) {
+        self.deb_version.push_str(distro.version_suffix);
+        self.target_distro = Some(distro);
+    }
+
     /// Executables AND dynamic libraries. May include symlinks.
-    fn all_binaries(&self) -> Vec<&AssetSource> {
+    fn all_binaries(&self) -> Vec<&Asset> {
         self.assets.resolved.iter()
             .filter(|asset| {
                 // Assumes files in build dir which have executable flag set are binaries
                 asset.c.is_dynamic_library() || asset.c.is_executable()
             })
-            .map(|asset| &asset.source)
             .collect()
     }
 
@@ -809,7 +1658,7 @@ impl PackageConfig {
         self.assets.resolved.iter_mut()
             .filter(move |asset| {
                 // Assumes files in build dir which have executable flag set are binaries
-                asset.c.is_built() && (asset.c.is_dynamic_library() || asset.c.is_executable())
+                !asset.c.verbatim && asset.c.is_built() && (asset.c.is_dynamic_library() || asset.c.is_executable())
             })
             .collect()
     }
@@ -825,113 +1674,81 @@ impl PackageConfig {
         });
     }
 
-    fn extended_description(&self, config: &Config) -> CDResult<Option<Cow<'_, str>>> {
-        let path = match &self.extended_description {
-            ExtendedDescription::None => return Ok(None),
-            ExtendedDescription::String(s) => return Ok(Some(s.as_str().into())),
-            ExtendedDescription::File(p) => Cow::Borrowed(p.as_path()),
-            ExtendedDescription::ReadmeFallback(p) => Cow::Owned(config.path_in_package(p)),
+    /// Finds contradictions between `Depends`, `Conflicts`, `Breaks` and `Replaces`, and invalid `Provides`
+    fn check_relationships(&self, listener: &dyn Listener) -> CDResult<()> {
+        // fields that can't be parsed are passed to dpkg as-is, and only skipped here
+        let parse = |field: &Option<String>| -> RelationList {
+            field.as_deref().and_then(|f| RelationList::parse(f).map_err(|e| listener.warning(format!("{e}. Relationships with it can't be checked"))).ok())
+                .unwrap_or_default()
         };
-        let desc = fs::read_to_string(&path)
-            .map_err(|err| CargoDebError::IoFile("unable to read extended description from file", err, path.into_owned()))?;
-        Ok(Some(desc.into()))
-    }
-
-    /// Generates the control file that obtains all the important information about the package.
-    pub fn generate_control(&self, config: &Config) -> CDResult<Vec<u8>> {
-        // Create and return the handle to the control file with write access.
-        let mut control: Vec<u8> = Vec::with_capacity(1024);
-
-        // Write all of the lines required by the control file.
-        writeln!(&mut control, "Package: {}", self.deb_name)?;
-        writeln!(&mut control, "Version: {}", self.deb_version)?;
-        writeln!(&mut control, "Architecture: {}", self.architecture)?;
-        let ma = match self.multiarch {
-            Multiarch::None => "",
-            Multiarch::Same => "same",
-            Multiarch::Foreign => "foreign",
-        };
-        if !ma.is_empty() {
-            writeln!(&mut control, "Multi-Arch: {ma}")?;
-        }
-        if let Some(homepage) = self.homepage.as_deref().or(self.documentation.as_deref()).or(self.repository.as_deref()) {
-            writeln!(&mut control, "Homepage: {homepage}")?;
-        }
-        if let Some(ref section) = self.section {
-            writeln!(&mut control, "Section: {section}")?;
-        }
-        writeln!(&mut control, "Priority: {}", self.priority)?;
-        writeln!(&mut control, "Maintainer: {}", self.maintainer)?;
-
-        let installed_size = self.assets.resolved
-            .iter()
-            .map(|m| (m.source.file_size().unwrap_or(0) + 2047) / 1024) // assume 1KB of fs overhead per file
-            .sum::<u64>();
-
-        writeln!(&mut control, "Installed-Size: {installed_size}")?;
-
-        if let Some(deps) = &self.resolved_depends {
-            writeln!(&mut control, "Depends: {deps}")?;
-        }
-
-        if let Some(ref pre_depends) = self.pre_depends {
-            let pre_depends_normalized = pre_depends.trim();
-
-            if !pre_depends_normalized.is_empty() {
-                writeln!(&mut control, "Pre-Depends: {pre_depends_normalized}")?;
+        let depends = parse(&self.resolved_depends);
+        let pre_depends = parse(&self.pre_depends);
+        let conflicts = parse(&self.conflicts);
+        let breaks = parse(&self.breaks);
+        let replaces = parse(&self.replaces);
+        let provides = parse(&self.provides);
+
+        // alternatives and versioned conflicts may be satisfiable, but an unversioned conflict with a required package is not
+        let required = depends.groups.iter().chain(&pre_depends.groups).filter(|g| g.len() == 1).flatten();
+        for dep in required {
+            if let Some(conflict) = conflicts.groups.iter().flatten().find(|c| c.name == dep.name && c.version.is_none()) {
+                return Err(CargoDebError::InvalidRelationship(format!("'{}' is in both Depends and Conflicts", conflict.name)));
             }
         }
 
-        if let Some(ref recommends) = self.recommends {
-            let recommends_normalized = recommends.trim();
-
-            if !recommends_normalized.is_empty() {
-                writeln!(&mut control, "Recommends: {recommends_normalized}")?;
+        for replaced in replaces.groups.iter().flatten() {
+            if !breaks.groups.iter().chain(&conflicts.groups).flatten().any(|b| b.name == replaced.name) {
+                listener.warning(format!("Replaces: {} should be accompanied by Breaks: {} (<< version).\n\
+                    https://www.debian.org/doc/debian-policy/ch-relationships.html#overwriting-files-and-replacing-packages-replaces", replaced.name, replaced.name));
             }
         }
 
-        if let Some(ref suggests) = self.suggests {
-            let suggests_normalized = suggests.trim();
-
-            if !suggests_normalized.is_empty() {
-                writeln!(&mut control, "Suggests: {suggests_normalized}")?;
+        for group in &provides.groups {
+            if group.len() != 1 {
+                return Err(CargoDebError::InvalidRelationship("Provides can't have alternatives".into()));
             }
-        }
-
-        if let Some(ref enhances) = self.enhances {
-            let enhances_normalized = enhances.trim();
-
-            if !enhances_normalized.is_empty() {
-                writeln!(&mut control, "Enhances: {enhances_normalized}")?;
+            let provided = &group[0];
+            if provided.arch_spec.is_some() || provided.arch_qualifier.is_some() {
+                return Err(CargoDebError::InvalidRelationship(format!("Provides: {provided} can't have an architecture restriction")));
+            }
+            if provided.version.as_ref().is_some_and(|(op, _)| *op != VersionOp::Equal) {
+                return Err(CargoDebError::InvalidRelationship(format!("Provides: {provided} can only use an exact (= version)")));
             }
         }
+        Ok(())
+    }
 
-        if let Some(ref conflicts) = self.conflicts {
-            writeln!(&mut control, "Conflicts: {conflicts}")?;
-        }
-        if let Some(ref breaks) = self.breaks {
-            writeln!(&mut control, "Breaks: {breaks}")?;
-        }
-        if let Some(ref replaces) = self.replaces {
-            writeln!(&mut control, "Replaces: {replaces}")?;
-        }
-        if let Some(ref provides) = self.provides {
-            writeln!(&mut control, "Provides: {provides}")?;
-        }
-
-        write!(&mut control, "Description:")?;
-        for line in self.description.split_by_chars(79) {
-            writeln!(&mut control, " {line}")?;
-        }
+    /// Generates the control file that obtains all the important information about the package.
+    ///
+    /// It reads the extended description file if it hasn't been resolved yet. Warnings about relationships are discarded,
+    /// see [`PackageConfig::generate_checked_control`].
+    pub fn generate_control(&self, config: &Config) -> CDResult<Vec<u8>> {
+        let file_description = config.read_extended_description_file(&self.extended_description)?;
+        self.check_relationships(&crate::listener::NoOpListener)?;
+        let extended_description = match &self.extended_description {
+            ExtendedDescription::String(desc) => Some(desc.as_str()),
+            _ => file_description.as_deref(),
+        };
+        Ok(crate::deb::control::render_with_extended_description(self, self.installed_size(), extended_description).into_bytes())
+    }
 
-        if let Some(desc) = self.extended_description(config)? {
-            for line in desc.split_by_chars(79) {
-                writeln!(&mut control, " {line}")?;
-            }
+    /// Generates the control file, and reports problems with relationships to the listener.
+    ///
+    /// The `extended_description` must have been resolved with [`Config::resolve_extended_description`].
+    pub fn generate_checked_control(&self, listener: &dyn Listener) -> CDResult<Vec<u8>> {
+        if matches!(self.extended_description, ExtendedDescription::File(_) | ExtendedDescription::ReadmeFallback(_)) {
+            return Err(CargoDebError::Str("extended description file hasn't been read yet (use Config::resolve_extended_description)"));
         }
-        control.push(b'\n');
+        self.check_relationships(listener)?;
+        Ok(crate::deb::control::render(self, self.installed_size()).into_bytes())
+    }
 
-        Ok(control)
+    /// Size of the assets in KiB for `Installed-Size`, assuming 1KiB of filesystem overhead per file
+    pub fn installed_size(&self) -> u64 {
+        self.assets.resolved
+            .iter()
+            .map(|m| (m.source.file_size().unwrap_or(0) + 2047) / 1024)
+            .sum()
     }
 
     pub(crate) fn append_copyright_metadata(&self, copyright: &mut Vec<u8>) -> Result<(), CargoDebError> {
@@ -946,6 +1763,19 @@ impl PackageConfig {
         if let Some(license) = self.license.as_deref() {
             writeln!(copyright, "License: {license}")?;
         }
+        for stanza in &self.copyright_stanzas {
+            if stanza.files.is_empty() {
+                return Err(CargoDebError::Str("copyright stanza must have at least one entry in `files`"));
+            }
+            writeln!(copyright, "\nFiles: {}", stanza.files.join(" "))?;
+            if let Some(c) = stanza.copyright.as_deref().or(self.copyright.as_deref()) {
+                write_multiline_field(copyright, "Copyright", c)?;
+            }
+            match stanza.license.as_deref().or(self.license.as_deref()) {
+                Some(license) => writeln!(copyright, "License: {license}")?,
+                None => return Err(CargoDebError::Str("copyright stanza must have a license")),
+            }
+        }
         Ok(())
     }
 
@@ -965,19 +1795,40 @@ impl TryFrom<CargoDebAssetArrayOrTable> for RawAsset {
             u32::from_str_radix(mode, 8).map_err(|e| format!("Unable to parse mode argument (third array element) as an octal number in an asset: {e}"))
         }
         let a = match toml {
-            CargoDebAssetArrayOrTable::Table(a) => Self {
-                source_path: a.source.into(), target_path: a.dest.into(), chmod: parse_chmod(&a.mode)?
+            CargoDebAssetArrayOrTable::Table(a) => {
+                let special = match (a.file_type, a.device) {
+                    (None, None) => None,
+                    (Some(SpecialFileType::Fifo), None) => Some(SpecialFile::Fifo),
+                    (Some(SpecialFileType::CharDevice), Some([major, minor])) => Some(SpecialFile::CharDevice { major, minor }),
+                    (Some(SpecialFileType::BlockDevice), Some([major, minor])) => Some(SpecialFile::BlockDevice { major, minor }),
+                    (Some(SpecialFileType::Fifo), Some(_)) => return Err(format!("FIFO asset {} can't have a `device`", a.dest)),
+                    (Some(_), None) => return Err(format!("Device asset {} must have `device = [major, minor]`", a.dest)),
+                    (None, Some(_)) => return Err(format!("Asset {} has `device`, but no `type`", a.dest)),
+                };
+                let source_path = match (a.source, special) {
+                    (Some(source), None) => source.into(),
+                    (None, Some(_)) => PathBuf::new(),
+                    (Some(_), Some(_)) => return Err(format!("Special file asset {} can't have a `source`", a.dest)),
+                    (None, None) => return Err(format!("Missing `source` in asset {}", a.dest)),
+                };
+                let owner = a.owner.as_deref().map(str::parse).transpose().map_err(|e| format!("Invalid `owner` of asset {}: {e}", a.dest))?;
+                Self { source_path, target_path: a.dest.into(), chmod: parse_chmod(&a.mode)?, when: a.when, special, multiarch: a.multiarch, owner, verbatim: a.verbatim.unwrap_or(false) }
             },
             CargoDebAssetArrayOrTable::Array(a) => {
                 let mut a = a.into_iter();
                 Self {
                     source_path: PathBuf::from(a.next().ok_or("Missing source path (first array element) in an asset in Cargo.toml")?),
                     target_path: PathBuf::from(a.next().ok_or("missing dest path (second array entry) for asset in Cargo.toml. Use something like \"usr/local/bin/\".")?),
-                    chmod: parse_chmod(&a.next().ok_or("Missing mode (third array element) in an asset")?)?
+                    chmod: parse_chmod(&a.next().ok_or("Missing mode (third array element) in an asset")?)?,
+                    when: None,
+                    special: None,
+                    multiarch: None,
+                    owner: None,
+                    verbatim: false,
                 }
             },
             CargoDebAssetArrayOrTable::Invalid(bad) => {
-                return Err(format!("Expected assets array to contain either an array of 3 strings, or a `{{source, dest, mode}}` object, but found: {bad}"));
+                return Err(format!("Expected assets array to contain either an array of 3 strings, or a `{{source, dest, mode, when?}}` object, but found: {bad}"));
             },
         };
         if a.source_path.starts_with("target/debug") {
@@ -989,21 +1840,86 @@ To add debug information or additional assertions use `[profile.release]` in `Ca
     }
 }
 
-fn parse_license_file(package: &cargo_toml::Package<CargoPackageMetadata>, license_file: Option<&LicenseFile>) -> CDResult<(Option<PathBuf>, usize)> {
+fn parse_license_file(package: &cargo_toml::Package<CargoPackageMetadata>, license_file: Option<&LicenseFile>) -> CDResult<(Option<PathBuf>, usize, Vec<PathBuf>)> {
     Ok(match license_file {
+        // `["LICENSE", "4"]` is a file and number of lines to skip, and anything else is a list of files
+        Some(LicenseFile::Vec(files)) if files.len() > 2 || files.get(1).is_some_and(|l| l.parse::<usize>().is_err()) => {
+            (None, 0, files.iter().map(PathBuf::from).collect())
+        },
         Some(LicenseFile::Vec(args)) => {
             let mut args = args.iter();
             let file = args.next();
             let lines = if let Some(lines) = args.next() {
                 lines.parse().map_err(|e| CargoDebError::NumParse("invalid number of lines", e))?
             } else {0};
-            (file.map(|s|s.into()), lines)
+            (file.map(|s|s.into()), lines, vec![])
         },
-        Some(LicenseFile::String(s)) => (Some(s.into()), 0),
-        None => (package.license_file().as_ref().map(|s| s.into()), 0),
+        Some(LicenseFile::String(s)) => (Some(s.into()), 0, vec![]),
+        None => (package.license_file().as_ref().map(|s| s.into()), 0, vec![]),
     })
 }
 
+/// Picks a license identifier from the package's SPDX expression that matches the file name,
+/// e.g. `LICENSE-APACHE` is `Apache-2.0`. Falls back to the file name.
+///
+/// Only whole words are compared, so that `LICENSE-LGPL` isn't taken for `GPL-3.0`.
+fn license_name_for_file(path: &Path, spdx_expression: Option<&str>) -> String {
+    fn words(s: &str) -> Vec<String> {
+        s.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty()).map(|w| w.to_ascii_lowercase()).collect()
+    }
+    let file_name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let file_words = words(&file_name);
+    let ids: Vec<_> = spdx_expression.unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '/')
+        .filter(|id| !id.is_empty() && !["OR", "AND", "WITH"].contains(id))
+        .collect();
+    // the whole id, like `GPL-3.0`, is better than only its name
+    let found = ids.iter().find(|id| {
+        let id_words = words(id);
+        !id_words.is_empty() && file_words.windows(id_words.len()).any(|w| w == id_words)
+    }).or_else(|| ids.iter().find(|id| words(id).first().is_some_and(|name| file_words.contains(name))));
+    if let Some(id) = found {
+        return (*id).to_owned();
+    }
+    // a generic `LICENSE` file is for the only license there is
+    let base_name = file_name.split('.').next().unwrap_or_default().to_ascii_lowercase();
+    let is_generic = ["license", "licence", "copying"].contains(&base_name.as_str());
+    match ids[..] {
+        [id] if is_generic => id.to_owned(),
+        _ => file_name.into_owned(),
+    }
+}
+
+/// Continuation lines of DEP-5 fields are indented
+fn write_multiline_field(out: &mut Vec<u8>, name: &str, value: &str) -> io::Result<()> {
+    let mut lines = value.trim().lines();
+    writeln!(out, "{name}: {}", lines.next().unwrap_or_default().trim())?;
+    for line in lines {
+        let line = line.trim();
+        writeln!(out, " {}", if line.is_empty() { "." } else { line })?;
+    }
+    Ok(())
+}
+
+/// `systemd-sysusers` reads `<package>.conf` from here
+pub(crate) const SYSUSERS_DIR: &str = "usr/lib/sysusers.d";
+
+/// `systemd-tmpfiles` reads `<package>.conf` from here
+pub(crate) const TMPFILES_DIR: &str = "usr/lib/tmpfiles.d";
+
+/// Dirs in `usr/lib` for files that are the same on all architectures
+const ARCH_INDEPENDENT_LIB_DIRS: [&str; 9] = ["systemd", "udev", "tmpfiles.d", "sysusers.d", "sysctl.d", "modules-load.d", "modprobe.d", "binfmt.d", "environment.d"];
+
+/// Path relative to `usr/lib` if the asset belongs in `usr/lib/$triple`.
+///
+/// Shared and static libraries, plugins, and `pkgconfig` files are architecture-specific,
+/// but config dirs like `usr/lib/systemd` aren't. The asset's `multiarch` key overrides that.
+fn multiarch_lib_rel_path(target_path: &Path, multiarch: Option<bool>) -> Option<&Path> {
+    let rel_path = target_path.strip_prefix("usr/lib").ok()?;
+    let is_arch_independent = ARCH_INDEPENDENT_LIB_DIRS.iter().any(|dir| rel_path.starts_with(dir));
+    multiarch.unwrap_or(!is_arch_independent).then_some(rel_path)
+}
+
 fn has_copyright_metadata(file: &str) -> bool {
     file.lines().take(10)
         .any(|l| ["Copyright: ", "License: ", "Source: ", "Upstream-Name: ", "Format: "].into_iter().any(|f| l.starts_with(f)))
@@ -1018,10 +1934,37 @@ fn debian_package_name(crate_name: &str) -> String {
 }
 
 impl Config {
+    /// The `assets` from the config, or the default ones. Built files and globs are resolved later.
+    pub(crate) fn configured_assets(&self, package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<Assets> {
+        if let Some(raw_assets) = package_deb.raw_assets.take() {
+            self.explicit_assets(raw_assets, package_deb, listener)
+        } else {
+            self.implicit_assets(package_deb)
+        }
+    }
+
     fn explicit_assets(&self, assets: Vec<RawAsset>, package_deb: &PackageConfig, listener: &dyn Listener) -> CDResult<Assets> {
         let custom_profile_target_dir = self.build_profile_override.as_deref().map(|profile| format!("target/{profile}"));
         // Treat all explicit assets as unresolved until after the build step
-        let unresolved_assets = assets.into_iter().map(|RawAsset { source_path, mut target_path, chmod }| {
+        let is_cross_compiling = self.rust_target_triple() != DEFAULT_TARGET;
+        let (special_assets, assets): (Vec<_>, Vec<_>) = assets.into_iter().filter(|a| {
+            let included = a.when.map_or(true, |when| when.matches(is_cross_compiling));
+            if !included {
+                log::debug!("skipping {} when {}", a.source_path.display(), if is_cross_compiling { "cross-compiling" } else { "native" });
+            }
+            included
+        }).partition(|a| a.special.is_some());
+
+        let special_assets = special_assets.into_iter().filter_map(|a| Some((a.special?, a.target_path, a.chmod, a.owner))).map(|(special, target_path, chmod, owner)| {
+            if !package_deb.allow_special_files {
+                return Err(CargoDebError::SpecialFileNotAllowed(target_path));
+            }
+            let mut asset = Asset::new(AssetSource::Special(special), target_path, chmod, IsBuilt::No, false);
+            asset.c.owner = owner;
+            Ok(asset)
+        }).collect::<CDResult<Vec<_>>>()?;
+
+        let unresolved_assets = assets.into_iter().map(|RawAsset { source_path, mut target_path, chmod, multiarch, owner, verbatim, .. }| {
             // target/release is treated as a magic prefix that resolves to any profile
             let target_artifact_rel_path = source_path.strip_prefix("target/release").ok()
                 .or_else(|| source_path.strip_prefix(custom_profile_target_dir.as_ref()?).ok());
@@ -1035,17 +1978,24 @@ impl Config {
                 (IsBuilt::No, self.path_in_package(&source_path), false)
             };
 
-            if package_deb.multiarch != Multiarch::None {
-                if let Ok(lib_file_name) = target_path.strip_prefix("usr/lib") {
+            if package_deb.multiarch != Multiarch::None && !verbatim {
+                if let Some(lib_file_name) = multiarch_lib_rel_path(&target_path, multiarch) {
                     let lib_dir = package_deb.library_install_dir(self.rust_target_triple());
                     if !target_path.starts_with(&lib_dir) {
                         target_path = lib_dir.join(lib_file_name);
                     }
+                } else if multiarch == Some(true) {
+                    listener.warning(format!("Asset {} has `multiarch = true`, but only assets in usr/lib can be moved to the multiarch lib dir", target_path.display()));
                 }
             }
-            Ok(UnresolvedAsset::new(source_path, target_path, chmod, is_built, is_example))
+            let mut asset = UnresolvedAsset::new(source_path, target_path, chmod, is_built, is_example);
+            asset.c.owner = owner;
+            asset.c.verbatim = verbatim;
+            Ok(asset)
         }).collect::<CDResult<Vec<_>>>()?;
-        Ok(Assets::with_unresolved_assets(unresolved_assets))
+        let mut assets = Assets::with_unresolved_assets(unresolved_assets);
+        assets.resolved = special_assets;
+        Ok(assets)
     }
 
     fn implicit_assets(&self, package_deb: &PackageConfig) -> CDResult<Assets> {
@@ -1111,6 +2061,19 @@ impl Config {
     }
 }
 
+/// Absolute path without `.`, `..` and repeated slashes
+fn normalized_conffile_path(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {},
+            ".." => { parts.pop(); },
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
 /// Format conffiles section, ensuring each path has a leading slash
 ///
 /// Starting with [dpkg 1.20.1](https://github.com/guillemj/dpkg/blob/68ab722604217d3ab836276acfc0ae1260b28f5f/debian/changelog#L393),
@@ -1128,7 +2091,83 @@ fn format_conffiles<S: AsRef<str>>(files: &[S]) -> String {
     })
 }
 
-fn check_debian_version(mut ver: &str) -> Result<(), &'static str> {
+/// Reads version for `--set-version-from`. Source is either a path to a file with the version,
+/// or `git` to use the latest git tag of the repository in `manifest_dir`. The leading `v` is removed.
+pub fn version_from_source(source: &str, manifest_dir: &Path) -> CDResult<String> {
+    let version = if source == "git" && !Path::new(source).exists() {
+        let output = offline::audit(Command::new("git").current_dir(manifest_dir).args(["describe", "--tags", "--abbrev=0"]))?
+            .output()
+            .map_err(|e| CargoDebError::CommandFailed(e, "git"))?;
+        if !output.status.success() {
+            return Err(CargoDebError::CommandError("git describe", "--tags".into(), output.stderr));
+        }
+        String::from_utf8(output.stdout)?
+    } else {
+        fs::read_to_string(source).map_err(|e| CargoDebError::IoFile("unable to read version file", e, source.into()))?
+    };
+    let version = version.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default();
+    let version = version.strip_prefix(['v', 'V']).filter(|v| v.starts_with(|c: char| c.is_ascii_digit())).unwrap_or(version);
+    if let Err(why) = check_debian_version(version) {
+        return Err(CargoDebError::InvalidVersion(why, version.into()));
+    }
+    Ok(version.to_owned())
+}
+
+/// Commit hash of `HEAD`, with `-dirty` appended if there are uncommitted changes
+fn git_revision(dir: &Path) -> CDResult<String> {
+    let output = offline::audit(Command::new("git").arg("-C").arg(dir).args(["describe", "--always", "--abbrev=40", "--dirty", "--exclude=*"]))?
+        .output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "git"))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError("git describe", "--always".into(), output.stderr));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+}
+
+/// Lower is more important. `None` if not a valid priority.
+fn priority_rank(priority: &str) -> Option<u8> {
+    Some(match priority {
+        "required" => 0,
+        "important" => 1,
+        "standard" => 2,
+        "optional" => 3,
+        "extra" => 4,
+        _ => return None,
+    })
+}
+
+/// Asks dpkg about the priority of a package installed on this system.
+/// It's only a hint, since the target system may have a different archive.
+fn installed_package_priority(package_name: &str) -> Option<String> {
+    let output = sandbox::apply(&mut Command::new("dpkg-query")).ok()?
+        // multiarch packages are listed once per architecture
+        .args(["-W", "-f", "${Priority}\n", package_name])
+        .output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8(output.stdout).ok()?;
+    output.lines().map(str::trim).find(|p| !p.is_empty()).map(String::from)
+}
+
+/// The synopsis is the first line of `Description`, and has to be a short phrase
+fn check_synopsis(synopsis: &str) -> Result<(), &'static str> {
+    if synopsis.trim().is_empty() {
+        return Err("it's empty");
+    }
+    if synopsis.contains('\n') {
+        return Err("it must be a single line");
+    }
+    if synopsis.chars().count() >= 80 {
+        return Err("it must be shorter than 80 characters");
+    }
+    if synopsis.trim_end().ends_with('.') {
+        return Err("it must not end with a period");
+    }
+    Ok(())
+}
+
+pub(crate) fn check_debian_version(mut ver: &str) -> Result<(), &'static str> {
     if ver.trim_start().is_empty() {
         return Err("empty string");
     }
@@ -1150,8 +2189,13 @@ fn check_debian_version(mut ver: &str) -> Result<(), &'static str> {
     Ok(())
 }
 
+fn appended_relation(existing: Option<&str>, relation: &str, field: &str, listener: &dyn Listener) -> String {
+    let list = existing.into_iter().chain([relation]).collect::<Vec<_>>().join(", ");
+    RelationList::normalize_str(&list, field, listener)
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::parse::manifest::SystemdUnitsConfig;
     use crate::util::tests::add_test_fs_paths;
@@ -1167,13 +2211,39 @@ mod tests {
         // req
         assert_eq!(
             get_architecture_specification("libjpeg64-turbo [armhf]").expect("arch"),
-            ("libjpeg64-turbo".to_owned(), Some(Require("armhf".to_owned())))
+            ("libjpeg64-turbo".to_owned(), Some(Require(vec!["armhf".to_owned()])))
         );
         // neg
         assert_eq!(
             get_architecture_specification("libjpeg64-turbo [!amd64]").expect("arch"),
-            ("libjpeg64-turbo".to_owned(), Some(NegRequire("amd64".to_owned())))
+            ("libjpeg64-turbo".to_owned(), Some(NegRequire(vec!["amd64".to_owned()])))
         );
+        // lists
+        assert_eq!(
+            get_architecture_specification("libssl3 (>= 3.0) [amd64 arm64]").expect("arch"),
+            ("libssl3 (>= 3.0)".to_owned(), Some(Require(vec!["amd64".to_owned(), "arm64".to_owned()])))
+        );
+        assert_eq!(
+            get_architecture_specification("libssl3 [!amd64 !arm64]").expect("arch"),
+            ("libssl3".to_owned(), Some(NegRequire(vec!["amd64".to_owned(), "arm64".to_owned()])))
+        );
+        assert!(get_architecture_specification("libssl3 [amd64 !arm64]").is_err());
+        assert!(get_architecture_specification("libssl3 []").is_err());
+    }
+
+    #[test]
+    fn arch_spec_lists_match_each_arch() {
+        if Command::new("dpkg-architecture").arg("--version").output().is_err() {
+            eprintln!("dpkg-architecture not available, skipping");
+            return;
+        }
+        let spec = |s: &str| get_architecture_specification(s).unwrap().1.unwrap();
+        assert!(match_architecture(spec("a [amd64 arm64]"), "arm64").unwrap());
+        assert!(match_architecture(spec("a [amd64 arm64]"), "amd64").unwrap());
+        assert!(!match_architecture(spec("a [amd64 arm64]"), "armhf").unwrap());
+        assert!(!match_architecture(spec("a [!amd64 !arm64]"), "arm64").unwrap());
+        assert!(match_architecture(spec("a [!amd64 !arm64]"), "armhf").unwrap());
+        assert!(match_architecture(spec("a [linux-any]"), "armhf").unwrap());
     }
 
     fn to_canon_static_str(s: &str) -> &'static str {
@@ -1183,6 +2253,24 @@ mod tests {
         Box::leak(abs_path_string.into_boxed_str())
     }
 
+    /// Configuration of cargo-deb's own `Cargo.toml` with the default settings
+    pub(crate) fn own_manifest_config(listener: &dyn Listener) -> (Config, PackageConfig) {
+        own_manifest_config_with(DebConfigOverrides::default(), listener).unwrap()
+    }
+
+    /// Configuration of cargo-deb's own `Cargo.toml` with the given overrides
+    pub(crate) fn own_manifest_config_with(overrides: DebConfigOverrides, listener: &dyn Listener) -> CDResult<(Config, PackageConfig)> {
+        Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, overrides, None, None, None, CargoLockingFlags::default(), listener)
+    }
+
+    /// Package of a minimal `example` 1.0.0 crate that "Does things", without any assets
+    pub(crate) fn example_package_config(deb: CargoDeb, overrides: DebConfigOverrides) -> CDResult<PackageConfig> {
+        let mut package = cargo_toml::Package::new("example", "1.0.0");
+        package.authors = cargo_toml::Inheritable::Set(vec!["Me <me@example.com>".into()]);
+        package.description = Some(cargo_toml::Inheritable::Set("Does things".into()));
+        PackageConfig::new(deb, &mut package, &crate::listener::NoOpListener, 0, overrides, "x86_64-unknown-linux-gnu")
+    }
+
     #[test]
     fn add_systemd_assets_with_no_config_does_nothing() {
         let mut mock_listener = crate::listener::MockListener::new();
@@ -1191,7 +2279,7 @@ mod tests {
         // supply a systemd unit file as if it were available on disk
         let _g = add_test_fs_paths(&[to_canon_static_str("cargo-deb.service")]);
 
-        let (config, mut package_deb) = Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, DebConfigOverrides::default(), None, None, None, CargoLockingFlags::default(), &mock_listener).unwrap();
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
         config.prepare_assets_before_build(&mut package_deb, &mock_listener).unwrap();
 
         let num_unit_assets = package_deb.assets.resolved.iter()
@@ -1205,17 +2293,18 @@ mod tests {
     fn add_systemd_assets_with_config_adds_unit_assets() {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().return_const(());
+        mock_listener.expect_wants_diagnostics().return_const(false);
 
         // supply a systemd unit file as if it were available on disk
         let _g = add_test_fs_paths(&[to_canon_static_str("cargo-deb.service")]);
 
-        let (config, mut package_deb) = Config::from_manifest(Some(Path::new("Cargo.toml")), None, None, None, None, DebConfigOverrides::default(), None, None, None, CargoLockingFlags::default(), &mock_listener).unwrap();
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
         config.prepare_assets_before_build(&mut package_deb, &mock_listener).unwrap();
 
         package_deb.systemd_units.get_or_insert(vec![SystemdUnitsConfig::default()]);
         package_deb.maintainer_scripts_rel_path.get_or_insert(PathBuf::new());
 
-        config.add_systemd_assets(&mut package_deb).unwrap();
+        config.add_systemd_assets(&mut package_deb, &mock_listener).unwrap();
 
         let num_unit_assets = package_deb.assets.resolved
             .iter()
@@ -1225,6 +2314,483 @@ mod tests {
         assert_eq!(1, num_unit_assets);
     }
 
+    #[test]
+    fn add_systemd_assets_with_stop_timeout_adds_drop_in() {
+        let listener = crate::listener::NoOpListener;
+        let _g = add_test_fs_paths(&[to_canon_static_str("cargo-deb.service")]);
+
+        let (config, mut package_deb) = own_manifest_config(&listener);
+        let stop_timeout = Some(crate::parse::manifest::StopTimeout("30s".into()));
+        package_deb.systemd_units = Some(vec![SystemdUnitsConfig { stop_timeout, ..SystemdUnitsConfig::default() }]);
+        package_deb.maintainer_scripts_rel_path = Some(PathBuf::new());
+
+        config.add_systemd_assets(&mut package_deb, &listener).unwrap();
+        let drop_in = package_deb.assets.resolved.iter()
+            .find(|a| a.c.target_path == Path::new("lib/systemd/system/cargo-deb.service.d/stop-timeout.conf")).unwrap();
+        assert_eq!(drop_in.source.data().unwrap().as_ref(), b"[Service]\nTimeoutStopSec=30s\n");
+    }
+
+    #[test]
+    fn add_sysusers_asset_and_dependency() {
+        let listener = crate::listener::NoOpListener;
+        let _g = add_test_fs_paths(&[to_canon_static_str("debian/cargo-deb.sysusers")]);
+
+        let (config, mut package_deb) = own_manifest_config(&listener);
+        package_deb.maintainer_scripts_rel_path = Some(PathBuf::from("debian"));
+        config.add_sysusers_asset(&mut package_deb).unwrap();
+        let asset = package_deb.assets.resolved.last().unwrap();
+        assert_eq!(asset.c.target_path, Path::new("usr/lib/sysusers.d/cargo-deb.conf"));
+        assert_eq!(asset.source.path(), Some(Path::new(to_canon_static_str("debian/cargo-deb.sysusers"))));
+
+        package_deb.wildcard_depends = "libc6".into();
+        package_deb.resolve_binary_dependencies(None, &listener).unwrap();
+        assert_eq!(package_deb.resolved_depends.as_deref(), Some("libc6, systemd | systemd-standalone-sysusers"));
+
+        package_deb.sysusers_rel_path = Some(PathBuf::from("missing.sysusers"));
+        assert!(config.add_sysusers_asset(&mut package_deb).is_err());
+    }
+
+    #[test]
+    fn add_tmpfiles_asset() {
+        let listener = crate::listener::NoOpListener;
+        let _g = add_test_fs_paths(&[to_canon_static_str("debian/cargo-deb.tmpfiles"), to_canon_static_str("tmp.conf")]);
+
+        let (config, mut package_deb) = own_manifest_config(&listener);
+        package_deb.maintainer_scripts_rel_path = Some(PathBuf::from("debian"));
+        config.add_tmpfiles_asset(&mut package_deb).unwrap();
+        let asset = package_deb.assets.resolved.pop().unwrap();
+        assert_eq!(asset.c.target_path, Path::new("usr/lib/tmpfiles.d/cargo-deb.conf"));
+        assert_eq!(asset.source.path(), Some(Path::new(to_canon_static_str("debian/cargo-deb.tmpfiles"))));
+
+        package_deb.tmpfiles_rel_path = Some(PathBuf::from("tmp.conf"));
+        config.add_tmpfiles_asset(&mut package_deb).unwrap();
+        assert_eq!(package_deb.assets.resolved.last().unwrap().source.path(), Some(Path::new(to_canon_static_str("tmp.conf"))));
+        assert_eq!(package_deb.tmpfiles_files(), ["cargo-deb.conf"]);
+    }
+
+    #[test]
+    fn add_systemd_assets_reports_missing_units() {
+        use crate::listener::{CapturingListener, Level};
+
+        let listener = CapturingListener::new();
+        let (config, mut package_deb) = own_manifest_config(&listener);
+        package_deb.systemd_units = Some(vec![SystemdUnitsConfig::default()]);
+        package_deb.maintainer_scripts_rel_path = Some(PathBuf::from("no-units-here"));
+
+        config.add_systemd_assets(&mut package_deb, &listener).unwrap();
+        let warnings = listener.messages(Level::Warning);
+        assert!(warnings[0].contains("no-units-here"), "{warnings:?}");
+        assert!(warnings[0].contains("cargo-deb.service, service, cargo-deb@.service"), "{warnings:?}");
+
+        package_deb.fail_on_missing_systemd_unit = true;
+        let err = config.add_systemd_assets(&mut package_deb, &listener).unwrap_err();
+        assert!(matches!(err, CargoDebError::SystemdUnitsNotFound(..)));
+        assert!(err.to_string().contains("cargo-deb.tmpfile, tmpfile"), "{err}");
+    }
+
+    #[test]
+    fn non_conf_files_are_not_auto_added() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        mock_listener.expect_wants_diagnostics().return_const(true);
+
+        let (_, mut package_deb) = own_manifest_config(&mock_listener);
+        for path in ["etc/app/app.conf", "etc/app/generated.json"] {
+            package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), path.into(), 0o644, IsBuilt::No, false));
+        }
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Symlink("nonexistent/link".into()), "etc/app/link".into(), 0o777, IsBuilt::No, false));
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Special(crate::assets::SpecialFile::Fifo), "etc/app/fifo".into(), 0o644, IsBuilt::No, false));
+        package_deb.non_conf_files = vec!["/etc/app/generated.json".into()];
+        package_deb.resolve_assets_with_listener(&mock_listener).unwrap();
+        assert_eq!(package_deb.conf_files, ["/etc/app/app.conf"]);
+
+        package_deb.non_conf_files = vec!["/etc/app/typo.json".into()];
+        assert!(matches!(package_deb.resolve_assets_with_listener(&mock_listener), Err(CargoDebError::NonConfFileNotInPackage(_))));
+
+        package_deb.non_conf_files = vec!["/etc/app/generated.json".into()];
+        package_deb.conf_files = vec!["etc/app/generated.json".into()];
+        assert!(matches!(package_deb.resolve_assets_with_listener(&mock_listener), Err(CargoDebError::ConfFileConflict(_))));
+    }
+
+    #[test]
+    fn auto_doc_can_be_disabled() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_, package_deb) = own_manifest_config(&mock_listener);
+        assert_eq!(package_deb.readme_rel_path.as_deref(), Some(Path::new("README.md")));
+
+        let overrides = DebConfigOverrides { auto_doc: Some(false), ..DebConfigOverrides::default() };
+        let (_, package_deb) = own_manifest_config_with(overrides, &mock_listener).unwrap();
+        assert_eq!(package_deb.readme_rel_path, None);
+    }
+
+    #[test]
+    fn multiple_license_files() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        assert_eq!(license_name_for_file(Path::new("LICENSE-APACHE"), Some("MIT OR Apache-2.0")), "Apache-2.0");
+        assert_eq!(license_name_for_file(Path::new("licenses/LICENSE-MIT.txt"), Some("(MIT OR Apache-2.0)")), "MIT");
+        assert_eq!(license_name_for_file(Path::new("NOTICES"), Some("MIT")), "NOTICES");
+        assert_eq!(license_name_for_file(Path::new("LICENSE.txt"), Some("MIT")), "MIT");
+        assert_eq!(license_name_for_file(Path::new("COPYING.LGPL"), Some("GPL-3.0-or-later AND LGPL-3.0-only")), "LGPL-3.0-only");
+        assert_eq!(license_name_for_file(Path::new("LICENSE-GPL-2.0"), Some("GPL-3.0 OR GPL-2.0")), "GPL-2.0");
+        assert_eq!(license_name_for_file(Path::new("LICENSE-LGPL"), Some("GPL-3.0")), "LICENSE-LGPL");
+
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
+        package_deb.license_file_rel_path = None;
+        package_deb.license_files_rel_paths = vec!["LICENSE".into(), "LICENSE".into()];
+        let (_, copyright) = config.generate_copyright_asset(&package_deb).unwrap();
+        let copyright = String::from_utf8(copyright).unwrap();
+        let mut paragraphs = copyright.split("\n\n");
+        assert!(paragraphs.next().unwrap().starts_with("Format: "));
+        let license = paragraphs.next().unwrap();
+        assert!(license.starts_with("License: MIT\n The MIT License (MIT)\n .\n Copyright (c) 2016"), "{license}");
+        assert_eq!(paragraphs.count(), 1);
+    }
+
+    #[test]
+    fn build_paths_in_binaries() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
+        package_deb.assets.resolved.clear();
+        let panic_message = format!("panicked at {}/src/main.rs", config.package_manifest_dir.display());
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(b"clean".to_vec()), "usr/bin/clean".into(), 0o755, IsBuilt::SamePackage, false));
+        package_deb.check_build_paths(&config, true, &mock_listener).unwrap();
+
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(panic_message.into_bytes()), "usr/bin/leaky".into(), 0o755, IsBuilt::SamePackage, false));
+        package_deb.check_build_paths(&config, false, &mock_listener).unwrap();
+        match package_deb.check_build_paths(&config, true, &mock_listener) {
+            Err(CargoDebError::BuildPaths(path, suggestion)) => {
+                assert_eq!(path, Path::new("usr/bin/leaky"));
+                assert_eq!(suggestion, format!("RUSTFLAGS=\"--remap-path-prefix={}=.\"", config.package_manifest_dir.display()));
+            },
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn copyright_stanzas() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_, mut package_deb) = own_manifest_config(&mock_listener);
+        let deb: CargoDeb = toml::from_str(r#"
+            [[copyright]]
+            files = ["*"]
+            copyright = "2024 Upstream"
+            [[copyright]]
+            files = ["vendor/foo/*", "vendor/bar.c"]
+            copyright = """
+            2019 Alice
+
+            2020 Bob"""
+            license = "BSD-2-Clause"
+        "#).unwrap();
+        let Some(Copyright::Stanzas(stanzas)) = deb.copyright else { panic!() };
+        package_deb.copyright_stanzas = stanzas;
+
+        let mut out = Vec::new();
+        package_deb.append_copyright_metadata(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\n\nFiles: *\nCopyright: 2024 Upstream\nLicense: MIT\n"), "{out}");
+        assert!(out.ends_with("\n\nFiles: vendor/foo/* vendor/bar.c\nCopyright: 2019 Alice\n .\n 2020 Bob\nLicense: BSD-2-Clause\n"), "{out}");
+    }
+
+    #[test]
+    fn systemd_units_with_required_features() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
+        let enabled = config.enabled_features(&["--features".into(), "static-lzma,sandbox".into()]);
+        assert!(enabled.contains("lzma") && enabled.contains("sandbox") && enabled.contains("debug-id"));
+        assert!(!enabled.contains("icons"));
+        assert!(!config.enabled_features(&["--no-default-features".into()]).contains("lzma"));
+
+        let deps = config.dependency_features(&["--features".into(), "icons".into()]).unwrap();
+        assert!(deps.contains_key("resvg") && deps.contains_key("xz2"));
+        assert!(!config.dependency_features(&["--no-default-features".into()]).unwrap().contains_key("xz2"));
+
+        package_deb.systemd_units = Some(vec![
+            SystemdUnitsConfig { unit_name: Some("always".into()), ..SystemdUnitsConfig::default() },
+            SystemdUnitsConfig { unit_name: Some("daemon".into()), required_features: Some(vec!["icons".into()]), ..SystemdUnitsConfig::default() },
+        ]);
+        package_deb.select_systemd_units(&enabled);
+        assert_eq!(package_deb.systemd_units.unwrap().iter().map(|u| u.unit_name.as_deref().unwrap()).collect::<Vec<_>>(), ["always"]);
+    }
+
+    #[test]
+    fn duplicate_assets_policy() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        mock_listener.expect_warning().times(1).return_const(());
+
+        let (_, mut package_deb) = own_manifest_config(&mock_listener);
+        let base = package_deb.assets.resolved.clone();
+        for policy in [None, Some(DuplicateAssets::First), Some(DuplicateAssets::Last), Some(DuplicateAssets::Error)] {
+            package_deb.assets.resolved.clone_from(&base);
+            for data in [b"first", b"last!"] {
+                package_deb.assets.resolved.push(Asset::new(AssetSource::Data(data.to_vec()), "usr/share/app/data".into(), 0o644, IsBuilt::No, false));
+            }
+            package_deb.duplicate_assets = policy;
+            if policy == Some(DuplicateAssets::Error) {
+                assert!(matches!(package_deb.resolve_assets_with_listener(&mock_listener), Err(CargoDebError::DuplicateAsset(_))));
+                continue;
+            }
+            package_deb.resolve_assets_with_listener(&mock_listener).unwrap();
+            let data = package_deb.assets.resolved.iter().filter(|a| a.c.target_path.ends_with("app/data")).map(|a| a.source.data().unwrap().into_owned()).collect::<Vec<_>>();
+            assert_eq!(data, [if policy == Some(DuplicateAssets::Last) { b"last!" } else { b"first" }]);
+        }
+    }
+
+    #[test]
+    fn add_assets_after_parsing() {
+        let listener = crate::listener::CapturingListener::new();
+        let (_, mut package_deb) = own_manifest_config(&listener);
+        package_deb.resolve_assets_with_listener(&listener).unwrap();
+
+        package_deb.add_data_asset(b"key = 1".to_vec(), "/etc/./app/app.conf", 0o644, &listener).unwrap();
+        let added = package_deb.assets.resolved.last().unwrap();
+        assert_eq!(added.c.target_path, Path::new("etc/app/app.conf"));
+        assert!(package_deb.conf_files.iter().any(|c| c == "/etc/app/app.conf"));
+
+        assert!(package_deb.add_data_asset(vec![], "usr/share/app/", 0o644, &listener).is_err());
+        #[cfg(unix)]
+        package_deb.add_data_asset(vec![], "usr/share/app\\notes.", 0o644, &listener).unwrap();
+        assert!(package_deb.add_data_asset(vec![], "usr/../etc/passwd", 0o644, &listener).is_err());
+
+        package_deb.duplicate_assets = Some(DuplicateAssets::Error);
+        let dup = Asset::new(AssetSource::Data(vec![]), "etc/app/app.conf".into(), 0o644, IsBuilt::No, false);
+        assert!(matches!(package_deb.add_asset(dup, &listener), Err(CargoDebError::DuplicateAsset(_))));
+    }
+
+    #[test]
+    fn important_packages_follow_stricter_policy() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let (_, mut package_deb) = own_manifest_config(&mock_listener);
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), "usr/local/bin/app".into(), 0o755, IsBuilt::No, false));
+        package_deb.resolved_depends = Some(String::new());
+        package_deb.check_priority_policy(&mock_listener).unwrap();
+
+        package_deb.priority = "important".into();
+        assert!(matches!(package_deb.check_priority_policy(&mock_listener), Err(CargoDebError::PriorityPolicy(..))));
+        assert_eq!(priority_rank("nonsense"), None);
+    }
+
+    #[test]
+    fn vcs_revision_field() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let overrides = DebConfigOverrides { vcs_revision: Some(Some("0123abc".into())), ..DebConfigOverrides::default() };
+        let (_, package_deb) = own_manifest_config_with(overrides, &mock_listener).unwrap();
+        let control = String::from_utf8(package_deb.generate_checked_control(&mock_listener).unwrap()).unwrap();
+        assert!(control.contains("\nX-Vcs-Revision: 0123abc\n"));
+
+        for bad in ["", "0123 abc", "0123abc\nX-Evil: 1", "\t"] {
+            let overrides = DebConfigOverrides { vcs_revision: Some(Some(bad.into())), ..DebConfigOverrides::default() };
+            let res = own_manifest_config_with(overrides, &mock_listener);
+            assert!(matches!(res, Err(CargoDebError::InvalidVcsRevision(_))), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn essential_requires_explicit_permission() {
+        let new = |overrides| example_package_config(CargoDeb { essential: Some(true), protected: Some(true), ..Default::default() }, overrides);
+        assert!(matches!(new(DebConfigOverrides::default()), Err(CargoDebError::EssentialNotAllowed(_))));
+
+        let package_deb = new(DebConfigOverrides { allow_essential: true, ..DebConfigOverrides::default() }).unwrap();
+        let control = String::from_utf8(package_deb.generate_checked_control(&crate::listener::NoOpListener).unwrap()).unwrap();
+        assert!(control.contains("\nEssential: yes\nProtected: yes\n"), "{control}");
+    }
+
+    #[test]
+    fn unresolved_extended_description_is_an_error() {
+        let deb = CargoDeb { extended_description_file: Some("README.md".into()), ..Default::default() };
+        let package_deb = example_package_config(deb, DebConfigOverrides::default()).unwrap();
+        assert!(matches!(package_deb.extended_description, ExtendedDescription::File(_)));
+        assert!(package_deb.generate_checked_control(&crate::listener::NoOpListener).is_err());
+
+        // the older API reads the file itself
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+        let (config, mut package_deb) = own_manifest_config(&mock_listener);
+        package_deb.extended_description = ExtendedDescription::File("README.md".into());
+        let control = String::from_utf8(package_deb.generate_control(&config).unwrap()).unwrap();
+        assert!(control.contains("\n # Debian packages from Cargo projects\n"), "{control}");
+    }
+
+    #[test]
+    fn conditional_assets() {
+        let assets: Vec<RawAsset> = toml::from_str::<BTreeMap<String, Vec<RawAsset>>>(r#"assets = [
+            ["a", "usr/bin/", "755"],
+            { source = "b", dest = "usr/bin/", mode = "755", when = "cross-compiling" },
+            { source = "c", dest = "usr/bin/", mode = "755", when = "native" },
+        ]"#).unwrap().remove("assets").unwrap();
+        assert_eq!(assets[0].when, None);
+        assert!(assets[1].when.unwrap().matches(true));
+        assert!(!assets[2].when.unwrap().matches(true));
+        assert!(toml::from_str::<BTreeMap<String, Vec<RawAsset>>>(r#"a = [{ source = "b", dest = "c", mode = "755", when = "always" }]"#).is_err());
+    }
+
+    #[test]
+    fn multiarch_asset_paths() {
+        let rel = |path: &'static str, multiarch| multiarch_lib_rel_path(Path::new(path), multiarch).and_then(Path::to_str);
+        assert_eq!(rel("usr/lib/libfoo.so.1", None), Some("libfoo.so.1"));
+        assert_eq!(rel("usr/lib/libfoo.a", None), Some("libfoo.a"));
+        assert_eq!(rel("usr/lib/pkgconfig/foo.pc", None), Some("pkgconfig/foo.pc"));
+        assert_eq!(rel("usr/lib/foo/plugins/bar.so", None), Some("foo/plugins/bar.so"));
+        assert_eq!(rel("usr/lib/foo/plugins/bar.so", Some(false)), None);
+        assert_eq!(rel("usr/lib/systemd/system/foo.service", None), None);
+        assert_eq!(rel("usr/lib/udev/rules.d/60-foo.rules", Some(true)), Some("udev/rules.d/60-foo.rules"));
+        assert_eq!(rel("usr/share/foo/bar.so", Some(true)), None);
+
+        let assets = toml::from_str::<BTreeMap<String, Vec<RawAsset>>>(r#"a = [
+            { source = "a", dest = "usr/lib/foo/", mode = "644", multiarch = false },
+            ["b", "usr/lib/", "644"],
+            { source = "c", dest = "usr/lib/", mode = "644", verbatim = true },
+        ]"#).unwrap().remove("a").unwrap();
+        assert_eq!(assets[0].multiarch, Some(false));
+        assert_eq!(assets[1].multiarch, None);
+        assert!(!assets[1].verbatim);
+        assert!(assets[2].verbatim);
+    }
+
+    #[test]
+    fn special_file_assets() {
+        let parse = |toml: &str| toml::from_str::<BTreeMap<String, Vec<RawAsset>>>(toml).map(|mut a| a.remove("a").unwrap());
+        let assets = parse(r#"a = [
+            { dest = "run/app/control", mode = "600", type = "fifo" },
+            { dest = "dev/app0", mode = "660", type = "char-device", device = [240, 1] },
+        ]"#).unwrap();
+        assert_eq!(assets[0].special, Some(SpecialFile::Fifo));
+        assert_eq!(assets[1].special, Some(SpecialFile::CharDevice { major: 240, minor: 1 }));
+        assert!(parse(r#"a = [{ dest = "dev/app0", mode = "660", type = "block-device" }]"#).is_err());
+        assert!(parse(r#"a = [{ source = "x", dest = "dev/app0", mode = "660", type = "fifo" }]"#).is_err());
+    }
+
+    #[test]
+    fn asset_owners() {
+        use crate::assets::{AssetOwner, OwnerId};
+
+        let parse = |toml: &str| toml::from_str::<BTreeMap<String, Vec<RawAsset>>>(toml).map(|mut a| a.remove("a").unwrap());
+        let assets = parse(r#"a = [
+            { source = "x", dest = "var/www/", mode = "640", owner = "www-data:www-data" },
+            { source = "x", dest = "var/lib/app/", mode = "600", owner = "33:4" },
+            { source = "x", dest = "var/log/app/", mode = "600", owner = "app" },
+        ]"#).unwrap();
+        assert_eq!(assets[0].owner, Some(AssetOwner { user: OwnerId::Name("www-data".into()), group: OwnerId::Name("www-data".into()) }));
+        assert_eq!(assets[1].owner, Some(AssetOwner { user: OwnerId::Id(33), group: OwnerId::Id(4) }));
+        assert_eq!(assets[2].owner, Some(AssetOwner { user: OwnerId::Name("app".into()), group: OwnerId::Id(0) }));
+        assert!(parse(r#"a = [{ source = "x", dest = "y", mode = "600", owner = "Bad User" }]"#).is_err());
+    }
+
+    #[test]
+    fn package_dirs() {
+        use crate::assets::{AssetOwner, OwnerId};
+
+        let parse = |toml: &str| toml::from_str::<CargoDeb>(toml).map(|deb| deb.dirs.unwrap());
+        let dirs = parse(r#"dirs = [["/var/lib/myapp/", "750", "myuser:myuser"], ["var/cache/myapp"]]"#).unwrap();
+        assert_eq!(dirs[0], PackageDir { target_path: "var/lib/myapp".into(), chmod: 0o750, owner: Some(AssetOwner { user: OwnerId::Name("myuser".into()), group: OwnerId::Name("myuser".into()) }) });
+        assert_eq!(dirs[1], PackageDir { target_path: "var/cache/myapp".into(), chmod: 0o755, owner: None });
+        assert!(parse(r#"dirs = [["var/../etc", "750"]]"#).is_err());
+        assert!(parse(r#"dirs = [["var/lib/x", "rwx"]]"#).is_err());
+        assert!(parse(r#"dirs = [[]]"#).is_err());
+    }
+
+    #[test]
+    fn maintscript_commands() {
+        assert_eq!(maintscript_command("rm_conffile  /etc/foo.conf 1.2.0~").unwrap(), "rm_conffile /etc/foo.conf 1.2.0~");
+        assert!(maintscript_command("mv_conffile /etc/old.conf /etc/new.conf 1:2.0~ foo").is_ok());
+        assert!(maintscript_command("symlink_to_dir /usr/share/doc/foo foo-common 1.0~").is_ok());
+        assert!(maintscript_command("dir_to_symlink /usr/share/doc/foo foo-common").is_ok());
+        assert!(maintscript_command("dir_to_symlink usr/share/doc/foo /usr/share/doc/foo-common").is_err());
+        assert!(maintscript_command("rm_conffile etc/foo.conf").is_err());
+        assert!(maintscript_command("rm_conffile /etc/foo.conf 1.0 foo extra").is_err());
+        assert!(maintscript_command("mv_conffile /etc/old.conf").is_err());
+        assert!(maintscript_command("rm_conffile /etc/$(reboot)").is_err());
+        assert!(maintscript_command("rm -rf /").is_err());
+    }
+
+    #[test]
+    fn synopsis() {
+        assert!(check_synopsis("A fast tool for doing things").is_ok());
+        assert!(check_synopsis("A fast tool for doing things.").is_err());
+        assert!(check_synopsis(&"x".repeat(80)).is_err());
+        assert!(check_synopsis("").is_err());
+
+        let deb = CargoDeb { synopsis: Some("Tool for everything".into()), ..Default::default() };
+        let package_deb = example_package_config(deb, DebConfigOverrides::default()).unwrap();
+        assert_eq!(package_deb.description, "Tool for everything");
+        assert!(matches!(&package_deb.extended_description, ExtendedDescription::String(s) if s == "Does things"));
+    }
+
+    #[test]
+    fn version_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("VERSION");
+        fs::write(&path, "\n v2.1.0 \nignored\n").unwrap();
+        assert_eq!(version_from_source(path.to_str().unwrap(), Path::new(".")).unwrap(), "2.1.0");
+        fs::write(&path, "version 1").unwrap();
+        assert!(version_from_source(path.to_str().unwrap(), Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn old_debs_match_exactly() {
+        assert!(is_old_deb_of("foo_1.0-1_amd64.deb", "foo", "amd64"));
+        assert!(is_old_deb_of("foo_2:1.0~rc1-1_amd64.deb.tmp", "foo", "amd64"));
+        assert!(!is_old_deb_of("foo_1.0-1_arm64.deb", "foo", "amd64"));
+        assert!(!is_old_deb_of("foo-dbgsym_1.0-1_amd64.deb", "foo", "amd64"));
+        assert!(!is_old_deb_of("foo_1.0-1_amd64.deb-delta", "foo", "amd64"));
+        assert!(!is_old_deb_of("foo_1.0_1_amd64.deb", "foo", "amd64"));
+        assert!(!is_old_deb_of("foo__amd64.deb", "foo", "amd64"));
+    }
+
+    #[test]
+    fn relationships_are_checked() {
+        let listener = crate::listener::CapturingListener::new();
+
+        let (_, mut package_deb) = own_manifest_config(&listener);
+        package_deb.resolved_depends = Some("foo (>= 1), bar | baz".into());
+        package_deb.conflicts = Some("foo (<< 1), bar".into());
+        package_deb.provides = Some("virtual, other (= 1.0)".into());
+        package_deb.replaces = Some("old".into());
+        package_deb.check_relationships(&listener).unwrap();
+        assert!(listener.has_warning("Replaces: old should be accompanied by Breaks: old"));
+
+        package_deb.replaces = None;
+        package_deb.conflicts = Some("foo".into());
+        assert!(package_deb.check_relationships(&listener).is_err());
+        package_deb.conflicts = None;
+        package_deb.provides = Some("virtual (>= 1)".into());
+        assert!(package_deb.check_relationships(&listener).is_err());
+    }
+
+    #[test]
+    fn conf_files_are_sorted_and_deduplicated() {
+        let mock_listener = crate::listener::NoOpListener;
+        let (_config, mut package_deb) = own_manifest_config(&mock_listener);
+        package_deb.assets = Assets::new();
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), "etc/app/b.conf".into(), 0o644, IsBuilt::No, false));
+        package_deb.conf_files = vec!["/etc/app/z.conf".into(), "etc/app/b.conf".into(), "/etc//app/./a.conf".into(), "/etc/app/a.conf".into(), "/etc/other/../app/z.conf".into()];
+        package_deb.add_conf_files().unwrap();
+        assert_eq!(package_deb.conf_files, ["/etc/app/a.conf", "/etc/app/b.conf", "/etc/app/z.conf"]);
+
+        #[cfg(unix)] {
+            let tmp = tempfile::tempdir().unwrap();
+            std::os::unix::fs::symlink("z.conf", tmp.path().join("link.conf")).unwrap();
+            package_deb.assets.resolved.push(Asset::new(AssetSource::Symlink(tmp.path().join("link.conf")), "etc/app/link.conf".into(), 0o644, IsBuilt::No, false));
+            package_deb.add_conf_files().unwrap();
+            assert_eq!(package_deb.conf_files, ["/etc/app/a.conf", "/etc/app/b.conf", "/etc/app/z.conf"]);
+        }
+    }
+
     #[test]
     fn format_conffiles_empty() {
         let actual = format_conffiles::<String>(&[]);
@@ -3,27 +3,37 @@ use crate::CDResult;
 use ar::{Builder, Header};
 use std::fs;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// The outermost `ar` archive that contains tarballs inside
+///
+/// It's written to a `.tmp` file first, and renamed when finished, so that a failed build doesn't leave a truncated deb.
 pub struct DebArchive {
     out_abspath: PathBuf,
-    ar_builder: Builder<File>,
+    tmp_abspath: PathBuf,
+    /// `None` when finished
+    ar_builder: Option<Builder<File>>,
     mtime_timestamp: u64,
 }
 
 impl DebArchive {
     pub fn new(out_abspath: PathBuf, mtime_timestamp: u64) -> CDResult<Self> {
-        let _ = fs::create_dir_all(out_abspath.parent().ok_or("invalid dir")?);
-        let ar_builder = Builder::new(File::create(&out_abspath)?);
+        let mut ar = Self::without_header(out_abspath, mtime_timestamp)?;
+        ar.add_file("debian-binary".into(), b"2.0\n")?;
+        Ok(ar)
+    }
 
-        let mut ar = Self {
+    /// For `ar` archives that are not debs
+    pub(crate) fn without_header(out_abspath: PathBuf, mtime_timestamp: u64) -> CDResult<Self> {
+        let _ = fs::create_dir_all(out_abspath.parent().ok_or("invalid dir")?);
+        let tmp_abspath = tmp_path(&out_abspath);
+        let ar_builder = Builder::new(File::create(&tmp_abspath)?);
+        Ok(Self {
             out_abspath,
-            ar_builder,
+            tmp_abspath,
+            ar_builder: Some(ar_builder),
             mtime_timestamp,
-        };
-        ar.add_file("debian-binary".into(), b"2.0\n")?;
-        Ok(ar)
+        })
     }
 
     pub fn add_control(&mut self, control_tarball: Compressed) -> CDResult<()> {
@@ -34,17 +44,53 @@ impl DebArchive {
         self.add_file(format!("data.tar.{}", data_tarball.extension()), &data_tarball)
     }
 
-    fn add_file(&mut self, dest_path: String, data: &[u8]) -> CDResult<()> {
+    pub(crate) fn add_file(&mut self, dest_path: String, data: &[u8]) -> CDResult<()> {
         let mut header = Header::new(dest_path.into(), data.len() as u64);
         header.set_mode(0o100644); // dpkg uses 100644
         header.set_mtime(self.mtime_timestamp);
         header.set_uid(0);
         header.set_gid(0);
-        self.ar_builder.append(&header, data)?;
+        self.ar_builder.as_mut().ok_or("archive already finished")?.append(&header, data)?;
         Ok(())
     }
 
-    pub fn finish(self) -> CDResult<PathBuf> {
-        Ok(self.out_abspath)
+    pub fn finish(mut self) -> CDResult<PathBuf> {
+        let file = self.ar_builder.take().ok_or("archive already finished")?.into_inner()?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.tmp_abspath, &self.out_abspath)?;
+        Ok(std::mem::take(&mut self.out_abspath))
+    }
+}
+
+impl Drop for DebArchive {
+    fn drop(&mut self) {
+        if self.ar_builder.take().is_some() {
+            let _ = fs::remove_file(&self.tmp_abspath);
+        }
     }
 }
+
+/// `foo.deb.tmp` for `foo.deb`
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp.into()
+}
+
+#[test]
+fn renamed_when_finished() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_path = dir.path().join("test.deb");
+
+    let deb = DebArchive::new(out_path.clone(), 0).unwrap();
+    assert!(tmp_path(&out_path).exists());
+    drop(deb);
+    assert!(!tmp_path(&out_path).exists());
+    assert!(!out_path.exists());
+
+    let deb = DebArchive::new(out_path.clone(), 0).unwrap();
+    assert_eq!(deb.finish().unwrap(), out_path);
+    assert!(out_path.exists());
+    assert!(!tmp_path(&out_path).exists());
+}
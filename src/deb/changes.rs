@@ -0,0 +1,131 @@
+//! `.changes` file for uploading the `.deb` with tools like `dput`, `dcut`, `reprepro include`, or `aptly repo include`
+//!
+//! <https://www.debian.org/doc/debian-policy/ch-controlfields.html#debian-changes-files-changes>
+
+use crate::config::{Config, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::rfc2822_date;
+use crate::util::sha::{sha1_hex, sha256_hex};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The newest entry of a Debian-formatted changelog
+struct ChangelogEntry<'a> {
+    version: &'a str,
+    distribution: &'a str,
+    urgency: &'a str,
+    /// Header and the items, without the ` -- ` trailer line
+    lines: Vec<&'a str>,
+    changed_by: Option<&'a str>,
+}
+
+fn newest_entry(changelog: &str) -> Option<ChangelogEntry<'_>> {
+    let mut lines = changelog.lines().skip_while(|l| l.trim().is_empty());
+    let header = lines.next()?;
+    // package (version) distribution; urgency=medium
+    let (_, rest) = header.split_once(" (")?;
+    let (version, rest) = rest.split_once(')')?;
+    let (distribution, options) = rest.split_once(';').unwrap_or((rest, ""));
+    let urgency = options.split(',').find_map(|o| o.trim().strip_prefix("urgency=")).unwrap_or("medium");
+
+    let mut entry = ChangelogEntry {
+        version,
+        distribution: distribution.split_whitespace().next().unwrap_or("unstable"),
+        urgency,
+        lines: vec![header],
+        changed_by: None,
+    };
+    for line in lines {
+        if let Some(trailer) = line.strip_prefix(" -- ") {
+            entry.changed_by = trailer.split_once("  ").map(|(name, _)| name.trim()).filter(|n| !n.is_empty());
+            break;
+        }
+        entry.lines.push(line);
+    }
+    while entry.lines.last().is_some_and(|l| l.trim().is_empty()) {
+        entry.lines.pop();
+    }
+    Some(entry)
+}
+
+/// Writes `<name>_<version>_<arch>.changes` next to the deb
+pub(crate) fn write_changes(config: &Config, package_deb: &PackageConfig, deb_path: &Path, listener: &dyn Listener) -> CDResult<PathBuf> {
+    let deb = fs::read(deb_path).map_err(|e| CargoDebError::IoFile("unable to read deb", e, deb_path.to_owned()))?;
+    let deb_file_name = deb_path.file_name().and_then(|f| f.to_str()).ok_or("bad deb path")?;
+    let changelog = config.changelog_text(package_deb)?;
+    let entry = changelog.as_deref().and_then(newest_entry);
+    match &entry {
+        None => listener.warning("The .changes file has no changelog entry. Set `changelog` in [package.metadata.deb]".into()),
+        Some(entry) if entry.version != package_deb.deb_version => {
+            listener.warning(format!("The newest changelog entry is for version {}, not {}", entry.version, package_deb.deb_version));
+        },
+        Some(_) => {},
+    }
+    let changes = changes_file(package_deb, entry.as_ref(), deb_file_name, &deb);
+
+    let path = deb_path.with_extension("changes");
+    fs::write(&path, changes).map_err(|e| CargoDebError::IoFile("unable to write .changes", e, path.clone()))?;
+    Ok(path)
+}
+
+fn changes_file(package_deb: &PackageConfig, entry: Option<&ChangelogEntry<'_>>, deb_file_name: &str, deb: &[u8]) -> String {
+    let default_header = format!("{} ({}) unstable; urgency=medium", package_deb.deb_name, package_deb.deb_version);
+    let (distribution, urgency, lines) = match entry {
+        Some(e) => (e.distribution, e.urgency, &e.lines[..]),
+        None => ("unstable", "medium", &[default_header.as_str()][..]),
+    };
+    let size = deb.len();
+    let section = package_deb.section.as_deref().unwrap_or("misc");
+
+    let mut out = String::new();
+    let _ = writeln!(out, "Format: 1.8");
+    let _ = writeln!(out, "Date: {}", rfc2822_date(package_deb.default_timestamp));
+    let _ = writeln!(out, "Source: {}", package_deb.deb_name);
+    let _ = writeln!(out, "Binary: {}", package_deb.deb_name);
+    let _ = writeln!(out, "Architecture: {}", package_deb.architecture);
+    let _ = writeln!(out, "Version: {}", package_deb.deb_version);
+    let _ = writeln!(out, "Distribution: {distribution}");
+    let _ = writeln!(out, "Urgency: {urgency}");
+    let _ = writeln!(out, "Maintainer: {}", package_deb.maintainer);
+    if let Some(changed_by) = entry.and_then(|e| e.changed_by) {
+        let _ = writeln!(out, "Changed-By: {changed_by}");
+    }
+    let _ = writeln!(out, "Description:\n {} - {}", package_deb.deb_name, package_deb.description);
+    let _ = writeln!(out, "Changes:");
+    for line in lines {
+        let _ = writeln!(out, " {}", if line.trim().is_empty() { "." } else { line });
+    }
+    let _ = writeln!(out, "Checksums-Sha1:\n {} {size} {deb_file_name}", sha1_hex(deb));
+    let _ = writeln!(out, "Checksums-Sha256:\n {} {size} {deb_file_name}", sha256_hex(deb));
+    let _ = writeln!(out, "Files:\n {:x} {size} {section} {} {deb_file_name}", md5::compute(deb), package_deb.priority);
+    out
+}
+
+#[test]
+fn changelog_entry() {
+    let changelog = "foo (1.2.0-1) bookworm; urgency=low\n\n  * Fixed it\n\n  * Again\n\n -- Jane Doe <jane@example.com>  Mon, 01 Jan 2024 00:00:00 +0000\n\nfoo (1.1.0-1) unstable; urgency=medium\n\n  * Old\n\n -- Someone <s@example.com>  Sun, 31 Dec 2023 00:00:00 +0000\n";
+    let entry = newest_entry(changelog).unwrap();
+    assert_eq!(entry.version, "1.2.0-1");
+    assert_eq!(entry.distribution, "bookworm");
+    assert_eq!(entry.urgency, "low");
+    assert_eq!(entry.changed_by, Some("Jane Doe <jane@example.com>"));
+    assert_eq!(entry.lines, ["foo (1.2.0-1) bookworm; urgency=low", "", "  * Fixed it", "", "  * Again"]);
+}
+
+#[test]
+fn changes_fields() {
+    use crate::config::tests::own_manifest_config;
+    use crate::listener::NoOpListener;
+
+    let (_, mut package_deb) = own_manifest_config(&NoOpListener);
+    package_deb.default_timestamp = 1_700_000_000;
+    let entry = newest_entry("cargo-deb (1.0-1) unstable; urgency=medium\n\n  * Hi\n\n -- Me <me@example.com>  Tue, 14 Nov 2023 22:13:20 +0000\n");
+    let changes = changes_file(&package_deb, entry.as_ref(), "cargo-deb_1.0-1_amd64.deb", b"abc");
+    assert!(changes.starts_with("Format: 1.8\nDate: Tue, 14 Nov 2023 22:13:20 +0000\nSource: cargo-deb\nBinary: cargo-deb\n"), "{changes}");
+    assert!(changes.contains("\nChanged-By: Me <me@example.com>\n"));
+    assert!(changes.contains("\nChanges:\n cargo-deb (1.0-1) unstable; urgency=medium\n .\n   * Hi\nChecksums-Sha1:\n"), "{changes}");
+    assert!(changes.contains("\nChecksums-Sha256:\n ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad 3 cargo-deb_1.0-1_amd64.deb\n"));
+    assert!(changes.ends_with("\nFiles:\n 900150983cd24fb0d6963f7d28e17f72 3 utility optional cargo-deb_1.0-1_amd64.deb\n"), "{changes}");
+}
@@ -0,0 +1,102 @@
+//! Support for installing packages with old versions of `dpkg`
+
+use crate::config::{check_debian_version, Multiarch, PackageConfig};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::relations::{compare_debian_versions, RelationList};
+use crate::util::compress::Format;
+use std::cmp::Ordering;
+
+/// `control.tar.xz` is supported since this version. Older versions only support `control.tar.gz`.
+const CONTROL_XZ_SINCE: &str = "1.17.6";
+/// `data.tar.xz` is supported since this version
+const DATA_XZ_SINCE: &str = "1.15.6";
+/// `Multi-Arch` field and `:any` qualifiers
+const MULTIARCH_SINCE: &str = "1.16.2";
+/// `Provides: foo (= 1.0)`
+const VERSIONED_PROVIDES_SINCE: &str = "1.17.11";
+
+/// The oldest `dpkg` version that should be able to install the package, from `--compat-level`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatLevel {
+    dpkg_version: String,
+}
+
+impl CompatLevel {
+    pub fn new(dpkg_version: &str) -> CDResult<Self> {
+        let dpkg_version = dpkg_version.trim();
+        check_debian_version(dpkg_version).map_err(|why| CargoDebError::InvalidVersion(why, dpkg_version.into()))?;
+        Ok(Self { dpkg_version: dpkg_version.into() })
+    }
+
+    fn supports(&self, since: &str) -> bool {
+        compare_debian_versions(&self.dpkg_version, since) != Ordering::Less
+    }
+
+    /// Compression for `control.tar`
+    #[must_use]
+    pub fn control_compression(&self, requested: Format) -> Format {
+        match requested {
+            Format::Xz if !self.supports(CONTROL_XZ_SINCE) => Format::Gzip,
+            other => other,
+        }
+    }
+
+    /// Compression for `data.tar`
+    #[must_use]
+    pub fn data_compression(&self, requested: Format, listener: &dyn Listener) -> Format {
+        match requested {
+            Format::Xz if !self.supports(DATA_XZ_SINCE) => {
+                listener.info(format!("dpkg {} doesn't support xz, using gzip instead", self.dpkg_version));
+                Format::Gzip
+            },
+            other => other,
+        }
+    }
+
+    /// Rejects control fields that the old `dpkg` would refuse or misinterpret
+    pub fn check_package(&self, package_deb: &PackageConfig) -> CDResult<()> {
+        let fail = |feature: &str, since: &str| Err(CargoDebError::Compat(self.dpkg_version.clone(), format!("{feature} requires dpkg {since} or later")));
+
+        if !self.supports(MULTIARCH_SINCE) {
+            if package_deb.multiarch != Multiarch::None {
+                return fail("Multi-Arch", MULTIARCH_SINCE);
+            }
+            for field in [&package_deb.resolved_depends, &package_deb.pre_depends, &package_deb.recommends, &package_deb.suggests] {
+                let Some(field) = field.as_deref() else { continue };
+                if let Some(rel) = RelationList::parse(field)?.groups.iter().flatten().find(|r| r.arch_qualifier.is_some()) {
+                    return fail(&format!("architecture qualifier in '{rel}'"), MULTIARCH_SINCE);
+                }
+            }
+        }
+        if !self.supports(VERSIONED_PROVIDES_SINCE) {
+            if let Some(provides) = package_deb.provides.as_deref() {
+                if let Some(rel) = RelationList::parse(provides)?.groups.iter().flatten().find(|r| r.version.is_some()) {
+                    return fail(&format!("versioned Provides '{rel}'"), VERSIONED_PROVIDES_SINCE);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_compression() {
+        let listener = crate::listener::NoOpListener;
+        let old = CompatLevel::new("1.15.5").unwrap();
+        assert!(matches!(old.control_compression(Format::Xz), Format::Gzip));
+        assert!(matches!(old.data_compression(Format::Xz, &listener), Format::Gzip));
+
+        let mid = CompatLevel::new("1.16.18").unwrap();
+        assert!(matches!(mid.control_compression(Format::Xz), Format::Gzip));
+        assert!(matches!(mid.data_compression(Format::Xz, &listener), Format::Xz));
+
+        let new = CompatLevel::new("1.21.22").unwrap();
+        assert!(matches!(new.control_compression(Format::Xz), Format::Xz));
+        assert!(CompatLevel::new("one").is_err());
+    }
+}
@@ -1,13 +1,17 @@
-use crate::config::{Config, PackageConfig};
-use crate::deb::tar::Tarball;
+use crate::config::{Config, ExtendedDescription, Multiarch, PackageConfig, TMPFILES_DIR};
+use crate::deb::tar::{FileHashes, Tarball};
+use crate::desktop;
 use crate::dh::{dh_installsystemd, dh_lib};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
-use crate::util::{is_path_file, read_file_to_bytes};
+use crate::udev;
+use crate::util::{is_path_file, read_file_to_bytes, read_file_to_string};
 use dh_lib::ScriptFragments;
+use crate::util::wordsplit::WordSplit;
+use std::fmt::{self, Write as _};
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct ControlArchiveBuilder<'l, W: Write> {
     archive: Tarball<W>,
@@ -16,15 +20,22 @@ pub struct ControlArchiveBuilder<'l, W: Write> {
 
 impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
     pub fn new(dest: W, time: u64, listener: &'l dyn Listener) -> Self {
+        Self::new_with_dpkg_compat(dest, time, false, listener)
+    }
+
+    /// `dpkg_compat` makes the tar headers the same as `dpkg-deb` makes
+    pub fn new_with_dpkg_compat(dest: W, time: u64, dpkg_compat: bool, listener: &'l dyn Listener) -> Self {
         Self {
-            archive: Tarball::new(dest, time),
+            archive: if dpkg_compat { Tarball::new_dpkg_compat(dest, time) } else { Tarball::new(dest, time) },
             listener,
         }
     }
 
-    /// Generates an uncompressed tar archive with `control`, and others
-    pub fn generate_archive(&mut self, config: &Config, package_deb: &PackageConfig) -> CDResult<()> {
-        self.add_control(&package_deb.generate_control(config)?)?;
+    /// Generates an uncompressed tar archive with `control`, and others.
+    /// `hashes` are of the files in the data archive.
+    pub fn generate_archive(&mut self, config: &Config, package_deb: &PackageConfig, hashes: &FileHashes) -> CDResult<()> {
+        self.add_control(&package_deb.generate_checked_control(self.listener)?)?;
+        self.add_md5sums(hashes, &package_deb.conf_files)?;
 
         if let Some(files) = package_deb.conf_files() {
             self.add_conf_files(&files)?;
@@ -48,7 +59,11 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
     /// Additionally, when `systemd_units` is configured, shell script fragments
     /// "for enabling, disabling, starting, stopping and restarting systemd unit
     /// files" (quoting `man 1 dh_installsystemd`) will replace the `#DEBHELPER#`
-    /// token in the provided maintainer scripts.
+    /// token in the provided maintainer scripts. `runtime_files` add a fragment
+    /// to `postrm` that deletes them on purge, `sysusers.d` configs add a fragment
+    /// to `postinst` that creates the users, `tmpfiles.d` configs add one that
+    /// creates their files, and implicit fragments are added
+    /// for assets that need them, like icon themes' caches, desktop files' URL handlers and udev rules.
     ///
     /// If a shell fragment cannot be inserted because the target script is missing
     /// then the entire script will be generated and appended to the archive.
@@ -59,20 +74,50 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
     /// contain a `#DEBHELPER#` token at the point where shell script fragments
     /// should be inserted.
     fn generate_scripts(&mut self, config: &Config, package_deb: &PackageConfig) -> CDResult<()> {
-        if let Some(ref maintainer_scripts_dir) = package_deb.maintainer_scripts_rel_path {
-            let maintainer_scripts_dir = config.path_in_package(maintainer_scripts_dir);
-            let mut scripts = ScriptFragments::with_capacity(0);
+        let maintainer_scripts_dir = package_deb.maintainer_scripts_rel_path.as_ref().map(|dir| config.path_in_package(dir));
+        let mut icon_theme_dirs = if package_deb.implicit_triggers { icon_theme_dirs(package_deb) } else { Vec::new() };
+        let mut has_url_handlers = package_deb.implicit_triggers && !desktop::url_scheme_handlers(&package_deb.assets.resolved)?.is_empty();
+        let mut has_udev_rules = package_deb.implicit_triggers && udev::has_rules(&package_deb.assets.resolved);
+        // Unlike explicitly configured fragments, these must not break packages with scripts that predate them
+        if !icon_theme_dirs.is_empty() || has_url_handlers || has_udev_rules {
+            if let Some(script) = maintainer_scripts_dir.as_deref().and_then(|dir| script_without_debhelper_token(dir, &package_deb.name)) {
+                let what = [(!icon_theme_dirs.is_empty(), "icon caches"), (has_url_handlers, "desktop URL handlers"), (has_udev_rules, "udev rules")]
+                    .into_iter().filter_map(|(has, what)| has.then_some(what)).collect::<Vec<_>>().join(" and ");
+                self.listener.warning(format!("{} has no #DEBHELPER# token, so {what} won't be updated after installation. \
+                    Add the token, or set implicit-triggers = false", script.display()));
+                icon_theme_dirs.clear();
+                has_url_handlers = false;
+                has_udev_rules = false;
+            }
+        }
+        let has_generated_fragments = !package_deb.runtime_files.is_empty() || !package_deb.maintscripts.is_empty() || !icon_theme_dirs.is_empty() || has_url_handlers || has_udev_rules
+            || !package_deb.sysusers_files().is_empty() || !package_deb.tmpfiles_files().is_empty();
+        if maintainer_scripts_dir.is_none() && !has_generated_fragments {
+            return Ok(());
+        }
+        let mut scripts = ScriptFragments::with_capacity(0);
 
-            if let Some(systemd_units_config_vec) = &package_deb.systemd_units {
+        match (&maintainer_scripts_dir, &package_deb.systemd_units) {
+            (Some(maintainer_scripts_dir), Some(systemd_units_config_vec)) if !systemd_units_config_vec.is_empty() => {
+                // add_tmpfiles_fragment handles these, using their installed file names
+                let unit_assets: Vec<_> = package_deb.assets.resolved.iter()
+                    .filter(|a| !a.c.target_path.starts_with(TMPFILES_DIR)).cloned().collect();
                 for systemd_units_config in systemd_units_config_vec {
                     // Select and populate autoscript templates relevant to the unit
                     // file(s) in this package and the configuration settings chosen.
                     scripts = dh_installsystemd::generate(
                         &package_deb.name,
-                        &package_deb.assets.resolved,
+                        &unit_assets,
                         &dh_installsystemd::Options::from(systemd_units_config),
                         self.listener,
                     )?;
+                    self.add_runtime_files_fragment(&mut scripts, package_deb)?;
+                    self.add_maintscript_fragments(&mut scripts, package_deb)?;
+                    self.add_icon_cache_fragments(&mut scripts, package_deb, &icon_theme_dirs)?;
+                    self.add_desktop_database_fragments(&mut scripts, package_deb, has_url_handlers)?;
+                    self.add_udev_fragments(&mut scripts, package_deb, has_udev_rules)?;
+                    self.add_tmpfiles_fragment(&mut scripts, package_deb)?;
+                    self.add_sysusers_fragment(&mut scripts, package_deb)?;
 
                     // Get Option<&str> from Option<String>
                     let unit_name = systemd_units_config.unit_name.as_deref();
@@ -80,38 +125,134 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
                     // Replace the #DEBHELPER# token in the users maintainer scripts
                     // and/or generate maintainer scripts from scratch as needed.
                     dh_lib::apply(
-                        &maintainer_scripts_dir,
+                        Some(maintainer_scripts_dir),
                         &mut scripts,
                         &package_deb.name,
                         unit_name,
                         self.listener,
                     )?;
                 }
-            }
+            },
+            _ if has_generated_fragments => {
+                self.add_runtime_files_fragment(&mut scripts, package_deb)?;
+                self.add_maintscript_fragments(&mut scripts, package_deb)?;
+                self.add_icon_cache_fragments(&mut scripts, package_deb, &icon_theme_dirs)?;
+                self.add_desktop_database_fragments(&mut scripts, package_deb, has_url_handlers)?;
+                self.add_udev_fragments(&mut scripts, package_deb, has_udev_rules)?;
+                self.add_tmpfiles_fragment(&mut scripts, package_deb)?;
+                self.add_sysusers_fragment(&mut scripts, package_deb)?;
+                dh_lib::apply(maintainer_scripts_dir.as_deref(), &mut scripts, &package_deb.name, None, self.listener)?;
+            },
+            _ => {},
+        }
+
+        // Add maintainer scripts to the archive, either those supplied by the
+        // user or if available prefer modified versions generated above.
+        for name in ["config", "preinst", "postinst", "prerm", "postrm", "templates"] {
+            let script_path;
+            let (contents, source_path) = if let Some(script) = scripts.remove(name) {
+                (script, Some(if package_deb.systemd_units.is_some() { "systemd_units" } else { "autoscripts" }))
+            } else {
+                let Some(maintainer_scripts_dir) = &maintainer_scripts_dir else { continue };
+                script_path = maintainer_scripts_dir.join(name);
+                if !is_path_file(&script_path) {
+                    continue;
+                }
+                (read_file_to_bytes(&script_path)?, script_path.to_str())
+            };
+
+            // The config, postinst, postrm, preinst, and prerm
+            // control files should use mode 0755; all other control files should use 0644.
+            // See Debian Policy Manual section 10.9
+            // and lintian tag control-file-has-bad-permissions
+            let permissions = if name == "templates" { 0o644 } else { 0o755 };
+            self.add_file_with_log(name.as_ref(), &contents, permissions, source_path)?;
+        }
 
-            // Add maintainer scripts to the archive, either those supplied by the
-            // user or if available prefer modified versions generated above.
-            for name in ["config", "preinst", "postinst", "prerm", "postrm", "templates"] {
-                let script_path;
-                let (contents, source_path) = if let Some(script) = scripts.remove(name) {
-                    (script, Some("systemd_units"))
-                } else {
-                    script_path = maintainer_scripts_dir.join(name);
-                    if !is_path_file(&script_path) {
-                        continue;
-                    }
-                    (read_file_to_bytes(&script_path)?, script_path.to_str())
-                };
-
-                // The config, postinst, postrm, preinst, and prerm
-                // control files should use mode 0755; all other control files should use 0644.
-                // See Debian Policy Manual section 10.9
-                // and lintian tag control-file-has-bad-permissions
-                let permissions = if name == "templates" { 0o644 } else { 0o755 };
-                self.add_file_with_log(name.as_ref(), &contents, permissions, source_path)?;
+        Ok(())
+    }
+
+    /// Files the program creates after installation are deleted on purge. Dirs are deleted only if empty.
+    fn add_runtime_files_fragment(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig) -> CDResult<()> {
+        if package_deb.runtime_files.is_empty() {
+            return Ok(());
+        }
+        let (mut dirs, files): (Vec<_>, Vec<_>) = package_deb.runtime_files.iter().partition(|f| f.ends_with('/'));
+        // children first
+        dirs.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        let quote = |paths: Vec<&String>| paths.iter().map(|p| format!("'{}'", p.replace('\'', "'\\''"))).collect::<Vec<_>>().join(" ");
+        dh_lib::autoscript(scripts, &package_deb.name, "postrm", "postrm-runtime-files", &[
+            ("FILES", quote(files)),
+            ("DIRS", quote(dirs)),
+        ].into_iter().collect(), false, self.listener)
+    }
+
+    /// Like `debian/*.maintscript` of `dh_installdeb`. `dpkg-maintscript-helper` needs to run in all of these scripts.
+    fn add_maintscript_fragments(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig) -> CDResult<()> {
+        for command in &package_deb.maintscripts {
+            let replacements = [("PARAMS", command.clone())].into_iter().collect();
+            for script in ["preinst", "postinst", "postrm"] {
+                dh_lib::autoscript(scripts, &package_deb.name, script, "maintscript-helper", &replacements, false, self.listener)?;
             }
         }
+        Ok(())
+    }
+
+    /// Like `dh_icons`. Icon themes have caches that are stale until `update-icon-caches` (from `gtk-update-icon-cache`) rebuilds them.
+    fn add_icon_cache_fragments(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig, theme_dirs: &[String]) -> CDResult<()> {
+        if theme_dirs.is_empty() {
+            return Ok(());
+        }
+        let replacements = [("DIRLIST", theme_dirs.join(" "))].into_iter().collect();
+        dh_lib::autoscript(scripts, &package_deb.name, "postinst", "postinst-icons", &replacements, false, self.listener)?;
+        dh_lib::autoscript(scripts, &package_deb.name, "postrm", "postrm-icons", &replacements, false, self.listener)
+    }
+
+    /// `x-scheme-handler/*` MIME types of desktop files are registered in `mimeinfo.cache`, which only `update-desktop-database` rebuilds.
+    fn add_desktop_database_fragments(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig, has_url_handlers: bool) -> CDResult<()> {
+        if !has_url_handlers {
+            return Ok(());
+        }
+        let replacements = [("DIR", format!("/{}", desktop::APPLICATIONS_DIR))].into_iter().collect();
+        dh_lib::autoscript(scripts, &package_deb.name, "postinst", "postinst-desktop-database", &replacements, false, self.listener)?;
+        dh_lib::autoscript(scripts, &package_deb.name, "postrm", "postrm-desktop-database", &replacements, false, self.listener)
+    }
+
+    /// Like `dh_installudev` used to. Rules are loaded on boot, so the running `udevd` has to be told about the changes.
+    fn add_udev_fragments(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig, has_udev_rules: bool) -> CDResult<()> {
+        if !has_udev_rules {
+            return Ok(());
+        }
+        let rules = package_deb.assets.resolved.iter().filter(|a| udev::is_rules_file(a))
+            .map(|a| format!("/{}", a.c.target_path.display())).collect::<Vec<_>>();
+        let replacements = [("RULES", rules.join(" "))].into_iter().collect();
+        dh_lib::autoscript(scripts, &package_deb.name, "postinst", "postinst-udev", &replacements, false, self.listener)?;
+        dh_lib::autoscript(scripts, &package_deb.name, "postrm", "postrm-udev", &replacements, false, self.listener)
+    }
 
+    /// Like `dh_installsysusers`. Services may run as these users, so the users are created before any other fragment runs.
+    fn add_sysusers_fragment(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig) -> CDResult<()> {
+        let files = package_deb.sysusers_files();
+        if files.is_empty() {
+            return Ok(());
+        }
+        let mut sysusers = ScriptFragments::with_capacity(1);
+        let replacements = [("CONFILE_BASENAME", files.join(" "))].into_iter().collect();
+        dh_lib::autoscript(&mut sysusers, &package_deb.name, "postinst", "postinst-sysusers", &replacements, false, self.listener)?;
+        prepend_fragments(scripts, sysusers);
+        Ok(())
+    }
+
+    /// Like `dh_installtmpfiles`. Services may need these files, so they're created before the services start.
+    fn add_tmpfiles_fragment(&self, scripts: &mut ScriptFragments, package_deb: &PackageConfig) -> CDResult<()> {
+        let files = package_deb.tmpfiles_files();
+        if files.is_empty() {
+            return Ok(());
+        }
+        let mut tmpfiles = ScriptFragments::with_capacity(1);
+        let replacements = [("TMPFILES", files.join(" "))].into_iter().collect();
+        dh_lib::autoscript(&mut tmpfiles, &package_deb.name, "postinst", "postinst-init-tmpfiles", &replacements, false, self.listener)?;
+        prepend_fragments(scripts, tmpfiles);
         Ok(())
     }
 
@@ -126,6 +267,22 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
         Ok(())
     }
 
+    /// Like `dh_md5sums`, for `debsums`. Conffiles are left out, because dpkg tracks their hashes itself.
+    fn add_md5sums(&mut self, hashes: &FileHashes, conf_files: &[String]) -> CDResult<()> {
+        let mut md5sums = String::new();
+        for (path, digest) in hashes {
+            let path = path.to_str().ok_or("utf8 path")?.trim_start_matches("./").trim_start_matches('/');
+            if conf_files.iter().any(|c| c.trim_start_matches('/') == path) {
+                continue;
+            }
+            md5sums.push_str(&format!("{digest:x}  {path}\n"));
+        }
+        if md5sums.is_empty() {
+            return Ok(());
+        }
+        self.archive.file("./md5sums", md5sums.as_bytes(), 0o644)
+    }
+
     /// If configuration files are required, the conffiles file will be created.
     fn add_conf_files(&mut self, list: &str) -> CDResult<()> {
         self.add_file_with_log("./conffiles".as_ref(), list.as_bytes(), 0o644, None)
@@ -141,6 +298,131 @@ impl<'l, W: Write> ControlArchiveBuilder<'l, W> {
     }
 }
 
+/// User's maintainer script that generated fragments can't be inserted into
+fn script_without_debhelper_token(dir: &Path, package: &str) -> Option<PathBuf> {
+    ["postinst", "preinst", "prerm", "postrm"].into_iter()
+        .filter_map(|script| dh_lib::pkgfile(dir, package, package, script, None))
+        .find(|path| read_file_to_string(path).is_ok_and(|text| !text.contains("#DEBHELPER#")))
+}
+
+/// The `control` file, with the fields in the usual order. It doesn't read any files, so it's deterministic:
+/// the `extended_description` must be already resolved (other kinds are skipped), and `installed_size` is in KiB,
+/// e.g. from [`PackageConfig::installed_size`].
+pub fn render(package_deb: &PackageConfig, installed_size: u64) -> String {
+    let extended_description = match &package_deb.extended_description {
+        ExtendedDescription::String(desc) => Some(desc.as_str()),
+        _ => None,
+    };
+    render_with_extended_description(package_deb, installed_size, extended_description)
+}
+
+/// Like `render`, but with the extended description read from elsewhere
+pub(crate) fn render_with_extended_description(package_deb: &PackageConfig, installed_size: u64, extended_description: Option<&str>) -> String {
+    let mut control = String::with_capacity(1024);
+    // fmt::Write for String never fails
+    let _ = write_control(&mut control, package_deb, installed_size, extended_description);
+    control
+}
+
+fn write_control(control: &mut String, package_deb: &PackageConfig, installed_size: u64, extended_description: Option<&str>) -> fmt::Result {
+    writeln!(control, "Package: {}", package_deb.deb_name)?;
+    writeln!(control, "Version: {}", package_deb.deb_version)?;
+    writeln!(control, "Architecture: {}", package_deb.architecture)?;
+    let ma = match package_deb.multiarch {
+        Multiarch::None => "",
+        Multiarch::Same => "same",
+        Multiarch::Foreign => "foreign",
+    };
+    if !ma.is_empty() {
+        writeln!(control, "Multi-Arch: {ma}")?;
+    }
+    if package_deb.essential {
+        writeln!(control, "Essential: yes")?;
+    }
+    if package_deb.protected {
+        writeln!(control, "Protected: yes")?;
+    }
+    if let Some(homepage) = package_deb.homepage.as_deref().or(package_deb.documentation.as_deref()).or(package_deb.repository.as_deref()) {
+        writeln!(control, "Homepage: {homepage}")?;
+    }
+    if let Some(ref section) = package_deb.section {
+        writeln!(control, "Section: {section}")?;
+    }
+    writeln!(control, "Priority: {}", package_deb.priority)?;
+    writeln!(control, "Maintainer: {}", package_deb.maintainer)?;
+    writeln!(control, "Installed-Size: {installed_size}")?;
+
+    let relations = [
+        ("Depends", package_deb.resolved_depends.as_deref()),
+        ("Pre-Depends", package_deb.pre_depends.as_deref()),
+        ("Recommends", package_deb.recommends.as_deref()),
+        ("Suggests", package_deb.suggests.as_deref()),
+        ("Enhances", package_deb.enhances.as_deref()),
+        ("Conflicts", package_deb.conflicts.as_deref()),
+        ("Breaks", package_deb.breaks.as_deref()),
+        ("Replaces", package_deb.replaces.as_deref()),
+        ("Provides", package_deb.provides.as_deref()),
+    ];
+    for (name, value) in relations {
+        if let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) {
+            write_relationship_field(control, name, value, package_deb.relations_line_width)?;
+        }
+    }
+
+    if let Some(revision) = &package_deb.vcs_revision {
+        writeln!(control, "X-Vcs-Revision: {revision}")?;
+    }
+
+    write!(control, "Description:")?;
+    for line in package_deb.description.split_by_chars(79) {
+        writeln!(control, " {line}")?;
+    }
+    if let Some(desc) = extended_description {
+        for line in desc.split_by_chars(79) {
+            writeln!(control, " {line}")?;
+        }
+    }
+    control.push('\n');
+    Ok(())
+}
+
+/// Folds the list into one relation per line if it's longer than `width` (0 = never)
+fn write_relationship_field(out: &mut String, name: &str, value: &str, width: usize) -> fmt::Result {
+    if width == 0 || name.len() + 2 + value.len() <= width {
+        return writeln!(out, "{name}: {value}");
+    }
+    write!(out, "{name}:")?;
+    let mut separator = " ";
+    for relation in value.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        write!(out, "{separator}{relation}")?;
+        separator = ",\n ";
+    }
+    out.push('\n');
+    Ok(())
+}
+
+fn prepend_fragments(scripts: &mut ScriptFragments, first: ScriptFragments) {
+    for (name, mut fragment) in first {
+        if let Some(rest) = scripts.remove(&name) {
+            fragment.extend(rest);
+        }
+        scripts.insert(name, fragment);
+    }
+}
+
+/// `/usr/share/icons/<theme>` dirs that have any files in the package
+fn icon_theme_dirs(package_deb: &PackageConfig) -> Vec<String> {
+    let mut dirs: Vec<_> = package_deb.assets.resolved.iter().filter_map(|asset| {
+        let mut components = asset.c.target_path.strip_prefix("usr/share/icons").ok()?.components();
+        let theme = components.next()?.as_os_str().to_str()?;
+        components.next()?; // a file directly in /usr/share/icons isn't in a theme
+        Some(format!("/usr/share/icons/{theme}"))
+    }).collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
 #[cfg(test)]
 mod tests {
     // The following test suite verifies that `fn generate_scripts()` correctly
@@ -238,6 +520,58 @@ mod tests {
         (config, package_deb, ar)
     }
 
+    #[test]
+    fn render_control_snapshot() {
+        let deb = crate::parse::manifest::CargoDeb {
+            extended_description: Some("It does many things,\nall of them well.".into()),
+            section: Some("utils".into()),
+            ..Default::default()
+        };
+        let mut package_deb = crate::config::tests::example_package_config(deb, Default::default()).unwrap();
+        package_deb.homepage = Some("https://example.com".into());
+        package_deb.resolved_depends = Some("libc6 (>= 2.34)".into());
+        package_deb.pre_depends = Some("dpkg (>= 1.17)".into());
+        package_deb.recommends = Some("example-data".into());
+        package_deb.breaks = Some("example-old (<< 1.0)".into());
+        package_deb.replaces = Some("example-old (<< 1.0)".into());
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![0; 5000]), "usr/bin/example-app".into(), 0o755, IsBuilt::No, false));
+        assert_eq!(package_deb.installed_size(), 6);
+
+        assert_eq!(render(&package_deb, package_deb.installed_size()), "Package: example\n\
+            Version: 1.0.0-1\n\
+            Architecture: amd64\n\
+            Homepage: https://example.com\n\
+            Section: utils\n\
+            Priority: optional\n\
+            Maintainer: Me <me@example.com>\n\
+            Installed-Size: 6\n\
+            Depends: libc6 (>= 2.34)\n\
+            Pre-Depends: dpkg (>= 1.17)\n\
+            Recommends: example-data\n\
+            Breaks: example-old (<< 1.0)\n\
+            Replaces: example-old (<< 1.0)\n\
+            Description: Does things\n \
+            It does many things,\n \
+            all of them well.\n\n");
+
+        // unresolved files aren't read
+        package_deb.extended_description = ExtendedDescription::File("does-not-exist".into());
+        assert!(render(&package_deb, 0).ends_with("Description: Does things\n\n"));
+    }
+
+    #[test]
+    fn long_relationships_are_folded() {
+        let mut control = String::new();
+        write_relationship_field(&mut control, "Provides", "a, b", 80).unwrap();
+        write_relationship_field(&mut control, "Depends", "libc6 (>= 2.34), libssl3 (>= 3.0.0) | libssl1.1, ", 30).unwrap();
+        write_relationship_field(&mut control, "Breaks", "old-package (<< 2.0), other-package", 0).unwrap();
+        assert_eq!(control, "Provides: a, b\n\
+            Depends: libc6 (>= 2.34),\n libssl3 (>= 3.0.0) | libssl1.1\n\
+            Breaks: old-package (<< 2.0), other-package\n");
+        let depends = crate::deb::read::control_field(&control, "Depends").unwrap();
+        assert_eq!(crate::relations::RelationList::parse(depends).unwrap().groups.len(), 2);
+    }
+
     #[test]
     fn generate_scripts_does_nothing_if_maintainer_scripts_is_not_set() {
         let mut listener = MockListener::new();
@@ -260,6 +594,166 @@ mod tests {
         assert!(archived_file_names.is_empty());
     }
 
+    #[test]
+    fn md5sums_skip_conffiles() {
+        let mut listener = MockListener::new();
+        let (_, _, mut in_ar) = prepare(vec![], None, &mut listener);
+        let hashes = [("usr/bin/app", &b"bin"[..]), ("etc/app.conf", b"conf"), ("usr/share/doc/app/README", b"")]
+            .into_iter().map(|(path, data)| (PathBuf::from(path), md5::compute(data))).collect();
+        in_ar.add_md5sums(&hashes, &["/etc/app.conf".into()]).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!(archived_content["md5sums"], "c1111bd512b29e821b120b86446026b8  usr/bin/app\n\
+            d41d8cd98f00b204e9800998ecf8427e  usr/share/doc/app/README\n");
+    }
+
+    #[test]
+    fn generate_scripts_deletes_runtime_files_on_purge() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.runtime_files = vec!["/var/lib/test/db.sqlite".into(), "/var/lib/test/".into(), "/var/lib/test/it's/".into()];
+
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+
+        assert_eq!(1, archived_content.len());
+        let postrm = &archived_content["postrm"];
+        assert!(postrm.starts_with("#!/bin/sh\nset -e\n"));
+        assert!(postrm.contains("\trm -f -- '/var/lib/test/db.sqlite'\n"));
+        assert!(postrm.contains("\trmdir --ignore-fail-on-non-empty -- '/var/lib/test/it'\\''s/' '/var/lib/test/' 2>/dev/null"));
+    }
+
+    #[test]
+    fn generate_scripts_updates_icon_caches() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        for target_path in ["usr/share/icons/hicolor/48x48/apps/test.png", "usr/share/icons/Adwaita/scalable/apps/test.svg", "usr/share/icons/stray.png", "usr/share/pixmaps/test.png"] {
+            package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), target_path.into(), 0o644, IsBuilt::No, false));
+        }
+        assert_eq!(icon_theme_dirs(&package_deb), ["/usr/share/icons/Adwaita", "/usr/share/icons/hicolor"]);
+
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!(2, archived_content.len());
+        for script in ["postinst", "postrm"] {
+            assert!(archived_content[script].contains("\t\tupdate-icon-caches /usr/share/icons/Adwaita /usr/share/icons/hicolor\n"), "{archived_content:?}");
+        }
+
+        let mut listener = MockListener::new();
+        let (config, _, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.implicit_triggers = false;
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        assert!(decode_names(&mut tar::Archive::new(&in_ar.finish().unwrap()[..])).is_empty());
+    }
+
+    #[test]
+    fn generate_scripts_updates_desktop_database() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(b"[Desktop Entry]\nName=Test\n".to_vec()), "usr/share/applications/test.desktop".into(), 0o644, IsBuilt::No, false));
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        assert!(decode_names(&mut tar::Archive::new(&in_ar.finish().unwrap()[..])).is_empty());
+
+        let mut listener = MockListener::new();
+        let (config, _, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(b"[Desktop Entry]\nMimeType=x-scheme-handler/test;\n".to_vec()), "usr/share/applications/test-url.desktop".into(), 0o644, IsBuilt::No, false));
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!(2, archived_content.len());
+        for script in ["postinst", "postrm"] {
+            assert!(archived_content[script].contains("\t\tupdate-desktop-database -q /usr/share/applications\n"), "{archived_content:?}");
+        }
+    }
+
+    #[test]
+    fn generate_scripts_reloads_udev_rules() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), "usr/lib/udev/rules.d/60-test.rules".into(), 0o644, IsBuilt::No, false));
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!(2, archived_content.len());
+        for script in ["postinst", "postrm"] {
+            assert!(archived_content[script].contains("\t\tudevadm control --reload || true\n"), "{archived_content:?}");
+        }
+
+        let mut listener = MockListener::new();
+        let (config, _, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.implicit_triggers = false;
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        assert!(decode_names(&mut tar::Archive::new(&in_ar.finish().unwrap()[..])).is_empty());
+    }
+
+    #[test]
+    fn generate_scripts_calls_maintscript_helper() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.maintscripts = vec!["rm_conffile /etc/test.conf 1.0~".into(), "mv_conffile /etc/a.conf /etc/b.conf 1.0~".into()];
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        assert_eq!(3, archived_content.len());
+        for script in ["preinst", "postinst", "postrm"] {
+            assert!(archived_content[script].contains("\ndpkg-maintscript-helper rm_conffile /etc/test.conf 1.0~ -- \"$@\"\n"), "{archived_content:?}");
+            assert!(archived_content[script].contains("\ndpkg-maintscript-helper mv_conffile /etc/a.conf /etc/b.conf 1.0~ -- \"$@\"\n"), "{archived_content:?}");
+        }
+    }
+
+    #[test]
+    fn generate_scripts_calls_maintscript_helper_once_with_systemd_units() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        package_deb.maintscripts = vec!["rm_conffile /etc/test.conf 1.0~".into()];
+        set_test_fs_path_content("test-resources/testroot/debian/some.service", "mock service file".into());
+        package_deb.assets.resolved.push(Asset::new(AssetSource::Path("test-resources/testroot/debian/some.service".into()), "lib/systemd/system/some.service".into(), 0o644, IsBuilt::No, false));
+        package_deb.maintainer_scripts_rel_path = Some("debian".into());
+        package_deb.systemd_units = Some(vec![SystemdUnitsConfig::default()]);
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        for script in ["preinst", "postinst", "postrm"] {
+            assert_eq!(1, archived_content[script].matches("dpkg-maintscript-helper rm_conffile /etc/test.conf 1.0~").count(), "{archived_content:?}");
+        }
+    }
+
+    #[test]
+    fn generate_scripts_creates_sysusers() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        for target_path in ["usr/lib/sysusers.d/test.conf", "usr/lib/sysusers.d/README", "usr/share/icons/hicolor/48x48/apps/test.png"] {
+            package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), target_path.into(), 0o644, IsBuilt::No, false));
+        }
+        assert_eq!(package_deb.sysusers_files(), ["test.conf"]);
+
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        let postinst = &archived_content["postinst"];
+        let sysusers = postinst.find("\tsystemd-sysusers ${DPKG_ROOT:+--root=\"$DPKG_ROOT\"} test.conf\n").unwrap();
+        assert!(sysusers < postinst.find("update-icon-caches").unwrap(), "{postinst}");
+    }
+
+    #[test]
+    fn generate_scripts_creates_tmpfiles() {
+        let mut listener = MockListener::new();
+        let (config, mut package_deb, mut in_ar) = prepare(vec![], None, &mut listener);
+        for target_path in ["usr/lib/tmpfiles.d/test.conf", "usr/lib/sysusers.d/test.conf"] {
+            package_deb.assets.resolved.push(Asset::new(AssetSource::Data(vec![]), target_path.into(), 0o644, IsBuilt::No, false));
+        }
+        assert_eq!(package_deb.tmpfiles_files(), ["test.conf"]);
+
+        in_ar.generate_scripts(&config, &package_deb).unwrap();
+        let archive_bytes = in_ar.finish().unwrap();
+        let archived_content = extract_contents(&mut tar::Archive::new(&archive_bytes[..]));
+        let postinst = &archived_content["postinst"];
+        let tmpfiles = postinst.find("\t\tsystemd-tmpfiles --create test.conf >/dev/null || true\n").unwrap();
+        assert!(postinst.find("systemd-sysusers").unwrap() < tmpfiles, "{postinst}");
+    }
+
     #[test]
     fn generate_scripts_archives_user_supplied_maintainer_scripts_in_root_package() {
         let maintainer_script_paths = vec![
@@ -0,0 +1,300 @@
+//! Deltas between two versions of a package, for updating machines that already have the old version
+
+use crate::deb::ar::DebArchive;
+use crate::deb::read::{control_field, read_ar_members, read_deb_members, read_tar_entries, DebMember};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::compress::{decompress, select_compressor, select_compressor_with_level, select_dpkg_compat_compressor_with_level, CompressConfig, CompressionLevel, Format};
+use crate::util::sha::sha256_hex;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Identifies the format, like `debian-binary` does for debs
+const DELTA_FORMAT: &[u8] = b"cargo-deb-delta 2\n";
+
+/// Writes `<name>_<old version>_<new version>_<arch>.deb-delta` next to the new package, and returns its path.
+///
+/// The delta is an `ar` archive with these members:
+///
+/// * `delta-format` — always `cargo-deb-delta 2`,
+/// * `info` — `Package`, `Architecture`, `Old-Version` and `New-Version` fields, SHA-256 of the old package's
+///   uncompressed `data.tar` and of the new package, and how the new package's `data.tar` has been compressed,
+/// * `control.tar.*` — copied as-is from the new package,
+/// * `data.patch.*` — instructions that make the new package's uncompressed `data.tar` from the old one:
+///   `c<offset> <len>\n` copies bytes from the old `data.tar`, and `i<len>\n` is followed by bytes to insert.
+///   Contents of files that are in the old package (at any path) are copied.
+///
+/// [`apply_delta`] makes a package byte-for-byte identical to the new one, as long as it compresses the same way.
+pub fn write_delta(old_deb_path: &Path, new_deb_path: &Path, compress: &CompressConfig, listener: &dyn Listener) -> CDResult<PathBuf> {
+    let old_members = read_deb_members(old_deb_path)?;
+    let new_members = read_deb_members(new_deb_path)?;
+
+    let old_control = control_file(&old_members)?;
+    let new_control = control_file(&new_members)?;
+    let field = |control: &str, name| control_field(control, name).map(str::to_owned)
+        .ok_or(CargoDebError::Str("control file is missing Package, Version or Architecture"));
+    let package = field(&new_control, "Package")?;
+    let arch = field(&new_control, "Architecture")?;
+    if field(&old_control, "Package")? != package || field(&old_control, "Architecture")? != arch {
+        return Err(CargoDebError::DeltaMismatch(old_deb_path.to_owned(), format!("{package}:{arch}")));
+    }
+    let old_version = field(&old_control, "Version")?;
+    let new_version = field(&new_control, "Version")?;
+
+    let old_tar = data_member(&old_members)?.decompressed()?;
+    let new_data_member = data_member(&new_members)?;
+    let new_tar = new_data_member.decompressed()?;
+    let (patch, copied, total) = make_patch(&old_tar, &new_tar)?;
+    let mut patch_compressor = select_compressor(false, compress.compress_type, false)?;
+    patch_compressor.write_all(&patch)?;
+    let patch = patch_compressor.finish()?;
+
+    let info = format!("Package: {package}\nArchitecture: {arch}\nOld-Version: {old_version}\nNew-Version: {new_version}\n\
+        Old-Data-SHA256: {}\nSHA256: {}\nData-Compression: {}\nCompression-Level: {}\nCompressor: {}\n",
+        sha256_hex(&old_tar), sha256_hex(&std::fs::read(new_deb_path)?),
+        new_data_member.compression_extension(), level_name(compress.level()),
+        if compress.compress_system { "system" } else if compress.dpkg_compat { "dpkg-compat" } else { "builtin" });
+
+    let new_control_member = new_members.iter().find(|m| m.is_control()).ok_or("deb archive doesn't have control.tar")?;
+    let file_name = format!("{package}_{}_{}_{arch}.deb-delta", strip_epoch(&old_version), strip_epoch(&new_version));
+    let out_path = new_deb_path.with_file_name(file_name);
+    let mut delta = DebArchive::without_header(out_path, new_members[0].mtime)?;
+    delta.add_file("delta-format".into(), DELTA_FORMAT)?;
+    delta.add_file("info".into(), info.as_bytes())?;
+    delta.add_file(new_control_member.name.clone(), &new_control_member.data)?;
+    delta.add_file(format!("data.patch.{}", patch.extension()), &patch)?;
+
+    listener.info(format!("Delta against {old_version} reuses {copied} of {total} files"));
+    delta.finish()
+}
+
+/// Makes the new package from the old one and a delta made by [`write_delta`], and returns its path.
+///
+/// Fails if the result isn't identical to the package the delta has been made from,
+/// e.g. when the compressor is a different version.
+pub fn apply_delta(old_deb_path: &Path, delta_path: &Path, out_path: Option<&Path>) -> CDResult<PathBuf> {
+    let invalid = |reason: &str| CargoDebError::CorruptedDeb(delta_path.to_owned(), reason.into());
+    let members = read_ar_members(delta_path)?;
+    if members.first().map(|m| &m.data[..]) != Some(DELTA_FORMAT) {
+        return Err(invalid("not a cargo-deb-delta 2 file"));
+    }
+    let member = |prefix: &str| members.iter().find(|m| m.name.starts_with(prefix)).ok_or_else(|| invalid("a member is missing"));
+    let info = String::from_utf8(member("info")?.data.clone()).map_err(|_| invalid("info isn't UTF-8"))?;
+    let field = |name| control_field(&info, name).ok_or_else(|| invalid("info is incomplete"));
+    let package = field("Package")?;
+    let arch = field("Architecture")?;
+
+    let old_members = read_deb_members(old_deb_path)?;
+    let old_control = control_file(&old_members)?;
+    let mismatch = || CargoDebError::DeltaMismatch(old_deb_path.to_owned(), format!("{package}:{arch} {}", field("Old-Version").unwrap_or_default()));
+    if control_field(&old_control, "Package") != Some(package) || control_field(&old_control, "Architecture") != Some(arch)
+        || control_field(&old_control, "Version") != Some(field("Old-Version")?) {
+        return Err(mismatch());
+    }
+    let old_tar = data_member(&old_members)?.decompressed()?;
+    if sha256_hex(&old_tar) != field("Old-Data-SHA256")? {
+        return Err(mismatch());
+    }
+
+    let patch_member = member("data.patch")?;
+    let patch = decompress(patch_member.name.rsplit_once('.').map_or("", |(_, ext)| ext), &patch_member.data)?;
+    let new_tar = apply_patch(&old_tar, &patch).ok_or_else(|| invalid("data.patch is corrupted"))?;
+
+    let format = Format::from_extension(field("Data-Compression")?).ok_or_else(|| invalid("unsupported Data-Compression"))?;
+    let level = match field("Compression-Level")? {
+        "fast" => CompressionLevel::Fast,
+        "best" => CompressionLevel::Best,
+        _ => CompressionLevel::Default,
+    };
+    let compressor = field("Compressor")?;
+    let mut data = if compressor == "dpkg-compat" { select_dpkg_compat_compressor_with_level } else { select_compressor_with_level }(level, format, compressor == "system")?;
+    data.write_all(&new_tar)?;
+
+    let new_version = strip_epoch(field("New-Version")?);
+    let out_path = out_path.map_or_else(|| delta_path.with_file_name(format!("{package}_{new_version}_{arch}.deb")), Path::to_path_buf);
+    let mut deb = DebArchive::new(out_path.clone(), members[0].mtime)?;
+    let control_member = member("control.tar")?;
+    deb.add_file(control_member.name.clone(), &control_member.data)?;
+    deb.add_data(data.finish()?)?;
+    let out_path = deb.finish()?;
+    if sha256_hex(&std::fs::read(&out_path)?) != field("SHA256")? {
+        let _ = std::fs::remove_file(&out_path);
+        return Err(CargoDebError::CorruptedDeb(out_path, "it differs from the package the delta has been made from. Was it compressed with a different tool?".into()));
+    }
+    Ok(out_path)
+}
+
+fn level_name(level: CompressionLevel) -> &'static str {
+    match level {
+        CompressionLevel::Fast => "fast",
+        CompressionLevel::Default => "default",
+        CompressionLevel::Best => "best",
+    }
+}
+
+/// Returns the patch, and how many of the files have been copied from the old tar
+fn make_patch(old_tar: &[u8], new_tar: &[u8]) -> CDResult<(Vec<u8>, usize, usize)> {
+    let mut old_contents = HashMap::new();
+    for (offset, size) in file_ranges(old_tar)? {
+        old_contents.entry(&old_tar[offset..offset + size]).or_insert(offset);
+    }
+
+    let mut patch = Vec::new();
+    let mut inserted = 0;
+    let (mut copied, mut total) = (0, 0);
+    for (offset, size) in file_ranges(new_tar)? {
+        total += 1;
+        let Some(&old_offset) = old_contents.get(&new_tar[offset..offset + size]) else { continue };
+        // headers, padding and new files
+        push_insert(&mut patch, &new_tar[inserted..offset]);
+        let _ = writeln!(patch, "c{old_offset} {size}");
+        inserted = offset + size;
+        copied += 1;
+    }
+    push_insert(&mut patch, &new_tar[inserted..]);
+    Ok((patch, copied, total))
+}
+
+fn push_insert(patch: &mut Vec<u8>, data: &[u8]) {
+    if !data.is_empty() {
+        let _ = writeln!(patch, "i{}", data.len());
+        patch.extend_from_slice(data);
+    }
+}
+
+fn apply_patch(old_tar: &[u8], mut patch: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(old_tar.len());
+    while !patch.is_empty() {
+        let line_end = patch.iter().position(|&c| c == b'\n')?;
+        let line = std::str::from_utf8(&patch[1..line_end]).ok()?;
+        let op = patch[0];
+        patch = &patch[line_end + 1..];
+        match op {
+            b'c' => {
+                let (offset, len) = line.split_once(' ')?;
+                let offset: usize = offset.parse().ok()?;
+                out.extend_from_slice(old_tar.get(offset..offset.checked_add(len.parse().ok()?)?)?);
+            },
+            b'i' => {
+                let len: usize = line.parse().ok()?;
+                out.extend_from_slice(patch.get(..len)?);
+                patch = &patch[len..];
+            },
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Offsets and sizes of non-empty file contents in the tar
+fn file_ranges(tar: &[u8]) -> CDResult<Vec<(usize, usize)>> {
+    let mut archive = tar::Archive::new(tar);
+    let mut ranges = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let size = entry.size() as usize;
+        if size > 0 && entry.header().entry_type().is_file() {
+            ranges.push((entry.raw_file_position() as usize, size));
+        }
+        // the reader must be at the end of the entry
+        std::io::copy(&mut entry, &mut std::io::sink())?;
+    }
+    Ok(ranges)
+}
+
+/// Version without `1:`, which is how dpkg names files
+fn strip_epoch(version: &str) -> &str {
+    version.split_once(':').map_or(version, |(_, v)| v)
+}
+
+fn control_file(members: &[DebMember]) -> CDResult<String> {
+    let control_member = members.iter().find(|m| m.is_control()).ok_or("deb archive doesn't have control.tar")?;
+    let entries = read_tar_entries(&control_member.decompressed()?)?;
+    let control = entries.into_iter()
+        .find(|e| e.path.strip_prefix(".").unwrap_or(&e.path) == Path::new("control"))
+        .ok_or("control.tar doesn't have a control file")?;
+    Ok(String::from_utf8(control.data).map_err(|e| e.utf8_error())?)
+}
+
+fn data_member(members: &[DebMember]) -> CDResult<&DebMember> {
+    members.iter().find(|m| m.is_data()).ok_or(CargoDebError::Str("deb archive doesn't have data.tar"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listener::NoOpListener;
+
+    fn tar(files: &[(&str, &[u8], u64)]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for &(path, data, mtime) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            tar.append_data(&mut header, path, data).unwrap();
+        }
+        tar.into_inner().unwrap()
+    }
+
+    fn write_deb(path: &Path, version: &str, files: &[(&str, &[u8], u64)], compress: &CompressConfig) {
+        let mut control = select_compressor_with_level(compress.level(), compress.compress_type, false).unwrap();
+        control.write_all(&tar(&[("./control", format!("Package: app\nVersion: {version}\nArchitecture: amd64\n").as_bytes(), 0)])).unwrap();
+        let mut data = select_compressor_with_level(compress.level(), compress.compress_type, false).unwrap();
+        data.write_all(&tar(files)).unwrap();
+        let mut deb = DebArchive::new(path.to_owned(), 1).unwrap();
+        deb.add_control(control.finish().unwrap()).unwrap();
+        deb.add_data(data.finish().unwrap()).unwrap();
+        deb.finish().unwrap();
+    }
+
+    #[test]
+    fn patches_round_trip() {
+        let old = tar(&[("./a", b"same", 1), ("./b", b"old", 1), ("./c", b"moved", 1)]);
+        let new = tar(&[("./a", b"same", 2), ("./b", b"new!", 2), ("./d", b"moved", 2), ("./e", b"", 2)]);
+        let (patch, copied, total) = make_patch(&old, &new).unwrap();
+        assert_eq!((copied, total), (2, 3));
+        assert_eq!(apply_patch(&old, &patch).unwrap(), new);
+        assert!(apply_patch(&old[..100], &patch).is_none());
+        assert_eq!(strip_epoch("1:2.0-1"), "2.0-1");
+    }
+
+    #[test]
+    fn rebuilds_identical_deb() {
+        let formats = [
+            #[cfg(feature = "lzma")]
+            Format::Xz,
+            Format::Gzip,
+        ];
+        for compress_type in formats {
+            let compress = CompressConfig {
+                fast: false,
+                best: false,
+                compress_type,
+                control_compress_type: compress_type,
+                compress_system: false,
+                rsyncable: false,
+                dpkg_compat: false,
+            };
+            let dir = tempfile::tempdir().unwrap();
+            // incompressible, like binaries mostly are
+            let mut x = 1u32;
+            let big: Vec<u8> = (0..100_000).map(|_| { x ^= x << 13; x ^= x >> 17; x ^= x << 5; x as u8 }).collect();
+            let old_deb = dir.path().join("app_1.0_amd64.deb");
+            write_deb(&old_deb, "1.0", &[("./usr/bin/app", b"v1", 1), ("./usr/share/app/data", &big, 1)], &compress);
+            let new_deb = dir.path().join("app_1:1.1_amd64.deb");
+            write_deb(&new_deb, "1:1.1", &[("./usr/bin/app", b"v2", 2), ("./usr/share/app/data", &big, 2)], &compress);
+
+            let delta = write_delta(&old_deb, &new_deb, &compress, &NoOpListener).unwrap();
+            assert_eq!(delta.file_name().unwrap(), "app_1.0_1.1_amd64.deb-delta");
+            assert!(std::fs::metadata(&delta).unwrap().len() < std::fs::metadata(&new_deb).unwrap().len() / 2);
+
+            let rebuilt = apply_delta(&old_deb, &delta, Some(&dir.path().join("rebuilt.deb"))).unwrap();
+            assert_eq!(std::fs::read(rebuilt).unwrap(), std::fs::read(&new_deb).unwrap());
+
+            // the old package must be the one the delta has been made from
+            assert!(matches!(apply_delta(&new_deb, &delta, None), Err(CargoDebError::DeltaMismatch(..))));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Rewriting of control fields of an already-built `.deb`, without rebuilding its data
+
+use crate::config::check_debian_version;
+use crate::deb::ar::DebArchive;
+use crate::deb::read::{control_field, control_field_range, read_deb_members, read_tar_entries};
+use crate::deb::tar::Tarball;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::compress::compress;
+use std::path::{Path, PathBuf};
+use tar::EntryType;
+
+/// Changes to apply to the `control` file of an existing package
+#[derive(Debug, Default, Clone)]
+pub struct MetadataEdits {
+    /// Replaces the whole version, including revision
+    pub version: Option<String>,
+    /// Replaces the part after the last `-` in the version
+    pub revision: Option<String>,
+    /// Appended to the version, e.g. `~bookworm1`
+    pub version_suffix: Option<String>,
+    pub maintainer: Option<String>,
+    /// Other fields to set, e.g. `("Distribution", "stable")`. An empty value removes the field.
+    pub fields: Vec<(String, String)>,
+}
+
+/// Reads the deb at `deb_path`, applies the edits to its control file, and writes a new deb.
+///
+/// The data archive is copied as-is, and timestamps and permissions of the control files are preserved,
+/// so the result is reproducible. Returns path of the new file.
+pub fn edit_deb(deb_path: &Path, output_path: Option<&Path>, edits: &MetadataEdits, listener: &dyn Listener) -> CDResult<PathBuf> {
+    let members = read_deb_members(deb_path)?;
+    let control_member = members.iter().find(|m| m.is_control()).ok_or("deb archive doesn't have control.tar")?;
+    if !members.iter().any(|m| m.is_data()) {
+        return Err("deb archive doesn't have data.tar".into());
+    }
+    let entries = read_tar_entries(&control_member.decompressed()?)?;
+    let control_entry = entries.iter().find(|e| is_control_file(&e.path)).ok_or("control.tar doesn't have a control file")?;
+    let control = std::str::from_utf8(&control_entry.data)?;
+    let control = apply_edits(control, edits)?;
+
+    let field = |name| control_field(&control, name).filter(|v| !v.is_empty())
+        .ok_or(CargoDebError::Str("edited control file is missing Package, Version or Architecture"));
+    let file_name = format!("{}_{}_{}.deb", field("Package")?, field("Version")?, field("Architecture")?);
+    let out_path = match output_path {
+        Some(out) if out.is_dir() || out.as_os_str().to_string_lossy().ends_with('/') => out.join(file_name),
+        Some(out) => out.to_owned(),
+        None => deb_path.parent().unwrap_or(Path::new(".")).join(file_name),
+    };
+    listener.info(format!("Edited control:\n{control}"));
+
+    let mut tarball = Tarball::new(Vec::new(), control_entry.mtime);
+    for entry in &entries {
+        match entry.entry_type {
+            EntryType::Directory => {},
+            EntryType::Regular | EntryType::Continuous => {
+                let data = if is_control_file(&entry.path) { control.as_bytes() } else { &entry.data[..] };
+                tarball.file(&entry.path, data, entry.mode)?;
+            },
+            EntryType::Symlink => {
+                tarball.symlink(&entry.path, entry.link_name.as_deref().ok_or("invalid symlink in control.tar")?)?;
+            },
+            _ => return Err("unsupported file type in control.tar".into()),
+        }
+    }
+    // the same compression as before, since tools reading the deb may not support others
+    let control_tarball = compress(control_member.compression_extension(), &tarball.into_inner()?)?;
+
+    let mut deb = DebArchive::new(out_path, control_member.mtime)?;
+    // dpkg requires control to be before data
+    deb.add_file(control_member.name.clone(), &control_tarball)?;
+    for member in members.iter().filter(|m| m.name != "debian-binary" && !m.is_control()) {
+        deb.add_file(member.name.clone(), &member.data)?;
+    }
+    deb.finish()
+}
+
+fn is_control_file(path: &Path) -> bool {
+    path.strip_prefix(".").unwrap_or(path) == Path::new("control")
+}
+
+fn apply_edits(control: &str, edits: &MetadataEdits) -> CDResult<String> {
+    let mut control = control.to_string();
+    let old_version = control_field(&control, "Version").ok_or("control file doesn't have a Version")?;
+    let mut version = match (&edits.version, &edits.revision) {
+        (Some(version), _) => version.clone(),
+        (None, Some(revision)) => {
+            let upstream = old_version.rsplit_once('-').map_or(old_version, |(upstream, _)| upstream);
+            if revision.is_empty() { upstream.to_string() } else { format!("{upstream}-{revision}") }
+        },
+        (None, None) => old_version.to_string(),
+    };
+    if let Some(suffix) = &edits.version_suffix {
+        version.push_str(suffix);
+    }
+    if let Err(why) = check_debian_version(&version) {
+        return Err(CargoDebError::InvalidVersion(why, version));
+    }
+    set_control_field(&mut control, "Version", &version);
+    if let Some(maintainer) = &edits.maintainer {
+        set_control_field(&mut control, "Maintainer", maintainer);
+    }
+    for (name, value) in &edits.fields {
+        if name.is_empty() || name.contains([':', ' ', '\n']) {
+            return Err(CargoDebError::Str("invalid control field name"));
+        }
+        set_control_field(&mut control, name, value);
+    }
+    Ok(control)
+}
+
+/// Replaces or appends the field. Empty value removes it.
+fn set_control_field(control: &mut String, name: &str, value: &str) {
+    let new_field = if value.is_empty() { String::new() } else { format!("{name}: {value}\n") };
+    if let Some(range) = control_field_range(control, name) {
+        control.replace_range(range, &new_field);
+    } else {
+        // the paragraph may end with a blank line, and the new field must not go after it
+        let trailing_newlines = control.len() - control.trim_end_matches('\n').len();
+        control.truncate(control.len() - trailing_newlines);
+        control.push('\n');
+        control.push_str(&new_field);
+        if trailing_newlines > 1 {
+            control.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROL: &str = "Package: foo\nVersion: 1:1.2.3-1\nArchitecture: amd64\nMaintainer: Old <old@example.com>\nDescription: short\n long\n\n";
+
+    #[test]
+    fn edit_control_fields() {
+        let edited = apply_edits(CONTROL, &MetadataEdits {
+            revision: Some("2".into()),
+            version_suffix: Some("~bookworm".into()),
+            maintainer: Some("New <new@example.com>".into()),
+            fields: vec![("Distribution".into(), "stable".into()), ("Description".into(), "new".into())],
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(edited, "Package: foo\nVersion: 1:1.2.3-2~bookworm\nArchitecture: amd64\nMaintainer: New <new@example.com>\nDescription: new\nDistribution: stable\n\n");
+
+        let edited = apply_edits(CONTROL, &MetadataEdits {
+            version: Some("2.0".into()),
+            fields: vec![("maintainer".into(), String::new())],
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(edited, "Package: foo\nVersion: 2.0\nArchitecture: amd64\nDescription: short\n long\n\n");
+
+        assert!(apply_edits(CONTROL, &MetadataEdits { version_suffix: Some("_bad".into()), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn edit_uncompressed_control_tar() {
+        let mut control = Tarball::new(Vec::new(), 0);
+        control.file("./control", CONTROL.as_bytes(), 0o644).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut deb = DebArchive::new(dir.path().join("in.deb"), 0).unwrap();
+        deb.add_file("control.tar".into(), &control.into_inner().unwrap()).unwrap();
+        deb.add_file("data.tar".into(), &Tarball::new(Vec::new(), 0).into_inner().unwrap()).unwrap();
+        let deb = deb.finish().unwrap();
+
+        let edits = MetadataEdits { revision: Some("2".into()), ..Default::default() };
+        let edited = edit_deb(&deb, Some(dir.path()), &edits, &crate::listener::NoOpListener).unwrap();
+        assert_eq!(edited.file_name().unwrap(), "foo_1:1.2.3-2_amd64.deb");
+        let members = read_deb_members(&edited).unwrap();
+        let names: Vec<_> = members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["debian-binary", "control.tar", "data.tar"]);
+        let entries = read_tar_entries(&members[1].data).unwrap();
+        assert!(std::str::from_utf8(&entries[0].data).unwrap().contains("Version: 1:1.2.3-2\n"));
+    }
+}
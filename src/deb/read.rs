@@ -0,0 +1,223 @@
+use crate::error::{CDResult, CargoDebError};
+use crate::util::compress::decompress;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tar::EntryType;
+
+/// A file inside the outermost `ar` archive of a `.deb`
+pub(crate) struct DebMember {
+    pub name: String,
+    pub mtime: u64,
+    pub data: Vec<u8>,
+}
+
+impl DebMember {
+    /// `xz` for `control.tar.xz`, empty for `control.tar`
+    pub fn compression_extension(&self) -> &str {
+        self.name.split_once(".tar").map_or("", |(_, ext)| ext.trim_start_matches('.'))
+    }
+
+    pub fn is_control(&self) -> bool {
+        self.name.starts_with("control.tar")
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.name.starts_with("data.tar")
+    }
+
+    pub fn decompressed(&self) -> CDResult<Vec<u8>> {
+        decompress(self.compression_extension(), &self.data)
+    }
+}
+
+/// All members of the deb in their original order, including `debian-binary`
+pub(crate) fn read_deb_members(path: &Path) -> CDResult<Vec<DebMember>> {
+    let members = read_ar_members(path)?;
+    if members.first().map(|m| m.name.as_str()) != Some("debian-binary") {
+        return Err(CargoDebError::Str("not a deb archive, debian-binary is missing"));
+    }
+    Ok(members)
+}
+
+/// Members of any `ar` archive, e.g. a delta
+pub(crate) fn read_ar_members(path: &Path) -> CDResult<Vec<DebMember>> {
+    let file = File::open(path).map_err(|e| CargoDebError::IoFile("unable to open deb", e, path.to_owned()))?;
+    let mut ar = ar::Archive::new(file);
+    let mut members = Vec::new();
+    while let Some(entry) = ar.next_entry() {
+        let mut entry = entry.map_err(|e| CargoDebError::IoFile("unable to read deb", e, path.to_owned()))?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).trim_end_matches('/').to_string();
+        let mtime = entry.header().mtime();
+        let mut data = Vec::with_capacity(entry.header().size() as usize);
+        entry.read_to_end(&mut data)?;
+        members.push(DebMember { name, mtime, data });
+    }
+    Ok(members)
+}
+
+/// Re-reads the deb and checks its files against `md5sums` and `conffiles`, to catch truncated or corrupted archives.
+/// Compressed members are also protected by their own checksums, which are checked when decompressing.
+pub(crate) fn verify_deb(path: &Path) -> CDResult<()> {
+    let corrupted = |reason: String| CargoDebError::CorruptedDeb(path.to_owned(), reason);
+    let members = read_deb_members(path)?;
+    let control = members.iter().find(|m| m.is_control()).ok_or_else(|| corrupted("control archive is missing".into()))?;
+    let data = members.iter().find(|m| m.is_data()).ok_or_else(|| corrupted("data archive is missing".into()))?;
+
+    let mut md5sums = None;
+    let mut conf_files = BTreeSet::new();
+    let control_tar = control.decompressed().map_err(|e| corrupted(format!("{}: {e}", control.name)))?;
+    for entry in read_tar_entries(&control_tar).map_err(|e| corrupted(format!("{}: {e}", control.name)))? {
+        let Some(name) = entry.path.file_name() else { continue };
+        if name == "md5sums" {
+            let text = String::from_utf8(entry.data).map_err(|_| corrupted("md5sums isn't UTF-8".into()))?;
+            let mut expected = BTreeMap::new();
+            for line in text.lines() {
+                let (digest, file) = line.split_once("  ").ok_or_else(|| corrupted(format!("bad md5sums line: {line}")))?;
+                expected.insert(PathBuf::from(file), digest.to_owned());
+            }
+            md5sums = Some(expected);
+        } else if name == "conffiles" {
+            let text = String::from_utf8(entry.data).map_err(|_| corrupted("conffiles isn't UTF-8".into()))?;
+            conf_files.extend(text.lines().filter(|l| !l.trim().is_empty()).map(|l| PathBuf::from(l.trim().trim_start_matches('/'))));
+        }
+    }
+
+    let has_md5sums = md5sums.is_some();
+    let mut expected = md5sums.unwrap_or_default();
+    let mut missing_conf_files = conf_files.clone();
+    let data_tar = data.decompressed().map_err(|e| corrupted(format!("{}: {e}", data.name)))?;
+    for entry in read_tar_entries(&data_tar).map_err(|e| corrupted(format!("{}: {e}", data.name)))? {
+        let file = entry.path.strip_prefix(".").unwrap_or(&entry.path);
+        if !entry.entry_type.is_file() {
+            continue;
+        }
+        missing_conf_files.remove(file);
+        if let Some(digest) = expected.remove(file) {
+            if format!("{:x}", md5::compute(&entry.data)) != digest {
+                return Err(corrupted(format!("checksum of /{} doesn't match", file.display())));
+            }
+        } else if !conf_files.contains(file) {
+            // conffiles are left out of md5sums, but every other file is in it
+            if !has_md5sums {
+                return Err(corrupted("md5sums is missing".into()));
+            }
+            return Err(corrupted(format!("/{} isn't in md5sums", file.display())));
+        }
+    }
+    if let Some(file) = expected.keys().next() {
+        return Err(corrupted(format!("/{} is missing", file.display())));
+    }
+    if let Some(file) = missing_conf_files.iter().next() {
+        return Err(corrupted(format!("conffile /{} is missing", file.display())));
+    }
+    Ok(())
+}
+
+/// An entry of an (uncompressed) tarball
+pub(crate) struct TarEntry {
+    pub path: PathBuf,
+    pub mode: u32,
+    pub mtime: u64,
+    pub entry_type: EntryType,
+    pub link_name: Option<PathBuf>,
+    pub data: Vec<u8>,
+}
+
+pub(crate) fn read_tar_entries(tar: &[u8]) -> CDResult<Vec<TarEntry>> {
+    let mut archive = tar::Archive::new(tar);
+    archive.entries()?.map(|entry| {
+        let mut entry = entry?;
+        let header = entry.header();
+        let mode = header.mode()?;
+        let mtime = header.mtime()?;
+        let entry_type = header.entry_type();
+        let link_name = entry.link_name()?.map(|l| l.into_owned());
+        let path = entry.path()?.into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        Ok(TarEntry { path, mode, mtime, entry_type, link_name, data })
+    }).collect()
+}
+
+/// Value of the field in a `control`-formatted paragraph, with continuation lines
+pub(crate) fn control_field<'a>(control: &'a str, name: &str) -> Option<&'a str> {
+    let field = &control[control_field_range(control, name)?];
+    field.split_once(':').map(|(_, value)| value.trim())
+}
+
+/// Byte range of the whole field, including the name and continuation lines
+pub(crate) fn control_field_range(control: &str, name: &str) -> Option<Range<usize>> {
+    let mut start = None;
+    let mut pos = 0;
+    for line in control.split_inclusive('\n') {
+        let is_continuation = line.starts_with([' ', '\t']);
+        if let Some(start) = start {
+            if !is_continuation {
+                return Some(start..pos);
+            }
+        } else if !is_continuation && line.split_once(':').is_some_and(|(field, _)| field.eq_ignore_ascii_case(name)) {
+            start = Some(pos);
+        }
+        pos += line.len();
+    }
+    start.map(|start| start..control.len())
+}
+
+#[test]
+fn parse_control_fields() {
+    let control = "Package: foo\nVersion: 1.0-1\nDescription: short\n long\n .\n end\nMaintainer: me\n";
+    assert_eq!(control_field(control, "package"), Some("foo"));
+    assert_eq!(control_field(control, "Version"), Some("1.0-1"));
+    assert_eq!(control_field(control, "Description"), Some("short\n long\n .\n end"));
+    assert_eq!(control_field(control, "Maintainer"), Some("me"));
+    assert_eq!(control_field(control, "Depends"), None);
+}
+
+#[test]
+fn verifies_md5sums() {
+    use crate::deb::ar::DebArchive;
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for &(path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, path, data).unwrap();
+        }
+        tar.into_inner().unwrap()
+    }
+    let write_deb = |path: &Path, control: &[(&str, &[u8])], data: &[(&str, &[u8])]| {
+        let mut ar = DebArchive::new(path.to_owned(), 0).unwrap();
+        ar.add_file("control.tar".into(), &tar(control)).unwrap();
+        ar.add_file("data.tar".into(), &tar(data)).unwrap();
+        ar.finish().unwrap();
+    };
+    let md5sums = ("./md5sums", &b"c1111bd512b29e821b120b86446026b8  usr/bin/app\n"[..]);
+    let conffiles = ("./conffiles", &b"/etc/app.conf\n"[..]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let good = dir.path().join("good.deb");
+    write_deb(&good, &[md5sums, conffiles], &[("./usr/bin/app", b"bin"), ("./etc/app.conf", b"x=1")]);
+    verify_deb(&good).unwrap();
+
+    let bad = dir.path().join("bad.deb");
+    write_deb(&bad, &[md5sums], &[("./usr/bin/app", b"bi\0")]);
+    let err = verify_deb(&bad).unwrap_err().to_string();
+    assert!(err.contains("checksum of /usr/bin/app doesn't match"), "{err}");
+
+    write_deb(&bad, &[], &[("./usr/bin/app", b"bin")]);
+    let err = verify_deb(&bad).unwrap_err().to_string();
+    assert!(err.contains("md5sums is missing"), "{err}");
+
+    write_deb(&bad, &[md5sums], &[("./usr/bin/app", b"bin"), ("./usr/bin/other", b"")]);
+    let err = verify_deb(&bad).unwrap_err().to_string();
+    assert!(err.contains("/usr/bin/other isn't in md5sums"), "{err}");
+
+    write_deb(&bad, &[md5sums, conffiles], &[("./usr/bin/app", b"bin")]);
+    let err = verify_deb(&bad).unwrap_err().to_string();
+    assert!(err.contains("conffile /etc/app.conf is missing"), "{err}");
+}
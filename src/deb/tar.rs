@@ -1,35 +1,76 @@
-use crate::assets::AssetSource;
+use crate::assets::{AssetOwner, AssetSource, OwnerId, PackageDir, SpecialFile};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
 use crate::PackageConfig;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::io::Write;
 use std::path::{Component, Path, PathBuf};
 use std::{fs, io};
 use tar::{EntryType, Header as TarHeader};
 
+/// MD5 hashes of regular files in the data archive, by their path in the archive
+pub(crate) type FileHashes = BTreeMap<PathBuf, md5::Digest>;
+
 /// Tarball for control and data files
 pub(crate) struct Tarball<W: Write> {
     added_directories: HashSet<PathBuf>,
     time: u64,
     tar: tar::Builder<W>,
+    /// In `dpkg-deb`-compatible mode entries are collected here, and written sorted when finished
+    dpkg_entries: Option<Vec<DpkgEntry>>,
+}
+
+/// Entry of a tarball made the way `dpkg-deb` makes it
+struct DpkgEntry {
+    /// Without the `./` prefix. Empty for the root dir.
+    path: PathBuf,
+    header: TarHeader,
+    link_name: Option<PathBuf>,
+    data: Vec<u8>,
 }
 
+impl DpkgEntry {
+    fn new(path: &Path, header: TarHeader, link_name: Option<PathBuf>, data: Vec<u8>) -> Self {
+        let path = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+        Self { path, header, link_name, data }
+    }
+}
+
+/// GNU tar writes archives in records of 20 blocks
+const DPKG_RECORD_SIZE: u64 = 20 * 512;
+
 impl<W: Write> Tarball<W> {
     pub fn new(dest: W, time: u64) -> Self {
         Self {
             added_directories: HashSet::new(),
             time,
             tar: tar::Builder::new(dest),
+            dpkg_entries: None,
+        }
+    }
+
+    /// Produces the same bytes as `dpkg-deb --root-owner-group` (GNU tar): entries are in `./`, sorted per directory,
+    /// with symlinks last, and owned by `root`. The archive is padded to whole tar records.
+    pub fn new_dpkg_compat(dest: W, time: u64) -> Self {
+        Self {
+            dpkg_entries: Some(Vec::new()),
+            ..Self::new(dest, time)
         }
     }
 
     /// Copies all the files to be packaged into the tar archive.
-    /// Returns MD5 hashes of files copied
-    pub fn archive_files(mut self, package_deb: &PackageConfig, rsyncable: bool, listener: &dyn Listener) -> CDResult<W> {
+    /// Returns MD5 hashes of the files copied
+    pub fn archive_files(mut self, package_deb: &PackageConfig, rsyncable: bool, listener: &dyn Listener) -> CDResult<(W, FileHashes)> {
+        let mut hashes = FileHashes::new();
         let mut archive_data_added = 0;
         let mut prev_is_built = false;
 
+        // before the assets, so that the parent directories of assets get the dirs' mode and owner
+        for dir in &package_deb.dirs {
+            listener.info(format!("-> {}/ (dir {:o})", dir.target_path.display(), dir.chmod));
+            self.package_dir(dir)?;
+        }
+
         debug_assert!(package_deb.assets.unresolved.is_empty());
         for asset in &package_deb.assets.resolved {
             let mut log_line = format!("{} {}-> {}",
@@ -49,10 +90,12 @@ impl<W: Write> Tarball<W> {
             if let AssetSource::Symlink(source_path) = &asset.source {
                 let link_name = fs::read_link(source_path)
                     .map_err(|e| CargoDebError::IoFile("symlink asset", e, source_path.clone()))?;
-                self.symlink(&asset.c.target_path, &link_name)?;
+                self.symlink_(&asset.c.target_path, &link_name, asset.c.owner.as_ref())?;
+            } else if let AssetSource::Special(special) = asset.source {
+                self.special_file(&asset.c.target_path, special, asset.c.chmod, asset.c.owner.as_ref())?;
             } else {
                 let out_data = asset.source.data()?;
-                if rsyncable {
+                if rsyncable && self.dpkg_entries.is_none() {
                     if archive_data_added > 1_000_000 || prev_is_built != asset.c.is_built() {
                         self.flush()?;
                         archive_data_added = 0;
@@ -61,18 +104,29 @@ impl<W: Write> Tarball<W> {
                     prev_is_built = asset.c.is_built();
                     archive_data_added += out_data.len();
                 }
-                self.file(&asset.c.target_path, &out_data, asset.c.chmod)?;
+                hashes.insert(asset.c.target_path.clone(), md5::compute(&out_data));
+                self.file_(&asset.c.target_path, &out_data, asset.c.chmod, asset.c.owner.as_ref())?;
             }
         }
 
-        Ok(self.tar.into_inner()?)
+        Ok((self.into_inner()?, hashes))
     }
 
     fn directory(&mut self, path: &Path) -> io::Result<()> {
+        self.directory_(path, 0o755, None)
+    }
+
+    fn directory_(&mut self, path: &Path, chmod: u32, owner: Option<&AssetOwner>) -> io::Result<()> {
         let mut header = TarHeader::new_gnu();
         header.set_mtime(self.time);
         header.set_size(0);
-        header.set_mode(0o755);
+        header.set_mode(chmod);
+        set_owner(&mut header, owner)?;
+        if let Some(entries) = &mut self.dpkg_entries {
+            header.set_entry_type(EntryType::Directory);
+            entries.push(DpkgEntry::new(path, header, None, Vec::new()));
+            return Ok(());
+        }
         // Lintian insists on dir paths ending with /, which Rust doesn't
         let mut path_str = path.to_string_lossy().to_string();
         if !path_str.ends_with('/') {
@@ -88,9 +142,13 @@ impl<W: Write> Tarball<W> {
         // For each directory pathname found, attempt to add it to the list of directories
         let asset_relative_dir = Path::new(".").join(path.parent().ok_or("invalid asset")?);
         let mut directory = PathBuf::new();
+        // dpkg-deb has the root dir in its archives
+        if self.dpkg_entries.is_some() && self.added_directories.insert(directory.clone()) {
+            self.directory(&directory)?;
+        }
         for comp in asset_relative_dir.components() {
             match comp {
-                Component::CurDir if !crate::TAR_REJECTS_CUR_DIR => directory.push("."),
+                Component::CurDir if !crate::TAR_REJECTS_CUR_DIR && self.dpkg_entries.is_none() => directory.push("."),
                 Component::Normal(c) => directory.push(c),
                 _ => continue,
             }
@@ -102,42 +160,195 @@ impl<W: Write> Tarball<W> {
         Ok(())
     }
 
+    fn package_dir(&mut self, dir: &PackageDir) -> CDResult<()> {
+        self.add_parent_directories(&dir.target_path)?;
+        // same form of the path as in add_parent_directories
+        let mut directory = PathBuf::new();
+        if !crate::TAR_REJECTS_CUR_DIR && self.dpkg_entries.is_none() {
+            directory.push(".");
+        }
+        directory.push(&dir.target_path);
+        if self.added_directories.insert(directory.clone()) {
+            self.directory_(&directory, dir.chmod, dir.owner.as_ref())?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn file<P: AsRef<Path>>(&mut self, path: P, out_data: &[u8], chmod: u32) -> CDResult<()> {
-        self.file_(path.as_ref(), out_data, chmod)
+        self.file_(path.as_ref(), out_data, chmod, None)
     }
 
-    fn file_(&mut self, path: &Path, out_data: &[u8], chmod: u32) -> CDResult<()> {
+    fn file_(&mut self, path: &Path, out_data: &[u8], chmod: u32, owner: Option<&AssetOwner>) -> CDResult<()> {
         self.add_parent_directories(path)?;
 
         let mut header = TarHeader::new_gnu();
         header.set_mtime(self.time);
         header.set_mode(chmod);
+        set_owner(&mut header, owner)?;
         header.set_size(out_data.len() as u64);
+        if let Some(entries) = &mut self.dpkg_entries {
+            header.set_entry_type(EntryType::Regular);
+            entries.push(DpkgEntry::new(path, header, None, out_data.to_vec()));
+            return Ok(());
+        }
         header.set_cksum();
         self.tar.append_data(&mut header, path, out_data)?;
         Ok(())
     }
 
     pub(crate) fn symlink(&mut self, path: &Path, link_name: &Path) -> CDResult<()> {
+        self.symlink_(path, link_name, None)
+    }
+
+    fn symlink_(&mut self, path: &Path, link_name: &Path, owner: Option<&AssetOwner>) -> CDResult<()> {
         self.add_parent_directories(path.as_ref())?;
 
         let mut header = TarHeader::new_gnu();
         header.set_mtime(self.time);
+        set_owner(&mut header, owner)?;
         header.set_entry_type(EntryType::Symlink);
         header.set_size(0);
         header.set_mode(0o777);
+        if let Some(entries) = &mut self.dpkg_entries {
+            entries.push(DpkgEntry::new(path, header, Some(link_name.to_owned()), Vec::new()));
+            return Ok(());
+        }
         header.set_cksum();
         self.tar.append_link(&mut header, path, link_name)?;
         Ok(())
     }
 
+    fn special_file(&mut self, path: &Path, special: SpecialFile, chmod: u32, owner: Option<&AssetOwner>) -> CDResult<()> {
+        self.add_parent_directories(path)?;
+
+        let mut header = TarHeader::new_gnu();
+        header.set_mtime(self.time);
+        header.set_size(0);
+        header.set_mode(chmod);
+        set_owner(&mut header, owner)?;
+        let (entry_type, device) = match special {
+            SpecialFile::Fifo => (EntryType::Fifo, None),
+            SpecialFile::CharDevice { major, minor } => (EntryType::Char, Some((major, minor))),
+            SpecialFile::BlockDevice { major, minor } => (EntryType::Block, Some((major, minor))),
+        };
+        header.set_entry_type(entry_type);
+        if let Some((major, minor)) = device {
+            header.set_device_major(major)?;
+            header.set_device_minor(minor)?;
+        }
+        if let Some(entries) = &mut self.dpkg_entries {
+            entries.push(DpkgEntry::new(path, header, None, Vec::new()));
+            return Ok(());
+        }
+        header.set_cksum();
+        self.tar.append_data(&mut header, path, io::empty())?;
+        Ok(())
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.tar.get_mut().flush()
     }
 
-    pub fn into_inner(self) -> io::Result<W> {
-        self.tar.into_inner()
+    pub fn into_inner(mut self) -> io::Result<W> {
+        let Some(mut entries) = self.dpkg_entries.take() else {
+            return self.tar.into_inner();
+        };
+        // dpkg-deb walks the tree sorting each directory by name, and defers symlinks to the end
+        entries.sort_by(|a, b| (a.link_name.is_some(), &a.path).cmp(&(b.link_name.is_some(), &b.path)));
+        let mut written = 0;
+        for entry in entries {
+            written += self.append_dpkg_entry(entry)?;
+        }
+        let mut dest = self.tar.into_inner()?;
+        written += 2 * 512;
+        let padding = (DPKG_RECORD_SIZE - written % DPKG_RECORD_SIZE) % DPKG_RECORD_SIZE;
+        dest.write_all(&vec![0; padding as usize])?;
+        Ok(dest)
+    }
+
+    /// Returns number of bytes written
+    fn append_dpkg_entry(&mut self, DpkgEntry { path, mut header, link_name, data }: DpkgEntry) -> io::Result<u64> {
+        let mut name = format!("./{}", path.display()).into_bytes();
+        if header.entry_type().is_dir() && !path.as_os_str().is_empty() {
+            name.push(b'/');
+        }
+        let mut written = 0;
+        if let Some(link_name) = link_name {
+            let link_name = link_name.to_string_lossy().into_owned().into_bytes();
+            written += self.append_dpkg_long_name(EntryType::GNULongLink, &link_name)?;
+            let field = &mut gnu(&mut header).linkname;
+            let len = link_name.len().min(field.len());
+            field[..len].copy_from_slice(&link_name[..len]);
+            header.set_entry_type(EntryType::Symlink);
+        }
+        written += self.append_dpkg_long_name(EntryType::GNULongName, &name)?;
+        let field = &mut gnu(&mut header).name;
+        let len = name.len().min(field.len());
+        field[..len].copy_from_slice(&name[..len]);
+
+        // Assets with an owner have a name or id already
+        let (uid, gid) = (header.uid().unwrap_or(0), header.gid().unwrap_or(0));
+        header.set_uid(uid);
+        header.set_gid(gid);
+        if uid == 0 && header.username_bytes().map_or(true, |n| n.is_empty()) {
+            header.set_username("root")?;
+        }
+        if gid == 0 && header.groupname_bytes().map_or(true, |n| n.is_empty()) {
+            header.set_groupname("root")?;
+        }
+        set_gnu_cksum(&mut header);
+        self.tar.append(&header, &data[..])?;
+        Ok(written + 512 + (data.len() as u64 + 511) / 512 * 512)
+    }
+
+    /// GNU tar's `././@LongLink` entry for names that don't fit in the header
+    fn append_dpkg_long_name(&mut self, entry_type: EntryType, name: &[u8]) -> io::Result<u64> {
+        if name.len() <= 100 {
+            return Ok(0);
+        }
+        let mut header = TarHeader::new_gnu();
+        let long_link = b"././@LongLink";
+        gnu(&mut header).name[..long_link.len()].copy_from_slice(long_link);
+        header.set_mode(0o644);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_size(name.len() as u64 + 1);
+        header.set_entry_type(entry_type);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+        set_gnu_cksum(&mut header);
+        let mut data = name.to_vec();
+        data.push(0);
+        self.tar.append(&header, &data[..])?;
+        Ok(512 + (data.len() as u64 + 511) / 512 * 512)
+    }
+}
+
+/// Without an owner, files belong to root
+fn set_owner(header: &mut TarHeader, owner: Option<&AssetOwner>) -> io::Result<()> {
+    let Some(AssetOwner { user, group }) = owner else {
+        return Ok(());
+    };
+    match user {
+        OwnerId::Name(name) => header.set_username(name)?,
+        OwnerId::Id(id) => header.set_uid(*id),
+    }
+    match group {
+        OwnerId::Name(name) => header.set_groupname(name)?,
+        OwnerId::Id(id) => header.set_gid(*id),
     }
+    Ok(())
+}
+
+fn gnu(header: &mut TarHeader) -> &mut tar::GnuHeader {
+    header.as_gnu_mut().expect("created with new_gnu")
+}
+
+/// GNU tar formats the checksum as 6 octal digits followed by NUL and space
+fn set_gnu_cksum(header: &mut TarHeader) {
+    header.as_old_mut().cksum = *b"        ";
+    let sum: u32 = header.as_bytes().iter().map(|&b| u32::from(b)).sum();
+    header.as_old_mut().cksum = format!("{sum:06o}\0 ").into_bytes().try_into().unwrap_or([b' '; 8]);
 }
 
 fn human_size(len: u64) -> (u64, &'static str) {
@@ -149,3 +360,57 @@ fn human_size(len: u64) -> (u64, &'static str) {
     }
     ((len + 999_999) / 1_000_000, "MB")
 }
+
+#[test]
+fn dpkg_compat_headers() {
+    let mut tarball = Tarball::new_dpkg_compat(Vec::new(), 1_700_000_000);
+    tarball.symlink(Path::new("usr/l"), Path::new("x")).unwrap();
+    tarball.file("usr/bin/foo", b"hi\n", 0o755).unwrap();
+    tarball.file("a-c", b"", 0o644).unwrap();
+    let tar = tarball.into_inner().unwrap();
+    assert_eq!(tar.len() as u64 % DPKG_RECORD_SIZE, 0);
+
+    // Golden header of the root dir, as written by dpkg-deb 1.21
+    let mut root = [0u8; 512];
+    root[..2].copy_from_slice(b"./");
+    root[100..157].copy_from_slice(b"0000755\x000000000\x000000000\x0000000000000\x0014524770400\x00007714\x00 5");
+    root[257..265].copy_from_slice(b"ustar  \0");
+    root[265..269].copy_from_slice(b"root");
+    root[297..301].copy_from_slice(b"root");
+    assert_eq!(tar[..512], root[..]);
+
+    let names: Vec<_> = tar::Archive::new(&tar[..]).entries().unwrap()
+        .map(|e| e.unwrap().path().unwrap().display().to_string()).collect();
+    assert_eq!(names, ["./", "./a-c", "./usr/", "./usr/bin/", "./usr/bin/foo", "./usr/l"]);
+}
+
+#[test]
+fn asset_owner_in_header() {
+    let mut tarball = Tarball::new(Vec::new(), 0);
+    let owner = "www-data:33".parse().unwrap();
+    tarball.file_(Path::new("var/www/index.html"), b"hi", 0o640, Some(&owner)).unwrap();
+    tarball.file("var/www/other.html", b"hi", 0o644).unwrap();
+    let tar = tarball.into_inner().unwrap();
+    let mut archive = tar::Archive::new(&tar[..]);
+    let headers: Vec<_> = archive.entries().unwrap().map(|e| e.unwrap().header().clone()).filter(|h| h.entry_type().is_file()).collect();
+    assert_eq!(headers[0].username().unwrap(), Some("www-data"));
+    assert_eq!(headers[0].gid().unwrap(), 33);
+    assert_eq!(headers[1].username().unwrap(), Some(""));
+}
+
+#[test]
+fn package_dir_in_header() {
+    let dir: PackageDir = vec!["/var/lib/myapp/".to_string(), "750".into(), "myuser:myuser".into()].try_into().unwrap();
+    let mut tarball = Tarball::new(Vec::new(), 0);
+    tarball.package_dir(&dir).unwrap();
+    tarball.file("var/lib/myapp/data", b"hi", 0o644).unwrap();
+    let tar = tarball.into_inner().unwrap();
+    let mut archive = tar::Archive::new(&tar[..]);
+    let headers: Vec<_> = archive.entries().unwrap().map(|e| e.unwrap().header().clone()).collect();
+    let paths: Vec<_> = headers.iter().map(|h| h.path().unwrap().into_owned()).collect();
+    assert_eq!(paths, [Path::new("var/"), Path::new("var/lib/"), Path::new("var/lib/myapp/"), Path::new("var/lib/myapp/data")]);
+    assert_eq!(headers[1].mode().unwrap(), 0o755);
+    assert_eq!(headers[2].mode().unwrap(), 0o750);
+    assert_eq!(headers[2].username().unwrap(), Some("myuser"));
+    assert_eq!(headers[2].groupname().unwrap(), Some("myuser"));
+}
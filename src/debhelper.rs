@@ -0,0 +1,86 @@
+//! `--emit-debhelper-shim` writes a minimal `debian/` dir for `dpkg-buildpackage`,
+//! which delegates building and packaging to cargo-deb, so the packaging metadata stays in `Cargo.toml`.
+
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::util::rfc2822_date;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Writes `debian/{control,changelog,rules}` next to `Cargo.toml`. Refuses to overwrite an existing `debian/rules`.
+///
+/// `cargo_deb_flags` are passed to both `cargo deb` invocations in `debian/rules`. Returns the `debian` dir.
+pub(crate) fn write_shim(package_dir: &Path, package_deb: &PackageConfig, cargo_deb_flags: &[String]) -> CDResult<PathBuf> {
+    let debian_dir = package_dir.join("debian");
+    let rules_path = debian_dir.join("rules");
+    if rules_path.exists() {
+        return Err(CargoDebError::IoFile("debian/rules already exists, and won't be overwritten", std::io::ErrorKind::AlreadyExists.into(), rules_path));
+    }
+    fs::create_dir_all(&debian_dir)?;
+    // cargo-deb itself isn't in Debian, so it has to be installed separately
+    fs::write(debian_dir.join("control"), control(package_deb, "debhelper-compat (= 13), cargo", &[]))?;
+    fs::write(debian_dir.join("changelog"), changelog(package_deb))?;
+    fs::write(&rules_path, rules(cargo_deb_flags))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&rules_path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(debian_dir)
+}
+
+/// `relations` are extra fields of the binary package, like `Depends`
+pub(crate) fn control(package_deb: &PackageConfig, build_depends: &str, relations: &[(&str, String)]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Source: {}", package_deb.deb_name);
+    let _ = writeln!(out, "Section: {}", package_deb.section.as_deref().unwrap_or("misc"));
+    let _ = writeln!(out, "Priority: {}", package_deb.priority);
+    let _ = writeln!(out, "Maintainer: {}", package_deb.maintainer);
+    let _ = writeln!(out, "Build-Depends: {build_depends}");
+    let _ = writeln!(out, "Standards-Version: 4.7.0");
+    let _ = writeln!(out, "Rules-Requires-Root: no");
+    if let Some(homepage) = package_deb.homepage.as_deref().or(package_deb.repository.as_deref()) {
+        let _ = writeln!(out, "Homepage: {homepage}");
+    }
+    let _ = writeln!(out, "\nPackage: {}", package_deb.deb_name);
+    let _ = writeln!(out, "Architecture: {}", if package_deb.architecture == "all" { "all" } else { "any" });
+    for (field, value) in relations {
+        let _ = writeln!(out, "{field}: {value}");
+    }
+    let _ = writeln!(out, "Description: {}", package_deb.description);
+    out
+}
+
+/// The version has to match the package made by cargo-deb, otherwise `debian/files` would list a wrong file
+pub(crate) fn changelog(package_deb: &PackageConfig) -> String {
+    format!("{} ({}) unstable; urgency=medium\n\n  * Packaged with cargo-deb.\n\n -- {}  {}\n",
+        package_deb.deb_name, package_deb.deb_version, package_deb.maintainer, rfc2822_date(package_deb.default_timestamp))
+}
+
+fn rules(cargo_deb_flags: &[String]) -> String {
+    format!("#!/usr/bin/make -f
+# Generated by `cargo deb --emit-debhelper-shim`. The package is configured in Cargo.toml.
+# The build step compiles it with cargo-deb, and the binary step packages it with `cargo deb --no-build`.
+
+CARGO_DEB_FLAGS = {}
+
+%:
+\tdh $@
+
+override_dh_auto_configure override_dh_auto_test override_dh_auto_install:
+
+override_dh_auto_build:
+\tcargo deb $(CARGO_DEB_FLAGS) --output=target/debian/
+
+override_dh_builddeb:
+\tcargo deb --no-build $(CARGO_DEB_FLAGS) --output=../
+", cargo_deb_flags.join(" "))
+}
+
+#[test]
+fn rules_file() {
+    let rules = rules(&["--variant=small".into(), "--locked".into()]);
+    assert!(rules.contains("\nCARGO_DEB_FLAGS = --variant=small --locked\n"));
+    assert!(rules.contains("\n\tcargo deb --no-build $(CARGO_DEB_FLAGS) --output=../\n"));
+}
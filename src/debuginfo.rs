@@ -2,8 +2,10 @@ use crate::assets::{Asset, AssetSource, IsBuilt, ProcessedFrom};
 use crate::config::{Config, DebugSymbols, PackageConfig};
 use crate::error::{CDResult, CargoDebError};
 use crate::listener::Listener;
+use crate::util::sandbox;
 use rayon::prelude::*;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::process::{Command, ExitStatus};
 use std::{fs, io};
 
@@ -17,65 +19,113 @@ fn ensure_success(status: ExitStatus) -> io::Result<()> {
 
 /// Strips the binary that was created with cargo
 pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, rust_target_triple: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
-    let mut cargo_config = None;
-    let objcopy_tmp;
-    let strip_tmp;
-    let mut objcopy_cmd = Path::new("objcopy");
-    let mut strip_cmd = Path::new("strip");
+    let mut stripper = Stripper::new(config.deb_temp_dir(package_deb).join("data"), config.debug_symbols);
+    stripper.lib_dir_base = package_deb.library_install_dir(config.rust_target_triple()).into_owned();
 
     if let Some(rust_target_triple) = rust_target_triple {
-        cargo_config = config.cargo_config()?;
-        if let Some(ref conf) = cargo_config {
+        if let Some(conf) = config.cargo_config()? {
             if let Some(cmd) = conf.objcopy_command(rust_target_triple) {
                 listener.info(format!("Using '{}' for '{rust_target_triple}'", cmd.display()));
-                objcopy_tmp = cmd;
-                objcopy_cmd = &objcopy_tmp;
+                stripper.objcopy_command = cmd.into_owned();
             }
 
             if let Some(cmd) = conf.strip_command(rust_target_triple) {
                 listener.info(format!("Using '{}' for '{rust_target_triple}'", cmd.display()));
-                strip_tmp = cmd;
-                strip_cmd = &strip_tmp;
+                stripper.strip_command = cmd.into_owned();
             }
+            stripper.cargo_config_hint = Some((rust_target_triple.into(), conf.path().to_owned()));
+        } else {
+            stripper.cargo_config_hint = Some((rust_target_triple.into(), ".cargo/config".into()));
         }
     }
 
-    let stripped_binaries_output_dir = config.default_deb_output_dir();
-    let (separate_debug_symbols, compress_debug_symbols) = match config.debug_symbols {
-        DebugSymbols::Keep | DebugSymbols::Strip => (false, false),
-        DebugSymbols::Separate { compress } => (true, compress),
-    };
+    let added_debug_assets = stripper.strip_assets(package_deb.built_binaries_mut(), listener)?;
+    package_deb.assets.resolved.extend(added_debug_assets);
+    Ok(())
+}
+
+/// Strips executables and libraries, and optionally extracts their debug symbols into separate files.
+///
+/// This doesn't need a Cargo project, so it can be used by other packaging tools.
+pub struct Stripper {
+    /// `strip` by default
+    pub strip_command: PathBuf,
+    /// `objcopy` by default
+    pub objcopy_command: PathBuf,
+    /// Stripped binaries and debug symbols are written there, in subdirectories of their install paths
+    pub output_dir: PathBuf,
+    pub debug_symbols: DebugSymbols,
+    /// Separate debug symbols are installed in `debug/` subdirectory of this dir, e.g. `usr/lib`
+    pub lib_dir_base: PathBuf,
+    /// Target and cargo config path for error messages
+    cargo_config_hint: Option<(String, PathBuf)>,
+}
+
+impl Stripper {
+    #[must_use]
+    pub fn new(output_dir: PathBuf, debug_symbols: DebugSymbols) -> Self {
+        Self {
+            strip_command: "strip".into(),
+            objcopy_command: "objcopy".into(),
+            output_dir,
+            debug_symbols,
+            lib_dir_base: "usr/lib".into(),
+            cargo_config_hint: None,
+        }
+    }
+
+    /// Replaces sources of the assets with stripped copies. Assets should be executables or libraries.
+    ///
+    /// Returns new assets for separate debug symbols, if enabled. Reports progress to the listener.
+    pub fn strip_assets<'a>(&self, assets: impl IntoIterator<Item = &'a mut Asset>, listener: &dyn Listener) -> CDResult<Vec<Asset>> {
+        let assets = assets.into_iter()
+            .filter(|asset| !asset.source.archive_as_symlink_only()) // data won't be included, so nothing to strip
+            .collect::<Vec<_>>();
+        let total = assets.len();
+        let done = AtomicUsize::new(0);
+
+        let added_debug_assets = assets.into_par_iter().map(|asset| {
+            let new_debug_asset = self.strip_asset(asset, listener)?;
+            listener.progress("Stripping", done.fetch_add(1, Ordering::Relaxed) + 1, total);
+            Ok(new_debug_asset)
+        }).collect::<CDResult<Vec<_>>>()?;
+        Ok(added_debug_assets.into_iter().flatten().collect())
+    }
+
+    fn tool_error(&self, tool: &Path, tool_key: &str, path: &Path, err: io::Error) -> CargoDebError {
+        if let Some((target, conf_path)) = &self.cargo_config_hint {
+            CargoDebError::StripFailed(path.to_owned(), format!("{}: {}.\nhint: Target-specific strip commands are configured in [target.{}] {} = {{ path = \"{}\" }} in {}", tool.display(), err, target, tool_key, tool.display(), conf_path.display()))
+        } else {
+            CargoDebError::CommandFailed(err, if tool_key == "strip" { "strip" } else { "objcopy" })
+        }
+    }
+
+    fn strip_asset(&self, asset: &mut Asset, listener: &dyn Listener) -> CDResult<Option<Asset>> {
+        let (separate_debug_symbols, compress_debug_symbols) = match self.debug_symbols {
+            DebugSymbols::Keep | DebugSymbols::Strip => (false, false),
+            DebugSymbols::Separate { compress } => (true, compress),
+        };
+        let strip_cmd = &self.strip_command;
+        let objcopy_cmd = &self.objcopy_command;
 
-    let lib_dir_base = package_deb.library_install_dir(config.rust_target_triple());
-    let added_debug_assets = package_deb.built_binaries_mut().into_par_iter().enumerate()
-        .filter(|(_, asset)| !asset.source.archive_as_symlink_only()) // data won't be included, so nothing to strip
-        .map(|(i, asset)| {
         let (new_source, new_debug_asset) = if let Some(path) = asset.source.path() {
             if !path.exists() {
                 return Err(CargoDebError::StripFailed(path.to_owned(), "The file doesn't exist".into()));
             }
 
-            let conf_path = cargo_config.as_ref().map(|c| c.path())
-                .unwrap_or_else(|| Path::new(".cargo/config"));
-            let file_name = path.file_stem().ok_or(CargoDebError::Str("bad path"))?.to_string_lossy();
-            let stripped_temp_path = stripped_binaries_output_dir.join(format!("{file_name}.tmp{i}-stripped"));
+            let stripped_temp_path = self.output_dir.join(&asset.c.target_path);
             let _ = fs::remove_file(&stripped_temp_path);
+            create_parent_dir(&stripped_temp_path)?;
 
             log::debug!("stripping with {} from {} into {}", strip_cmd.display(), path.display(), stripped_temp_path.display());
-            Command::new(strip_cmd)
+            sandbox::apply(&mut Command::new(strip_cmd))?
                // same as dh_strip
                .args(["--strip-unneeded", "--remove-section=.comment", "--remove-section=.note"])
                .arg("-o").arg(&stripped_temp_path)
                .arg(path)
                .status()
                .and_then(ensure_success)
-               .map_err(|err| {
-                    if let Some(target) = rust_target_triple {
-                        CargoDebError::StripFailed(path.to_owned(), format!("{}: {}.\nhint: Target-specific strip commands are configured in [target.{}] strip = {{ path = \"{}\" }} in {}", strip_cmd.display(), err, target, strip_cmd.display(), conf_path.display()))
-                    } else {
-                        CargoDebError::CommandFailed(err, "strip")
-                    }
-                })?;
+               .map_err(|err| self.tool_error(strip_cmd, "strip", path, err))?;
 
             if !stripped_temp_path.exists() {
                 return Err(CargoDebError::StripFailed(path.to_owned(), format!("{} command failed to create output '{}'", strip_cmd.display(), stripped_temp_path.display())));
@@ -85,34 +135,29 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, rust
                 log::debug!("extracting debug info with {} from {}", objcopy_cmd.display(), path.display());
 
                 // parse the ELF and use debug-id-based path if available
-                let debug_target_path = get_target_debug_path(asset, path, &lib_dir_base)?;
+                let debug_target_path = get_target_debug_path(asset, path, &self.lib_dir_base)?;
 
                 // --add-gnu-debuglink reads the file path given, so it can't get to-be-installed target path
                 // and the recommended fallback solution is to give it relative path in the same dir
-                let debug_temp_path = stripped_temp_path.with_file_name(debug_target_path.file_name().ok_or(CargoDebError::Str("bad path"))?);
+                let debug_temp_path = self.output_dir.join(&debug_target_path);
 
                 let _ = fs::remove_file(&debug_temp_path);
+                create_parent_dir(&debug_temp_path)?;
                 let mut args: &[_] = &["--only-keep-debug", "--compress-debug-sections=zstd"];
                 if !compress_debug_symbols {
                     args = &args[..1];
                 }
-                Command::new(objcopy_cmd)
+                sandbox::apply(&mut Command::new(objcopy_cmd))?
                     .args(args)
                     .arg(path)
                     .arg(&debug_temp_path)
                     .status()
                     .and_then(ensure_success)
-                    .map_err(|err| {
-                        if let Some(target) = rust_target_triple {
-                            CargoDebError::StripFailed(path.to_owned(), format!("{}: {}.\nhint: Target-specific strip commands are configured in [target.{}] objcopy = {{ path =\"{}\" }} in {}", objcopy_cmd.display(), err, target, objcopy_cmd.display(), conf_path.display()))
-                        } else {
-                            CargoDebError::CommandFailed(err, "objcopy")
-                        }
-                    })?;
+                    .map_err(|err| self.tool_error(objcopy_cmd, "objcopy", path, err))?;
 
                 let relative_debug_temp_path = debug_temp_path.file_name().ok_or(CargoDebError::Str("bad path"))?;
                 log::debug!("linking debug info with {} from {} into {:?}", objcopy_cmd.display(), stripped_temp_path.display(), relative_debug_temp_path);
-                Command::new(objcopy_cmd)
+                sandbox::apply(&mut Command::new(objcopy_cmd))?
                     .current_dir(debug_temp_path.parent().ok_or(CargoDebError::Str("bad path"))?)
                     .arg("--add-gnu-debuglink")
                     // intentionally relative - the file name must match debug_target_path
@@ -146,13 +191,13 @@ pub fn strip_binaries(config: &mut Config, package_deb: &mut PackageConfig, rust
             original_path: old_source.into_path(),
             action: "strip",
         });
-        Ok::<_, CargoDebError>(new_debug_asset)
-    }).collect::<Result<Vec<_>, _>>()?;
-
-    package_deb.assets.resolved
-        .extend(added_debug_assets.into_iter().flatten());
+        Ok(new_debug_asset)
+    }
+}
 
-    Ok(())
+fn create_parent_dir(path: &Path) -> CDResult<()> {
+    let dir = path.parent().ok_or(CargoDebError::Str("bad path"))?;
+    fs::create_dir_all(dir).map_err(|e| CargoDebError::IoFile("unable to create dir", e, dir.to_owned()))
 }
 
 fn get_target_debug_path(asset: &Asset, asset_path: &Path, lib_dir_base: &Path) -> Result<PathBuf, CargoDebError> {
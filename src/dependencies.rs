@@ -1,11 +1,23 @@
 use crate::error::{CDResult, CargoDebError};
-use std::path::Path;
+use crate::listener::Listener;
+use crate::util::sandbox;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 const DPKG_SHLIBDEPS_COMMAND: &str = "dpkg-shlibdeps";
 
+/// Result of dpkg-shlibdeps for one binary
+pub(crate) struct ShlibDeps {
+    /// Entries for `Depends`
+    pub depends: Vec<String>,
+    /// `DT_NEEDED` libraries, and paths where dpkg-shlibdeps found them. Only collected for `--explain-deps`.
+    pub libraries: Vec<(String, Vec<PathBuf>)>,
+}
+
 /// Resolves the dependencies based on the output of dpkg-shlibdeps on the binary.
-pub(crate) fn resolve_with_dpkg(path: &Path, mut lib_dir_search_path: Option<&Path>) -> CDResult<Vec<String>> {
+pub(crate) fn resolve_with_dpkg(path: &Path, mut lib_dir_search_path: Option<&Path>, find_libraries: bool) -> CDResult<ShlibDeps> {
     let temp_folder = tempfile::tempdir()?;
     let debian_folder = temp_folder.path().join("debian");
     let control_file_path = debian_folder.join("control");
@@ -15,8 +27,13 @@ pub(crate) fn resolve_with_dpkg(path: &Path, mut lib_dir_search_path: Option<&Pa
     let _ = std::fs::File::create(control_file_path);
 
     let mut cmd = Command::new(DPKG_SHLIBDEPS_COMMAND);
-    // Print result to stdout instead of a file.
+    sandbox::apply(&mut cmd)?;
+    // Print result to stdout instead of a file
     cmd.arg("-O");
+    if find_libraries {
+        // Verbose output has paths of the libraries
+        cmd.arg("-v");
+    }
     // determine library search path from target
     if let Some(dir) = lib_dir_search_path {
         if dir.is_dir() {
@@ -32,7 +49,10 @@ pub(crate) fn resolve_with_dpkg(path: &Path, mut lib_dir_search_path: Option<&Pa
         .output()
         .map_err(|e| CargoDebError::CommandFailed(e, DPKG_SHLIBDEPS_COMMAND))?;
     if !output.status.success() {
-        use std::fmt::Write;
+        let unpackaged = unpackaged_libraries(&String::from_utf8_lossy(&output.stderr));
+        if !unpackaged.is_empty() {
+            return Err(CargoDebError::UnpackagedLibraries(path.to_owned(), unpackaged.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")));
+        }
         let mut args = String::new();
         if let Some(lib_dir_search_path) = lib_dir_search_path {
             let _ = write!(&mut args, "-l {} ", lib_dir_search_path.display());
@@ -47,7 +67,7 @@ pub(crate) fn resolve_with_dpkg(path: &Path, mut lib_dir_search_path: Option<&Pa
 
     log::debug!("dpkg-shlibdeps for {}: {}", path.display(), String::from_utf8_lossy(&output.stdout));
 
-    let deps = output.stdout.as_slice().split(|&c| c == b'\n')
+    let depends = output.stdout.as_slice().split(|&c| c == b'\n')
         .find_map(|line| line.strip_prefix(b"shlibs:Depends="))
         .ok_or(CargoDebError::Str("Failed to find dependency specification."))?
         .split(|&c| c == b',')
@@ -58,14 +78,261 @@ pub(crate) fn resolve_with_dpkg(path: &Path, mut lib_dir_search_path: Option<&Pa
         .map(|dep| dep.to_string())
         .collect();
 
-    Ok(deps)
+    let mut libraries: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    // with -O the debug messages are mixed with the result in stdout
+    for line in String::from_utf8_lossy(&output.stdout).lines().chain(String::from_utf8_lossy(&output.stderr).lines()) {
+        let Some((soname, lib_path)) = line.strip_prefix("dpkg-shlibdeps: debug: Library ").and_then(|l| l.split_once(" found in ")) else { continue };
+        match libraries.iter_mut().find(|(name, _)| name == soname) {
+            Some((_, paths)) => paths.push(lib_path.into()),
+            None => libraries.push((soname.into(), vec![lib_path.into()])),
+        }
+    }
+
+    Ok(ShlibDeps { depends, libraries })
+}
+
+/// Libraries outside of system lib dirs, e.g. in `/usr/local/lib` or a home dir, that dpkg-shlibdeps
+/// reported as having no dependency information
+fn unpackaged_libraries(dpkg_shlibdeps_stderr: &str) -> Vec<PathBuf> {
+    let mut libs = Vec::new();
+    for line in dpkg_shlibdeps_stderr.lines() {
+        let Some((_, rest)) = line.split_once("no dependency information found for ") else { continue };
+        let Some((lib_path, _)) = rest.split_once(" (used by ") else { continue };
+        // /lib64, /usr/lib/x86_64-linux-gnu, etc.
+        if !lib_path.starts_with("/lib") && !lib_path.starts_with("/usr/lib") && !libs.iter().any(|l| l == Path::new(lib_path)) {
+            libs.push(PathBuf::from(lib_path));
+        }
+    }
+    libs
+}
+
+/// Libraries that aren't in a package named after their soname, e.g. glibc has many
+const SONAME_PACKAGES: &[(&str, &str)] = &[
+    ("libc.so.6", "libc6"),
+    ("libm.so.6", "libc6"),
+    ("libdl.so.2", "libc6"),
+    ("libpthread.so.0", "libc6"),
+    ("librt.so.1", "libc6"),
+    ("libutil.so.1", "libc6"),
+    ("ld-linux.so.2", "libc6"),
+    ("ld-linux-x86-64.so.2", "libc6"),
+    ("ld-linux-aarch64.so.1", "libc6"),
+    ("ld-linux-armhf.so.3", "libc6"),
+    ("libgcc_s.so.1", "libgcc-s1"),
+    ("libstdc++.so.6", "libstdc++6"),
+    ("libcrypto.so.3", "libssl3"),
+    ("libcrypto.so.1.1", "libssl1.1"),
+    ("libz.so.1", "zlib1g"),
+    ("libbz2.so.1.0", "libbz2-1.0"),
+    ("libcurl.so.4", "libcurl4"),
+    ("libglib-2.0.so.0", "libglib2.0-0"),
+    ("libgobject-2.0.so.0", "libglib2.0-0"),
+    ("libgio-2.0.so.0", "libglib2.0-0"),
+    ("libX11.so.6", "libx11-6"),
+];
+
+/// Resolves the dependencies without dpkg, from `DT_NEEDED` entries of the ELF file.
+/// Sonames are mapped to packages using `soname_packages`, the built-in table, or Debian's library naming convention.
+/// The minimum versions are unknown, except glibc's, which is known from the versioned symbols the binary uses.
+pub(crate) fn resolve_from_elf(path: &Path, soname_packages: &BTreeMap<String, String>, listener: &dyn Listener) -> CDResult<ShlibDeps> {
+    let needed = needed_libraries(path).map_err(|e| CargoDebError::ElfParse(e.to_string(), path.to_owned()))?;
+    let mut depends = Vec::new();
+    for soname in &needed.sonames {
+        let Some(package) = package_for_soname(soname, soname_packages) else {
+            listener.warning(format!("{} needs {soname}, but its package is unknown. Add it to soname-packages", path.display()));
+            continue;
+        };
+        // libgcc guaranteed by LSB to always be present
+        if !package.starts_with("libgcc-") && !depends.contains(&package) {
+            depends.push(package);
+        }
+    }
+    if let Some(glibc_version) = &needed.glibc_version {
+        for dep in depends.iter_mut().filter(|dep| *dep == "libc6") {
+            *dep = format!("libc6 (>= {glibc_version})");
+        }
+    }
+    Ok(ShlibDeps {
+        depends,
+        libraries: needed.sonames.into_iter().map(|soname| (soname, Vec::new())).collect(),
+    })
+}
+
+struct NeededLibraries {
+    sonames: Vec<String>,
+    /// The newest `GLIBC_2.x` symbol version used, without the prefix
+    glibc_version: Option<String>,
+}
+
+fn needed_libraries(path: &Path) -> Result<NeededLibraries, elf::ParseError> {
+    use elf::endian::AnyEndian;
+    use elf::gnu_symver::VerNeedIterator;
+    use elf::ElfBytes;
+
+    let data = std::fs::read(path)?;
+    let file = ElfBytes::<AnyEndian>::minimal_parse(&data)?;
+    let common = file.find_common_data()?;
+    let (Some(dynamic), Some(strings)) = (common.dynamic, common.dynsyms_strs) else {
+        return Ok(NeededLibraries { sonames: Vec::new(), glibc_version: None }); // statically linked
+    };
+    let sonames = dynamic.iter()
+        .filter(|d| d.d_tag == elf::abi::DT_NEEDED)
+        .map(|d| Ok(strings.get(d.d_val() as usize)?.to_owned()))
+        .collect::<Result<_, elf::ParseError>>()?;
+
+    // Versions required from each library are in the `.gnu.version_r` section
+    let mut glibc_version = None;
+    let verneed = file.section_headers().and_then(|shdrs| {
+        let shdr = shdrs.iter().find(|s| s.sh_type == elf::abi::SHT_GNU_VERNEED)?;
+        Some((shdr, shdrs.get(shdr.sh_link as usize).ok()?))
+    });
+    if let Some((shdr, strs_shdr)) = verneed {
+        let (needs, _) = file.section_data(&shdr)?;
+        let strings = file.section_data_as_strtab(&strs_shdr)?;
+        for (_, versions) in VerNeedIterator::new(file.ehdr.endianness, file.ehdr.class, shdr.sh_info.into(), 0, needs) {
+            for version in versions {
+                let Some(version) = strings.get(version.vna_name as usize)?.strip_prefix("GLIBC_") else { continue };
+                // there's also GLIBC_PRIVATE, and GLIBC_ABI_DT_RELR that isn't a release
+                if version.starts_with(|c: char| c.is_ascii_digit()) && glibc_version.as_deref().map_or(true, |v| compare_glibc_versions(version, v).is_gt()) {
+                    glibc_version = Some(version.to_owned());
+                }
+            }
+        }
+    }
+    Ok(NeededLibraries { sonames, glibc_version })
+}
+
+/// `2.2.5` < `2.17`
+fn compare_glibc_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parse = |v: &str| v.split('.').map(|n| n.parse::<u32>().unwrap_or(0)).collect::<Vec<_>>();
+    parse(a).cmp(&parse(b))
+}
+
+/// `libfoo.so.1` is in `libfoo1`, and `libfoo-2.0.so.3` in `libfoo-2.0-3`
+fn package_for_soname(soname: &str, soname_packages: &BTreeMap<String, String>) -> Option<String> {
+    if let Some(package) = soname_packages.get(soname) {
+        return Some(package.clone());
+    }
+    if let Some(&(_, package)) = SONAME_PACKAGES.iter().find(|&&(s, _)| s == soname) {
+        return Some(package.into());
+    }
+    let (name, version) = soname.split_once(".so.")?;
+    if !name.starts_with("lib") || version.is_empty() {
+        return None;
+    }
+    let name = name.to_ascii_lowercase().replace('_', "-");
+    let separator = if name.ends_with(|c: char| c.is_ascii_digit()) { "-" } else { "" };
+    Some(format!("{name}{separator}{version}"))
+}
+
+/// For `--explain-deps`. Lists which libraries of which binaries caused each dependency.
+/// `explicit` are the dependencies from the `depends` setting.
+pub(crate) fn explain(binaries: &[(&Path, &ShlibDeps)], explicit: &[String]) -> String {
+    let mut out = String::new();
+    for dep in explicit {
+        let _ = writeln!(out, "{dep}\n  from `depends` in [package.metadata.deb]");
+    }
+    for &(binary, shlibs) in binaries {
+        let _ = writeln!(out, "/{}:", binary.display());
+        let libraries = shlibs.libraries.iter().map(|(soname, paths)| {
+            let owner = paths.iter().find_map(|p| dpkg_owner(p));
+            (soname, paths.first(), owner)
+        }).collect::<Vec<_>>();
+        for dep in &shlibs.depends {
+            let name = dep.split([' ', '(', ':']).next().unwrap_or(dep);
+            let _ = writeln!(out, "  {dep}");
+            let mut found = false;
+            for (soname, path, _) in libraries.iter().filter(|(_, _, owner)| owner.as_deref() == Some(name)) {
+                found = true;
+                let _ = writeln!(out, "    needs {soname} ({}, from package {name})", path.map(|p| p.display().to_string()).unwrap_or_default());
+            }
+            if !found {
+                let _ = writeln!(out, "    (dpkg-shlibdeps didn't say which library needs it)");
+            }
+        }
+        for (soname, path, owner) in &libraries {
+            let path = path.map(|p| p.display().to_string()).unwrap_or_default();
+            match owner {
+                Some(owner) if shlibs.depends.iter().any(|d| d.split([' ', '(', ':']).next() == Some(owner.as_str())) => {},
+                Some(owner) => { let _ = writeln!(out, "  {soname} ({path}) is from package {owner}, which is omitted from Depends"); },
+                None => { let _ = writeln!(out, "  {soname} ({path}) isn't from any installed package"); },
+            }
+        }
+    }
+    out
+}
+
+/// Name of the installed package that has the file, without the `:arch` suffix
+fn dpkg_owner(path: &Path) -> Option<String> {
+    // the dpkg database may have the path before /usr merge
+    let canonical = path.canonicalize().ok();
+    let found = [Some(path), canonical.as_deref()].into_iter().flatten().find_map(|path| {
+        let output = sandbox::apply(&mut Command::new("dpkg-query")).ok()?
+            .arg("-S").arg(path)
+            .output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        let packages = stdout.lines().next()?.split_once(": ")?.0;
+        let first = packages.split(',').next()?.trim();
+        Some(first.split(':').next().unwrap_or(first).to_owned())
+    });
+    found
 }
 
 #[test]
 #[cfg(target_os = "linux")]
 fn resolve_test() {
     let exe = std::env::current_exe().unwrap();
-    let deps = resolve_with_dpkg(&exe, None).unwrap();
-    assert!(deps.iter().any(|d| d.starts_with("libc")));
-    assert!(!deps.iter().any(|d| d.starts_with("libgcc")), "{deps:?}");
+    let deps = resolve_with_dpkg(&exe, None, true).unwrap();
+    assert!(deps.depends.iter().any(|d| d.starts_with("libc")));
+    assert!(!deps.depends.iter().any(|d| d.starts_with("libgcc")), "{:?}", deps.depends);
+    assert!(deps.libraries.iter().any(|(soname, paths)| soname.starts_with("libc.so") && !paths.is_empty()));
+
+    let explained = explain(&[(Path::new("usr/bin/test"), &deps)], &["foo".into()]);
+    assert!(explained.starts_with("foo\n  from `depends`"));
+    assert!(explained.contains("\n/usr/bin/test:\n  libc6 (>= "), "{explained}");
+}
+
+#[test]
+fn unpackaged_libraries_in_errors() {
+    let stderr = "dpkg-shlibdeps: error: no dependency information found for /usr/local/lib/libfoo.so.1 (used by target/release/app)
+Hint: check if the library actually comes from a package.
+dpkg-shlibdeps: error: no dependency information found for /usr/lib/x86_64-linux-gnu/libbar.so.2 (used by target/release/app)
+dpkg-shlibdeps: error: no dependency information found for /home/me/src/baz/lib/libbaz.so (used by target/release/app)
+dpkg-shlibdeps: error: no dependency information found for /usr/local/lib/libfoo.so.1 (used by target/release/app)
+dpkg-shlibdeps: error: cannot continue due to the errors listed above
+";
+    assert_eq!(unpackaged_libraries(stderr), [Path::new("/usr/local/lib/libfoo.so.1"), Path::new("/home/me/src/baz/lib/libbaz.so")]);
+    assert!(unpackaged_libraries("dpkg-shlibdeps: error: cannot continue due to the errors listed above").is_empty());
+}
+
+#[test]
+fn soname_package_names() {
+    let custom = [("libfoo.so.2".to_string(), "foo-runtime".to_string())].into_iter().collect();
+    assert_eq!(package_for_soname("libfoo.so.2", &custom).as_deref(), Some("foo-runtime"));
+    assert_eq!(package_for_soname("libm.so.6", &custom).as_deref(), Some("libc6"));
+    assert_eq!(package_for_soname("libssl.so.3", &custom).as_deref(), Some("libssl3"));
+    assert_eq!(package_for_soname("libdbus-1.so.3", &custom).as_deref(), Some("libdbus-1-3"));
+    assert_eq!(package_for_soname("libSDL2-2.0.so.0", &custom).as_deref(), Some("libsdl2-2.0-0"));
+    assert_eq!(package_for_soname("libpcre2_8.so.0", &custom).as_deref(), Some("libpcre2-8-0"));
+    assert_eq!(package_for_soname("libplugin.so", &custom), None);
+}
+
+#[test]
+fn glibc_version_order() {
+    assert!(compare_glibc_versions("2.2.5", "2.17").is_lt());
+    assert!(compare_glibc_versions("2.34", "2.4").is_gt());
+    assert!(compare_glibc_versions("2.3", "2.3.4").is_lt());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn resolve_from_elf_test() {
+    let exe = std::env::current_exe().unwrap();
+    let deps = resolve_from_elf(&exe, &BTreeMap::new(), &crate::listener::NoOpListener).unwrap();
+    assert!(deps.depends.iter().any(|d| d.starts_with("libc6 (>= 2.")), "{:?}", deps.depends);
+    assert!(!deps.depends.iter().any(|d| d.starts_with("libgcc")), "{:?}", deps.depends);
+    assert!(deps.libraries.iter().any(|(soname, _)| soname == "libc.so.6"));
 }
@@ -0,0 +1,221 @@
+//! Checks for desktop entries and [AppStream metainfo](https://www.freedesktop.org/software/appstream/docs/)
+//! files, which are needed for GUI apps to show up in GNOME Software or KDE Discover.
+
+use crate::assets::Asset;
+use crate::config::PackageConfig;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::path::{Path, PathBuf};
+
+pub(crate) const METAINFO_DIR: &str = "usr/share/metainfo";
+pub(crate) const APPLICATIONS_DIR: &str = "usr/share/applications";
+
+/// The few fields of a metainfo file that are worth checking
+#[derive(Debug, Default)]
+pub(crate) struct MetaInfo<'a> {
+    pub id: Option<&'a str>,
+    pub metadata_license: Option<&'a str>,
+    pub has_description: bool,
+    pub launchables: Vec<&'a str>,
+}
+
+impl<'a> MetaInfo<'a> {
+    /// This is not a real XML parser. It only finds the few top-level tags of the metainfo format.
+    pub fn parse(xml: &'a str) -> CDResult<Self> {
+        if find_tag(xml, "component").is_none() {
+            return Err(CargoDebError::Str("metainfo file doesn't have a <component> tag"));
+        }
+        let mut launchables = Vec::new();
+        let mut rest = xml;
+        while let Some((attrs, text, after)) = find_tag(rest, "launchable") {
+            if attrs.contains("desktop-id") {
+                launchables.push(text);
+            }
+            rest = after;
+        }
+        Ok(Self {
+            id: find_tag(xml, "id").map(|(_, id, _)| id).filter(|id| !id.is_empty()),
+            metadata_license: find_tag(xml, "metadata_license").map(|(_, l, _)| l).filter(|l| !l.is_empty()),
+            has_description: find_tag(xml, "description").is_some_and(|(_, d, _)| !d.is_empty()),
+            launchables,
+        })
+    }
+
+    /// Whether this metainfo describes the given `*.desktop` file
+    pub fn describes_desktop_file(&self, desktop_file_name: &str) -> bool {
+        let desktop_id = desktop_file_name.strip_suffix(".desktop").unwrap_or(desktop_file_name);
+        self.launchables.contains(&desktop_file_name) ||
+            self.id.is_some_and(|id| id == desktop_file_name || id == desktop_id)
+    }
+}
+
+/// Returns (attributes, trimmed text content, rest of input after the closing tag)
+fn find_tag<'a>(xml: &'a str, tag: &str) -> Option<(&'a str, &'a str, &'a str)> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut rest = xml;
+    loop {
+        let start = rest.find(&open)? + open.len();
+        rest = &rest[start..];
+        // don't match <idfoo> when looking for <id>
+        if rest.starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace()) {
+            break;
+        }
+    }
+    let tag_end = rest.find('>')?;
+    let attrs = rest[..tag_end].trim();
+    if let Some(attrs) = attrs.strip_suffix('/') {
+        return Some((attrs.trim(), "", &rest[tag_end + 1..]));
+    }
+    let rest = &rest[tag_end + 1..];
+    let content_end = rest.find(&close)?;
+    Some((attrs, rest[..content_end].trim(), &rest[content_end + close.len()..]))
+}
+
+/// Where the metainfo file should be installed, based on its component id
+pub(crate) fn metainfo_target_path(source_path: &Path) -> CDResult<PathBuf> {
+    let xml = std::fs::read_to_string(source_path)
+        .map_err(|e| CargoDebError::IoFile("unable to read metainfo file", e, source_path.to_owned()))?;
+    let info = MetaInfo::parse(&xml)?;
+    let id = info.id.ok_or(CargoDebError::Str("metainfo file must have an <id> tag"))?;
+    Ok(Path::new(METAINFO_DIR).join(format!("{id}.metainfo.xml")))
+}
+
+fn is_metainfo_path(path: &Path) -> bool {
+    path.starts_with(METAINFO_DIR) && path.to_str().is_some_and(|p| p.ends_with(".metainfo.xml") || p.ends_with(".appdata.xml"))
+}
+
+/// Validates metainfo assets, and warns about desktop files that don't have one
+pub(crate) fn check_desktop_assets(assets: &[Asset], listener: &dyn Listener) -> CDResult<()> {
+    let metainfo_assets = assets.iter().filter(|a| is_metainfo_path(&a.c.target_path))
+        .map(|a| Ok((a, String::from_utf8(a.source.data()?.into_owned())?)))
+        .collect::<CDResult<Vec<_>>>()?;
+
+    let mut metainfos = Vec::with_capacity(metainfo_assets.len());
+    for (asset, xml) in &metainfo_assets {
+        let info = MetaInfo::parse(xml)?;
+        let path = asset.c.target_path.display();
+        if info.id.is_none() {
+            listener.warning(format!("/{path} is missing an <id> tag"));
+        }
+        if info.metadata_license.is_none() {
+            listener.warning(format!("/{path} is missing a <metadata_license> tag"));
+        }
+        if !info.has_description {
+            listener.warning(format!("/{path} is missing a <description>"));
+        }
+        metainfos.push(info);
+    }
+
+    for asset in assets.iter().filter(|a| a.c.target_path.starts_with(APPLICATIONS_DIR)) {
+        let Some(desktop_file_name) = asset.c.target_path.file_name().and_then(|f| f.to_str()) else { continue };
+        if !desktop_file_name.ends_with(".desktop") {
+            continue;
+        }
+        if !metainfos.iter().any(|m| m.describes_desktop_file(desktop_file_name)) {
+            listener.warning(format!("{desktop_file_name} doesn't have a corresponding AppStream metainfo file in /{METAINFO_DIR}/.\n\
+                Set `metainfo` in [package.metadata.deb] to make the app visible in software centers."));
+        }
+    }
+    Ok(())
+}
+
+/// URL schemes from `MimeType=x-scheme-handler/<scheme>` of the `[Desktop Entry]` group
+fn desktop_entry_url_schemes(desktop_file: &str) -> impl Iterator<Item = &str> {
+    let mut in_desktop_entry = false;
+    desktop_file.lines().filter_map(move |line| {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_desktop_entry = line == "[Desktop Entry]";
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        (in_desktop_entry && key.trim_end() == "MimeType").then_some(value)
+    })
+    .flat_map(|value| value.split(';'))
+    .filter_map(|mime| mime.trim().strip_prefix("x-scheme-handler/"))
+    .filter(|scheme| !scheme.is_empty())
+}
+
+/// URL schemes handled by the desktop files in the package. `update-desktop-database` needs to run for these to work.
+pub(crate) fn url_scheme_handlers(assets: &[Asset]) -> CDResult<Vec<String>> {
+    let mut schemes = Vec::new();
+    for asset in assets.iter().filter(|a| a.c.target_path.starts_with(APPLICATIONS_DIR) && a.c.target_path.extension().is_some_and(|e| e == "desktop")) {
+        let data = asset.source.data()?;
+        schemes.extend(desktop_entry_url_schemes(&String::from_utf8_lossy(&data)).map(String::from));
+    }
+    schemes.sort();
+    schemes.dedup();
+    Ok(schemes)
+}
+
+/// Desktop files that handle URLs are opened via `xdg-open` or the desktop portal, so one of them should be installed.
+pub(crate) fn add_url_handler_dependency(package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    let schemes = url_scheme_handlers(&package_deb.assets.resolved)?;
+    if !schemes.is_empty() && package_deb.add_depends("xdg-utils | xdg-desktop-portal", listener) {
+        listener.info(format!("Adding 'xdg-utils | xdg-desktop-portal' to Depends, because desktop files handle {} URLs", schemes.join(", ")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<component type="desktop-application">
+  <id>org.example.App</id>
+  <metadata_license>CC0-1.0</metadata_license>
+  <project_license>MIT</project_license>
+  <name>App</name>
+  <description>
+    <p>Does things</p>
+  </description>
+  <launchable type="desktop-id">example-app.desktop</launchable>
+</component>"#;
+
+    #[test]
+    fn parse_metainfo() {
+        let info = MetaInfo::parse(EXAMPLE).unwrap();
+        assert_eq!(info.id, Some("org.example.App"));
+        assert_eq!(info.metadata_license, Some("CC0-1.0"));
+        assert!(info.has_description);
+        assert!(info.describes_desktop_file("example-app.desktop"));
+        assert!(info.describes_desktop_file("org.example.App.desktop"));
+        assert!(!info.describes_desktop_file("other.desktop"));
+    }
+
+    #[test]
+    fn parse_incomplete_metainfo() {
+        let info = MetaInfo::parse("<component><idx>nope</idx><description/></component>").unwrap();
+        assert_eq!(info.id, None);
+        assert_eq!(info.metadata_license, None);
+        assert!(!info.has_description);
+        assert!(MetaInfo::parse("<application></application>").is_err());
+    }
+
+    #[test]
+    fn url_schemes_of_desktop_entries() {
+        use crate::assets::{AssetSource, IsBuilt};
+
+        let desktop = "[Desktop Entry]\nName=App\nMimeType=text/html;x-scheme-handler/https;x-scheme-handler/myapp;\n\n[Desktop Action new]\nMimeType=x-scheme-handler/other\n";
+        assert_eq!(desktop_entry_url_schemes(desktop).collect::<Vec<_>>(), ["https", "myapp"]);
+
+        let assets = [
+            Asset::new(AssetSource::Data(desktop.into()), "usr/share/applications/app.desktop".into(), 0o644, IsBuilt::No, false),
+            Asset::new(AssetSource::Data(desktop.into()), "usr/share/doc/app/app.desktop".into(), 0o644, IsBuilt::No, false),
+            Asset::new(AssetSource::Data(b"[Desktop Entry]\nMimeType = x-scheme-handler/myapp\n".to_vec()), "usr/share/applications/b.desktop".into(), 0o644, IsBuilt::No, false),
+        ];
+        assert_eq!(url_scheme_handlers(&assets).unwrap(), ["https", "myapp"]);
+
+        let mut package_deb = crate::config::tests::example_package_config(Default::default(), Default::default()).unwrap();
+        package_deb.assets.resolved = assets.into();
+        package_deb.resolved_depends = Some("libc6".into());
+        add_url_handler_dependency(&mut package_deb, &crate::listener::NoOpListener).unwrap();
+        assert_eq!(package_deb.resolved_depends.as_deref(), Some("libc6, xdg-utils | xdg-desktop-portal"));
+
+        package_deb.resolved_depends = Some("xdg-desktop-portal".into());
+        add_url_handler_dependency(&mut package_deb, &crate::listener::NoOpListener).unwrap();
+        assert_eq!(package_deb.resolved_depends.as_deref(), Some("xdg-desktop-portal"));
+    }
+}
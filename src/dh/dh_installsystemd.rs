@@ -14,14 +14,16 @@
 use itertools::Itertools; // for .next_tuple()
 
 use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str;
 
 use crate::assets::Asset;
-use crate::dh::dh_lib::{autoscript, pkgfile, ScriptFragments};
-use crate::listener::Listener;
-use crate::util::{fname_from_path, MyJoin};
+use crate::dh::dh_lib::{autoscript, pkgfile, pkgfile_candidates, ScriptFragments};
+use crate::listener::{Listener, UnitCandidateInfo};
+use crate::parse::manifest::RestartPolicy;
+use crate::util::{fname_from_path, is_path_file, MyJoin};
 use crate::{CDResult, CargoDebError};
 
 /// From `man 1 dh_installsystemd` on Ubuntu 20.04 LTS. See:
@@ -40,6 +42,7 @@ use crate::{CDResult, CargoDebError};
 ///            package build directory. Note that the "tmpfiles.d" mechanism is
 ///            currently only used by systemd.
 const LIB_SYSTEMD_SYSTEM_DIR: &str = "lib/systemd/system/";
+const USR_LIB_SYSTEMD_USER_DIR: &str = "usr/lib/systemd/user/";
 const USR_LIB_TMPFILES_D_DIR: &str = "usr/lib/tmpfiles.d/";
 const SYSTEMD_UNIT_FILE_INSTALL_MAPPINGS: [(&str, &str, &str); 12] = [
     ("",  "mount",   LIB_SYSTEMD_SYSTEM_DIR),
@@ -56,6 +59,9 @@ const SYSTEMD_UNIT_FILE_INSTALL_MAPPINGS: [(&str, &str, &str); 12] = [
     ("",  "tmpfile", USR_LIB_TMPFILES_D_DIR),
 ];
 
+/// Units that start a service of the same name (or the one in their `Service=`/`Unit=`) when triggered
+const ACTIVATING_UNIT_TYPES: [&str; 3] = ["socket", "timer", "path"];
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct InstallRecipe {
     pub path: PathBuf,
@@ -123,6 +129,22 @@ pub struct Options {
     pub no_start: bool,
     pub restart_after_upgrade: bool,
     pub no_stop_on_upgrade: bool,
+    /// Units for `systemd --user`, which are enabled for all users, but not started
+    pub user: bool,
+    /// `try-restart` or no restart instead of `restart` after upgrades. `Never` also keeps services running during upgrades.
+    pub restart_policy: RestartPolicy,
+    /// Use `reload-or-restart` actions after upgrades, so that services that have `ExecReload=` aren't stopped
+    pub reload_on_upgrade: bool,
+}
+
+/// Moves units found by [`find_units`] to `/usr/lib/systemd/user/`. Other files, like tmpfiles, stay where they were.
+pub fn into_user_units(units: PackageUnitFiles) -> PackageUnitFiles {
+    units.into_iter().map(|(source, mut recipe)| {
+        if let Ok(file_name) = recipe.path.strip_prefix(LIB_SYSTEMD_SYSTEM_DIR) {
+            recipe.path = Path::new(USR_LIB_SYSTEMD_USER_DIR).join(file_name);
+        }
+        (source, recipe)
+    }).collect()
 }
 
 /// Find installable systemd unit files for the specified debian package (and
@@ -175,6 +197,100 @@ pub fn find_units(dir: &Path, main_package: &str, unit_name: Option<&str>) -> Pa
     installables
 }
 
+/// File names that `find_units()` looks for in the `dir`, for reporting when nothing has been found
+pub fn unit_file_names_searched(dir: &Path, main_package: &str, unit_name: Option<&str>) -> Vec<String> {
+    SYSTEMD_UNIT_FILE_INSTALL_MAPPINGS.iter().flat_map(|(package_suffix, unit_type, _)| {
+        let package_name = format!("{main_package}{package_suffix}");
+        pkgfile_candidates(dir, main_package, &package_name, unit_type, unit_name)
+    })
+    .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+    .collect()
+}
+
+/// Why `find_units()` has or hasn't picked a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnitCandidateStatus {
+    Used,
+    NotFound,
+    /// The file exists, but a more specific one for the same unit type takes precedence
+    Shadowed(PathBuf),
+    /// A file with a unit file extension that doesn't match any of the searched names
+    NameMismatch,
+}
+
+/// One step of `trace_find_units()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitCandidate {
+    pub path: PathBuf,
+    /// File extension of the unit type, e.g. `service`
+    pub unit_type: &'static str,
+    /// `@` units are templates
+    pub template: bool,
+    pub status: UnitCandidateStatus,
+}
+
+impl UnitCandidate {
+    /// For `Listener::systemd_unit_candidate`
+    pub fn info(&self) -> UnitCandidateInfo<'_> {
+        let (status, shadowed_by) = match &self.status {
+            UnitCandidateStatus::Used => ("used", None),
+            UnitCandidateStatus::NotFound => ("not-found", None),
+            UnitCandidateStatus::Shadowed(by) => ("shadowed", Some(by.as_path())),
+            UnitCandidateStatus::NameMismatch => ("name-mismatch", None),
+        };
+        UnitCandidateInfo { path: &self.path, unit_type: self.unit_type, template: self.template, status, shadowed_by }
+    }
+}
+
+impl fmt::Display for UnitCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "systemd unit candidate {} ({}{}): ", self.path.display(), if self.template { "template " } else { "" }, self.unit_type)?;
+        match &self.status {
+            UnitCandidateStatus::Used => f.write_str("used"),
+            UnitCandidateStatus::NotFound => f.write_str("not found"),
+            UnitCandidateStatus::Shadowed(by) => write!(f, "ignored, because {} takes precedence", by.display()),
+            UnitCandidateStatus::NameMismatch => f.write_str("ignored, because its name doesn't match the package name or unit-name"),
+        }
+    }
+}
+
+/// Every file name `find_units()` tries, in order of precedence, and what became of it.
+///
+/// Files in the `dir` that look like unit files, but aren't named after the package or unit,
+/// are listed at the end, since that's usually the reason why a unit hasn't been found.
+pub fn trace_find_units(dir: &Path, main_package: &str, unit_name: Option<&str>) -> Vec<UnitCandidate> {
+    let mut candidates = Vec::new();
+    for (package_suffix, unit_type, _) in &SYSTEMD_UNIT_FILE_INSTALL_MAPPINGS {
+        let package_name = format!("{main_package}{package_suffix}");
+        let mut used: Option<PathBuf> = None;
+        for path in pkgfile_candidates(dir, main_package, &package_name, unit_type, unit_name) {
+            let status = if !is_path_file(&path) {
+                UnitCandidateStatus::NotFound
+            } else if let Some(used) = &used {
+                UnitCandidateStatus::Shadowed(used.clone())
+            } else {
+                used = Some(path.clone());
+                UnitCandidateStatus::Used
+            };
+            candidates.push(UnitCandidate { path, unit_type, template: !package_suffix.is_empty(), status });
+        }
+    }
+
+    let mut mismatched = std::fs::read_dir(dir).into_iter().flatten().filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| !candidates.iter().any(|c| &c.path == path) && path.is_file())
+        .filter_map(|path| {
+            let ext = path.extension()?.to_str()?;
+            let (_, unit_type, _) = SYSTEMD_UNIT_FILE_INSTALL_MAPPINGS.iter().find(|(_, t, _)| *t == ext)?;
+            let template = path.file_stem()?.to_str()?.ends_with('@');
+            Some(UnitCandidate { path, unit_type, template, status: UnitCandidateStatus::NameMismatch })
+        })
+        .collect::<Vec<_>>();
+    mismatched.sort_by(|a, b| a.path.cmp(&b.path));
+    candidates.extend(mismatched);
+    candidates
+}
+
 /// Determine if the given string is a systemd unit file comment line.
 ///
 /// See:
@@ -243,11 +359,12 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
 
     // skip template service files. Enabling, disabling, starting or stopping
     // those services without specifying the instance is not useful.
+    let units_dir = if options.user { USR_LIB_SYSTEMD_USER_DIR } else { LIB_SYSTEMD_SYSTEM_DIR };
     let mut installed_non_template_units: BTreeSet<String> = BTreeSet::new();
     installed_non_template_units.extend(
         assets
             .iter()
-            .filter(|a| a.c.target_path.parent() == Some(LIB_SYSTEMD_SYSTEM_DIR.as_ref()))
+            .filter(|a| a.c.target_path.parent() == Some(units_dir.as_ref()))
             .filter_map(|a| fname_from_path(a.c.target_path.as_path()))
             .filter(|fname| !fname.contains('@')),
     );
@@ -258,6 +375,8 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
     let mut aliases = BTreeSet::new();
     let mut enable_units = BTreeSet::new();
     let mut start_units = BTreeSet::new();
+    // services that socket, timer, and path units start when they're triggered
+    let mut activated_services = BTreeSet::new();
     let mut seen = BTreeSet::new();
 
     // note: we do not support handling of services with a sysv-equivalent
@@ -279,9 +398,11 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
             start_units.insert(unit.clone());
 
             // get the unit file contents
-            let needle = Path::new(LIB_SYSTEMD_SYSTEM_DIR).join(unit);
+            let needle = Path::new(units_dir).join(unit);
             let data = assets.iter().find(move |&item| item.c.target_path == needle).unwrap().source.data()?;
             let reader = data.into_owned();
+            let activating_unit_type = Path::new(unit).extension().and_then(|e| e.to_str()).filter(|e| ACTIVATING_UNIT_TYPES.contains(e));
+            let mut activates = None;
 
             // for every line in the file look for specific keys that we are
             // interested in:
@@ -306,6 +427,7 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
                     let other_unit = unquote(value).to_string();
                     match key {
                         "Also" => {
+                            #[allow(clippy::collapsible_match)]
                             // The seen lookup prevents us from looping forever over
                             // unit files that refer to each other. An actual
                             // real-world example of such a loop is systemd's
@@ -321,16 +443,40 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
                         "Alias" => {
                             aliases.insert(other_unit);
                         },
+                        // Service= of [Socket], Unit= of [Timer] and [Path]
+                        "Service" | "Unit" if activating_unit_type.is_some() => {
+                            activates = Some(other_unit);
+                        },
                         _ => (),
                     };
                 } else if line.starts_with("[Install]") {
                     enable_units.insert(unit.clone());
                 }
             }
+            // by default it's the service of the same name
+            if let Some(unit_type) = activating_unit_type {
+                activated_services.insert(activates.unwrap_or_else(|| format!("{}.service", &unit[..unit.len() - unit_type.len() - 1])));
+            }
         }
         units = also_units;
     }
 
+    // User units can't be started or stopped for every user's session, so they're
+    // only enabled globally, and start on the next login.
+    if options.user {
+        if !enable_units.is_empty() {
+            if !options.no_enable {
+                for unit in &enable_units {
+                    autoscript(&mut scripts, package, "postinst", "postinst-systemd-user-enable",
+                        &map!{ "UNITFILE" => unit.clone() }, true, listener)?;
+                }
+            }
+            autoscript(&mut scripts, package, "postrm", "postrm-systemd-user",
+                &map!{ "UNITFILES" => enable_units.join(" ") }, false, listener)?;
+        }
+        return Ok(scripts);
+    }
+
     // update the maintainer scripts to enable units unless forbidden by the
     // options passed to us.
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n390
@@ -344,27 +490,45 @@ pub fn generate(package: &str, assets: &[Asset], options: &Options, listener: &d
             &map!{ "UNITFILES" => enable_units.join(" ") }, false, listener)?;
     }
 
+    // Like dh_installsystemd, sockets, timers and paths are started, and they start their services
+    // when needed. These services are only restarted if they're already running, and stopped on removal.
+    let stop_units = start_units.clone();
+    let (activated_units, start_units): (BTreeSet<_>, BTreeSet<_>) = start_units.into_iter()
+        .partition(|unit| activated_services.contains(unit));
+
     // update the maintainer scripts to start units, where the exact action to
     // be taken is influenced by the options passed to us.
     // see: https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installsystemd?h=applied/12.10ubuntu1#n398
-    if !start_units.is_empty() {
-        let mut replace = map! { "UNITFILES" => start_units.join(" ") };
+    if !stop_units.is_empty() {
+        let replace = map! { "UNITFILES" => stop_units.join(" ") };
+        let never_restart = options.restart_policy == RestartPolicy::Never;
 
-        if options.restart_after_upgrade {
-            let snippet = if options.no_start {
-                replace.insert("RESTART_ACTION", "try-restart".into());
-                "postinst-systemd-restartnostart"
+        if options.restart_after_upgrade && !never_restart {
+            let try_restart_units = if options.no_start {
+                &stop_units
             } else {
-                replace.insert("RESTART_ACTION", "restart".into());
-                "postinst-systemd-restart"
+                let restart_action = match (options.restart_policy == RestartPolicy::TryRestart, options.reload_on_upgrade) {
+                    (false, false) => "restart",
+                    (true, false) => "try-restart",
+                    (false, true) => "reload-or-restart",
+                    (true, true) => "try-reload-or-restart",
+                };
+                let replace = map! { "UNITFILES" => start_units.join(" "), "RESTART_ACTION" => restart_action.into() };
+                autoscript(&mut scripts, package, "postinst", "postinst-systemd-restart", &replace, true, listener)?;
+                &activated_units
             };
-            autoscript(&mut scripts, package, "postinst", snippet, &replace, true, listener)?;
+            if !try_restart_units.is_empty() {
+                let try_restart_action = if options.reload_on_upgrade { "try-reload-or-restart" } else { "try-restart" };
+                let replace = map! { "UNITFILES" => try_restart_units.join(" "), "RESTART_ACTION" => try_restart_action.into() };
+                autoscript(&mut scripts, package, "postinst", "postinst-systemd-restartnostart", &replace, true, listener)?;
+            }
         } else if !options.no_start {
             // (stop|start) service (before|after) upgrade
+            let replace = map! { "UNITFILES" => start_units.join(" ") };
             autoscript(&mut scripts, package, "postinst", "postinst-systemd-start", &replace, true, listener)?;
         }
 
-        if options.no_stop_on_upgrade || options.restart_after_upgrade {
+        if options.no_stop_on_upgrade || options.restart_after_upgrade || never_restart {
             // stop service only on remove
             autoscript(&mut scripts, package, "prerm", "prerm-systemd-restart", &replace, true, listener)?;
         } else if !options.no_start {
@@ -468,6 +632,42 @@ mod tests {
         assert_eq!(&expected, actual);
     }
 
+    #[test]
+    fn trace_find_units_explains_candidates() {
+        let _g = add_test_fs_paths(&[
+            "debian/mypkg.myunit.service",
+            "debian/myunit.service",
+            "debian/mypkg.timer",
+        ]);
+        let trace = trace_find_units(Path::new("debian"), "mypkg", Some("myunit"));
+        let status = |path: &str| trace.iter().find(|c| c.path == Path::new(path)).map(|c| c.status.clone());
+        assert_eq!(status("debian/mypkg.myunit.service"), Some(UnitCandidateStatus::Used));
+        assert_eq!(status("debian/myunit.service"), Some(UnitCandidateStatus::Shadowed("debian/mypkg.myunit.service".into())));
+        assert_eq!(status("debian/mypkg.service"), Some(UnitCandidateStatus::NotFound));
+        assert_eq!(status("debian/mypkg@.myunit.service"), Some(UnitCandidateStatus::NotFound));
+        assert_eq!(status("debian/mypkg.timer"), Some(UnitCandidateStatus::Used));
+        assert_eq!(trace.len(), unit_file_names_searched(Path::new("debian"), "mypkg", Some("myunit")).len());
+
+        let found = find_units(Path::new("debian"), "mypkg", Some("myunit"));
+        let used = trace.iter().filter(|c| c.status == UnitCandidateStatus::Used).count();
+        assert_eq!(used, found.len());
+
+        let shadowed = trace.iter().find(|c| c.path == Path::new("debian/myunit.service")).unwrap();
+        assert_eq!(shadowed.to_string(), "systemd unit candidate debian/myunit.service (service): ignored, because debian/mypkg.myunit.service takes precedence");
+    }
+
+    #[test]
+    fn trace_find_units_lists_mismatched_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("my-pkg.service"), "").unwrap();
+        std::fs::write(dir.path().join("README"), "").unwrap();
+        let trace = trace_find_units(dir.path(), "mypkg", None);
+        let mismatched: Vec<_> = trace.iter().filter(|c| c.status == UnitCandidateStatus::NameMismatch).collect();
+        assert_eq!(mismatched.len(), 1);
+        assert_eq!(mismatched[0].path, dir.path().join("my-pkg.service"));
+        assert_eq!(mismatched[0].unit_type, "service");
+    }
+
     #[test]
     fn find_units_for_package() {
         // one of each valid pattern (without a specific unit) and one
@@ -704,6 +904,139 @@ mod tests {
         assert_eq!(0, fragments.len());
     }
 
+    #[test]
+    fn generate_starts_sockets_and_timers_instead_of_their_services() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let unit = |name: &str, content: &str| Asset::new(
+            AssetSource::Data(content.into()),
+            format!("lib/systemd/system/{name}").into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        );
+        let assets = vec![
+            unit("mypkg.socket", "[Socket]\nListenStream=80\n[Install]\nWantedBy=sockets.target\n"),
+            unit("mypkg.service", "[Service]\nExecStart=/usr/bin/mypkg\n"),
+            unit("mypkg-cleanup.timer", "[Timer]\nOnCalendar=daily\nUnit=mypkg-gc.service\n[Install]\nWantedBy=timers.target\n"),
+            unit("mypkg-gc.service", "[Service]\nType=oneshot\n"),
+            unit("mypkg-web.service", "[Service]\nExecStart=/usr/bin/mypkg-web\n[Install]\nWantedBy=multi-user.target\n"),
+        ];
+
+        let fragments = generate("mypkg", &assets, &Options::default(), &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("deb-systemd-invoke start mypkg-cleanup.timer mypkg-web.service mypkg.socket >"), "{postinst}");
+        assert!(postinst.contains("deb-systemd-helper enable mypkg.socket") && !postinst.contains("enable mypkg.service"), "{postinst}");
+        let prerm = String::from_utf8(fragments["mypkg.prerm.service"].clone()).unwrap();
+        assert!(prerm.contains("deb-systemd-invoke stop mypkg-cleanup.timer mypkg-gc.service mypkg-web.service mypkg.service mypkg.socket >"), "{prerm}");
+
+        let options = Options { restart_after_upgrade: true, ..Options::default() };
+        let fragments = generate("mypkg", &assets, &options, &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("_dh_action=restart"), "{postinst}");
+        assert!(postinst.contains("deb-systemd-invoke try-restart mypkg-gc.service mypkg.service >"), "{postinst}");
+    }
+
+    #[test]
+    fn generate_applies_restart_policy() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let assets = vec![Asset::new(
+            AssetSource::Data("[Service]\nExecStart=/usr/bin/mypkg\n[Install]\nWantedBy=multi-user.target\n".into()),
+            "lib/systemd/system/mypkg.service".into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        )];
+        let options = |restart_policy| Options {
+            restart_after_upgrade: true,
+            restart_policy,
+            ..Options::default()
+        };
+
+        let fragments = generate("mypkg", &assets, &options(RestartPolicy::TryRestart), &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("_dh_action=try-restart"), "{postinst}");
+        let prerm = String::from_utf8(fragments["mypkg.prerm.service"].clone()).unwrap();
+        assert!(prerm.contains("[ \"$1\" = remove ]") && prerm.contains("\tdeb-systemd-invoke stop mypkg.service >"), "{prerm}");
+
+        let fragments = generate("mypkg", &assets, &options(RestartPolicy::Never), &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("deb-systemd-invoke start mypkg.service >") && !postinst.contains("restart"), "{postinst}");
+        let prerm = String::from_utf8(fragments["mypkg.prerm.service"].clone()).unwrap();
+        assert!(prerm.contains("[ \"$1\" = remove ]"), "{prerm}");
+
+        let fragments = generate("mypkg", &assets, &Options { restart_policy: RestartPolicy::Never, ..Options::default() }, &mock_listener).unwrap();
+        let prerm = String::from_utf8(fragments["mypkg.prerm.service"].clone()).unwrap();
+        assert!(prerm.contains("[ \"$1\" = remove ]") && prerm.contains("\tdeb-systemd-invoke stop mypkg.service >"), "{prerm}");
+    }
+
+    #[test]
+    fn generate_reloads_on_upgrade() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let unit = |name: &str, content: &str| Asset::new(
+            AssetSource::Data(content.into()),
+            format!("lib/systemd/system/{name}").into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        );
+        let assets = vec![
+            unit("mypkg.service", "[Service]\nExecStart=/usr/bin/mypkg\nExecReload=/bin/kill -HUP $MAINPID\n[Install]\nWantedBy=multi-user.target\n"),
+            unit("mypkg-api.socket", "[Socket]\nListenStream=80\n[Install]\nWantedBy=sockets.target\n"),
+            unit("mypkg-api.service", "[Service]\nExecStart=/usr/bin/mypkg-api\n"),
+        ];
+        let options = |restart_policy| Options { restart_after_upgrade: true, reload_on_upgrade: true, restart_policy, ..Options::default() };
+
+        let fragments = generate("mypkg", &assets, &options(RestartPolicy::Restart), &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("_dh_action=reload-or-restart"), "{postinst}");
+        assert!(postinst.contains("deb-systemd-invoke try-reload-or-restart mypkg-api.service >"), "{postinst}");
+        let prerm = String::from_utf8(fragments["mypkg.prerm.service"].clone()).unwrap();
+        assert!(prerm.contains("[ \"$1\" = remove ]"), "{prerm}");
+
+        let fragments = generate("mypkg", &assets, &options(RestartPolicy::TryRestart), &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("_dh_action=try-reload-or-restart"), "{postinst}");
+    }
+
+    #[test]
+    fn generate_enables_user_units_globally() {
+        let mut mock_listener = crate::listener::MockListener::new();
+        mock_listener.expect_info().return_const(());
+
+        let mut units = PackageUnitFiles::new();
+        for name in ["mypkg.service", "mypkg.tmpfile"] {
+            let path = if name.ends_with(".tmpfile") { "usr/lib/tmpfiles.d/mypkg.conf" } else { "lib/systemd/system/mypkg.service" };
+            units.insert(PathBuf::from(format!("debian/{name}")), InstallRecipe { path: path.into(), mode: 0o644 });
+        }
+        let units = into_user_units(units);
+        assert_eq!(units[Path::new("debian/mypkg.service")].path, Path::new("usr/lib/systemd/user/mypkg.service"));
+        assert_eq!(units[Path::new("debian/mypkg.tmpfile")].path, Path::new("usr/lib/tmpfiles.d/mypkg.conf"));
+
+        let assets = vec![Asset::new(
+            AssetSource::Data(b"[Service]\nExecStart=/usr/bin/mypkg\n[Install]\nWantedBy=default.target\n".to_vec()),
+            "usr/lib/systemd/user/mypkg.service".into(),
+            0o644,
+            crate::assets::IsBuilt::No,
+            false,
+        )];
+        assert!(generate("mypkg", &assets, &Options::default(), &mock_listener).unwrap().is_empty());
+
+        let options = Options { user: true, ..Options::default() };
+        let fragments = generate("mypkg", &assets, &options, &mock_listener).unwrap();
+        let postinst = String::from_utf8(fragments["mypkg.postinst.service"].clone()).unwrap();
+        assert!(postinst.contains("deb-systemd-helper --user enable mypkg.service"), "{postinst}");
+        assert!(!postinst.contains("deb-systemd-invoke"), "{postinst}");
+        let postrm = String::from_utf8(fragments["mypkg.postrm.debhelper"].clone()).unwrap();
+        assert!(postrm.contains("deb-systemd-helper --user purge mypkg.service"), "{postrm}");
+        assert!(!fragments.contains_key("mypkg.prerm.service"));
+    }
+
     #[rstest(ip, inst, ne, rau, ns, nsou,
       case("ult", false, false, false, false, false),
 
@@ -771,6 +1104,9 @@ mod tests {
             no_start: ns,
             restart_after_upgrade: rau,
             no_stop_on_upgrade: nsou,
+            user: false,
+            restart_policy: RestartPolicy::Restart,
+            reload_on_upgrade: false,
         };
 
         // setup mocks
@@ -27,16 +27,26 @@ use crate::CDResult;
 ///   <https://git.launchpad.net/ubuntu/+source/debhelper/tree/autoscripts?h=applied/12.10ubuntu1>
 /// To understand which scripts are invoked when, consult:
 ///   <https://www.debian.org/doc/debian-policy/ap-flowcharts.htm>
-
-static AUTOSCRIPTS: [(&str, &[u8]); 10] = [
+static AUTOSCRIPTS: [(&str, &[u8]); 21] = [
+    ("maintscript-helper", include_bytes!("../../autoscripts/maintscript-helper")),
+    ("postinst-desktop-database", include_bytes!("../../autoscripts/postinst-desktop-database")),
+    ("postinst-icons", include_bytes!("../../autoscripts/postinst-icons")),
     ("postinst-init-tmpfiles", include_bytes!("../../autoscripts/postinst-init-tmpfiles")),
     ("postinst-systemd-dont-enable", include_bytes!("../../autoscripts/postinst-systemd-dont-enable")),
     ("postinst-systemd-enable", include_bytes!("../../autoscripts/postinst-systemd-enable")),
     ("postinst-systemd-restart", include_bytes!("../../autoscripts/postinst-systemd-restart")),
     ("postinst-systemd-restartnostart", include_bytes!("../../autoscripts/postinst-systemd-restartnostart")),
     ("postinst-systemd-start", include_bytes!("../../autoscripts/postinst-systemd-start")),
+    ("postinst-systemd-user-enable", include_bytes!("../../autoscripts/postinst-systemd-user-enable")),
+    ("postinst-sysusers", include_bytes!("../../autoscripts/postinst-sysusers")),
+    ("postinst-udev", include_bytes!("../../autoscripts/postinst-udev")),
+    ("postrm-desktop-database", include_bytes!("../../autoscripts/postrm-desktop-database")),
+    ("postrm-icons", include_bytes!("../../autoscripts/postrm-icons")),
+    ("postrm-runtime-files", include_bytes!("../../autoscripts/postrm-runtime-files")),
     ("postrm-systemd", include_bytes!("../../autoscripts/postrm-systemd")),
     ("postrm-systemd-reload-only", include_bytes!("../../autoscripts/postrm-systemd-reload-only")),
+    ("postrm-systemd-user", include_bytes!("../../autoscripts/postrm-systemd-user")),
+    ("postrm-udev", include_bytes!("../../autoscripts/postrm-udev")),
     ("prerm-systemd", include_bytes!("../../autoscripts/prerm-systemd")),
     ("prerm-systemd-restart", include_bytes!("../../autoscripts/prerm-systemd-restart")),
 ];
@@ -72,6 +82,11 @@ pub(crate) type ScriptFragments = HashMap<String, Vec<u8>>;
 /// <https://git.launchpad.net/ubuntu/+source/debhelper/tree/lib/Debian/Debhelper/Dh_Lib.pm?h=applied/12.10ubuntu1#n286>
 /// <https://git.launchpad.net/ubuntu/+source/debhelper/tree/lib/Debian/Debhelper/Dh_Lib.pm?h=applied/12.10ubuntu1#n957>
 pub(crate) fn pkgfile(dir: &Path, main_package: &str, package: &str, filename: &str, unit_name: Option<&str>) -> Option<PathBuf> {
+    pkgfile_candidates(dir, main_package, package, filename, unit_name).into_iter().find(|p| is_path_file(p))
+}
+
+/// Paths that `pkgfile()` looks for, the most specific first
+pub(crate) fn pkgfile_candidates(dir: &Path, main_package: &str, package: &str, filename: &str, unit_name: Option<&str>) -> Vec<PathBuf> {
     let mut paths_to_try = Vec::new();
     let is_main_package = main_package == package;
 
@@ -99,7 +114,7 @@ pub(crate) fn pkgfile(dir: &Path, main_package: &str, package: &str, filename: &
         paths_to_try.push(dir.join(filename));
     }
 
-    paths_to_try.into_iter().find(|p| is_path_file(p))
+    paths_to_try
 }
 
 /// Get the bytes for the specified filename whose contents were embedded in our
@@ -246,10 +261,10 @@ fn autoscript_sed(snippet_filename: &str, replacements: &HashMap<&str, String>)
 /// # References
 ///
 /// <https://git.launchpad.net/ubuntu/+source/debhelper/tree/lib/Debian/Debhelper/Dh_Lib.pm?h=applied/12.10ubuntu1#n2161>
-fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments, package: &str, script: &str, unit_name: Option<&str>,
+fn debhelper_script_subst(user_scripts_dir: Option<&Path>, scripts: &mut ScriptFragments, package: &str, script: &str, unit_name: Option<&str>,
     listener: &dyn Listener) -> CDResult<()>
 {
-    let user_file = pkgfile(user_scripts_dir, package, package, script, unit_name);
+    let user_file = user_scripts_dir.and_then(|dir| pkgfile(dir, package, package, script, unit_name));
     let mut generated_scripts: Vec<String> = vec![
         format!("{package}.{script}.debhelper"),
         format!("{package}.{script}.service"),
@@ -299,7 +314,7 @@ fn debhelper_script_subst(user_scripts_dir: &Path, scripts: &mut ScriptFragments
 /// on disk supplied by the user.
 ///
 /// See: <https://git.launchpad.net/ubuntu/+source/debhelper/tree/dh_installdeb?h=applied/12.10ubuntu1#n300>
-pub(crate) fn apply(user_scripts_dir: &Path, scripts: &mut ScriptFragments, package: &str, unit_name: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
+pub(crate) fn apply(user_scripts_dir: Option<&Path>, scripts: &mut ScriptFragments, package: &str, unit_name: Option<&str>, listener: &dyn Listener) -> CDResult<()> {
     for script in &["postinst", "preinst", "prerm", "postrm"] {
         // note: we don't support custom defines thus we don't have the final
         // 'package_subst' argument to debhelper_script_subst().
@@ -443,14 +458,25 @@ mod tests {
         actual_scripts.sort_unstable();
 
         let expected_scripts = vec![
+            "maintscript-helper",
+            "postinst-desktop-database",
+            "postinst-icons",
             "postinst-init-tmpfiles",
             "postinst-systemd-dont-enable",
             "postinst-systemd-enable",
             "postinst-systemd-restart",
             "postinst-systemd-restartnostart",
             "postinst-systemd-start",
+            "postinst-systemd-user-enable",
+            "postinst-sysusers",
+            "postinst-udev",
+            "postrm-desktop-database",
+            "postrm-icons",
+            "postrm-runtime-files",
             "postrm-systemd",
             "postrm-systemd-reload-only",
+            "postrm-systemd-user",
+            "postrm-udev",
             "prerm-systemd",
             "prerm-systemd-restart",
         ];
@@ -594,7 +620,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(0, scripts.len());
     }
 
@@ -609,7 +635,7 @@ mod tests {
 
         let mut scripts = ScriptFragments::new();
 
-        match debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener) {
+        match debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener) {
             Ok(()) => (),
             Err(CargoDebError::DebHelperReplaceFailed(_)) => panic!("Test failed as expected"),
             Err(err) => panic!("Unexpected error {err:?}"),
@@ -628,7 +654,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(1, scripts.len());
         assert!(scripts.contains_key("myscript"));
     }
@@ -647,7 +673,7 @@ mod tests {
         scripts.insert("mypkg.myscript.debhelper".to_owned(), b"injected".to_vec());
 
         assert_eq!(1, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(2, scripts.len());
         assert!(scripts.contains_key("mypkg.myscript.debhelper"));
         assert!(scripts.contains_key("myscript"));
@@ -669,7 +695,7 @@ mod tests {
         scripts.insert("mypkg.myscript.debhelper".to_owned(), b"injected".to_vec());
 
         assert_eq!(1, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener).unwrap();
         assert_eq!(2, scripts.len());
         assert!(scripts.contains_key("mypkg.myscript.debhelper"));
         assert!(scripts.contains_key("myscript"));
@@ -701,7 +727,7 @@ mod tests {
         scripts.insert(format!("mypkg.{maintainer_script}.service"), b"second".to_vec());
 
         assert_eq!(2, scripts.len());
-        debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", maintainer_script, None, &mock_listener).unwrap();
+        debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", maintainer_script, None, &mock_listener).unwrap();
         assert_eq!(3, scripts.len());
         assert!(scripts.contains_key(&format!("mypkg.{maintainer_script}.debhelper")));
         assert!(scripts.contains_key(&format!("mypkg.{maintainer_script}.service")));
@@ -735,7 +761,7 @@ mod tests {
         let mut scripts = ScriptFragments::new();
 
         assert_eq!(0, scripts.len());
-        let result = debhelper_script_subst(Path::new(""), &mut scripts, "mypkg", "myscript", None, &mock_listener);
+        let result = debhelper_script_subst(Some(Path::new("")), &mut scripts, "mypkg", "myscript", None, &mock_listener);
 
         assert!(matches!(result, Err(CargoDebError::Io(_))));
         if let CargoDebError::Io(err) = result.unwrap_err() {
@@ -749,7 +775,7 @@ mod tests {
     fn apply_with_no_matching_files() {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(0).return_const(());
-        apply(Path::new(""), &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
+        apply(Some(Path::new("")), &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
     }
 
     #[rstest]
@@ -765,6 +791,6 @@ mod tests {
         let mut mock_listener = crate::listener::MockListener::new();
         mock_listener.expect_info().times(scripts.len()).return_const(());
 
-        apply(Path::new(""), &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
+        apply(Some(Path::new("")), &mut ScriptFragments::new(), "mypkg", None, &mock_listener).unwrap();
     }
 }
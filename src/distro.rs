@@ -0,0 +1,156 @@
+//! Distribution releases that the same build can be packaged for, see `--target-distro`
+
+use crate::error::{CDResult, CargoDebError};
+use std::collections::BTreeMap;
+
+/// A Debian or Ubuntu release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetDistro {
+    /// Name used on the command line and in `dependency-aliases`, e.g. `debian12`
+    pub name: &'static str,
+    pub codename: &'static str,
+    /// Appended to the package version, so that per-distro packages have distinct names and versions
+    pub version_suffix: &'static str,
+    /// Packages that have a different name in this release (from, to)
+    builtin_aliases: &'static [(&'static str, &'static str)],
+}
+
+/// Packages renamed for the 64-bit `time_t` transition
+const T64: &[(&str, &str)] = &[
+    ("libssl3", "libssl3t64"),
+    ("libcurl4", "libcurl4t64"),
+    ("libglib2.0-0", "libglib2.0-0t64"),
+    ("libgtk-3-0", "libgtk-3-0t64"),
+];
+
+/// Released before OpenSSL 3
+const OPENSSL1: &[(&str, &str)] = &[
+    ("libssl3", "libssl1.1"),
+];
+
+const DISTROS: &[TargetDistro] = &[
+    TargetDistro { name: "debian11", codename: "bullseye", version_suffix: "+deb11u1", builtin_aliases: OPENSSL1 },
+    TargetDistro { name: "debian12", codename: "bookworm", version_suffix: "+deb12u1", builtin_aliases: &[] },
+    TargetDistro { name: "debian13", codename: "trixie", version_suffix: "+deb13u1", builtin_aliases: T64 },
+    TargetDistro { name: "ubuntu20.04", codename: "focal", version_suffix: "+ubuntu20.04", builtin_aliases: OPENSSL1 },
+    TargetDistro { name: "ubuntu22.04", codename: "jammy", version_suffix: "+ubuntu22.04", builtin_aliases: &[] },
+    TargetDistro { name: "ubuntu24.04", codename: "noble", version_suffix: "+ubuntu24.04", builtin_aliases: T64 },
+];
+
+impl TargetDistro {
+    /// Accepts names like `debian12`, as well as codenames like `bookworm`
+    pub fn from_name(name: &str) -> CDResult<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        DISTROS.iter().find(|d| d.name == name || d.codename == name).copied()
+            .ok_or_else(|| CargoDebError::UnknownDistro(name, DISTROS.iter().map(|d| d.name).collect::<Vec<_>>().join(", ")))
+    }
+
+    /// Renames packages in a `Depends`-style list. User-defined aliases for this distro take precedence over the built-in ones.
+    pub(crate) fn alias_dependencies(&self, deps: &str, user_aliases: &BTreeMap<String, BTreeMap<String, String>>) -> String {
+        let user_aliases = user_aliases.get(self.name).or_else(|| user_aliases.get(self.codename));
+        rename_packages(deps, |package| {
+            user_aliases.and_then(|a| a.get(package)).cloned()
+                .or_else(|| self.builtin_aliases.iter().find(|&&(from, _)| from == package).map(|&(_, to)| to.to_owned()))
+        })
+    }
+}
+
+/// Debian derivative with a different ABI baseline for the same architecture name, see `--flavor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistroFlavor {
+    #[default]
+    Debian,
+    /// Raspbian's `armhf` is ARMv6 with VFPv2, while Debian's `armhf` requires ARMv7
+    Raspbian,
+}
+
+impl DistroFlavor {
+    pub fn from_name(name: &str) -> CDResult<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "debian" => Ok(Self::Debian),
+            "raspbian" | "raspios" => Ok(Self::Raspbian),
+            other => Err(CargoDebError::UnknownFlavor(other.into())),
+        }
+    }
+
+    /// Key in `dependency-aliases`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Debian => "debian",
+            Self::Raspbian => "raspbian",
+        }
+    }
+
+    /// The Debian architecture name is the same (`armhf`), but binaries built for a newer CPU than the flavor's baseline won't run.
+    /// Returns a warning if the Rust target doesn't match the flavor's idea of its architecture.
+    pub(crate) fn check_target(self, rust_target_triple: &str) -> Option<String> {
+        let mut parts = rust_target_triple.split('-');
+        let arch = parts.next().unwrap_or_default();
+        let abi = parts.next_back().unwrap_or_default();
+        let is_arm32 = arch.starts_with("arm") || arch.starts_with("thumb");
+        let is_hard_float = abi.ends_with("hf");
+        let is_armv6 = matches!(arch, "arm" | "armv6");
+        match self {
+            Self::Raspbian if !is_arm32 => Some(format!("--flavor raspbian is for 32-bit armhf, but the target is {rust_target_triple}. \
+                64-bit Raspberry Pi OS uses Debian's arm64, and doesn't need the flavor")),
+            Self::Raspbian if !is_hard_float => Some(format!("Raspbian has only armhf, but {rust_target_triple} is soft-float armel. \
+                Use --target=arm-unknown-linux-gnueabihf")),
+            Self::Raspbian if !is_armv6 => Some(format!("Raspbian's armhf is ARMv6, but {rust_target_triple} requires ARMv7 or newer. \
+                The package won't work on Raspberry Pi Zero and 1. Use --target=arm-unknown-linux-gnueabihf")),
+            Self::Debian if is_armv6 && is_hard_float => Some(format!("{rust_target_triple} is ARMv6, which is Raspbian's armhf. \
+                Debian's armhf is ARMv7, so the package may link with libraries that don't run on ARMv6. Use --flavor raspbian if it's for Raspbian")),
+            _ => None,
+        }
+    }
+
+    /// Renames packages using `dependency-aliases` for this flavor. Debian has no aliases.
+    pub(crate) fn alias_dependencies(self, deps: &str, user_aliases: &BTreeMap<String, BTreeMap<String, String>>) -> String {
+        match (self, user_aliases.get(self.name())) {
+            (Self::Debian, _) | (_, None) => deps.to_owned(),
+            (_, Some(aliases)) => rename_packages(deps, |package| aliases.get(package).cloned()),
+        }
+    }
+}
+
+fn rename_packages(deps: &str, alias: impl Fn(&str) -> Option<String>) -> String {
+    deps.split(',').map(|dep| {
+        dep.split('|').map(|alternative| {
+            let alternative = alternative.trim();
+            let name_len = alternative.find([' ', '(', '[', ':']).unwrap_or(alternative.len());
+            match alias(&alternative[..name_len]) {
+                Some(new_name) => format!("{new_name}{}", &alternative[name_len..]),
+                None => alternative.to_owned(),
+            }
+        }).collect::<Vec<_>>().join(" | ")
+    }).collect::<Vec<_>>().join(", ")
+}
+
+#[test]
+fn distro_aliases() {
+    let noble = TargetDistro::from_name("noble").unwrap();
+    assert_eq!(noble.name, "ubuntu24.04");
+    let mut user = BTreeMap::new();
+    user.insert("ubuntu24.04".to_string(), [("libfoo1".to_string(), "libfoo2".to_string())].into_iter().collect());
+    assert_eq!(noble.alias_dependencies("libc6 (>= 2.34), libssl3 (>= 3.0.0), libfoo1 | libbar", &user),
+        "libc6 (>= 2.34), libssl3t64 (>= 3.0.0), libfoo2 | libbar");
+    assert_eq!(TargetDistro::from_name("debian11").unwrap().alias_dependencies("libssl3", &user), "libssl1.1");
+    assert!(TargetDistro::from_name("debian1").is_err());
+}
+
+#[test]
+fn raspbian_flavor() {
+    let raspbian = DistroFlavor::from_name("Raspbian").unwrap();
+    assert_eq!(raspbian.check_target("arm-unknown-linux-gnueabihf"), None);
+    assert!(raspbian.check_target("armv7-unknown-linux-gnueabihf").unwrap().contains("ARMv7"));
+    assert!(raspbian.check_target("arm-unknown-linux-gnueabi").is_some());
+    assert!(raspbian.check_target("aarch64-unknown-linux-gnu").is_some());
+    assert!(DistroFlavor::Debian.check_target("arm-unknown-linux-gnueabihf").is_some());
+    assert_eq!(DistroFlavor::Debian.check_target("armv7-unknown-linux-gnueabihf"), None);
+    assert_eq!(DistroFlavor::Debian.check_target("x86_64-unknown-linux-gnu"), None);
+
+    let mut user = BTreeMap::new();
+    user.insert("raspbian".to_string(), [("libfoo1".to_string(), "libfoo1-rpi".to_string())].into_iter().collect());
+    assert_eq!(raspbian.alias_dependencies("libc6, libfoo1 (>= 1)", &user), "libc6, libfoo1-rpi (>= 1)");
+    assert_eq!(DistroFlavor::Debian.alias_dependencies("libfoo1", &user), "libfoo1");
+    assert!(DistroFlavor::from_name("gentoo").is_err());
+}
@@ -19,6 +19,9 @@ quick_error! {
             display("{}: {}", msg, file.display())
             source(err)
         }
+        ElfParse(err: String, path: PathBuf) {
+            display("Unable to read ELF file {}: {}", path.display(), err)
+        }
         CommandFailed(err: io::Error, cmd: &'static str) {
             display("Command {} failed to launch", cmd)
             source(err)
@@ -37,12 +40,21 @@ quick_error! {
         InvalidVersion(msg: &'static str, ver: String) {
             display("Version '{}' is invalid: {}", ver, msg)
         }
+        Downgrade(package: String, installed_version: String, new_version: String) {
+            display("{} {} is already installed, which is newer than {}. Use --allow-downgrade to install it anyway", package, installed_version, new_version)
+        }
+        CorruptedDeb(path: PathBuf, reason: String) {
+            display("{} is corrupted: {}", path.display(), reason)
+        }
         InstallFailed {
             display("Installation failed, because dpkg -i returned error")
         }
         BuildFailed {
             display("Build failed")
         }
+        BuildFailedWithOutput(output: String) {
+            display("Build failed:\n{}", output)
+        }
         DebHelperReplaceFailed(name: PathBuf) {
             display("Unable to replace #DEBHELPER# token in maintainer script '{}'", name.display())
         }
@@ -77,6 +89,101 @@ quick_error! {
         NoRootFoundInWorkspace(available: String) {
             display("This is a workspace with multiple packages, and there is no single package at the root. Please specify package name with -p. Available packages are: {}", available)
         }
+        ConfFileConflict(path: String) {
+            display("'{}' is listed in both conf-files and non-conf-files", path)
+        }
+        NonConfFileNotInPackage(path: String) {
+            display("'{}' is listed in non-conf-files, but the package doesn't install it", path)
+        }
+        InvalidRelation(relation: String) {
+            display("Unable to parse dependency '{}'. Expected format is 'package (>= version)'", relation)
+        }
+        InvalidRelationship(msg: String) {
+            display("{}", msg)
+        }
+        InvalidSynopsis(synopsis: String, reason: &'static str) {
+            display("Synopsis '{}' is invalid: {}", synopsis, reason)
+        }
+        EssentialNotAllowed(package: String) {
+            display("{} has `essential = true`, which makes it impossible to uninstall, and can break systems. Use `protected = true` instead, or if you're sure, --i-know-what-im-doing", package)
+        }
+        PriorityPolicy(priority: String, reason: String) {
+            display("Packages with 'Priority: {}' {}", priority, reason)
+        }
+        UnknownDistro(name: String, available: String) {
+            display("Unknown target distro '{}'. Supported: {}", name, available)
+        }
+        InvalidCrateSpec(spec: String) {
+            display("'{}' is not a valid crate name and version. Use --crate <name> or --crate <name>@<version>", spec)
+        }
+        CrateNotFound(spec: String) {
+            display("Crate '{}' not found in the registry", spec)
+        }
+        UnknownFlavor(name: String) {
+            display("Unknown distro flavor '{}'. Supported: debian, raspbian", name)
+        }
+        Compat(dpkg_version: String, reason: String) {
+            display("The package would not be installable with dpkg {}: {}", dpkg_version, reason)
+        }
+        InvalidIcon(path: PathBuf, reason: String) {
+            display("Unable to use {} as the icon: {}", path.display(), reason)
+        }
+        OfflineStrict(program: String) {
+            display("--offline-strict doesn't allow running '{}', because it may access the network", program)
+        }
+        SpecialFileNotAllowed(path: PathBuf) {
+            display("Asset {} is a FIFO or a device. Set `allow-special-files = true` in [package.metadata.deb] if that's intended", path.display())
+        }
+        BuildPaths(path: PathBuf, suggestion: String) {
+            display("/{} contains absolute paths of the build machine (--deny-build-paths). Build with {}", path.display(), suggestion)
+        }
+        DeltaMismatch(old_deb: PathBuf, package: String) {
+            display("{} is not a previous version of {}", old_deb.display(), package)
+        }
+        InvalidPublishUrl(url: String) {
+            display("Unable to publish to '{}'. The URL must start with https://, http://, or s3://", url)
+        }
+        InvalidRepoSpec(spec: String) {
+            display("--add-to-repo '{}' should be aptly:<repo> or reprepro:<basedir>:<codename>", spec)
+        }
+        PublishPasswordEnv(var: String) {
+            display("The password for publishing should be in the {} env var (password-env in [package.metadata.deb.publish]), but it's not set", var)
+        }
+        SignTokenEnv(var: String) {
+            display("The OIDC token for signing should be in the {} env var (identity-token-env in [package.metadata.deb.sign.sigstore]), but it's not set", var)
+        }
+        InvalidMaintscript(command: String, reason: &'static str) {
+            display("Invalid maintscripts entry '{}': {}", command, reason)
+        }
+        UnpackagedLibraries(binary: PathBuf, libraries: String) {
+            display("{} needs {}, which dpkg-shlibdeps can't attribute to any package, because they're not in system library dirs.\n\
+                Link them statically, or add them to assets (with an rpath that finds them), or build against libraries from -dev packages \
+                (check LD_LIBRARY_PATH, RUSTFLAGS and build scripts for dirs like /usr/local/lib)", binary.display(), libraries)
+        }
+        FileConflict(conflicts: Vec<String>) {
+            display("{}.\nOne package of each pair needs to declare `replaces` and `conflicts` (or `breaks`) in [package.metadata.deb]", conflicts.join(".\n"))
+        }
+        InvalidAssetPath(path: PathBuf, reason: String) {
+            display("Invalid asset installation path '{}': {}", path.display(), reason)
+        }
+        RuntimeFileIsAsset(path: PathBuf) {
+            display("/{} is in runtime-files, but it's also an asset. Runtime files are created by the program, and can't be in the package", path.display())
+        }
+        DuplicateAsset(path: PathBuf) {
+            display("More than one asset is installed as /{}, and `duplicate-assets = \"error\"` is set", path.display())
+        }
+        SystemdUnitsNotFound(dir: PathBuf, searched: String) {
+            display("systemd-units are configured, but no unit files have been found in {}. Looked for: {}", dir.display(), searched)
+        }
+        InvalidSourceVersion(version: String) {
+            display("Source packages need a Debian revision in the version, e.g. 1.0-1, but the version is {}", version)
+        }
+        InvalidVcsRevision(revision: String) {
+            display("The --version-metadata revision {:?} is invalid. It can't be empty, or contain whitespace or control characters", revision)
+        }
+        ConfigCheckFailed(problems: usize) {
+            display("Found {} problem(s) in the configuration", problems)
+        }
         VariantNotFound(variant: String) {
             display("[package.metadata.deb.variants.{}] not found in Cargo.toml", variant)
         }
@@ -0,0 +1,83 @@
+//! `dependency-hints = true` suggests run-time `Depends` that `$auto` can't detect,
+//! based on well-known crates (and their features) in the dependency tree.
+
+use crate::config::PackageConfig;
+use crate::error::CDResult;
+use crate::listener::Listener;
+use crate::parse::manifest::DependencyFeatures;
+use std::collections::BTreeSet;
+
+enum Needs {
+    /// A package that isn't linked, so dpkg-shlibdeps won't find it
+    Package(&'static str),
+    /// A dynamically linked library, which `$auto` would find
+    SharedLibrary,
+}
+
+struct Hint {
+    krate: &'static str,
+    /// The hint doesn't apply when this feature is enabled (e.g. the library is statically linked)
+    unless_feature: Option<&'static str>,
+    needs: Needs,
+    reason: &'static str,
+}
+
+const HINTS: &[Hint] = &[
+    Hint { krate: "native-tls", unless_feature: None, needs: Needs::Package("ca-certificates"), reason: "verifies TLS certificates using the system's root certificates" },
+    Hint { krate: "rustls-native-certs", unless_feature: None, needs: Needs::Package("ca-certificates"), reason: "loads the system's root certificates" },
+    Hint { krate: "openssl-probe", unless_feature: None, needs: Needs::Package("ca-certificates"), reason: "looks for the system's root certificates" },
+    Hint { krate: "open", unless_feature: None, needs: Needs::Package("xdg-utils"), reason: "runs xdg-open" },
+    Hint { krate: "opener", unless_feature: None, needs: Needs::Package("xdg-utils"), reason: "runs xdg-open" },
+    Hint { krate: "webbrowser", unless_feature: None, needs: Needs::Package("xdg-utils"), reason: "runs xdg-open" },
+    Hint { krate: "openssl-sys", unless_feature: Some("vendored"), needs: Needs::SharedLibrary, reason: "links libssl dynamically" },
+    Hint { krate: "libsqlite3-sys", unless_feature: Some("bundled"), needs: Needs::SharedLibrary, reason: "links libsqlite3 dynamically" },
+];
+
+/// Adds packages implied by the crates in the dependency tree to `resolved_depends`, and explains why.
+/// Runs after `resolve_binary_dependencies`.
+pub(crate) fn add_dependency_hints(package_deb: &mut PackageConfig, dependency_features: &DependencyFeatures, listener: &dyn Listener) -> CDResult<()> {
+    let has_auto = package_deb.wildcard_depends.split(',').any(|dep| dep.trim() == "$auto");
+
+    for (hint, features) in applicable_hints(dependency_features) {
+        match hint.needs {
+            Needs::Package(name) => {
+                if package_deb.add_depends(name, listener) {
+                    listener.info(format!("Adding '{name}' to Depends, because the '{}' crate {}", hint.krate, hint.reason));
+                }
+            },
+            Needs::SharedLibrary if !has_auto => {
+                let feature = hint.unless_feature.map(|f| format!(" (or enable its '{f}' feature)")).unwrap_or_default();
+                listener.warning(format!("The '{}' crate {}, but depends doesn't include $auto. Add $auto{feature} to have the library in Depends (enabled features: {})",
+                    hint.krate, hint.reason, features.iter().map(String::as_str).collect::<Vec<_>>().join(", ")));
+            },
+            Needs::SharedLibrary => {},
+        }
+    }
+    Ok(())
+}
+
+fn applicable_hints(dependency_features: &DependencyFeatures) -> impl Iterator<Item = (&'static Hint, &BTreeSet<String>)> {
+    HINTS.iter().filter_map(move |hint| {
+        let features = dependency_features.get(hint.krate)?;
+        if hint.unless_feature.is_some_and(|f| features.contains(f)) {
+            return None;
+        }
+        Some((hint, features))
+    })
+}
+
+#[test]
+fn hints_for_crates_and_features() {
+    let deps = |list: &[(&str, &[&str])]| -> DependencyFeatures {
+        list.iter().map(|&(name, features)| (name.to_owned(), features.iter().map(|&f| f.to_owned()).collect())).collect()
+    };
+    let found = |d| applicable_hints(&d).map(|(h, _)| h.krate).collect::<Vec<_>>();
+    assert_eq!(found(deps(&[("native-tls", &[]), ("serde", &["std"])])), ["native-tls"]);
+    assert_eq!(found(deps(&[("libsqlite3-sys", &["bundled"]), ("openssl-sys", &[])])), ["openssl-sys"]);
+    assert!(found(deps(&[("openssl-sys", &["vendored"])])).is_empty());
+
+    let mut package_deb = crate::config::tests::example_package_config(Default::default(), Default::default()).unwrap();
+    package_deb.resolved_depends = Some("libc6 (>= 2.34), xdg-utils".into());
+    add_dependency_hints(&mut package_deb, &deps(&[("rustls-native-certs", &[]), ("native-tls", &[]), ("open", &[])]), &crate::listener::NoOpListener).unwrap();
+    assert_eq!(package_deb.resolved_depends.as_deref(), Some("ca-certificates, libc6 (>= 2.34), xdg-utils"));
+}
@@ -0,0 +1,153 @@
+//! Installs the app's `icon` in the freedesktop.org icon theme dirs
+//!
+//! <https://specifications.freedesktop.org/icon-theme-spec/latest/>
+
+use crate::assets::{Asset, AssetSource, IsBuilt};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::path::{Path, PathBuf};
+
+const HICOLOR_DIR: &str = "usr/share/icons/hicolor";
+const PIXMAPS_DIR: &str = "usr/share/pixmaps";
+
+/// Sizes of PNG icons that are rendered from SVG, or downscaled from a large PNG
+#[cfg_attr(not(feature = "icons"), allow(dead_code))]
+const ICON_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+
+/// Makes assets for `/usr/share/icons/hicolor/*/apps/<icon_name>.{svg,png}` and `/usr/share/pixmaps/`
+pub(crate) fn icon_assets(source_path: &Path, icon_name: &str, listener: &dyn Listener) -> CDResult<Vec<Asset>> {
+    let data = std::fs::read(source_path)
+        .map_err(|e| CargoDebError::IoFile("unable to read icon", e, source_path.to_owned()))?;
+    let is_svg = source_path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg"));
+    let invalid = |reason: &str| CargoDebError::InvalidIcon(source_path.to_owned(), reason.into());
+
+    let (ext, pngs) = if is_svg {
+        let pngs = render::svg_to_pngs(&data).map_err(|e| invalid(&e))?;
+        if pngs.is_empty() && cfg!(feature = "icons") {
+            listener.warning(format!("{} has no width or height, so it's installed only as a scalable icon", source_path.display()));
+        }
+        ("svg", pngs)
+    } else {
+        let (width, height) = png_size(&data).ok_or_else(|| invalid("only SVG and PNG icons are supported"))?;
+        if width == 0 || height == 0 {
+            return Err(invalid("the icon has zero width or height"));
+        }
+        if width != height {
+            return Err(invalid(&format!("the icon must be square, but it's {width}x{height}")));
+        }
+        let mut pngs = render::downscale_png(&data, width).map_err(|e| invalid(&e))?;
+        pngs.push((width, data.clone()));
+        ("png", pngs)
+    };
+    if !cfg!(feature = "icons") {
+        listener.info(format!("{} is installed only in its original size. Build cargo-deb with the 'icons' feature to make PNGs in more sizes", source_path.display()));
+    }
+
+    let icon_asset = |data, target_path: PathBuf| Asset::new(AssetSource::Data(data), target_path, 0o644, IsBuilt::No, false)
+        .processed("icon", source_path.to_owned());
+
+    let mut assets = Vec::with_capacity(pngs.len() + 2);
+    if is_svg {
+        assets.push(icon_asset(data.clone(), Path::new(HICOLOR_DIR).join(format!("scalable/apps/{icon_name}.svg"))));
+    }
+    for (size, png) in pngs {
+        assets.push(icon_asset(png, Path::new(HICOLOR_DIR).join(format!("{size}x{size}/apps/{icon_name}.png"))));
+    }
+    // legacy location used by some window managers and menus
+    assets.push(icon_asset(data, Path::new(PIXMAPS_DIR).join(format!("{icon_name}.{ext}"))));
+    Ok(assets)
+}
+
+/// Width and height from the PNG header
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    let header = data.strip_prefix(b"\x89PNG\r\n\x1a\n")?;
+    let ihdr = header.get(4..16)?.strip_prefix(b"IHDR")?;
+    let width = u32::from_be_bytes(ihdr[0..4].try_into().ok()?);
+    let height = u32::from_be_bytes(ihdr[4..8].try_into().ok()?);
+    Some((width, height))
+}
+
+#[cfg(feature = "icons")]
+mod render {
+    use super::ICON_SIZES;
+    use resvg::tiny_skia::{FilterQuality, Pixmap, PixmapPaint, Transform};
+    use resvg::usvg;
+
+    pub(super) fn svg_to_pngs(svg: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, String> {
+        let tree = usvg::Tree::from_data(svg, &usvg::Options::default()).map_err(|e| e.to_string())?;
+        let svg_size = tree.size();
+        let svg_size = svg_size.width().max(svg_size.height());
+        // the scale would be infinite
+        if !(svg_size.is_finite() && svg_size > 0.) {
+            return Ok(Vec::new());
+        }
+        ICON_SIZES.iter().map(|&size| {
+            let mut pixmap = Pixmap::new(size, size).ok_or("invalid size")?;
+            let scale = size as f32 / svg_size;
+            resvg::render(&tree, Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+            Ok((size, pixmap.encode_png().map_err(|e| e.to_string())?))
+        }).collect()
+    }
+
+    pub(super) fn downscale_png(png: &[u8], width: u32) -> Result<Vec<(u32, Vec<u8>)>, String> {
+        let source = Pixmap::decode_png(png).map_err(|e| e.to_string())?;
+        let paint = PixmapPaint { quality: FilterQuality::Bicubic, ..PixmapPaint::default() };
+        ICON_SIZES.iter().filter(|&&size| size < width).map(|&size| {
+            let mut pixmap = Pixmap::new(size, size).ok_or("invalid size")?;
+            let scale = size as f32 / width as f32;
+            pixmap.draw_pixmap(0, 0, source.as_ref(), &paint, Transform::from_scale(scale, scale), None);
+            Ok((size, pixmap.encode_png().map_err(|e| e.to_string())?))
+        }).collect()
+    }
+}
+
+#[cfg(not(feature = "icons"))]
+mod render {
+    pub(super) fn svg_to_pngs(_: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, String> {
+        Ok(Vec::new())
+    }
+
+    pub(super) fn downscale_png(_: &[u8], _: u32) -> Result<Vec<(u32, Vec<u8>)>, String> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="10" height="10" fill="red"/></svg>"#;
+
+    #[test]
+    fn svg_icon_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logo.svg");
+        std::fs::write(&path, SVG).unwrap();
+        let assets = icon_assets(&path, "example-app", &crate::listener::NoOpListener).unwrap();
+        let targets = assets.iter().map(|a| a.c.target_path.to_str().unwrap()).collect::<Vec<_>>();
+        assert!(targets.contains(&"usr/share/icons/hicolor/scalable/apps/example-app.svg"));
+        assert!(targets.contains(&"usr/share/pixmaps/example-app.svg"));
+        if cfg!(feature = "icons") {
+            assert!(targets.contains(&"usr/share/icons/hicolor/48x48/apps/example-app.png"));
+            let png = assets.iter().find(|a| a.c.target_path.ends_with("256x256/apps/example-app.png")).unwrap().source.data().unwrap();
+            assert_eq!(png_size(&png), Some((256, 256)));
+        }
+    }
+
+    #[test]
+    fn zero_size_icons() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logo.svg");
+        std::fs::write(&path, SVG.replace(r#"width="10" height="10"><rect"#, r#"width="0" height="0"><rect"#)).unwrap();
+        if let Ok(assets) = icon_assets(&path, "example-app", &crate::listener::NoOpListener) {
+            let targets = assets.iter().map(|a| a.c.target_path.to_str().unwrap()).collect::<Vec<_>>();
+            assert_eq!(targets, ["usr/share/icons/hicolor/scalable/apps/example-app.svg", "usr/share/pixmaps/example-app.svg"]);
+        }
+
+        let path = dir.path().join("logo.png");
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend([0; 8]);
+        std::fs::write(&path, png).unwrap();
+        assert!(matches!(icon_assets(&path, "example-app", &crate::listener::NoOpListener), Err(CargoDebError::InvalidIcon(..))));
+    }
+}
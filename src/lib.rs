@@ -1,4 +1,4 @@
-#![recursion_limit = "128"]
+#![recursion_limit = "256"]
 #![allow(clippy::case_sensitive_file_extension_comparisons)]
 #![allow(clippy::if_not_else)]
 #![allow(clippy::missing_errors_doc)]
@@ -24,11 +24,18 @@ cargo deb # run this in your Cargo project directory
 ## Making tools for making deb packages
 
 The library interface is experimental. See `main.rs` for usage.
+
+With the `testing` feature, the `testing` module can build a fixture project into a `.deb` and return its contents for assertions.
 */
 
 pub mod deb {
     pub mod ar;
+    pub(crate) mod changes;
+    pub mod compat;
     pub mod control;
+    pub mod delta;
+    pub mod edit;
+    pub(crate) mod read;
     pub mod tar;
 }
 #[macro_use]
@@ -41,27 +48,52 @@ pub mod listener;
 pub(crate) mod parse {
     pub(crate) mod cargo;
     pub(crate) mod manifest;
+    pub(crate) mod metadata_cache;
 }
 pub use crate::config::{Config, DebugSymbols, PackageConfig};
 pub use crate::deb::ar::DebArchive;
 pub use crate::error::*;
 pub use crate::util::compress;
-use crate::util::compress::{CompressConfig, Format};
+use crate::deb::compat::CompatLevel;
+use crate::parse::manifest::{PublishConfig, SignConfig};
+use crate::util::compress::{CompressConfig, Format, GzipConfig};
 
 pub mod assets;
 pub mod config;
+mod conffiles;
+mod changelog;
 mod dependencies;
+mod relations;
+mod desktop;
+mod udev;
+pub mod publish;
+pub mod apt_repo;
+mod sign;
+mod upstream;
+mod debhelper;
+mod source;
+mod hints;
+mod remote;
+mod librust;
+mod icons;
+pub mod distro;
 mod error;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod debuginfo;
-pub use debuginfo::strip_binaries;
+pub use debuginfo::{strip_binaries, Stripper};
 
-use crate::assets::compress_assets;
+use crate::assets::compress_assets_with_gzip;
 use crate::deb::control::ControlArchiveBuilder;
 use crate::deb::tar::Tarball;
+use crate::distro::TargetDistro;
 use crate::listener::Listener;
 use config::{DebConfigOverrides, Multiarch};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
+use std::io::IsTerminal;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{env, fs};
 
 const TAR_REJECTS_CUR_DIR: bool = true;
@@ -71,16 +103,22 @@ const DEFAULT_TARGET: &str = env!("CARGO_DEB_DEFAULT_TARGET");
 
 pub struct CargoDeb {
     options: CargoDebOptions,
+    /// Without the target triple
+    last_target_dir: Option<PathBuf>,
+    /// Packages for multiple distros or variants may need exactly the same build
+    completed_builds: BTreeSet<String>,
+    /// Warn only once per run, not for every package
+    warned_dev_profile: AtomicBool,
 }
 
 impl CargoDeb {
     #[must_use]
     pub const fn new(options: CargoDebOptions) -> Self {
-        Self { options }
+        Self { options, last_target_dir: None, completed_builds: BTreeSet::new(), warned_dev_profile: AtomicBool::new(false) }
     }
 
     pub fn process(mut self, listener: &dyn Listener) -> CDResult<()> {
-        if self.options.install || self.options.target.is_none() {
+        if self.options.install != Install::None || self.options.target.is_none() {
             warn_if_not_linux(listener); // compiling natively for non-linux = nope
         }
 
@@ -91,46 +129,288 @@ impl CargoDeb {
             self.options.compress_system = true;
         }
 
-        // The profile is selected based on the given ClI options and then passed to
-        // cargo build accordingly. you could argue that the other way around is
-        // more desirable. However for now we want all commands coming in via the
-        // same `interface`
-        let selected_profile = self.options.profile;
-        if selected_profile.as_deref() == Some("dev") {
-            listener.warning("dev profile is not supported and will be a hard error in the future. \
-                cargo-deb is for making releases, and it doesn't make sense to use it with dev profiles.".into());
-            listener.warning("To enable debug symbols set `[profile.release] debug = true` instead.".into());
+        if self.options.offline_strict {
+            util::offline::enable_strict();
+            self.options.cargo_locking_flags.offline = true;
+        }
+        if self.options.no_metadata_cache {
+            parse::metadata_cache::disable();
+        }
+
+        let remote_crate = self.options.remote_crate.take()
+            .map(|spec| self.fetch_remote_crate(&spec, listener)).transpose()?;
+
+        if self.options.emit_debhelper_shim {
+            let (config, package_deb) = self.load_config(listener)?;
+            let cargo_deb_flags: Vec<_> = self.options.selected_package_name.iter().map(|p| format!("--package={p}"))
+                .chain(self.options.variant.iter().map(|v| format!("--variant={v}")))
+                .chain(self.options.profile.iter().map(|p| format!("--profile={p}")))
+                .chain(self.options.cargo_locking_flags.flags().map(String::from))
+                .collect();
+            let debian_dir = debhelper::write_shim(&config.package_manifest_dir, &package_deb, &cargo_deb_flags)?;
+            listener.info(format!("debian/rules written to {}. Build with dpkg-buildpackage -b", debian_dir.display()));
+            return Ok(());
+        }
+
+        if self.options.source_package {
+            let (config, mut package_deb) = self.load_config(listener)?;
+            let source = source::make_source_package(&config, &mut package_deb, self.options.cargo_locking_flags, listener)?;
+            listener.info(format!("Upstream tarball written to {}", source.orig_tarball.display()));
+            listener.info(format!("debian/ tarball written to {}", source.debian_tarball.display()));
+            listener.generated_archive(&source.dsc);
+            return Ok(());
+        }
+
+        let mut target_distros = self.options.target_distros.iter()
+            .map(|name| TargetDistro::from_name(name).map(Some))
+            .collect::<CDResult<Vec<_>>>()?;
+        if target_distros.is_empty() {
+            target_distros.push(None);
+        }
+        let variants: Vec<_> = std::iter::once(self.options.variant.take())
+            .chain(self.options.extra_variants.drain(..).map(Some))
+            .collect();
+
+        let manifest_paths: Vec<_> = std::iter::once(self.options.manifest_path.take())
+            .chain(self.options.extra_manifest_paths.drain(..).map(Some))
+            .collect();
+        if self.options.check_config {
+            return self.check_config(manifest_paths, &variants, listener);
+        }
+        let share_target_dir = manifest_paths.len() > 1 && env::var_os("CARGO_TARGET_DIR").is_none();
+        if self.options.print_paths {
+            return self.print_paths(manifest_paths, &variants, &target_distros, share_target_dir, listener);
+        }
+
+        let mut generated_debs = Vec::new();
+        for manifest_path in manifest_paths {
+            self.options.manifest_path = manifest_path;
+            for variant in &variants {
+                self.options.variant.clone_from(variant);
+                for distro in &target_distros {
+                    let generated = self.package(*distro, !self.options.no_build, &generated_debs, listener)?;
+                    generated_debs.push(generated);
+                }
+            }
+            if share_target_dir && self.options.overrides.target_dir.is_none() {
+                // Crates that aren't in a workspace have their own target dirs, but can reuse built dependencies
+                self.options.overrides.target_dir.clone_from(&self.last_target_dir);
+            }
+        }
+
+        check_file_conflicts(&generated_debs)?;
+        self.distribute(&generated_debs, listener)?;
+
+        let to_install = match self.options.install {
+            Install::None => &[][..],
+            Install::Main => &generated_debs[..1],
+            Install::All => &generated_debs[..],
+        };
+        // Cross-compiled packages can't be installed on this machine
+        let host_arch = debian_architecture_from_rust_triple(DEFAULT_TARGET);
+        let (to_install, foreign): (Vec<_>, Vec<_>) = to_install.iter().partition(|deb| deb.architecture == host_arch || deb.architecture == "all");
+        for deb in foreign {
+            listener.warning(format!("Not installing {}, because its architecture is {}, not {host_arch}", deb.path.display(), deb.architecture));
+        }
+        for deb in &to_install {
+            check_downgrade(&deb.name, installed_package_version(&deb.name, &deb.architecture).as_deref(), &deb.version, self.options.allow_downgrade, listener)?;
+        }
+        if self.options.verify {
+            for deb in &to_install {
+                deb::read::verify_deb(&deb.path)?;
+                if let Some(sigstore) = deb.sign.as_ref().and_then(|s| s.sigstore.as_ref()).filter(|_| self.options.sign) {
+                    sign::verify_sigstore(&deb.path, sigstore)?;
+                }
+                listener.info(format!("Verified {}", deb.path.display()));
+            }
+        }
+        if !to_install.is_empty() {
+            install_debs(&to_install.iter().map(|deb| deb.path.as_path()).collect::<Vec<_>>())?;
+        }
+
+        if self.options.offline_strict {
+            let invocations = util::offline::invocations();
+            listener.info(format!("--offline-strict: ran {} commands:\n  {}", invocations.len(), invocations.join("\n  ")));
+        }
+        if let Some(remote_crate) = remote_crate.filter(|_| self.options.keep_temp) {
+            listener.info(format!("Crate's source kept in {}", remote_crate.keep().display()));
+        }
+        Ok(())
+    }
+
+    /// `--crate` replaces the manifest path, and the deb goes to the current dir, since the crate's own dir is temporary
+    fn fetch_remote_crate(&mut self, spec: &str, listener: &dyn Listener) -> CDResult<remote::RemoteCrate> {
+        if self.options.manifest_path.is_some() || !self.options.extra_manifest_paths.is_empty() || self.options.emit_debhelper_shim {
+            return Err(CargoDebError::Str("--crate can't be used with --manifest-path or --emit-debhelper-shim"));
+        }
+        let remote_crate = remote::fetch_crate(spec, self.options.cargo_locking_flags, listener)?;
+        self.options.manifest_path = Some(remote_crate.manifest_path.display().to_string());
+        if self.options.output_path.is_none() {
+            self.options.output_path = Some(format!("{}/", env::current_dir()?.display()));
+        }
+        Ok(remote_crate)
+    }
+
+    /// Parses and validates every package and variant without building anything.
+    /// Reports all problems found, rather than only the first one.
+    fn check_config(&mut self, manifest_paths: Vec<Option<String>>, variants: &[Option<String>], listener: &dyn Listener) -> CDResult<()> {
+        let mut problems = 0;
+        for manifest_path in manifest_paths {
+            self.options.manifest_path = manifest_path;
+            for variant in variants {
+                self.options.variant.clone_from(variant);
+                let (name, found) = match self.load_config(listener) {
+                    Ok((config, mut package_deb)) => {
+                        package_deb.set_multiarch(self.options.multiarch);
+                        package_deb.select_systemd_units(&config.enabled_features(&self.options.cargo_build_flags));
+                        let found = config.check_package(&mut package_deb, listener);
+                        (package_deb.deb_name, found)
+                    },
+                    Err(err) => {
+                        let name = self.options.manifest_path.clone().unwrap_or_else(|| "Cargo.toml".into());
+                        (variant.as_ref().map_or(name.clone(), |v| format!("{name} (variant {v})")), vec![err])
+                    },
+                };
+                for err in &found {
+                    let mut msg = format!("{name}: {err}");
+                    if let Some(reason) = std::error::Error::source(err) {
+                        msg = format!("{msg}\nbecause: {reason}");
+                    }
+                    listener.report(msg);
+                }
+                problems += found.len();
+            }
+        }
+        if problems > 0 {
+            return Err(CargoDebError::ConfigCheckFailed(problems));
+        }
+        listener.info("Configuration is OK".into());
+        Ok(())
+    }
+
+    /// For `--print=paths`. The paths are the same ones that building the package would use.
+    fn print_paths(&mut self, manifest_paths: Vec<Option<String>>, variants: &[Option<String>], target_distros: &[Option<TargetDistro>], share_target_dir: bool, listener: &dyn Listener) -> CDResult<()> {
+        for manifest_path in manifest_paths {
+            self.options.manifest_path = manifest_path;
+            for variant in variants {
+                self.options.variant.clone_from(variant);
+                for distro in target_distros {
+                    let (config, mut package_deb) = self.load_config(listener)?;
+                    self.last_target_dir = Some(config.cargo_target_dir().to_owned());
+                    if let Some(distro) = *distro {
+                        package_deb.set_target_distro(distro);
+                    }
+                    let package = package_deb.deb_name.as_str();
+                    listener.resolved_path(package, "target-dir", config.cargo_target_dir());
+                    listener.resolved_path(package, "profile-dir", &config.profile_dir());
+                    listener.resolved_path(package, "deb-temp-dir", &config.deb_temp_dir(&package_deb));
+                    let deb_path = config.deb_output_path(&package_deb);
+                    listener.resolved_path(package, "deb-output-dir", deb_path.parent().unwrap_or(&deb_path));
+                    listener.resolved_path(package, "deb-path", &deb_path);
+                }
+            }
+            // the same as when building
+            if share_target_dir && self.options.overrides.target_dir.is_none() {
+                self.options.overrides.target_dir.clone_from(&self.last_target_dir);
+            }
         }
+        Ok(())
+    }
 
-        let root_manifest_path = self.options.manifest_path.as_deref().map(Path::new);
-        let (mut config, mut package_deb) = Config::from_manifest(
-            root_manifest_path,
+    fn load_config(&self, listener: &dyn Listener) -> CDResult<(Config, PackageConfig)> {
+        let (config, package_deb) = Config::from_manifest(
+            self.options.manifest_path.as_deref().map(Path::new),
             self.options.selected_package_name.as_deref(),
-            self.options.output_path,
+            self.options.output_path.clone(),
             self.options.target.as_deref(),
             self.options.variant.as_deref(),
-            self.options.overrides,
-            selected_profile,
+            self.options.overrides.clone(),
+            self.options.profile.clone(),
             self.options.separate_debug_symbols,
             self.options.compress_debug_symbols,
             self.options.cargo_locking_flags,
             listener,
         )?;
+        if config.is_dev_profile() && !self.warned_dev_profile.swap(true, Ordering::Relaxed) {
+            listener.warning("dev profile is not supported and will be a hard error in the future. \
+                cargo-deb is for making releases, and it doesn't make sense to use it with dev profiles.".into());
+            listener.warning("To enable debug symbols set `[profile.release] debug = true` instead.".into());
+        }
+        Ok((config, package_deb))
+    }
+
+    /// Makes one deb file
+    /// `already_generated` are kept, even if they're other versions of the same package
+    fn package(&mut self, target_distro: Option<TargetDistro>, build: bool, already_generated: &[GeneratedDeb], listener: &dyn Listener) -> CDResult<GeneratedDeb> {
+        let (mut config, mut package_deb) = self.load_config(listener)?;
+        self.last_target_dir = Some(config.cargo_target_dir().to_owned());
+        if self.options.sandbox {
+            util::sandbox::enable(util::sandbox::SandboxDirs {
+                readable: vec![config.package_manifest_dir.clone(), config.cargo_run_current_dir.clone()],
+                writable: vec![config.target_dir.clone()],
+            })?;
+        }
         package_deb.set_multiarch(self.options.multiarch);
-        config.prepare_assets_before_build(&mut package_deb, listener)?;
+        if let Some(distro) = target_distro {
+            package_deb.set_target_distro(distro);
+        }
+        package_deb.select_systemd_units(&config.enabled_features(&self.options.cargo_build_flags));
+        // held until the deb is written, because preparation of assets cleans up the dir
+        let _lock = util::lock::lock_dir(&config.default_deb_output_dir(), listener)?;
+        // Debs for other target distros differ only in the version, so they'd look like old versions
+        if !package_deb.keep_old_debs {
+            config.remove_old_debs(&package_deb, &already_generated.iter().map(|deb| deb.path.as_path()).collect::<Vec<_>>());
+            package_deb.keep_old_debs = true;
+        }
+        let crate_source_assets = if self.options.crate_source {
+            Some(librust::convert_to_crate_source_package(&config, &mut package_deb, self.options.cargo_locking_flags)?)
+        } else {
+            None
+        };
+        let gzip = GzipConfig {
+            fast: self.options.fast,
+            // a dot dir can't collide with a package name
+            cache_dir: Some(config.default_deb_output_dir().join(".cache/gzip")),
+        };
+        config.prepare_assets_before_build_with_gzip(&mut package_deb, &gzip, listener)?;
+        if let Some(assets) = crate_source_assets {
+            package_deb.assets.resolved.extend(assets);
+        }
 
-        if !self.options.no_build {
-            config.set_cargo_build_flags_for_package(&package_deb, &mut self.options.cargo_build_flags);
-            cargo_build(&config, self.options.target.as_deref(), &self.options.cargo_build_cmd, &self.options.cargo_build_flags, self.options.verbose)?;
+        if build && !self.options.crate_source {
+            // the flags are package-specific, and there may be more packages
+            let mut cargo_build_flags = self.options.cargo_build_flags.clone();
+            config.set_cargo_build_flags_for_package(&package_deb, &mut cargo_build_flags);
+            let build_key = format!("{}|{cargo_build_flags:?}|{:?}|{}", config.package_manifest_dir.display(), config.features, config.default_features);
+            if self.completed_builds.insert(build_key) {
+                cargo_build(&config, self.options.target.as_deref(), &self.options.cargo_build_cmd, &cargo_build_flags, self.options.verbose, listener)?;
+            }
         }
 
-        package_deb.resolve_assets()?;
+        package_deb.resolve_assets_with_listener(listener)?;
+        desktop::check_desktop_assets(&package_deb.assets.resolved, listener)?;
+        if let Some(previous_deb) = &self.options.previous_deb {
+            conffiles::add_changed_conffile_defaults(&mut package_deb, Path::new(previous_deb), &gzip, listener)?;
+        }
 
         // When cross-compiling, resolve dependencies using libs for the target platform (where multiarch is supported)
         let lib_search_path = config.rust_target_triple.as_deref().map(|triple| package_deb.multiarch_lib_dir(triple));
-        package_deb.resolve_binary_dependencies(lib_search_path.as_deref(), listener)?;
+        package_deb.resolve_binary_dependencies_with_explain(lib_search_path.as_deref(), self.options.explain_deps, listener)?;
+        if package_deb.dependency_hints {
+            let dependency_features = config.dependency_features(&self.options.cargo_build_flags)?;
+            hints::add_dependency_hints(&mut package_deb, &dependency_features, listener)?;
+        }
+        if package_deb.implicit_triggers {
+            desktop::add_url_handler_dependency(&mut package_deb, listener)?;
+            udev::add_udev_recommendation(&mut package_deb, listener)?;
+        }
+        package_deb.check_priority_policy(listener)?;
+        let compat_level = self.options.compat_level.as_deref().map(CompatLevel::new).transpose()?;
+        if let Some(compat_level) = &compat_level {
+            compat_level.check_package(&package_deb)?;
+        }
 
-        compress_assets(&mut package_deb, listener)?;
+        compress_assets_with_gzip(&mut package_deb, &gzip, listener)?;
 
         if self.options.strip_override.unwrap_or(config.debug_symbols != DebugSymbols::Keep) {
             strip_binaries(&mut config, &mut package_deb, self.options.target.as_deref(), listener)?;
@@ -138,24 +418,113 @@ impl CargoDeb {
             log::debug!("not stripping debug={:?} strip-flag={:?}", config.debug_symbols, self.options.strip_override);
         }
 
+        package_deb.check_build_paths(&config, self.options.deny_build_paths, listener)?;
         package_deb.sort_assets_by_type();
 
-        let generated = write_deb(&config, &package_deb, &CompressConfig {
+        let (compress_type, control_compress_type) = match &compat_level {
+            Some(compat_level) => (compat_level.data_compression(self.options.compress_type, listener), compat_level.control_compression(self.options.compress_type)),
+            None => (self.options.compress_type, self.options.compress_type),
+        };
+        let compress = CompressConfig {
             fast: self.options.fast,
-            compress_type: self.options.compress_type,
+            best: self.options.best,
+            compress_type,
+            control_compress_type,
             compress_system: self.options.compress_system,
             rsyncable: self.options.rsyncable,
-        }, listener)?;
+            dpkg_compat: self.options.dpkg_compat,
+        };
+        let generated = write_deb(&config, &package_deb, &compress, listener)?;
 
-        listener.generated_archive(&generated);
+        listener.generated_package(&generated, &package_deb.deb_name, &package_deb.deb_version, &package_deb.architecture);
 
-        if self.options.install {
-            install_deb(&generated)?;
+        let deb_temp_dir = config.deb_temp_dir(&package_deb);
+        if self.options.keep_temp {
+            save_control_files(&generated, &deb_temp_dir.join("control"))?;
+            listener.kept_temp_dir(&deb_temp_dir);
+        } else {
+            let _ = fs::remove_dir_all(&deb_temp_dir);
+        }
+        if let Some(old_deb) = &self.options.delta_against {
+            let delta = deb::delta::write_delta(Path::new(old_deb), &generated, &compress, listener)?;
+            listener.info(format!("Delta written to {}", delta.display()));
+        }
+        if self.options.upstream_metadata {
+            let debian_dir = upstream::write_source_metadata(&config, &package_deb, listener)?;
+            listener.info(format!("Upstream metadata written to {}", debian_dir.display()));
+        }
+        let replaces = [&package_deb.replaces, &package_deb.conflicts].into_iter().flatten()
+            .filter_map(|field| relations::RelationList::parse(field).ok())
+            .flat_map(|list| list.groups.into_iter().flatten().map(|r| r.name))
+            .collect();
+        Ok(GeneratedDeb {
+            path: generated,
+            files: package_deb.assets.resolved.into_iter().map(|a| a.c.target_path).collect(),
+            replaces,
+            name: package_deb.deb_name,
+            version: package_deb.deb_version,
+            architecture: package_deb.architecture,
+            sign: package_deb.sign,
+            publish: package_deb.publish,
+        })
+    }
+
+    /// Done only after all packages have been made without conflicts, so that a failed run doesn't leave a partial set in a repository
+    fn distribute(&self, generated_debs: &[GeneratedDeb], listener: &dyn Listener) -> CDResult<()> {
+        if self.options.sign {
+            for deb in generated_debs {
+                sign::sign_package(&deb.path, deb.sign.as_ref(), listener)?;
+            }
+        }
+        if self.options.publish {
+            let publishers = generated_debs.iter()
+                .map(|deb| publish::publisher_from_config(self.options.publish_url.as_deref(), deb.publish.as_ref()))
+                .collect::<CDResult<Vec<_>>>()?;
+            for (deb, publisher) in generated_debs.iter().zip(publishers) {
+                publisher.publish(&deb.path, listener)?;
+            }
+        }
+        if let Some(repo) = &self.options.add_to_repo {
+            let publisher = publish::repo_publisher(repo)?;
+            for deb in generated_debs {
+                publisher.publish(&deb.path, listener)?;
+            }
+        }
+        if let Some(dir) = &self.options.apt_repo_dir {
+            let repo = apt_repo::AptRepo::new(dir);
+            for deb in generated_debs {
+                let dest = repo.add_package(&deb.path, listener)?;
+                listener.info(format!("Added {} to the repository in {}", dest.display(), repo.dir.display()));
+            }
+            repo.update_indexes()?;
         }
         Ok(())
     }
 }
 
+struct GeneratedDeb {
+    path: PathBuf,
+    name: String,
+    version: String,
+    architecture: String,
+    /// Target paths of the assets
+    files: Vec<PathBuf>,
+    /// Packages from `Replaces` and `Conflicts`, which are allowed to have the same files
+    replaces: Vec<String>,
+    sign: Option<SignConfig>,
+    publish: Option<PublishConfig>,
+}
+
+/// Which of the generated packages to install
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Install {
+    None,
+    /// Only the first one
+    Main,
+    /// All that have the same architecture as this machine
+    All,
+}
+
 pub struct CargoDebOptions {
     pub no_build: bool,
     pub strip_override: Option<bool>,
@@ -163,26 +532,82 @@ pub struct CargoDebOptions {
     pub compress_debug_symbols: Option<bool>,
     /// Don't compress heavily
     pub fast: bool,
+    /// Compress as much as possible, e.g. `xz -9e`. Ignored if `fast` is set.
+    pub best: bool,
     /// Build with --verbose
     pub verbose: bool,
     /// Run dpkg -i
-    pub install: bool,
+    pub install: Install,
+    /// Allow `install` to replace a newer installed version
+    pub allow_downgrade: bool,
+    /// Check debs against their `md5sums` before `install`
+    pub verify: bool,
+    /// Sign each package as configured in `[package.metadata.deb.sign]`, before `publish`
+    pub sign: bool,
+    /// Upload the packages after all of them have been written
+    pub publish: bool,
+    /// Overrides `url` of `[package.metadata.deb.publish]`
+    pub publish_url: Option<String>,
+    /// `aptly:<repo>` or `reprepro:<basedir>:<codename>`
+    pub add_to_repo: Option<String>,
+    /// Dir of an apt repository made by cargo-deb, see [`apt_repo::AptRepo`]
+    pub apt_repo_dir: Option<String>,
+    /// Always run `cargo metadata`
+    pub no_metadata_cache: bool,
     pub selected_package_name: Option<String>,
     pub output_path: Option<String>,
     pub variant: Option<String>,
     pub target: Option<String>,
     pub manifest_path: Option<String>,
+    /// Package these variants too, after the `variant`
+    pub extra_variants: Vec<String>,
+    /// Package these crates too, one after another, building them in the same target dir
+    pub extra_manifest_paths: Vec<String>,
     pub cargo_build_cmd: String,
     pub cargo_build_flags: Vec<String>,
     pub overrides: DebConfigOverrides,
     pub compress_type: Format,
     pub compress_system: bool,
+    /// Force Cargo offline, and don't run anything that could access the network
+    pub offline_strict: bool,
+    /// Oldest dpkg version that must be able to install the package
+    pub compat_level: Option<String>,
+    /// Fail instead of informing about build dir paths embedded in binaries
+    pub deny_build_paths: bool,
     pub system_xz: bool,
     pub rsyncable: bool,
+    /// Reproduce tar and ar headers, order of files, and compression of `dpkg-deb`
+    pub dpkg_compat: bool,
     pub profile: Option<String>,
     pub cargo_locking_flags: CargoLockingFlags,
     /// Use Debian's multiarch lib dirs
     pub multiarch: Multiarch,
+    /// Make a separate package for each of these distros, e.g. `debian12`
+    pub target_distros: Vec<String>,
+    /// Run external tools in a sandbox that can only write to the target dir
+    pub sandbox: bool,
+    /// Previous version of the package, to compare conffiles with
+    pub previous_deb: Option<String>,
+    /// Older version of the package to make a delta against
+    pub delta_against: Option<String>,
+    /// Package the crate's source as `librust-<name>-dev` instead of building it
+    pub crate_source: bool,
+    /// Write `debian/watch` and `debian/upstream/metadata` for source packages
+    pub upstream_metadata: bool,
+    /// Write `debian/rules` that runs cargo-deb, instead of making a package
+    pub emit_debhelper_shim: bool,
+    /// Make a Debian source package with vendored dependencies, instead of a `.deb`
+    pub source_package: bool,
+    /// Don't delete stripped binaries and other intermediate files
+    pub keep_temp: bool,
+    /// Only parse and validate the configuration, without building
+    pub check_config: bool,
+    /// Only print where the build and the package files would go, without building
+    pub print_paths: bool,
+    /// Print which binaries and libraries caused each dependency
+    pub explain_deps: bool,
+    /// Download this `name@version` crate from crates.io and package it instead of a local project
+    pub remote_crate: Option<String>,
 }
 
 #[derive(Copy, Clone, Default, Debug)]
@@ -214,30 +639,115 @@ impl Default for CargoDebOptions {
             separate_debug_symbols: None,
             compress_debug_symbols: None,
             fast: false,
+            best: false,
             verbose: false,
-            install: false,
+            install: Install::None,
+            allow_downgrade: false,
+            verify: false,
+            sign: false,
+            publish: false,
+            publish_url: None,
+            add_to_repo: None,
+            apt_repo_dir: None,
+            no_metadata_cache: false,
             selected_package_name: None,
             output_path: None,
             variant: None,
             target: None,
             manifest_path: None,
+            extra_manifest_paths: Vec::new(),
+            extra_variants: Vec::new(),
             cargo_build_cmd: "build".into(),
             cargo_build_flags: Vec::new(),
             overrides: DebConfigOverrides::default(),
             compress_type: Format::Xz,
             compress_system: false,
+            compat_level: None,
+            deny_build_paths: false,
+            offline_strict: false,
             system_xz: false,
             rsyncable: false,
+            dpkg_compat: false,
             profile: None,
             cargo_locking_flags: CargoLockingFlags::default(),
             multiarch: Multiarch::None,
+            target_distros: Vec::new(),
+            sandbox: false,
+            previous_deb: None,
+            delta_against: None,
+            crate_source: false,
+            upstream_metadata: false,
+            emit_debhelper_shim: false,
+            source_package: false,
+            keep_temp: false,
+            check_config: false,
+            print_paths: false,
+            explain_deps: false,
+            remote_crate: None,
         }
     }
 }
 
 /// Run `dpkg` to install `deb` archive at the given path
 pub fn install_deb(path: &Path) -> CDResult<()> {
-    let status = Command::new("sudo").arg("dpkg").arg("-i").arg(path)
+    install_debs(&[path])
+}
+
+/// Version of the package as currently installed on this machine
+fn installed_package_version(name: &str, architecture: &str) -> Option<String> {
+    let name = if architecture == "all" { name.to_owned() } else { format!("{name}:{architecture}") };
+    let output = util::offline::audit(Command::new("dpkg-query").args(["-W", "-f", "${Version}", &name])).ok()?
+        .output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok().filter(|v| !v.trim().is_empty()).map(|v| v.trim().to_owned())
+}
+
+/// Installing an older version by accident is easy when the installed one comes from elsewhere, e.g. a repository
+fn check_downgrade(name: &str, installed_version: Option<&str>, new_version: &str, allow_downgrade: bool, listener: &dyn Listener) -> CDResult<()> {
+    let Some(installed_version) = installed_version else {
+        return Ok(());
+    };
+    if relations::compare_debian_versions(new_version, installed_version) != std::cmp::Ordering::Less {
+        return Ok(());
+    }
+    if !allow_downgrade {
+        return Err(CargoDebError::Downgrade(name.to_owned(), installed_version.to_owned(), new_version.to_owned()));
+    }
+    listener.warning(format!("Downgrading {name} from {installed_version} to {new_version}"));
+    Ok(())
+}
+
+/// dpkg refuses to install a package that overwrites files of another package, unless it declares `Replaces`.
+/// Packages with the same name are alternative builds (e.g. for other distros), so they can't conflict.
+/// All conflicting pairs are reported at once.
+fn check_file_conflicts(debs: &[GeneratedDeb]) -> CDResult<()> {
+    let mut owners: HashMap<&Path, Vec<&GeneratedDeb>> = HashMap::new();
+    let mut conflicts: BTreeMap<(&str, &str), BTreeSet<&Path>> = BTreeMap::new();
+    for deb in debs {
+        for path in &deb.files {
+            let path_owners = owners.entry(path.as_path()).or_default();
+            for other in path_owners.iter() {
+                if other.name == deb.name || other.replaces.contains(&deb.name) || deb.replaces.contains(&other.name) {
+                    continue;
+                }
+                conflicts.entry((&other.name, &deb.name)).or_default().insert(path);
+            }
+            path_owners.push(deb);
+        }
+    }
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    Err(CargoDebError::FileConflict(conflicts.into_iter().map(|((a, b), paths)| {
+        format!("Packages {a} and {b} both contain {}", paths.iter().map(|p| format!("/{}", p.display())).collect::<Vec<_>>().join(", "))
+    }).collect()))
+}
+
+/// Run `dpkg` to install all the `deb` archives at once, so that they can depend on each other
+pub fn install_debs(paths: &[&Path]) -> CDResult<()> {
+    let status = util::offline::audit(Command::new("sudo").arg("dpkg").arg("-i").args(paths))?
         .status()?;
     if !status.success() {
         return Err(CargoDebError::InstallFailed);
@@ -245,26 +755,29 @@ pub fn install_deb(path: &Path) -> CDResult<()> {
     Ok(())
 }
 
-pub fn write_deb(config: &Config, package_deb: &PackageConfig, &compress::CompressConfig { fast, compress_type, compress_system, rsyncable }: &compress::CompressConfig, listener: &dyn Listener) -> Result<PathBuf, CargoDebError> {
-    let (control_builder, data_result) = rayon::join(
+pub fn write_deb(config: &Config, package_deb: &PackageConfig, &compress::CompressConfig { fast, best, compress_type, control_compress_type, compress_system, rsyncable, dpkg_compat }: &compress::CompressConfig, listener: &dyn Listener) -> Result<PathBuf, CargoDebError> {
+    let level = compress::CompressionLevel::new(fast, best);
+    let select_compressor = if dpkg_compat { util::compress::select_dpkg_compat_compressor_with_level } else { util::compress::select_compressor_with_level };
+    if dpkg_compat && matches!(compress_type, Format::Gzip) {
+        listener.warning("dpkg-deb compresses gzip with zlib, so gzipped archives can't be byte-for-byte the same. Use xz".into());
+    }
+    // Initialize the contents of the data archive (files that go into the filesystem).
+    let dest = select_compressor(level, compress_type, compress_system)?;
+    let archive = if dpkg_compat { Tarball::new_dpkg_compat(dest, package_deb.default_timestamp) } else { Tarball::new(dest, package_deb.default_timestamp) };
+    let (compressed, hashes) = archive.archive_files(package_deb, rsyncable, listener)?;
+    let original_data_size = compressed.uncompressed_size;
+    // The control archive needs hashes of the data, but can be built while the data compression finishes
+    let (data_compressed, control_compressed) = rayon::join(
+        move || compressed.finish(),
         move || {
             // The control archive is the metadata for the package manager
-            let mut control_builder = ControlArchiveBuilder::new(util::compress::select_compressor(fast, compress_type, compress_system)?, package_deb.default_timestamp, listener);
-            control_builder.generate_archive(config, package_deb)?;
-            Ok::<_, CargoDebError>(control_builder)
-        },
-        move || {
-            // Initialize the contents of the data archive (files that go into the filesystem).
-            let dest = util::compress::select_compressor(fast, compress_type, compress_system)?;
-            let archive = Tarball::new(dest, package_deb.default_timestamp);
-            let compressed = archive.archive_files(package_deb, rsyncable, listener)?;
-            let original_data_size = compressed.uncompressed_size;
-            Ok::<_, CargoDebError>((compressed.finish()?, original_data_size))
+            let mut control_builder = ControlArchiveBuilder::new_with_dpkg_compat(select_compressor(level, control_compress_type, compress_system)?, package_deb.default_timestamp, dpkg_compat, listener);
+            control_builder.generate_archive(config, package_deb, &hashes)?;
+            control_builder.finish()?.finish()
         },
     );
-    let control_builder = control_builder?;
-    let (data_compressed, original_data_size) = data_result?;
-    let control_compressed = control_builder.finish()?.finish()?;
+    let data_compressed = data_compressed?;
+    let control_compressed = control_compressed?;
 
     let mut deb_contents = DebArchive::new(config.deb_output_path(package_deb), package_deb.default_timestamp)?;
 
@@ -276,17 +789,35 @@ pub fn write_deb(config: &Config, package_deb: &PackageConfig, &compress::Compre
     ));
     deb_contents.add_data(data_compressed)?;
     let generated = deb_contents.finish()?;
-
-    let deb_temp_dir = config.deb_temp_dir(package_deb);
-    let _ = fs::remove_dir(deb_temp_dir);
-
+    if package_deb.changes_file {
+        let changes = deb::changes::write_changes(config, package_deb, &generated, listener)?;
+        listener.info(format!("Changes file written to {}", changes.display()));
+    }
     Ok(generated)
 }
 
+/// Extracts the control files from the deb, for debugging
+fn save_control_files(deb_path: &Path, dir: &Path) -> CDResult<()> {
+    let members = deb::read::read_deb_members(deb_path)?;
+    let control_member = members.iter().find(|m| m.is_control()).ok_or("deb archive doesn't have control.tar")?;
+    fs::create_dir_all(dir).map_err(|e| CargoDebError::IoFile("unable to create dir", e, dir.to_owned()))?;
+    for entry in deb::read::read_tar_entries(&control_member.decompressed()?)? {
+        if entry.entry_type.is_file() {
+            let path = dir.join(entry.path.file_name().ok_or("bad path")?);
+            fs::write(&path, &entry.data).map_err(|e| CargoDebError::IoFile("unable to write control file", e, path))?;
+        }
+    }
+    Ok(())
+}
+
 /// Builds a binary with `cargo build`
-pub fn cargo_build(config: &Config, rust_target_triple: Option<&str>, build_command: &str, build_flags: &[String], verbose: bool) -> CDResult<()> {
+/// Cargo's output is shown as-is in a terminal, and with `verbose`. Otherwise it's summarized via the `listener`.
+pub fn cargo_build(config: &Config, rust_target_triple: Option<&str>, build_command: &str, build_flags: &[String], verbose: bool, listener: &dyn Listener) -> CDResult<()> {
     let mut cmd = Command::new("cargo");
     cmd.current_dir(&config.cargo_run_current_dir);
+    if let Some(dir) = &config.cargo_target_dir_override {
+        cmd.env("CARGO_TARGET_DIR", dir);
+    }
     cmd.args(build_command.split(' ')
         .filter(|cmd| if !cmd.starts_with('-') { true } else {
             log::error!("unexpected flag in build command name: {cmd}");
@@ -298,6 +829,12 @@ pub fn cargo_build(config: &Config, rust_target_triple: Option<&str>, build_comm
     if verbose {
         cmd.arg("--verbose");
     }
+    // Build scripts and C build systems may follow Debian conventions. dpkg-buildpackage sets these already.
+    for (name, value) in deb_host_env(rust_target_triple.unwrap_or(DEFAULT_TARGET)) {
+        if env::var_os(name).is_none() {
+            cmd.env(name, value);
+        }
+    }
     if let Some(rust_target_triple) = rust_target_triple {
         cmd.args(["--target", rust_target_triple]);
         // Set helpful defaults for cross-compiling
@@ -319,7 +856,13 @@ pub fn cargo_build(config: &Config, rust_target_triple: Option<&str>, build_comm
 
     log::debug!("cargo build {:?}", cmd.get_args());
 
-    let status = cmd.status()
+    // Other build commands (e.g. `auditable build`) may not support JSON messages, and user's flags may change their format
+    let show_cargo_output = verbose || std::io::stderr().is_terminal() || build_command != "build"
+        || build_flags.iter().any(|f| f.starts_with("--message-format") || f == "--verbose" || (f.starts_with("-v") && f[1..].bytes().all(|c| c == b'v')));
+    if !show_cargo_output {
+        return util::build_progress::run(&mut cmd, config.build_crate_count, listener);
+    }
+    let status = util::offline::audit(&mut cmd)?.status()
         .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
     if !status.success() {
         return Err(CargoDebError::BuildFailed);
@@ -327,11 +870,26 @@ pub fn cargo_build(config: &Config, rust_target_triple: Option<&str>, build_comm
     Ok(())
 }
 
+/// `DEB_HOST_*` variables, like `dpkg-architecture` would set for the target
+fn deb_host_env(rust_target_triple: &str) -> [(&'static str, String); 3] {
+    let multiarch = debian_triple_from_rust_triple(rust_target_triple);
+    // the GNU type differs from multiarch only for i386
+    let gnu_type = match multiarch.strip_prefix("i386-") {
+        Some(rest) => format!("i686-{rest}"),
+        None => multiarch.clone(),
+    };
+    [
+        ("DEB_HOST_ARCH", debian_architecture_from_rust_triple(rust_target_triple).to_owned()),
+        ("DEB_HOST_MULTIARCH", multiarch),
+        ("DEB_HOST_GNU_TYPE", gnu_type),
+    ]
+}
+
 // Maps Rust's blah-unknown-linux-blah to Debian's blah-linux-blah. This is debian's multiarch.
 fn debian_triple_from_rust_triple(rust_target_triple: &str) -> String {
     let mut p = rust_target_triple.split('-');
     let arch = p.next().unwrap();
-    let abi = p.last().unwrap_or("gnu");
+    let abi = p.next_back().unwrap_or("gnu");
 
     let (darch, dabi) = match (arch, abi) {
         ("i586" | "i686", _) => ("i386", "gnu"),
@@ -355,7 +913,7 @@ fn debian_triple_from_rust_triple(rust_target_triple: &str) -> String {
 pub(crate) fn debian_architecture_from_rust_triple(rust_target_triple: &str) -> &str {
     let mut parts = rust_target_triple.split('-');
     let arch = parts.next().unwrap();
-    let abi = parts.last().unwrap_or("");
+    let abi = parts.next_back().unwrap_or("");
     match (arch, abi) {
         // https://wiki.debian.org/Multiarch/Tuples
         // rustc --print target-list
@@ -372,7 +930,7 @@ pub(crate) fn debian_architecture_from_rust_triple(rust_target_triple: &str) ->
         ("powerpc", "gnuspe" | "muslspe") => "powerpcspe",
         ("powerpc64", _) => "ppc64",
         ("powerpc64le", _) => "ppc64el",
-        ("riscv64gc", _) => "riscv64",
+        (risc, _) if risc.starts_with("riscv64") => "riscv64",
         ("i586" | "i686" | "x86", _) => "i386",
         ("x86_64", "gnux32") => "x32",
         ("x86_64", _) => "amd64",
@@ -409,7 +967,7 @@ fn ensure_all_rust_targets_map_to_debian_targets() {
 
     let list = std::process::Command::new("rustc").arg("--print=target-list").output().unwrap().stdout;
     for rust_target in std::str::from_utf8(&list).unwrap().lines().filter(|a| a.contains("linux")) {
-        if ["csky", "hexagon", "riscv32gc"].contains(&rust_target.split_once('-').unwrap().0) {
+        if ["csky", "hexagon", "riscv32gc", "wasm32"].contains(&rust_target.split_once('-').unwrap().0) {
             continue; // Rust supports more than Debian!
         }
         let deb_arch = debian_architecture_from_rust_triple(rust_target);
@@ -419,6 +977,14 @@ fn ensure_all_rust_targets_map_to_debian_targets() {
     }
 }
 
+#[test]
+fn deb_host_env_for_cross_targets() {
+    let env = |triple| deb_host_env(triple).map(|(_, v)| v);
+    assert_eq!(env("armv7-unknown-linux-gnueabihf"), ["armhf", "arm-linux-gnueabihf", "arm-linux-gnueabihf"]);
+    assert_eq!(env("i686-unknown-linux-gnu"), ["i386", "i386-linux-gnu", "i686-linux-gnu"]);
+    assert_eq!(env("aarch64-unknown-linux-musl"), ["arm64", "aarch64-linux-gnu", "aarch64-linux-gnu"]);
+}
+
 #[cfg(target_os = "linux")]
 fn warn_if_not_linux(_: &dyn Listener) {
 }
@@ -427,3 +993,36 @@ fn warn_if_not_linux(_: &dyn Listener) {
 fn warn_if_not_linux(listener: &dyn Listener) {
     listener.warning(format!("You're creating a package only for {}, and not for Linux.\nUse --target if you want to cross-compile.", std::env::consts::OS));
 }
+
+#[test]
+fn detects_file_conflicts() {
+    let deb = |name: &str, files: &[&str], replaces: &[&str]| GeneratedDeb {
+        path: PathBuf::new(),
+        name: name.into(),
+        version: "1.0".into(),
+        architecture: "amd64".into(),
+        files: files.iter().map(PathBuf::from).collect(),
+        replaces: replaces.iter().map(|&r| r.into()).collect(),
+        sign: None,
+        publish: None,
+    };
+    assert!(check_file_conflicts(&[deb("a", &["usr/bin/a", "etc/x"], &[]), deb("a", &["etc/x"], &[])]).is_ok());
+    assert!(check_file_conflicts(&[deb("a", &["usr/bin/a", "etc/x"], &[]), deb("b", &["etc/x"], &["a"])]).is_ok());
+    assert!(matches!(check_file_conflicts(&[deb("a", &["usr/bin/a", "etc/x"], &[]), deb("b", &["etc/x"], &[])]),
+        Err(CargoDebError::FileConflict(conflicts)) if conflicts == ["Packages a and b both contain /etc/x"]));
+    let err = check_file_conflicts(&[deb("a", &["etc/x", "etc/y"], &[]), deb("b", &["etc/x", "etc/y"], &[]), deb("c", &["etc/x"], &[])]).unwrap_err();
+    let CargoDebError::FileConflict(conflicts) = &err else { panic!("{err}") };
+    assert_eq!(conflicts, &["Packages a and b both contain /etc/x, /etc/y", "Packages a and c both contain /etc/x", "Packages b and c both contain /etc/x"]);
+}
+
+#[test]
+fn refuses_downgrade() {
+    let listener = listener::CapturingListener::new();
+
+    assert!(check_downgrade("foo", None, "1.0-1", false, &listener).is_ok());
+    assert!(check_downgrade("foo", Some("1.0-1"), "1.0-1", false, &listener).is_ok());
+    assert!(check_downgrade("foo", Some("1.0~rc1-1"), "1.0-1", false, &listener).is_ok());
+    assert!(matches!(check_downgrade("foo", Some("1:0.9-1"), "1.0-1", false, &listener), Err(CargoDebError::Downgrade(..))));
+    assert!(check_downgrade("foo", Some("1.0-2"), "1.0-1", true, &listener).is_ok());
+    assert_eq!(listener.warnings(), ["Downgrading foo from 1.0-2 to 1.0-1"]);
+}
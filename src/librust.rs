@@ -0,0 +1,177 @@
+//! `--crate-source` packages a library crate's source as `librust-<name>-dev`, following the conventions of
+//! [debcargo](https://salsa.debian.org/rust-team/debcargo), so that the crate can be used from
+//! `/usr/share/cargo/registry` as a local registry with `cargo --offline`.
+
+use crate::assets::{Asset, AssetSource, IsBuilt};
+use crate::config::{Config, PackageConfig};
+use crate::deb::read::read_tar_entries;
+use crate::error::{CDResult, CargoDebError};
+use crate::util::offline;
+use crate::CargoLockingFlags;
+use cargo_toml::{Dependency, Manifest};
+use flate2::read::GzDecoder;
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+use tar::EntryType;
+
+const REGISTRY_DIR: &str = "usr/share/cargo/registry";
+
+/// Runs `cargo package`, and makes the package an arch-independent `librust-<name>-dev` with the source of the `.crate` file.
+///
+/// Must be called before assets are prepared, because it changes the package name. Returns the assets to add.
+pub(crate) fn convert_to_crate_source_package(config: &Config, package_deb: &mut PackageConfig, cargo_locking_flags: CargoLockingFlags) -> CDResult<Vec<Asset>> {
+    let crate_file = run_cargo_package(config, package_deb, cargo_locking_flags)?;
+    let mut tar = Vec::new();
+    GzDecoder::new(&crate_file[..]).read_to_end(&mut tar)?;
+    let entries = read_tar_entries(&tar)?;
+
+    let crate_dir = format!("{}-{}", package_deb.name, package_deb.cargo_version);
+    let manifest_path = PathBuf::from(&crate_dir).join("Cargo.toml");
+    let manifest = entries.iter().find(|e| e.path == manifest_path)
+        .ok_or(CargoDebError::Str("the .crate file made by `cargo package` doesn't have Cargo.toml"))?;
+    let manifest = Manifest::from_slice(&manifest.data)
+        .map_err(|e| CargoDebError::TomlParsing(e, manifest_path.clone()))?;
+
+    let base_name = debian_crate_name(&package_deb.name);
+    package_deb.deb_name = format!("librust-{base_name}-dev");
+    package_deb.architecture = "all".into();
+    package_deb.section = Some("rust".into());
+    package_deb.description = format!("{} - Rust source code", package_deb.description);
+    package_deb.provides = Some(provides(&base_name, &package_deb.cargo_version, &package_deb.deb_version, &manifest));
+    // dependencies for other platforms aren't needed to build the crate here
+    let platform_dependencies = crate::parse::manifest::platform_dependencies(&config.package_manifest_dir.join("Cargo.toml"),
+        config.rust_target_triple(), config.cargo_target_dir_override.as_deref(), cargo_locking_flags)?;
+    package_deb.wildcard_depends = depends(&manifest, &platform_dependencies);
+    // only the copyright and changelog are added to the source
+    package_deb.raw_assets = Some(Vec::new());
+
+    let mut assets: Vec<_> = entries.into_iter()
+        .filter(|e| e.entry_type == EntryType::Regular)
+        .filter(|e| !e.path.ends_with(".cargo_vcs_info.json"))
+        .map(|e| {
+            let target_path = PathBuf::from(REGISTRY_DIR).join(&e.path);
+            let chmod = if e.mode & 0o111 != 0 { 0o755 } else { 0o644 };
+            Asset::new(AssetSource::Data(e.data), target_path, chmod, IsBuilt::No, false)
+        })
+        .collect();
+    // Cargo requires this in directory sources. Without the package checksum it works with any Cargo.lock.
+    assets.push(Asset::new(AssetSource::Data(br#"{"package":null,"files":{}}"#.to_vec()),
+        PathBuf::from(REGISTRY_DIR).join(&crate_dir).join(".cargo-checksum.json"), 0o644, IsBuilt::No, false));
+    Ok(assets)
+}
+
+pub(crate) fn run_cargo_package(config: &Config, package_deb: &PackageConfig, cargo_locking_flags: CargoLockingFlags) -> CDResult<Vec<u8>> {
+    let manifest_path = config.package_manifest_dir.join("Cargo.toml");
+    let mut cmd = Command::new("cargo");
+    cmd.current_dir(&config.cargo_run_current_dir)
+        .args(["package", "--no-verify", "--allow-dirty", "--manifest-path"])
+        .arg(&manifest_path)
+        .args(["-p", &package_deb.name])
+        .args(cargo_locking_flags.flags());
+    if let Some(dir) = &config.cargo_target_dir_override {
+        cmd.env("CARGO_TARGET_DIR", dir);
+    }
+    let output = offline::audit(&mut cmd)?.output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError("cargo", "package".into(), output.stderr));
+    }
+    // not in the per-target dir when cross-compiling
+    let crate_path = config.cargo_target_dir().join("package").join(format!("{}-{}.crate", package_deb.name, package_deb.cargo_version));
+    std::fs::read(&crate_path).map_err(|e| CargoDebError::IoFile("cargo package didn't make the crate file", e, crate_path))
+}
+
+fn debian_crate_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+/// debcargo's `1` for `1.x.y`, but `0.4` for `0.4.x`
+fn semver_compatible_version(version: &str) -> Option<String> {
+    let mut parts = version.split('.');
+    let major = parts.next().filter(|m| !m.is_empty() && m.bytes().all(|c| c.is_ascii_digit()))?;
+    match (major, parts.next()) {
+        ("0", Some(minor)) => Some(format!("0.{minor}")),
+        _ => Some(major.to_owned()),
+    }
+}
+
+/// Versioned names and features, e.g. `librust-foo-1-dev`, `librust-foo-1.2-dev`, and `librust-foo+serde-dev`
+fn provides(base_name: &str, cargo_version: &str, deb_version: &str, manifest: &Manifest) -> String {
+    let mut names = BTreeSet::new();
+    let core_version = cargo_version.split(['-', '+']).next().unwrap_or(cargo_version);
+    let parts: Vec<_> = core_version.split('.').collect();
+    for len in 1..=parts.len() {
+        names.insert(format!("librust-{base_name}-{}-dev", parts[..len].join(".")));
+    }
+    let uses_dep_syntax = manifest.features.values().flatten().any(|f| f.starts_with("dep:"));
+    let optional_deps = manifest.dependencies.iter()
+        .filter(|(_, dep)| dep.optional() && !uses_dep_syntax)
+        .map(|(name, _)| name);
+    for feature in manifest.features.keys().chain(optional_deps) {
+        names.insert(format!("librust-{base_name}+{}-dev", debian_crate_name(feature)));
+    }
+    names.into_iter().map(|name| format!("{name} (= {deb_version})")).collect::<Vec<_>>().join(", ")
+}
+
+/// Required (non-optional) dependencies and build-dependencies, e.g. `librust-serde-1-dev (>= 1.0.100-~~)`.
+/// Platform-specific ones are included if they're in `platform_dependencies`.
+fn depends(manifest: &Manifest, platform_dependencies: &BTreeSet<String>) -> String {
+    let mut deps = BTreeSet::new();
+    let all_platforms = manifest.dependencies.iter().chain(&manifest.build_dependencies).map(|dep| (dep, true));
+    let target_specific = manifest.target.values().flat_map(|t| t.dependencies.iter().chain(&t.build_dependencies)).map(|dep| (dep, false));
+    for ((name, dep), is_for_all_platforms) in all_platforms.chain(target_specific) {
+        let crate_name = dep.package().unwrap_or(name);
+        if dep.optional() || !(is_for_all_platforms || platform_dependencies.contains(crate_name)) {
+            continue;
+        }
+        deps.insert(librust_dependency(crate_name, dep));
+    }
+    deps.into_iter().collect::<Vec<_>>().join(", ")
+}
+
+fn librust_dependency(crate_name: &str, dep: &Dependency) -> String {
+    let name = debian_crate_name(crate_name);
+    let req = dep.req().trim();
+    let (op, version) = match req.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => (req[..start].trim(), req[start..].split([',', ' ']).next().unwrap_or_default()),
+        None => return format!("librust-{name}-dev"),
+    };
+    let Some(compatible) = semver_compatible_version(version) else {
+        return format!("librust-{name}-dev");
+    };
+    match op {
+        "" | "^" | "~" | "=" | ">=" => format!("librust-{name}-{compatible}-dev (>= {version}-~~)"),
+        _ => format!("librust-{name}-dev"),
+    }
+}
+
+#[test]
+fn debcargo_names() {
+    let manifest = Manifest::from_str(r#"
+        [package]
+        name = "foo_bar"
+        version = "0.4.2"
+        [features]
+        default = ["std"]
+        std = []
+        [dependencies]
+        serde = "1.0.100"
+        log = { version = "~0.4.17", optional = true }
+        libc = ">= 0.2, < 0.3"
+        anything = "*"
+        [build-dependencies]
+        cc = { version = "1", package = "cc_rs" }
+        [target.'cfg(unix)'.dependencies]
+        nix = "0.29"
+        [target.'cfg(windows)'.dependencies]
+        winapi = "0.3"
+    "#).unwrap();
+    assert_eq!(provides("foo-bar", "0.4.2", "0.4.2-1", &manifest),
+        "librust-foo-bar+default-dev (= 0.4.2-1), librust-foo-bar+log-dev (= 0.4.2-1), librust-foo-bar+std-dev (= 0.4.2-1), \
+        librust-foo-bar-0-dev (= 0.4.2-1), librust-foo-bar-0.4-dev (= 0.4.2-1), librust-foo-bar-0.4.2-dev (= 0.4.2-1)");
+    let platform_dependencies = ["nix".to_string()].into();
+    assert_eq!(depends(&manifest, &platform_dependencies),
+        "librust-anything-dev, librust-cc-rs-1-dev (>= 1-~~), librust-libc-0.2-dev (>= 0.2-~~), librust-nix-0.29-dev (>= 0.29-~~), librust-serde-1-dev (>= 1.0.100-~~)");
+}
@@ -1,22 +1,182 @@
+use serde::Serialize;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 #[cfg_attr(test, mockall::automock)]
 pub trait Listener: Send + Sync {
     fn warning(&self, s: String);
     fn info(&self, s: String);
 
+    /// Notified after each step of a long operation, e.g. stripping of each binary
+    fn progress(&self, operation: &str, done: usize, total: usize) {
+        self.info(format!("{operation} ({done}/{total})"));
+    }
+
     /// Notified when finished writing .deb file (possibly before install)
     fn generated_archive(&self, path: &Path) {
         println!("{}", path.display());
     }
+
+    /// Like `generated_archive`, with the package's metadata
+    fn generated_package(&self, path: &Path, _name: &str, _version: &str, _architecture: &str) {
+        self.generated_archive(path);
+    }
+
+    /// Output that has been explicitly requested, like `--explain-deps`. It's shown regardless of verbosity.
+    fn report(&self, s: String) {
+        eprintln!("{s}");
+    }
+
+    /// Notified when intermediate files have been kept with `--keep-temp`
+    fn kept_temp_dir(&self, path: &Path) {
+        eprintln!("Intermediate files are in {}", path.display());
+    }
+
+    /// Paths requested with `--print=paths`, e.g. `target-dir` of the package. Printed to stdout as `package:name=path`.
+    fn resolved_path(&self, package: &str, name: &str, path: &Path) {
+        println!("{package}:{name}={}", path.display());
+    }
+
+    /// Whether diagnostics that take extra work to collect, like `systemd_unit_candidate`, are going to be shown
+    fn wants_diagnostics(&self) -> bool {
+        true
+    }
+
+    /// Explains how a file was treated when searching for systemd units. Only sent if `wants_diagnostics` is true.
+    fn systemd_unit_candidate<'a>(&self, _candidate: &UnitCandidateInfo<'a>, message: String) {
+        self.info(message);
+    }
+}
+
+/// A file considered when searching for systemd units
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitCandidateInfo<'a> {
+    pub path: &'a Path,
+    /// File extension of the unit type, e.g. `service`
+    pub unit_type: &'a str,
+    pub template: bool,
+    /// `used`, `not-found`, `shadowed` or `name-mismatch`
+    pub status: &'a str,
+    /// For `shadowed`, the file that has been used instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadowed_by: Option<&'a Path>,
 }
 
+/// For `--quiet`. Output that has been explicitly requested, like `report` and `resolved_path`, is still shown.
 pub struct NoOpListener;
 impl Listener for NoOpListener {
     fn info(&self, _s: String) {}
     fn warning(&self, _s: String) {}
     fn generated_archive(&self, _: &Path) {}
+
+    fn wants_diagnostics(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Warning,
+    Info,
+    Progress,
+    Report,
+    GeneratedArchive,
+    KeptTempDir,
+    ResolvedPath,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub level: Level,
+    /// For `GeneratedArchive` and `KeptTempDir` it's the path, for `ResolvedPath` it's `package:name=path`
+    pub message: String,
+    pub time: SystemTime,
+}
+
+/// Stores all events in memory, so that they can be checked afterwards.
+/// Useful for tests, and for programs that use cargo-deb as a library and show messages their own way.
+#[derive(Default)]
+pub struct CapturingListener {
+    events: Mutex<Vec<Event>>,
+    generated: Mutex<Vec<PathBuf>>,
+}
+
+impl CapturingListener {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, level: Level, message: String) {
+        self.events.lock().unwrap().push(Event { level, message, time: SystemTime::now() });
+    }
+
+    /// All events so far, in order
+    #[must_use]
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Returns events so far, and clears the list
+    pub fn take_events(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    /// Messages of the given level
+    #[must_use]
+    pub fn messages(&self, level: Level) -> Vec<String> {
+        self.events.lock().unwrap().iter().filter(|e| e.level == level).map(|e| e.message.clone()).collect()
+    }
+
+    #[must_use]
+    pub fn warnings(&self) -> Vec<String> {
+        self.messages(Level::Warning)
+    }
+
+    /// Any warning containing this substring
+    #[must_use]
+    pub fn has_warning(&self, needle: &str) -> bool {
+        self.events.lock().unwrap().iter().any(|e| e.level == Level::Warning && e.message.contains(needle))
+    }
+
+    /// Paths of `.deb` files generated so far
+    #[must_use]
+    pub fn generated_archives(&self) -> Vec<PathBuf> {
+        self.generated.lock().unwrap().clone()
+    }
+}
+
+impl Listener for CapturingListener {
+    fn warning(&self, s: String) {
+        self.push(Level::Warning, s);
+    }
+
+    fn info(&self, s: String) {
+        self.push(Level::Info, s);
+    }
+
+    fn progress(&self, operation: &str, done: usize, total: usize) {
+        self.push(Level::Progress, format!("{operation} ({done}/{total})"));
+    }
+
+    fn generated_archive(&self, path: &Path) {
+        self.generated.lock().unwrap().push(path.to_owned());
+        self.push(Level::GeneratedArchive, path.display().to_string());
+    }
+
+    fn report(&self, s: String) {
+        self.push(Level::Report, s);
+    }
+
+    fn kept_temp_dir(&self, path: &Path) {
+        self.push(Level::KeptTempDir, path.display().to_string());
+    }
+
+    fn resolved_path(&self, package: &str, name: &str, path: &Path) {
+        self.push(Level::ResolvedPath, format!("{package}:{name}={}", path.display()));
+    }
 }
 
 pub struct StdErrListener {
@@ -38,4 +198,131 @@ impl Listener for StdErrListener {
             }
         }
     }
+
+    /// Progress of the build is shown regardless of verbosity, because it replaces Cargo's own output
+    fn progress(&self, operation: &str, done: usize, total: usize) {
+        if self.verbose || operation == "Building" {
+            eprintln!("{operation} ({done}/{total})");
+        }
+    }
+
+    fn wants_diagnostics(&self) -> bool {
+        self.verbose
+    }
+}
+
+/// For `--message-format=json`. Prints one JSON object per line to stdout, with a `reason` field like Cargo's messages.
+pub struct JsonListener {
+    /// Also emit `info` and `progress` messages
+    pub verbose: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum JsonMessage<'a> {
+    Warning { message: &'a str },
+    Info { message: &'a str },
+    Progress { operation: &'a str, done: usize, total: usize },
+    GeneratedArchive {
+        path: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        architecture: Option<&'a str>,
+    },
+    Report { message: &'a str },
+    KeptTempDir { path: &'a Path },
+    ResolvedPath { package: &'a str, name: &'a str, path: &'a Path },
+    SystemdUnitCandidate {
+        #[serde(flatten)]
+        candidate: &'a UnitCandidateInfo<'a>,
+        message: &'a str,
+    },
+}
+
+impl JsonListener {
+    fn emit(&self, message: &JsonMessage<'_>) {
+        if let Ok(json) = serde_json::to_string(message) {
+            let _ = writeln!(std::io::stdout().lock(), "{json}");
+        }
+    }
+}
+
+impl Listener for JsonListener {
+    fn warning(&self, s: String) {
+        self.emit(&JsonMessage::Warning { message: &s });
+    }
+
+    fn info(&self, s: String) {
+        if self.verbose {
+            self.emit(&JsonMessage::Info { message: &s });
+        }
+    }
+
+    fn progress(&self, operation: &str, done: usize, total: usize) {
+        if self.verbose {
+            self.emit(&JsonMessage::Progress { operation, done, total });
+        }
+    }
+
+    fn generated_archive(&self, path: &Path) {
+        self.emit(&JsonMessage::GeneratedArchive { path, name: None, version: None, architecture: None });
+    }
+
+    fn generated_package(&self, path: &Path, name: &str, version: &str, architecture: &str) {
+        self.emit(&JsonMessage::GeneratedArchive { path, name: Some(name), version: Some(version), architecture: Some(architecture) });
+    }
+
+    fn report(&self, s: String) {
+        self.emit(&JsonMessage::Report { message: &s });
+    }
+
+    fn kept_temp_dir(&self, path: &Path) {
+        self.emit(&JsonMessage::KeptTempDir { path });
+    }
+
+    fn resolved_path(&self, package: &str, name: &str, path: &Path) {
+        self.emit(&JsonMessage::ResolvedPath { package, name, path });
+    }
+
+    /// Sent regardless of verbosity, since tools reading JSON may want to explain why units weren't found
+    fn systemd_unit_candidate<'a>(&self, candidate: &UnitCandidateInfo<'a>, message: String) {
+        self.emit(&JsonMessage::SystemdUnitCandidate { candidate, message: &message });
+    }
+}
+
+#[test]
+fn capturing_listener() {
+    let listener = CapturingListener::new();
+    let dyn_listener: &dyn Listener = &listener;
+    dyn_listener.info("starting".into());
+    dyn_listener.warning("something's odd".into());
+    dyn_listener.progress("Stripping", 1, 2);
+    dyn_listener.generated_archive(Path::new("target/debian/a.deb"));
+    assert_eq!(listener.warnings(), ["something's odd"]);
+    assert!(listener.has_warning("odd"));
+    assert!(!listener.has_warning("starting"));
+    assert_eq!(listener.messages(Level::Progress), ["Stripping (1/2)"]);
+    assert_eq!(listener.generated_archives(), [Path::new("target/debian/a.deb")]);
+    let events = listener.take_events();
+    assert_eq!(events.iter().map(|e| e.level).collect::<Vec<_>>(), [Level::Info, Level::Warning, Level::Progress, Level::GeneratedArchive]);
+    assert!(events.windows(2).all(|w| w[0].time <= w[1].time));
+    assert!(listener.events().is_empty());
+}
+
+#[test]
+fn json_messages() {
+    let json = serde_json::to_string(&JsonMessage::GeneratedArchive {
+        path: Path::new("target/debian/a_1.0-1_amd64.deb"), name: Some("a"), version: Some("1.0-1"), architecture: Some("amd64"),
+    }).unwrap();
+    assert_eq!(json, r#"{"reason":"generated-archive","path":"target/debian/a_1.0-1_amd64.deb","name":"a","version":"1.0-1","architecture":"amd64"}"#);
+    let json = serde_json::to_string(&JsonMessage::Progress { operation: "Stripping", done: 1, total: 2 }).unwrap();
+    assert_eq!(json, r#"{"reason":"progress","operation":"Stripping","done":1,"total":2}"#);
+    let json = serde_json::to_string(&JsonMessage::ResolvedPath { package: "a", name: "target-dir", path: Path::new("target") }).unwrap();
+    assert_eq!(json, r#"{"reason":"resolved-path","package":"a","name":"target-dir","path":"target"}"#);
+    let candidate = UnitCandidateInfo { path: Path::new("debian/a.service"), unit_type: "service", template: false, status: "shadowed", shadowed_by: Some(Path::new("debian/a.a.service")) };
+    let json = serde_json::to_string(&JsonMessage::SystemdUnitCandidate { candidate: &candidate, message: "ignored" }).unwrap();
+    assert_eq!(json, r#"{"reason":"systemd-unit-candidate","path":"debian/a.service","unit_type":"service","template":false,"status":"shadowed","shadowed_by":"debian/a.a.service","message":"ignored"}"#);
 }
@@ -1,45 +1,24 @@
 use cargo_deb::compress::Format;
 use cargo_deb::config::Multiarch;
-use cargo_deb::{listener, CargoDeb, CargoDebError, CargoDebOptions, CargoLockingFlags};
+use cargo_deb::deb::edit::MetadataEdits;
+use cargo_deb::distro::DistroFlavor;
+use cargo_deb::{listener, CargoDeb, CargoDebError, CargoDebOptions, CargoLockingFlags, Install};
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
+mod cli;
+use cli::CLI_OPTIONS;
+
 fn main() -> ExitCode {
     env_logger::init();
 
     let args: Vec<String> = env::args().collect();
 
     let mut cli_opts = getopts::Options::new();
-    cli_opts.optflag("", "no-strip", "Do not strip debug symbols from the binary");
-    cli_opts.optflag("", "strip", "Always try to strip debug symbols");
-    cli_opts.optflag("", "no-separate-debug-symbols", "Do not strip debug symbols into a separate .debug file");
-    cli_opts.optflag("", "separate-debug-symbols", "Strip debug symbols into a separate .debug file");
-    cli_opts.optflag("", "compress-debug-symbols", "Apply objcopy --compress-debug-sections");
-    cli_opts.optopt("o", "output", "Write .deb to this file or directory", "path");
-    cli_opts.optopt("p", "package", "Select which Cargo workspace package to use", "name");
-    cli_opts.optflag("", "install", "Immediately install the created deb package");
-    cli_opts.optflag("q", "quiet", "Don't print warnings");
-    cli_opts.optflag("v", "verbose", "Print progress");
-    cli_opts.optflag("", "version", "Show version of the cargo-deb tool");
-    cli_opts.optopt("", "deb-version", "Override version string for the package", "version");
-    cli_opts.optopt("", "deb-revision", "Override revision suffix string for the package", "num");
-    cli_opts.optopt("", "maintainer", "Override Maintainer field", "name");
-    cli_opts.optopt("", "manifest-path", "Cargo project file location", "./Cargo.toml");
-    cli_opts.optflag("", "offline", "Passed to Cargo");
-    cli_opts.optflag("", "locked", "Passed to Cargo");
-    cli_opts.optflag("", "frozen", "Passed to Cargo");
-    cli_opts.optopt("", "variant", "Alternative Cargo.toml configuration section to use", "name");
-    cli_opts.optopt("", "target", "Rust target for cross-compilation", "triple");
-    cli_opts.optopt("", "multiarch", "Put libs in /usr/lib/$arch-linux-gnu/", "none|same|foreign");
-    cli_opts.optopt("", "profile", "Select which Cargo build profile to use", "release|<custom>");
-    cli_opts.optflag("", "no-build", "Assume the project is already built");
-    cli_opts.optopt("", "cargo-build", "Override cargo build subcommand", "subcommand");
-    cli_opts.optflag("", "fast", "Use faster compression, which makes a larger deb file");
-    cli_opts.optopt("Z", "compress-type", "Compress with the given compression format", "gz|xz");
-    cli_opts.optflag("", "compress-system", "Use the corresponding command-line tool for compression");
-    cli_opts.optflag("", "system-xz", "Compress using command-line xz command instead of built-in. Deprecated, use --compress-system instead");
-    cli_opts.optflag("", "rsyncable", "Use worse compression, but reduce differences between versions of packages");
-    cli_opts.optflag("h", "help", "Print this help menu");
+    for opt in CLI_OPTIONS {
+        opt.register(&mut cli_opts);
+    }
 
     let mut matches = match cli_opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -80,13 +59,42 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS;
     }
 
+    if let Some(shell) = matches.opt_str("generate-completions") {
+        let Some(script) = cli::completions(&shell) else {
+            print_error(&CargoDebError::Str("unsupported shell. Supported: bash, zsh, fish"));
+            return ExitCode::FAILURE;
+        };
+        print!("{script}");
+        return ExitCode::SUCCESS;
+    }
+
+    if matches.opt_present("generate-man") {
+        print!("{}", cli::man_page());
+        return ExitCode::SUCCESS;
+    }
+
     if matches.opt_present("version") {
         println!("{}", env!("CARGO_PKG_VERSION"));
         return ExitCode::SUCCESS;
     }
 
-    let install = matches.opt_present("install");
+    let install = match (matches.opt_present("install"), matches.opt_str("install").as_deref()) {
+        (false, _) | (true, Some("none")) => Install::None,
+        (true, None | Some("main")) => Install::Main,
+        (true, Some("all")) => Install::All,
+        _ => {
+            print_error(&CargoDebError::Str("--install must be 'main', 'all', or 'none'"));
+            return ExitCode::FAILURE;
+        },
+    };
 
+    let flavor = match matches.opt_str("flavor").as_deref().map(DistroFlavor::from_name).transpose() {
+        Ok(flavor) => flavor.unwrap_or_default(),
+        Err(err) => {
+            print_error(&err);
+            return ExitCode::FAILURE;
+        },
+    };
     let compress_type = match matches.opt_str("compress-type").as_deref() {
         Some("gz" | "gzip") => Format::Gzip,
         Some("xz") | None => Format::Xz,
@@ -106,6 +114,21 @@ fn main() -> ExitCode {
         },
     };
 
+    let print_paths = match matches.opt_str("print").as_deref() {
+        Some("paths") => true,
+        None => false,
+        _ => {
+            print_error(&CargoDebError::Str("--print supports only 'paths'"));
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let best = matches.opt_present("best");
+    if best && matches.opt_present("fast") {
+        print_error(&CargoDebError::Str("--fast and --best can't be used together"));
+        return ExitCode::FAILURE;
+    }
+
     // `cargo deb` invocation passes the `deb` arg through.
     if matches.free.first().is_some_and(|arg| arg == "deb") {
         matches.free.remove(0);
@@ -114,9 +137,21 @@ fn main() -> ExitCode {
     let quiet = matches.opt_present("quiet");
     let verbose = matches.opt_present("verbose") || env::var_os("RUST_LOG").is_some_and(|v| v == "debug");
 
+    let json = match matches.opt_str("message-format").as_deref() {
+        None | Some("human") => false,
+        Some("json") => true,
+        Some(_) => {
+            print_error(&CargoDebError::Str("--message-format must be 'human' or 'json'"));
+            return ExitCode::FAILURE;
+        },
+    };
+
     // Listener conditionally prints warnings
-    let (listener_tmp1, listener_tmp2);
-    let listener: &dyn listener::Listener = if quiet {
+    let (listener_tmp1, listener_tmp2, listener_tmp3);
+    let listener: &dyn listener::Listener = if json {
+        listener_tmp3 = listener::JsonListener { verbose };
+        &listener_tmp3
+    } else if quiet {
         listener_tmp1 = listener::NoOpListener;
         &listener_tmp1
     } else {
@@ -131,6 +166,65 @@ fn main() -> ExitCode {
         listener.warning(format!("--deb-version takes precedence over --deb-revision. Revision '{}' will be ignored", deb_revision.as_deref().unwrap_or_default()));
     }
 
+    if let Some(deb_path) = matches.opt_str("edit-metadata") {
+        let fields = matches.opt_strs("field").into_iter().map(|f| match f.split_once(':') {
+            Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+            None => Err(CargoDebError::Str("--field must be in the Name:value format")),
+        }).collect::<Result<Vec<_>, _>>();
+        let result = fields.and_then(|fields| cargo_deb::deb::edit::edit_deb(deb_path.as_ref(), matches.opt_str("output").as_deref().map(Path::new), &MetadataEdits {
+            version: deb_version,
+            revision: deb_revision,
+            version_suffix: matches.opt_str("version-suffix"),
+            maintainer: matches.opt_str("maintainer"),
+            fields,
+        }, listener));
+        return match result {
+            Ok(generated) => {
+                listener.generated_archive(&generated);
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    if let Some(delta_path) = matches.opt_str("apply-delta") {
+        let result = match matches.opt_str("delta-against") {
+            Some(old_deb) => cargo_deb::deb::delta::apply_delta(old_deb.as_ref(), delta_path.as_ref(), matches.opt_str("output").as_deref().map(Path::new)),
+            None => Err(CargoDebError::Str("--apply-delta needs the old package in --delta-against")),
+        };
+        return match result {
+            Ok(generated) => {
+                listener.generated_archive(&generated);
+                ExitCode::SUCCESS
+            },
+            Err(err) => {
+                print_error(&err);
+                ExitCode::FAILURE
+            },
+        };
+    }
+
+    // the tag is of the crate's repository, not the current dir's
+    let manifest_dir = matches.opt_str("manifest-path").map(PathBuf::from)
+        .and_then(|p| p.parent().filter(|d| !d.as_os_str().is_empty()).map(Path::to_path_buf))
+        .unwrap_or_else(|| ".".into());
+    let upstream_version = match matches.opt_str("set-version-from").map(|source| cargo_deb::config::version_from_source(&source, &manifest_dir)).transpose() {
+        Ok(v) => v,
+        Err(err) => {
+            print_error(&err);
+            return ExitCode::FAILURE;
+        },
+    };
+    if deb_version.is_some() && upstream_version.is_some() {
+        listener.warning("--deb-version takes precedence over --set-version-from".into());
+    }
+
+    let mut manifest_paths = matches.opt_strs("manifest-path").into_iter();
+    let variants = matches.opt_strs("variant");
+    let mut variants = variants.iter().flat_map(|v| v.split(',')).map(|v| v.trim().to_owned()).filter(|v| !v.is_empty());
     match CargoDeb::new(CargoDebOptions {
         no_build: matches.opt_present("no-build"),
         strip_override: if matches.opt_present("strip") { Some(true) } else if matches.opt_present("no-strip") { Some(false) } else { None },
@@ -138,23 +232,59 @@ fn main() -> ExitCode {
         compress_debug_symbols: if matches.opt_present("compress-debug-symbols") { Some(true) } else { None },
         verbose,
         install,
+        allow_downgrade: matches.opt_present("allow-downgrade"),
+        verify: matches.opt_present("verify"),
+        no_metadata_cache: matches.opt_present("no-metadata-cache"),
+        sign: matches.opt_present("sign"),
+        publish: matches.opt_present("publish"),
+        publish_url: matches.opt_str("publish"),
+        add_to_repo: matches.opt_str("add-to-repo"),
+        apt_repo_dir: matches.opt_str("repo"),
+        upstream_metadata: matches.opt_present("upstream-metadata"),
+        emit_debhelper_shim: matches.opt_present("emit-debhelper-shim"),
+        source_package: matches.opt_present("source"),
+        crate_source: matches.opt_present("crate-source"),
         // when installing locally it won't be transferred anywhere, so allow faster compression
-        fast: install || matches.opt_present("fast"),
-        variant: matches.opt_str("variant"),
+        fast: (install != Install::None && !best) || matches.opt_present("fast"),
+        best,
+        variant: variants.next(),
+        extra_variants: variants.collect(),
         target: matches.opt_str("target"),
         multiarch,
         output_path: matches.opt_str("output"),
         selected_package_name: matches.opt_str("package"),
-        manifest_path: matches.opt_str("manifest-path"),
+        manifest_path: manifest_paths.next(),
+        extra_manifest_paths: manifest_paths.collect(),
         overrides: cargo_deb::config::DebConfigOverrides {
             deb_version,
+            upstream_version,
+            auto_doc: if matches.opt_present("no-auto-doc") { Some(false) } else { None },
             deb_revision,
             maintainer: matches.opt_str("maintainer"),
+            vcs_revision: matches.opt_present("version-metadata").then(|| matches.opt_str("version-metadata")),
+            allow_essential: matches.opt_present("i-know-what-im-doing"),
+            flavor,
+            keep_old_debs: matches.opt_present("keep-old-debs"),
+            changes_file: matches.opt_present("changes"),
+            fail_on_missing_systemd_unit: matches.opt_present("fail-on-missing-systemd-unit"),
+            target_dir: None,
         },
         compress_type,
         compress_system: matches.opt_present("compress-system"),
+        compat_level: matches.opt_str("compat-level"),
+        deny_build_paths: matches.opt_present("deny-build-paths"),
+        offline_strict: matches.opt_present("offline-strict"),
         system_xz: matches.opt_present("system-xz"),
         rsyncable: matches.opt_present("rsyncable"),
+        dpkg_compat: matches.opt_present("dpkg-compat"),
+        sandbox: matches.opt_present("sandbox"),
+        previous_deb: matches.opt_str("previous-deb"),
+        delta_against: matches.opt_str("delta-against"),
+        keep_temp: matches.opt_present("keep-temp"),
+        check_config: matches.opt_present("check-config"),
+        print_paths,
+        explain_deps: matches.opt_present("explain-deps"),
+        remote_crate: matches.opt_str("crate"),
         profile: matches.opt_str("profile"),
         cargo_build_cmd: matches.opt_str("cargo-build").unwrap_or("build".to_string()),
         cargo_locking_flags: CargoLockingFlags {
@@ -162,6 +292,8 @@ fn main() -> ExitCode {
             frozen: matches.opt_present("frozen"),
             locked: matches.opt_present("locked"),
         },
+        target_distros: matches.opt_strs("target-distro").iter()
+            .flat_map(|d| d.split(',')).filter(|d| !d.is_empty()).map(String::from).collect(),
         cargo_build_flags: matches.free,
     }).process(listener) {
         Ok(()) => ExitCode::SUCCESS,
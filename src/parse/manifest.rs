@@ -1,11 +1,15 @@
-use crate::assets::RawAsset;
+use crate::assets::{AssetCondition, PackageDir, RawAsset};
 use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::offline;
+use crate::parse::metadata_cache::MetadataCache;
+use crate::relations::Relation;
 use crate::CargoLockingFlags;
 use cargo_toml::DebugSetting;
 use log::debug;
 use serde::Deserialize;
 use std::borrow::Cow;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -20,6 +24,11 @@ use std::process::Command;
 /// `unit_name`: (optjonal) in cases where the `unit_scripts` directory contains
 /// multiple units, only process those matching this unit name.
 ///
+/// `required_features`: (optional) skip the units unless all of these Cargo features are enabled.
+///
+/// `restart_policy`, `reload_on_upgrade` and `stop_timeout`: (optional) how services are restarted
+/// after upgrades, and how long systemd waits for them to stop.
+///
 /// For details on the other options please see `dh_installsystemd::Options`.
 #[derive(Clone, Debug, Deserialize, Default)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
@@ -30,20 +39,68 @@ pub(crate) struct SystemdUnitsConfig {
     pub start: Option<bool>,
     pub restart_after_upgrade: Option<bool>,
     pub stop_on_upgrade: Option<bool>,
+    pub required_features: Option<Vec<String>>,
+    /// Install in `/usr/lib/systemd/user/` for `systemd --user`
+    pub user_unit: Option<bool>,
+    pub restart_policy: Option<RestartPolicy>,
+    pub stop_timeout: Option<StopTimeout>,
+    /// Reload services that support it instead of restarting them after upgrades
+    pub reload_on_upgrade: Option<bool>,
+}
+
+/// What `postinst` does to running services after an upgrade
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Restart, or start if not running
+    #[default]
+    Restart,
+    /// Restart only if running
+    TryRestart,
+    /// Leave them running the old version until restarted manually
+    #[serde(rename = "none")]
+    Never,
 }
 
-pub(crate) fn manifest_debug_flag(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str) -> Option<bool> {
+/// Duration for systemd's `TimeoutStopSec=`, e.g. `30s` or `2m`
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct StopTimeout(pub String);
+
+impl TryFrom<String> for StopTimeout {
+    type Error = String;
+
+    fn try_from(duration: String) -> Result<Self, Self::Error> {
+        let number = duration.strip_suffix(['s', 'm', 'h', 'd']).unwrap_or(&duration);
+        if number.parse::<f64>().map_or(true, |n| !n.is_finite() || n <= 0.) {
+            return Err(format!("`stop-timeout = \"{duration}\"` must be a number with an optional s, m, h, or d suffix, e.g. \"30s\""));
+        }
+        Ok(Self(duration))
+    }
+}
+
+/// Takes into account `[profile.release.package.<name>]` overrides of the `debug` setting.
+/// `package."*"` is for dependencies, but their debug info ends up in the binary too.
+pub(crate) fn manifest_debug_flag(manifest: &cargo_toml::Manifest<CargoPackageMetadata>, selected_profile: &str, package_name: &str) -> Option<bool> {
     let profile = if selected_profile == "release" {
         manifest.profile.release.as_ref()?
     } else {
         manifest.profile.custom.get(selected_profile)?
     };
-    Some(*profile.debug.as_ref()? != DebugSetting::None)
+    let package_override = [package_name, "*"].into_iter()
+        .filter_map(|name| profile.package.get(name)?.get("debug"))
+        .find_map(|debug| debug.clone().try_into::<DebugSetting>().ok());
+    Some(package_override.as_ref().or(profile.debug.as_ref())? != &DebugSetting::None)
 }
 
 /// Debian-compatible version of the semver version
 pub(crate) fn manifest_version_string<'a>(package: &'a cargo_toml::Package<CargoPackageMetadata>, revision: Option<&str>) -> Cow<'a, str> {
-    let mut version = Cow::Borrowed(package.version());
+    debian_version_string(package.version(), revision)
+}
+
+/// Converts a semver version to a Debian version with the revision (`1` by default)
+pub(crate) fn debian_version_string<'a>(version: &'a str, revision: Option<&str>) -> Cow<'a, str> {
+    let mut version = Cow::Borrowed(version);
 
     // Make debian's version ordering (newer versions) more compatible with semver's.
     // Keep "semver-1" and "semver-xxx" as-is (assuming these are irrelevant, or debian revision already),
@@ -76,6 +133,26 @@ pub(crate) enum LicenseFile {
     Vec(Vec<String>),
 }
 
+/// Either a plain `Copyright:` value, or a list of DEP-5 `Files:` stanzas
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Copyright {
+    String(String),
+    Stanza(CopyrightStanza),
+    Stanzas(Vec<CopyrightStanza>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct CopyrightStanza {
+    /// Glob patterns, as in the `Files:` field
+    pub files: Vec<String>,
+    /// Defaults to the package's copyright
+    pub copyright: Option<String>,
+    /// Defaults to the package's license
+    pub license: Option<String>,
+}
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(untagged)]
 pub(crate) enum SystemUnitsSingleOrMultiple {
@@ -87,15 +164,48 @@ pub(crate) enum SystemUnitsSingleOrMultiple {
 #[serde(untagged)]
 pub(crate) enum DependencyList {
     String(String),
-    Vec(Vec<String>),
+    Vec(Vec<DependencyItem>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum DependencyItem {
+    String(String),
+    Table(DependencyTable),
+}
+
+/// `{ package = "libssl3", version = ">= 3.0", arch = ["amd64"] }`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DependencyTable {
+    pub package: String,
+    pub version: Option<String>,
+    pub arch: Option<Vec<String>>,
 }
 
 impl DependencyList {
-    pub(crate) fn into_depends_string(self) -> String {
-        match self {
+    pub(crate) fn into_depends_string(self) -> CDResult<String> {
+        Ok(match self {
             Self::String(s) => s,
-            Self::Vec(vals) => vals.join(", "),
+            Self::Vec(vals) => vals.into_iter().map(|item| Ok(match item {
+                DependencyItem::String(s) => s,
+                DependencyItem::Table(t) => Relation::from_parts(&t.package, t.version.as_deref(), t.arch.as_deref().unwrap_or_default())?.to_string(),
+            })).collect::<CDResult<Vec<_>>>()?.join(", "),
+        })
+    }
+
+    /// For fields other than `depends`, which can't have architecture restrictions in binary packages
+    pub(crate) fn into_relations_string(self, field: &str) -> CDResult<String> {
+        if let Self::Vec(items) = &self {
+            let with_arch = items.iter().find_map(|item| match item {
+                DependencyItem::Table(t) if t.arch.is_some() => Some(&t.package),
+                _ => None,
+            });
+            if let Some(package) = with_arch {
+                return Err(CargoDebError::InvalidRelationship(format!("{field}: {package} can't have arch, because only depends supports architecture restrictions")));
+            }
         }
+        self.into_depends_string()
     }
 }
 
@@ -105,7 +215,7 @@ pub(crate) type AssetList = Vec<RawAsset>;
 
 /// Type-alias for a merge map,
 ///
-pub(crate) type MergeMap<'a> = BTreeMap<&'a PathBuf, (&'a PathBuf, u32)>;
+pub(crate) type MergeMap<'a> = BTreeMap<&'a PathBuf, &'a RawAsset>;
 
 #[derive(Deserialize)]
 #[serde(untagged)]
@@ -117,9 +227,29 @@ pub(crate) enum CargoDebAssetArrayOrTable {
 
 #[derive(Clone, Debug, Deserialize, Default)]
 pub(crate) struct CargoDebAsset {
-    pub source: String,
+    /// Not used for special files
+    pub source: Option<String>,
     pub dest: String,
     pub mode: String,
+    pub when: Option<AssetCondition>,
+    /// `fifo`, `char-device`, or `block-device`
+    #[serde(rename = "type")]
+    pub file_type: Option<SpecialFileType>,
+    /// Major and minor number of a device
+    pub device: Option<[u32; 2]>,
+    pub multiarch: Option<bool>,
+    /// `user:group` in the data archive
+    pub owner: Option<String>,
+    /// Package as-is, without compression, stripping or multiarch paths
+    pub verbatim: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum SpecialFileType {
+    Fifo,
+    CharDevice,
+    BlockDevice,
 }
 
 #[derive(Clone, Debug, Deserialize, Default)]
@@ -127,35 +257,116 @@ pub(crate) struct CargoDebAsset {
 pub(crate) struct CargoDeb {
     pub name: Option<String>,
     pub maintainer: Option<String>,
-    pub copyright: Option<String>,
+    pub copyright: Option<Copyright>,
     pub license_file: Option<LicenseFile>,
     pub changelog: Option<String>,
+    pub changelog_format: Option<ChangelogFormat>,
     pub depends: Option<DependencyList>,
     pub pre_depends: Option<DependencyList>,
     pub recommends: Option<DependencyList>,
     pub suggests: Option<DependencyList>,
-    pub enhances: Option<String>,
-    pub conflicts: Option<String>,
-    pub breaks: Option<String>,
-    pub replaces: Option<String>,
-    pub provides: Option<String>,
+    pub enhances: Option<DependencyList>,
+    pub conflicts: Option<DependencyList>,
+    pub breaks: Option<DependencyList>,
+    pub replaces: Option<DependencyList>,
+    pub provides: Option<DependencyList>,
+    /// Fold relationship fields longer than this, 0 to never fold
+    pub relations_line_width: Option<usize>,
+    /// Short `Description`, instead of the one in `Cargo.toml`
+    pub synopsis: Option<String>,
     pub extended_description: Option<String>,
     pub extended_description_file: Option<String>,
     pub section: Option<String>,
     pub priority: Option<String>,
+    /// `Essential: yes`, only allowed with `--i-know-what-im-doing`
+    pub essential: Option<bool>,
+    /// `Protected: yes`
+    pub protected: Option<bool>,
     pub revision: Option<String>,
     pub conf_files: Option<Vec<String>>,
+    pub dependency_aliases: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    /// Debian packages of libraries, for resolving `$auto` without dpkg
+    pub soname_packages: Option<BTreeMap<String, String>>,
+    /// Packages removed from the resolved `Depends`
+    pub depends_remove: Option<Vec<String>>,
+    /// Version constraints that replace the resolved ones, e.g. `libssl3 = ">= 3.0.0"`
+    pub depends_pin: Option<BTreeMap<String, String>>,
+    /// Add `Depends` implied by well-known crates in the dependency tree
+    pub dependency_hints: Option<bool>,
+    pub non_conf_files: Option<Vec<String>>,
+    /// Files created by the program after installation, deleted on purge
+    pub runtime_files: Option<Vec<String>>,
+    /// `dpkg-maintscript-helper` commands, like lines of debhelper's `debian/*.maintscript`
+    pub maintscripts: Option<Vec<String>>,
+    /// Empty directories to create, `[path, mode, owner]`
+    pub dirs: Option<Vec<PackageDir>>,
     pub assets: Option<AssetList>,
     pub merge_assets: Option<MergeAssets>,
+    pub duplicate_assets: Option<DuplicateAssets>,
+    pub auto_doc: Option<bool>,
     pub triggers_file: Option<String>,
+    pub metainfo: Option<String>,
+    pub icon: Option<String>,
+    /// `sysusers.d` config to install as `/usr/lib/sysusers.d/<name>.conf`
+    pub sysusers: Option<String>,
+    /// `tmpfiles.d` config to install as `/usr/lib/tmpfiles.d/<name>.conf`
+    pub tmpfiles: Option<String>,
     pub maintainer_scripts: Option<String>,
     pub features: Option<Vec<String>>,
     pub default_features: Option<bool>,
     pub separate_debug_symbols: Option<bool>,
     pub compress_debug_symbols: Option<bool>,
     pub preserve_symlinks: Option<bool>,
+    pub allow_special_files: Option<bool>,
+    /// Don't delete other versions of the package from `target/debian`
+    pub keep_old_debs: Option<bool>,
+    /// Maintainer script fragments for assets that need them, e.g. icon caches
+    pub implicit_triggers: Option<bool>,
     pub systemd_units: Option<SystemUnitsSingleOrMultiple>,
+    pub publish: Option<PublishConfig>,
+    pub sign: Option<SignConfig>,
+    pub vcs_revision_file: Option<bool>,
+    /// Cargo profile to build with, unless `--profile` is used
+    pub profile: Option<String>,
     pub variants: Option<HashMap<String, CargoDeb>>,
+    /// Overrides for a Rust target triple or a Debian architecture
+    #[serde(alias = "targets")]
+    pub target: Option<HashMap<String, CargoDeb>>,
+}
+
+/// `[package.metadata.deb.publish]` used by `--publish`
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct PublishConfig {
+    /// `https://` for HTTP PUT, or `s3://bucket/prefix/`. The file name is appended if it ends with `/`.
+    pub url: Option<String>,
+    /// HTTP basic auth
+    pub username: Option<String>,
+    /// Name of the env var that has the password or token, so that it's not in Cargo.toml
+    pub password_env: Option<String>,
+    /// Extra HTTP headers, as `Name: value`
+    pub headers: Option<Vec<String>>,
+    /// S3 region
+    pub region: Option<String>,
+    /// S3-compatible storage that isn't AWS
+    pub endpoint: Option<String>,
+}
+
+/// `[package.metadata.deb.sign]` used by `--sign`
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct SignConfig {
+    pub sigstore: Option<SigstoreConfig>,
+}
+
+/// `[package.metadata.deb.sign.sigstore]`, signing with `cosign sign-blob`
+#[derive(Clone, Debug, Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct SigstoreConfig {
+    /// cosign's `--key`: a key file, `env://VAR`, or a KMS URI. Keyless signing if not set.
+    pub key: Option<String>,
+    /// Name of the env var that has the OIDC identity token for keyless signing, e.g. in CI
+    pub identity_token_env: Option<String>,
 }
 
 /// Struct containing merge configuration
@@ -171,6 +382,25 @@ pub(crate) struct MergeAssets {
     pub by: Option<MergeByKey>,
 }
 
+/// What to do when more than one asset has the same target path
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum DuplicateAssets {
+    Error,
+    First,
+    Last,
+}
+
+/// Syntax of the `changelog` file
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ChangelogFormat {
+    Debian,
+    /// Keep-a-Changelog style `CHANGELOG.md`
+    #[serde(alias = "keepachangelog")]
+    Markdown,
+}
+
 /// Enumeration of merge by key strategies
 ///
 #[derive(Clone, Debug, Deserialize)]
@@ -194,45 +424,34 @@ impl MergeByKey {
         self.merge_with(merge_map)
     }
 
+    fn key<'a>(&self, asset: &'a RawAsset) -> &'a PathBuf {
+        match self {
+            Self::Src(_) => &asset.source_path,
+            Self::Dest(_) => &asset.target_path,
+        }
+    }
+
     /// Folds the parent asset into a merge-map preparing to prepare for a merge,
     ///
-    fn prep_parent_item<'a>(&'a self, mut parent: MergeMap<'a>, RawAsset { source_path: src,target_path: dest, chmod: perm }: &'a RawAsset) -> MergeMap<'a> {
-        match &self {
-            Self::Src(_) => {
-                parent.insert(src, (dest, *perm));
-            },
-            Self::Dest(_) => {
-                parent.insert(dest, (src, *perm));
-            },
-        }
+    fn prep_parent_item<'a>(&'a self, mut parent: MergeMap<'a>, asset: &'a RawAsset) -> MergeMap<'a> {
+        parent.insert(self.key(asset), asset);
         parent
     }
 
     /// Merges w/ a parent merge map and returns the resulting asset list,
     ///
     fn merge_with(&self, parent: MergeMap<'_>) -> AssetList {
-        match self {
-            Self::Src(assets) => assets.iter()
-                .fold(parent, |mut acc, RawAsset { source_path: src,target_path: dest, chmod: perm }| {
-                    if let Some((replaced_dest, replaced_perm)) = acc.insert(src, (dest, *perm)) {
-                        debug!("Replacing {:?} w/ {:?}", (replaced_dest, replaced_perm), (dest, perm));
-                    }
-                    acc
-                })
-                .into_iter()
-                .map(|(src, (dest, perm))| RawAsset { source_path: src.clone(), target_path: dest.clone(), chmod: perm })
-                .collect(),
-            Self::Dest(assets) => assets.iter()
-                .fold(parent, |mut acc, RawAsset { source_path: src, target_path: dest, chmod: perm }| {
-                    if let Some((replaced_src, replaced_perm)) = acc.insert(dest, (src, *perm)) {
-                        debug!("Replacing {:?} w/ {:?}", (replaced_src, replaced_perm), (src, perm));
-                    }
-                    acc
-                })
-                .into_iter()
-                .map(|(dest, (src, perm))| RawAsset { source_path: src.clone(), target_path: dest.clone(), chmod: perm })
-                .collect(),
-        }
+        let (Self::Src(assets) | Self::Dest(assets)) = self;
+        assets.iter()
+            .fold(parent, |mut acc, asset| {
+                if let Some(replaced) = acc.insert(self.key(asset), asset) {
+                    debug!("Replacing {replaced:?} w/ {asset:?}");
+                }
+                acc
+            })
+            .into_values()
+            .cloned()
+            .collect()
     }
 }
 
@@ -246,6 +465,14 @@ impl CargoDeb {
 
         if let (Some(merge_assets), Some(old_assets)) = (self.merge_assets, assets.as_mut()) {
             if let Some(mut append) = merge_assets.append {
+                // the variant's asset replaces the base asset with the same file target path
+                old_assets.retain(|old| {
+                    let replaced = !old.target_path.to_string_lossy().ends_with('/') && append.iter().any(|new| new.target_path == old.target_path);
+                    if replaced {
+                        debug!("Replacing {} w/ the variant's asset", old.target_path.display());
+                    }
+                    !replaced
+                });
                 old_assets.append(&mut append);
             }
 
@@ -260,6 +487,7 @@ impl CargoDeb {
             copyright: self.copyright.or(parent.copyright),
             license_file: self.license_file.or(parent.license_file),
             changelog: self.changelog.or(parent.changelog),
+            changelog_format: self.changelog_format.or(parent.changelog_format),
             depends: self.depends.or(parent.depends),
             pre_depends: self.pre_depends.or(parent.pre_depends),
             recommends: self.recommends.or(parent.recommends),
@@ -269,27 +497,145 @@ impl CargoDeb {
             breaks: self.breaks.or(parent.breaks),
             replaces: self.replaces.or(parent.replaces),
             provides: self.provides.or(parent.provides),
+            relations_line_width: self.relations_line_width.or(parent.relations_line_width),
+            synopsis: self.synopsis.or(parent.synopsis),
             extended_description: self.extended_description.or(parent.extended_description),
             extended_description_file: self.extended_description_file.or(parent.extended_description_file),
             section: self.section.or(parent.section),
             priority: self.priority.or(parent.priority),
+            essential: self.essential.or(parent.essential),
+            protected: self.protected.or(parent.protected),
+            dependency_hints: self.dependency_hints.or(parent.dependency_hints),
+            soname_packages: self.soname_packages.or(parent.soname_packages),
+            depends_remove: self.depends_remove.or(parent.depends_remove),
+            depends_pin: self.depends_pin.or(parent.depends_pin),
             revision: self.revision.or(parent.revision),
             conf_files: self.conf_files.or(parent.conf_files),
+            dependency_aliases: self.dependency_aliases.or(parent.dependency_aliases),
+            non_conf_files: self.non_conf_files.or(parent.non_conf_files),
+            runtime_files: self.runtime_files.or(parent.runtime_files),
+            maintscripts: self.maintscripts.or(parent.maintscripts),
+            dirs: self.dirs.or(parent.dirs),
             assets,
             merge_assets: None,
             triggers_file: self.triggers_file.or(parent.triggers_file),
+            metainfo: self.metainfo.or(parent.metainfo),
+            icon: self.icon.or(parent.icon),
+            sysusers: self.sysusers.or(parent.sysusers),
+            tmpfiles: self.tmpfiles.or(parent.tmpfiles),
             maintainer_scripts: self.maintainer_scripts.or(parent.maintainer_scripts),
             features: self.features.or(parent.features),
             default_features: self.default_features.or(parent.default_features),
             separate_debug_symbols: self.separate_debug_symbols.or(parent.separate_debug_symbols),
             compress_debug_symbols: self.compress_debug_symbols.or(parent.compress_debug_symbols),
             preserve_symlinks: self.preserve_symlinks.or(parent.preserve_symlinks),
+            keep_old_debs: self.keep_old_debs.or(parent.keep_old_debs),
+            implicit_triggers: self.implicit_triggers.or(parent.implicit_triggers),
+            allow_special_files: self.allow_special_files.or(parent.allow_special_files),
+            duplicate_assets: self.duplicate_assets.or(parent.duplicate_assets),
+            auto_doc: self.auto_doc.or(parent.auto_doc),
             systemd_units: self.systemd_units.or(parent.systemd_units),
+            publish: self.publish.or(parent.publish),
+            sign: self.sign.or(parent.sign),
+            vcs_revision_file: self.vcs_revision_file.or(parent.vcs_revision_file),
+            profile: self.profile.or(parent.profile),
             variants: self.variants.or(parent.variants),
+            target: self.target.or(parent.target),
+        }
+    }
+
+    /// Merges `[package.metadata.deb.target.'<arch>']` tables matching the target.
+    /// The Rust target triple is more specific, so it takes precedence over the Debian architecture.
+    pub(crate) fn with_target_overrides(mut self, rust_target_triple: &str) -> Self {
+        let Some(mut targets) = self.target.take() else {
+            return self;
+        };
+        let deb_arch = crate::debian_architecture_from_rust_triple(rust_target_triple);
+        for key in [deb_arch, rust_target_triple] {
+            if let Some(overrides) = targets.remove(key) {
+                debug!("using [package.metadata.deb.target.'{key}']");
+                self = overrides.inherit_from(self);
+                self.target = None;
+            }
+        }
+        self
+    }
+
+    /// Prepares `[workspace.metadata.deb]` to be inherited by packages in the workspace.
+    /// Its paths are relative to the workspace root. Names and assets are specific to each package, so they're not inherited.
+    pub(crate) fn into_workspace_defaults(mut self, workspace_root: &Path, listener: &dyn Listener) -> Self {
+        for (key, is_set) in [("name", self.name.is_some()), ("assets", self.assets.is_some()), ("merge-assets", self.merge_assets.is_some())] {
+            if is_set {
+                listener.warning(format!("`{key}` in [workspace.metadata.deb] is ignored. Set it in [package.metadata.deb] of each package"));
+            }
+        }
+        self.name = None;
+        self.assets = None;
+        self.merge_assets = None;
+        self.rebase_paths(workspace_root);
+        self
+    }
+
+    /// Makes relative paths relative to `workspace_root`, including paths in variants and target tables
+    fn rebase_paths(&mut self, workspace_root: &Path) {
+        let is_relative = |path: &Path| path.is_relative() && !path.starts_with(WORKSPACE_ROOT_PLACEHOLDER);
+        let rebase = |path: &mut String| {
+            if is_relative(Path::new(path.as_str())) {
+                *path = workspace_root.join(&*path).to_string_lossy().into_owned();
+            }
+        };
+        match &mut self.license_file {
+            Some(LicenseFile::String(path)) => rebase(path),
+            // `["LICENSE", "4"]` has a number of lines to skip, not a file
+            Some(LicenseFile::Vec(vec)) if vec.len() == 2 && vec[1].parse::<usize>().is_ok() => rebase(&mut vec[0]),
+            Some(LicenseFile::Vec(vec)) => vec.iter_mut().for_each(rebase),
+            None => {},
+        }
+        [&mut self.changelog, &mut self.extended_description_file, &mut self.triggers_file, &mut self.maintainer_scripts, &mut self.metainfo, &mut self.icon, &mut self.sysusers, &mut self.tmpfiles]
+            .into_iter().flatten().for_each(rebase);
+
+        for path in self.systemd_units_mut().iter_mut().filter_map(|u| u.unit_scripts.as_mut()) {
+            if is_relative(path) {
+                *path = workspace_root.join(&*path);
+            }
+        }
+        for nested in self.variants.iter_mut().chain(self.target.iter_mut()).flat_map(|tables| tables.values_mut()) {
+            nested.rebase_paths(workspace_root);
+        }
+    }
+
+    /// Expands `${workspace_root}` at the start of `maintainer-scripts` and `unit-scripts`,
+    /// so that a `debian/` dir at the root of the repo can be shared by workspace members.
+    pub(crate) fn with_workspace_root_paths(mut self, workspace_root: &Path) -> Self {
+        let expand = |path: &Path| -> Option<PathBuf> {
+            let rest = path.to_str()?.strip_prefix(WORKSPACE_ROOT_PLACEHOLDER)?;
+            Some(workspace_root.join(rest.trim_start_matches('/')))
+        };
+        if let Some(path) = self.maintainer_scripts.as_mut() {
+            if let Some(expanded) = expand(Path::new(path.as_str())) {
+                *path = expanded.to_string_lossy().into_owned();
+            }
+        }
+        for path in self.systemd_units_mut().iter_mut().filter_map(|u| u.unit_scripts.as_mut()) {
+            if let Some(expanded) = expand(path) {
+                *path = expanded;
+            }
+        }
+        self
+    }
+
+    fn systemd_units_mut(&mut self) -> &mut [SystemdUnitsConfig] {
+        match self.systemd_units.as_mut() {
+            Some(SystemUnitsSingleOrMultiple::Single(unit)) => std::slice::from_mut(unit),
+            Some(SystemUnitsSingleOrMultiple::Multi(units)) => &mut units[..],
+            None => &mut [],
         }
     }
 }
 
+/// Placeholder for the directory of the workspace's `Cargo.toml` in paths
+const WORKSPACE_ROOT_PLACEHOLDER: &str = "${workspace_root}";
+
 #[derive(Deserialize)]
 struct CargoMetadata {
     pub packages: Vec<CargoMetadataPackage>,
@@ -306,12 +652,37 @@ struct CargoMetadata {
 #[derive(Deserialize)]
 struct CargoMetadataResolve {
     pub root: Option<String>,
+    #[serde(default)]
+    pub nodes: Vec<CargoMetadataNode>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadataNode {
+    pub id: String,
+    #[serde(default)]
+    pub deps: Vec<CargoMetadataNodeDep>,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadataNodeDep {
+    pub pkg: String,
+    #[serde(default)]
+    pub dep_kinds: Vec<CargoMetadataDepKind>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadataDepKind {
+    /// `None` for normal deps, `dev` or `build`
+    pub kind: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct CargoMetadataPackage {
     pub id: String,
     pub name: String,
+    pub version: String,
     pub targets: Vec<CargoMetadataTarget>,
     pub manifest_path: PathBuf,
 }
@@ -328,14 +699,61 @@ pub(crate) struct ManifestFound {
     pub build_targets: Vec<CargoMetadataTarget>,
     pub manifest_path: PathBuf,
     pub root_manifest: Option<cargo_toml::Manifest<CargoPackageMetadata>>,
+    pub workspace_root: PathBuf,
     pub target_dir: PathBuf,
     pub manifest: cargo_toml::Manifest<CargoPackageMetadata>,
     /// Cargo is sensitive to the current directory it's been invoked from - relative `CARGO_TARGET_DIR` and `.cargo` dir discovery
     /// can significantly affect the build, and are disconnected from locations of the manifest and the workspace!
     pub cargo_run_current_dir: PathBuf,
+    /// Approximate number of crates `cargo build` compiles for the package, for progress
+    pub build_crate_count: usize,
 }
 
-fn parse_metadata(mut metadata: CargoMetadata, selected_package_name: Option<&str>) -> Result<(CargoMetadataPackage, PathBuf, PathBuf), CargoDebError> {
+/// Crate names and their enabled features
+pub(crate) type DependencyFeatures = BTreeMap<String, BTreeSet<String>>;
+
+/// Crates reachable from the package via non-dev, non-build dependencies.
+/// The features are unified for the whole workspace, so they may be a superset of what the package uses.
+fn dependency_features_of(metadata: &CargoMetadata, package_id: &str) -> DependencyFeatures {
+    let names = metadata.packages.iter().map(|p| (p.id.as_str(), p.name.as_str())).collect::<HashMap<_, _>>();
+    let nodes = metadata.resolve.nodes.iter().map(|n| (n.id.as_str(), n)).collect::<HashMap<_, _>>();
+    let mut found = BTreeMap::new();
+    let mut seen = HashSet::new();
+    let mut queue = vec![package_id];
+    while let Some(id) = queue.pop() {
+        let Some(node) = nodes.get(id) else { continue };
+        for dep in &node.deps {
+            let is_runtime = dep.dep_kinds.is_empty() || dep.dep_kinds.iter().any(|k| k.kind.is_none());
+            if is_runtime && seen.insert(dep.pkg.as_str()) {
+                if let (Some(name), Some(dep_node)) = (names.get(dep.pkg.as_str()), nodes.get(dep.pkg.as_str())) {
+                    found.insert((*name).to_owned(), dep_node.features.iter().cloned().collect());
+                }
+                queue.push(&dep.pkg);
+            }
+        }
+    }
+    found
+}
+
+/// Crates reachable from the package via non-dev dependencies, including the package itself.
+/// Dependencies for other platforms are counted too, so it's an overestimate.
+fn build_crate_count(metadata: &CargoMetadata, package_id: &str) -> usize {
+    let nodes = metadata.resolve.nodes.iter().map(|n| (n.id.as_str(), n)).collect::<HashMap<_, _>>();
+    let mut seen = HashSet::from([package_id]);
+    let mut queue = vec![package_id];
+    while let Some(id) = queue.pop() {
+        let Some(node) = nodes.get(id) else { continue };
+        for dep in &node.deps {
+            let is_built = dep.dep_kinds.is_empty() || dep.dep_kinds.iter().any(|k| k.kind.as_deref() != Some("dev"));
+            if is_built && seen.insert(dep.pkg.as_str()) {
+                queue.push(&dep.pkg);
+            }
+        }
+    }
+    seen.len()
+}
+
+fn parse_metadata(mut metadata: CargoMetadata, selected_package_name: Option<&str>) -> Result<(CargoMetadataPackage, PathBuf, PathBuf, usize), CargoDebError> {
     let available_package_names = || {
         metadata.packages.iter()
             .filter(|p| metadata.workspace_members.iter().any(|w| w == &p.id))
@@ -352,12 +770,14 @@ fn parse_metadata(mut metadata: CargoMetadata, selected_package_name: Option<&st
             .and_then(|root_id| metadata.packages.iter().position(move |p| &p.id == root_id))
         .ok_or_else(|| CargoDebError::NoRootFoundInWorkspace(available_package_names()))
     }?;
-    Ok((metadata.packages.swap_remove(target_package_pos), metadata.target_directory.into(), metadata.workspace_root.into()))
+    let build_crate_count = build_crate_count(&metadata, &metadata.packages[target_package_pos].id);
+    Ok((metadata.packages.swap_remove(target_package_pos), metadata.target_directory.into(), metadata.workspace_root.into(), build_crate_count))
 }
 
-pub(crate) fn cargo_metadata(root_manifest_path: Option<&Path>, selected_package_name: Option<&str>, cargo_locking_flags: CargoLockingFlags) -> Result<ManifestFound, CargoDebError> {
-    let (metadata, cargo_run_current_dir) = run_cargo_metadata(root_manifest_path, cargo_locking_flags)?;
-    let (target_package, target_dir, workspace_root) = parse_metadata(metadata, selected_package_name)?;
+/// `target_dir` overrides `CARGO_TARGET_DIR`
+pub(crate) fn cargo_metadata(root_manifest_path: Option<&Path>, selected_package_name: Option<&str>, target_dir: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> Result<ManifestFound, CargoDebError> {
+    let (metadata, cargo_run_current_dir) = run_cargo_metadata(root_manifest_path, target_dir, cargo_locking_flags, &[])?;
+    let (target_package, target_dir, workspace_root, build_crate_count) = parse_metadata(metadata, selected_package_name)?;
 
     let workspace_root_manifest_path = workspace_root.join("Cargo.toml");
     let root_manifest = cargo_toml::Manifest::<CargoPackageMetadata>::from_path_with_metadata(workspace_root_manifest_path).ok();
@@ -368,23 +788,67 @@ pub(crate) fn cargo_metadata(root_manifest_path: Option<&Path>, selected_package
     let ws_root = root_manifest.as_ref().map(|ws| (ws, workspace_root.as_path()));
     manifest.complete_from_path_and_workspace(manifest_path, ws_root)
         .map_err(move |e| CargoDebError::TomlParsing(e, manifest_path.to_path_buf()))?;
+    // The workspace may be in a place cargo_toml can't find, but Cargo knows the version anyway
+    if let Some(package) = manifest.package.as_mut() {
+        if !package.version.is_set() {
+            package.version.set(target_package.version.clone());
+        }
+    }
 
     Ok(ManifestFound {
         manifest_path: target_package.manifest_path,
         build_targets: target_package.targets,
         root_manifest,
+        workspace_root,
         target_dir,
         manifest,
         cargo_run_current_dir,
+        build_crate_count,
     })
 }
 
+/// Crates the package at `manifest_path` depends on at run time on the target platform, with features enabled by `feature_flags`.
+///
+/// This needs another run of `cargo metadata`, because the features of the build aren't known until the manifest has been read.
+pub(crate) fn dependency_features(manifest_path: &Path, rust_target_triple: &str, feature_flags: &[String], target_dir: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<DependencyFeatures> {
+    let (metadata, package_id) = platform_metadata(manifest_path, rust_target_triple, feature_flags, target_dir, cargo_locking_flags)?;
+    Ok(dependency_features_of(&metadata, &package_id))
+}
+
+/// Names of crates that the package at `manifest_path` uses as dependencies or build-dependencies on the target platform,
+/// including ones from `[target.'cfg(…)'.dependencies]` that apply to it
+pub(crate) fn platform_dependencies(manifest_path: &Path, rust_target_triple: &str, target_dir: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<BTreeSet<String>> {
+    let (metadata, package_id) = platform_metadata(manifest_path, rust_target_triple, &[], target_dir, cargo_locking_flags)?;
+    let names = metadata.packages.iter().map(|p| (p.id.as_str(), p.name.as_str())).collect::<HashMap<_, _>>();
+    let Some(node) = metadata.resolve.nodes.iter().find(|n| n.id == package_id) else { return Ok(BTreeSet::new()) };
+    Ok(node.deps.iter()
+        .filter(|dep| dep.dep_kinds.is_empty() || dep.dep_kinds.iter().any(|k| k.kind.as_deref() != Some("dev")))
+        .filter_map(|dep| names.get(dep.pkg.as_str()).map(|&name| name.to_owned()))
+        .collect())
+}
+
+/// `cargo metadata` resolved only for the target platform, and the id of the package at `manifest_path`
+fn platform_metadata(manifest_path: &Path, rust_target_triple: &str, feature_flags: &[String], target_dir: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<(CargoMetadata, String)> {
+    let mut args = vec![format!("--filter-platform={rust_target_triple}")];
+    args.extend_from_slice(feature_flags);
+    let (metadata, _) = run_cargo_metadata(Some(manifest_path), target_dir, cargo_locking_flags, &args)?;
+    let manifest_path = manifest_path.canonicalize().map_err(|e| CargoDebError::IoFile("bad manifest path", e, manifest_path.into()))?;
+    let package_id = metadata.packages.iter().find(|p| Path::new(&p.manifest_path) == manifest_path)
+        .ok_or_else(|| CargoDebError::IoFile("package not found in cargo metadata", std::io::ErrorKind::NotFound.into(), manifest_path.clone()))?
+        .id.clone();
+    Ok((metadata, package_id))
+}
+
 /// Returns the workspace metadata based on the `Cargo.toml` that we want to build,
 /// and directory that paths may be relative to
-fn run_cargo_metadata(manifest_rel_path: Option<&Path>, cargo_locking_flags: CargoLockingFlags) -> CDResult<(CargoMetadata, PathBuf)> {
+fn run_cargo_metadata(manifest_rel_path: Option<&Path>, target_dir: Option<&Path>, cargo_locking_flags: CargoLockingFlags, extra_args: &[String]) -> CDResult<(CargoMetadata, PathBuf)> {
     let mut cmd = Command::new("cargo");
     cmd.args(["metadata", "--format-version=1"]);
     cmd.args(cargo_locking_flags.flags());
+    cmd.args(extra_args);
+    if let Some(dir) = target_dir {
+        cmd.env("CARGO_TARGET_DIR", dir);
+    }
 
     let current_dir = if let Some(path) = manifest_rel_path {
         // cargo will read ./.config relative to the current dir,
@@ -403,13 +867,27 @@ fn run_cargo_metadata(manifest_rel_path: Option<&Path>, cargo_locking_flags: Car
     };
     cmd.current_dir(&current_dir);
 
-    let output = cmd.output()
+    let cache = MetadataCache::new(&current_dir, cmd.get_args(), target_dir, cargo_locking_flags.locked || cargo_locking_flags.frozen);
+    if let Some(cached) = cache.as_ref().and_then(|c| c.load()) {
+        if let Ok(metadata) = serde_json::from_slice(&cached) {
+            return Ok((metadata, current_dir));
+        }
+    }
+
+    let output = offline::audit(&mut cmd)?.output()
         .map_err(|e| CargoDebError::CommandFailed(e, "cargo (is it in your PATH?)"))?;
     if !output.status.success() {
         return Err(CargoDebError::CommandError("cargo", "metadata".to_owned(), output.stderr));
     }
 
-    let metadata = serde_json::from_slice(&output.stdout)?;
+    let metadata: CargoMetadata = serde_json::from_slice(&output.stdout)?;
+    if let Some(cache) = cache.filter(|c| c.target_dir == Path::new(&metadata.target_directory)) {
+        let workspace_root = Path::new(&metadata.workspace_root);
+        let member_manifests = metadata.packages.iter()
+            .filter(|p| metadata.workspace_members.contains(&p.id))
+            .map(|p| p.manifest_path.clone());
+        cache.store(&output.stdout, [workspace_root.join("Cargo.lock"), workspace_root.join("Cargo.toml")].into_iter().chain(member_manifests));
+    }
     Ok((metadata, current_dir))
 }
 
@@ -422,7 +900,7 @@ mod tests {
         // Test merging assets by dest
         fn create_test_asset(src: impl Into<PathBuf>, target_path: impl Into<PathBuf>, perm: u32) -> RawAsset {
             RawAsset {
-                source_path: src.into(), target_path: target_path.into(), chmod: perm
+                source_path: src.into(), target_path: target_path.into(), chmod: perm, when: None, special: None, multiarch: None, owner: None, verbatim: false,
             }
         }
 
@@ -535,6 +1013,147 @@ mod tests {
         assert_eq!("/opt/test/other-empty.txt", additional_asset.target_path.as_os_str(), "should preserve dest location");
         assert_eq!(0o655, additional_asset.chmod, "should have merged the dest location");
     }
+
+    #[test]
+    fn systemd_unit_restart_options() {
+        let units = toml::from_str::<CargoDeb>(r#"systemd-units = { restart-policy = "none", stop-timeout = "1.5m" }"#).unwrap().systemd_units.unwrap();
+        let SystemUnitsSingleOrMultiple::Single(unit) = units else { panic!() };
+        assert_eq!(unit.restart_policy, Some(RestartPolicy::Never));
+        assert_eq!(unit.stop_timeout, Some(StopTimeout("1.5m".into())));
+        for bad in ["30sec", "s", "-1s", "0", "inf"] {
+            assert!(toml::from_str::<SystemdUnitsConfig>(&format!(r#"stop-timeout = "{bad}""#)).is_err(), "{bad}");
+        }
+        assert!(toml::from_str::<SystemdUnitsConfig>(r#"restart-policy = "always""#).is_err());
+    }
+
+    #[test]
+    fn appended_variant_assets_replace_base() {
+        let asset = |src: &str, dest: &str| RawAsset { source_path: src.into(), target_path: dest.into(), chmod: 0o644, when: None, special: None, multiarch: None, owner: None, verbatim: false };
+        let parent = CargoDeb { assets: Some(vec![asset("base.conf", "etc/app.conf"), asset("README", "usr/share/doc/app/")]), ..Default::default() };
+        let variant = CargoDeb {
+            merge_assets: Some(MergeAssets { append: Some(vec![asset("variant.conf", "etc/app.conf"), asset("NOTES", "usr/share/doc/app/")]), by: None }),
+            ..Default::default()
+        };
+        let merged = variant.inherit_from(parent).assets.unwrap();
+        let sources = merged.iter().map(|a| a.source_path.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(sources, ["README", "variant.conf", "NOTES"]);
+    }
+
+    #[test]
+    fn variant_profile_overrides_package_profile() {
+        let parent = CargoDeb { profile: Some("dist".into()), ..Default::default() };
+        let small = CargoDeb { profile: Some("minsize".into()), ..Default::default() };
+        assert_eq!(Some("minsize"), small.inherit_from(parent.clone()).profile.as_deref());
+        assert_eq!(Some("dist"), CargoDeb::default().inherit_from(parent).profile.as_deref());
+    }
+
+    #[test]
+    fn workspace_defaults() {
+        use crate::listener::{CapturingListener, Level};
+
+        let workspace: CargoDeb = toml::from_str(r#"
+            name = "shared"
+            maintainer = "Team <team@example.com>"
+            section = "net"
+            license-file = ["LICENSE", "2"]
+            maintainer-scripts = "debian/"
+            changelog = "/abs/changelog"
+        "#).unwrap();
+        let listener = CapturingListener::new();
+        let defaults = workspace.into_workspace_defaults(Path::new("/ws"), &listener);
+        assert_eq!(listener.messages(Level::Warning).len(), 1);
+
+        let package = CargoDeb { section: Some("utils".into()), ..Default::default() }.inherit_from(defaults);
+        assert_eq!(package.name, None);
+        assert_eq!(package.maintainer.as_deref(), Some("Team <team@example.com>"));
+        assert_eq!(package.section.as_deref(), Some("utils"));
+        assert!(matches!(package.license_file.as_ref(), Some(LicenseFile::Vec(v)) if v[0] == "/ws/LICENSE" && v[1] == "2"));
+        assert_eq!(package.maintainer_scripts.as_deref(), Some("/ws/debian/"));
+        assert_eq!(package.changelog.as_deref(), Some("/abs/changelog"));
+
+        let workspace: CargoDeb = toml::from_str(r#"
+            license-file = ["LICENSE-MIT", "LICENSE-APACHE"]
+            systemd-units = { unit-scripts = "debian/units" }
+            variants.debug.changelog = "CHANGELOG"
+            target.arm64.license-file = "LICENSE-ARM"
+        "#).unwrap();
+        let defaults = workspace.into_workspace_defaults(Path::new("/ws"), &crate::listener::NoOpListener);
+        assert!(matches!(defaults.license_file.as_ref(), Some(LicenseFile::Vec(v)) if v == &["/ws/LICENSE-MIT", "/ws/LICENSE-APACHE"]));
+        let Some(SystemUnitsSingleOrMultiple::Single(unit)) = &defaults.systemd_units else { panic!() };
+        assert_eq!(unit.unit_scripts.as_deref(), Some(Path::new("/ws/debian/units")));
+        assert_eq!(defaults.variants.as_ref().unwrap()["debug"].changelog.as_deref(), Some("/ws/CHANGELOG"));
+        assert!(matches!(defaults.target.as_ref().unwrap()["arm64"].license_file.as_ref(), Some(LicenseFile::String(f)) if f == "/ws/LICENSE-ARM"));
+    }
+
+    #[test]
+    fn workspace_root_placeholder() {
+        let deb: CargoDeb = toml::from_str(r#"
+            maintainer-scripts = "${workspace_root}/debian/"
+            systemd-units = [{ unit-scripts = "${workspace_root}/debian/units" }, { unit-scripts = "units" }]
+        "#).unwrap();
+        let deb = deb.with_workspace_root_paths(Path::new("/ws"));
+        assert_eq!(deb.maintainer_scripts.as_deref(), Some("/ws/debian/"));
+        let Some(SystemUnitsSingleOrMultiple::Multi(units)) = &deb.systemd_units else { panic!() };
+        assert_eq!(units[0].unit_scripts.as_deref(), Some(Path::new("/ws/debian/units")));
+        assert_eq!(units[1].unit_scripts.as_deref(), Some(Path::new("units")));
+
+        let workspace: CargoDeb = toml::from_str(r#"maintainer-scripts = "${workspace_root}/debian/""#).unwrap();
+        let defaults = workspace.into_workspace_defaults(Path::new("/ws"), &crate::listener::NoOpListener);
+        assert_eq!(defaults.with_workspace_root_paths(Path::new("/ws")).maintainer_scripts.as_deref(), Some("/ws/debian/"));
+    }
+
+    #[test]
+    fn target_overrides() {
+        let deb: CargoDeb = toml::from_str(r#"
+            depends = "libc6"
+            conf-files = ["/etc/app.conf"]
+            assets = [["target/release/app", "usr/bin/", "755"]]
+            [target.arm64]
+            depends = "libc6, tpm-udev"
+            merge-assets.append = [["udev/rules", "usr/lib/udev/rules.d/60-app.rules", "644"]]
+            [target.aarch64-unknown-linux-gnu]
+            conf-files = []
+        "#).unwrap();
+
+        let amd64 = deb.clone().with_target_overrides("x86_64-unknown-linux-gnu");
+        assert_eq!(amd64.depends.unwrap().into_depends_string().unwrap(), "libc6");
+        assert_eq!(amd64.assets.unwrap().len(), 1);
+        assert!(amd64.target.is_none());
+
+        let arm64 = deb.with_target_overrides("aarch64-unknown-linux-gnu");
+        assert_eq!(arm64.depends.unwrap().into_depends_string().unwrap(), "libc6, tpm-udev");
+        assert_eq!(arm64.assets.unwrap().len(), 2);
+        assert_eq!(arm64.conf_files.unwrap().len(), 0);
+
+        let deb: CargoDeb = toml::from_str(r#"
+            assets = [["target/release/app", "usr/bin/", "755"]]
+            [targets."aarch64-unknown-linux-gnu"]
+            merge-assets.append = [["data/neon.bin", "usr/share/app/", "644"]]
+        "#).unwrap();
+        let assets = deb.with_target_overrides("aarch64-unknown-linux-gnu").assets.unwrap();
+        assert_eq!(assets[1].source_path, Path::new("data/neon.bin"));
+    }
+
+    #[test]
+    fn structured_dependencies() {
+        let deb: CargoDeb = toml::from_str(r#"
+            depends = ["$auto", { package = "libssl3", version = ">= 3.0", arch = ["amd64", "arm64"] }, { package = "curl" }]
+            conflicts = [{ package = "old-tool", version = "<< 2" }]
+            provides = "tool"
+        "#).unwrap();
+        assert_eq!(deb.depends.unwrap().into_depends_string().unwrap(), "$auto, libssl3 (>= 3.0) [amd64 arm64], curl");
+        assert_eq!(deb.conflicts.unwrap().into_relations_string("conflicts").unwrap(), "old-tool (<< 2)");
+        assert_eq!(deb.provides.unwrap().into_relations_string("provides").unwrap(), "tool");
+        let deb: CargoDeb = toml::from_str(r#"conflicts = [{ package = "old-tool", arch = ["amd64"] }]"#).unwrap();
+        assert!(deb.conflicts.unwrap().into_relations_string("conflicts").is_err());
+
+        let invalid = |toml: &str| toml::from_str::<CargoDeb>(toml).unwrap().depends.unwrap().into_depends_string().is_err();
+        assert!(invalid(r#"depends = [{ package = "libssl3, evil" }]"#));
+        assert!(invalid(r#"depends = [{ package = "libssl3", version = "~> 3" }]"#));
+        assert!(invalid(r#"depends = [{ package = "libssl3", version = ">= 3) [x"}]"#));
+        assert!(invalid(r#"depends = [{ package = "libssl3", arch = ["amd64]"] }]"#));
+        assert!(toml::from_str::<CargoDeb>(r#"depends = [{ package = "a", versoin = "1" }]"#).is_err());
+    }
 }
 
 #[test]
@@ -552,3 +1171,42 @@ fn deb_ver() {
     assert_eq!("1.2.0-new-11", manifest_version_string(&c, Some("11")));
     assert_eq!("1.2.0-new", manifest_version_string(&c, Some("0")));
 }
+
+#[test]
+fn profile_package_debug_override() {
+    let manifest = cargo_toml::Manifest::<CargoPackageMetadata>::from_slice_with_metadata(br#"
+        [package]
+        name = "mybin"
+        version = "1.0.0"
+        [profile.release]
+        debug = false
+        [profile.release.package.mybin]
+        debug = 2
+        [profile.dist.package.other]
+        debug = "full"
+    "#).unwrap();
+    assert_eq!(Some(true), manifest_debug_flag(&manifest, "release", "mybin"));
+    assert_eq!(Some(false), manifest_debug_flag(&manifest, "release", "other"));
+    assert_eq!(None, manifest_debug_flag(&manifest, "dist", "mybin"));
+
+    let manifest = cargo_toml::Manifest::<CargoPackageMetadata>::from_slice_with_metadata(br#"
+        [package]
+        name = "mybin"
+        version = "1.0.0"
+        [profile.release.package."*"]
+        debug = 1
+        [profile.release.package.mybin]
+        debug = 0
+    "#).unwrap();
+    assert_eq!(Some(false), manifest_debug_flag(&manifest, "release", "mybin"));
+    assert_eq!(Some(true), manifest_debug_flag(&manifest, "release", "other"));
+}
+
+#[test]
+fn platform_specific_dependencies() {
+    let linux = platform_dependencies(Path::new("Cargo.toml"), "x86_64-unknown-linux-gnu", None, CargoLockingFlags::default()).unwrap();
+    assert!(linux.contains("libc") && linux.contains("tar"));
+    assert!(!linux.contains("mockall"), "dev-dependencies aren't included");
+    let windows = platform_dependencies(Path::new("Cargo.toml"), "x86_64-pc-windows-msvc", None, CargoLockingFlags::default()).unwrap();
+    assert!(!windows.contains("libc") && windows.contains("tar"));
+}
@@ -0,0 +1,123 @@
+//! Cache of `cargo metadata` output in `target/debian/.cache`, because it can take seconds in large workspaces.
+//!
+//! The cache is invalidated when `Cargo.lock` or any manifest of the workspace is modified.
+//! Changes elsewhere (e.g. in path dependencies outside of the workspace) aren't tracked,
+//! so the cache expires after a while, unless Cargo runs with `--locked` or `--frozen`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsStr;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+const MAX_AGE: Duration = Duration::from_secs(15 * 60);
+
+/// For `--no-metadata-cache`
+pub(crate) fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// Seconds since the Unix epoch
+    created: u64,
+    /// Paths and their modification times in nanoseconds
+    files: Vec<(PathBuf, u128)>,
+}
+
+pub(crate) struct MetadataCache {
+    path: PathBuf,
+    /// Cache is in a guessed location, which must match Cargo's
+    pub target_dir: PathBuf,
+    /// Cargo's lock makes the metadata stable
+    locked: bool,
+}
+
+impl MetadataCache {
+    /// `args` and `current_dir` of the `cargo metadata` command are the key.
+    /// `target_dir` is the `CARGO_TARGET_DIR` set for the command, if any.
+    /// Returns `None` if the target dir can't be known without running Cargo.
+    pub fn new<'a>(current_dir: &Path, args: impl Iterator<Item = &'a OsStr>, target_dir: Option<&Path>, locked: bool) -> Option<Self> {
+        if DISABLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        let target_dir = match target_dir.map(|d| d.as_os_str().to_owned()).or_else(|| std::env::var_os("CARGO_TARGET_DIR")) {
+            Some(dir) => current_dir.join(dir),
+            None => current_dir.ancestors().find(|dir| dir.join("Cargo.lock").exists())?.join("target"),
+        };
+        let mut hasher = DefaultHasher::new();
+        current_dir.hash(&mut hasher);
+        for arg in args {
+            arg.hash(&mut hasher);
+        }
+        let path = target_dir.join("debian/.cache").join(format!("metadata-{:016x}.json", hasher.finish()));
+        Some(Self { path, target_dir, locked })
+    }
+
+    /// The raw JSON, if it's still valid
+    pub fn load(&self) -> Option<Vec<u8>> {
+        let data = fs::read(&self.path).ok()?;
+        let (header, metadata) = data.split_at(data.iter().position(|&c| c == b'\n')?);
+        let header: Header = serde_json::from_slice(header).ok()?;
+        let age = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs().checked_sub(header.created)?;
+        if !self.locked && age > MAX_AGE.as_secs() {
+            return None;
+        }
+        if header.files.iter().any(|(path, mtime)| modified_nanos(path) != Some(*mtime)) {
+            return None;
+        }
+        log::debug!("using cached cargo metadata from {}", self.path.display());
+        Some(metadata[1..].to_vec())
+    }
+
+    /// `files` are the manifests and `Cargo.lock` that the metadata depends on.
+    /// The cache is not essential, so errors are ignored.
+    pub fn store(&self, metadata: &[u8], files: impl Iterator<Item = PathBuf>) {
+        let Some(created) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok() else { return };
+        let files = files.filter_map(|path| {
+            let mtime = modified_nanos(&path)?;
+            Some((path, mtime))
+        }).collect();
+        let Ok(mut data) = serde_json::to_vec(&Header { created: created.as_secs(), files }) else { return };
+        data.push(b'\n');
+        data.extend_from_slice(metadata);
+
+        let Some(dir) = self.path.parent() else { return };
+        let _ = fs::create_dir_all(dir);
+        // written to a temp file, so that concurrent runs don't read incomplete files
+        let Ok(mut tmp) = tempfile::NamedTempFile::new_in(dir) else { return };
+        if tmp.write_all(&data).is_ok() {
+            let _ = tmp.persist(&self.path);
+        }
+    }
+}
+
+fn modified_nanos(path: &Path) -> Option<u128> {
+    Some(fs::metadata(path).ok()?.modified().ok()?.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_nanos())
+}
+
+#[test]
+fn invalidated_by_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("Cargo.lock"), "").unwrap();
+    let manifest = dir.path().join("Cargo.toml");
+    fs::write(&manifest, "[package]").unwrap();
+
+    let cache = MetadataCache::new(dir.path(), ["metadata".as_ref()].into_iter(), None, false).unwrap();
+    assert!(cache.load().is_none());
+    cache.store(b"{}", [manifest.clone()].into_iter());
+    assert_eq!(cache.load().unwrap(), b"{}");
+
+    let other_args = MetadataCache::new(dir.path(), ["metadata".as_ref(), "--offline".as_ref()].into_iter(), None, false).unwrap();
+    assert!(other_args.load().is_none());
+
+    std::thread::sleep(Duration::from_millis(10));
+    fs::write(&manifest, "[package]\n").unwrap();
+    assert!(cache.load().is_none());
+}
@@ -0,0 +1,228 @@
+//! Uploading of finished packages with `--publish`, configured in `[package.metadata.deb.publish]`
+//!
+//! Uploads use `curl` and the AWS CLI, so that their proxy settings and credentials work as usual.
+//! Local aptly and reprepro repositories can be updated with `--add-to-repo`.
+
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::parse::manifest::PublishConfig;
+use crate::util::offline;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Called for every package after it's been written
+pub trait Publisher {
+    /// Uploads the `.deb` file
+    fn publish(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<()>;
+}
+
+/// HTTP `PUT`, which Artifactory, Nexus, and similar repository managers accept for apt repositories
+pub struct HttpPut {
+    /// The file name is appended if it ends with `/`
+    pub url: String,
+    /// Basic auth, or a bearer token if there's no username
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// `Name: value`
+    pub headers: Vec<String>,
+}
+
+impl Publisher for HttpPut {
+    fn publish(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<()> {
+        let url = target_url(&self.url, deb_path);
+        listener.info(format!("Publishing {url}"));
+
+        // Secrets are passed via stdin, because arguments are visible to other users
+        let mut curl_config = String::new();
+        match (&self.username, &self.password) {
+            (Some(username), password) => curl_config_line(&mut curl_config, "user", &format!("{username}:{}", password.as_deref().unwrap_or_default())),
+            (None, Some(token)) => curl_config_line(&mut curl_config, "header", &format!("Authorization: Bearer {token}")),
+            (None, None) => {},
+        }
+        for header in &self.headers {
+            curl_config_line(&mut curl_config, "header", header);
+        }
+
+        let mut cmd = Command::new("curl");
+        cmd.args(["--fail", "--silent", "--show-error", "--netrc-optional", "--config", "-", "--upload-file"])
+            .arg(deb_path)
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let mut child = offline::audit(&mut cmd)?.spawn()
+            .map_err(|e| CargoDebError::CommandFailed(e, "curl (is it installed?)"))?;
+        child.stdin.take().ok_or("curl stdin")?.write_all(curl_config.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(CargoDebError::CommandError("Unable to publish", url, output.stderr));
+        }
+        Ok(())
+    }
+}
+
+/// Amazon S3 or a compatible storage, using the `aws` CLI and its usual credentials
+pub struct S3 {
+    /// `s3://bucket/key`. The file name is appended if it ends with `/`
+    pub url: String,
+    pub region: Option<String>,
+    /// For S3-compatible storage that isn't AWS
+    pub endpoint: Option<String>,
+}
+
+impl Publisher for S3 {
+    fn publish(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<()> {
+        let url = target_url(&self.url, deb_path);
+        listener.info(format!("Publishing {url}"));
+
+        let mut cmd = Command::new("aws");
+        cmd.args(["s3", "cp", "--only-show-errors"]).arg(deb_path).arg(&url);
+        if let Some(region) = &self.region {
+            cmd.args(["--region", region]);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            cmd.args(["--endpoint-url", endpoint]);
+        }
+        let output = offline::audit(&mut cmd)?.output()
+            .map_err(|e| CargoDebError::CommandFailed(e, "aws (is AWS CLI installed?)"))?;
+        if !output.status.success() {
+            return Err(CargoDebError::CommandError("Unable to publish", url, output.stderr));
+        }
+        Ok(())
+    }
+}
+
+/// Local repository managed by [aptly](https://www.aptly.info)
+pub struct Aptly {
+    pub repo: String,
+}
+
+impl Publisher for Aptly {
+    fn publish(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<()> {
+        let mut cmd = Command::new("aptly");
+        cmd.args(["repo", "add", &self.repo]).arg(deb_path);
+        run_repo_tool(&mut cmd, "aptly", &self.repo, listener)
+    }
+}
+
+/// Repository managed by [reprepro](https://salsa.debian.org/debian/reprepro)
+pub struct Reprepro {
+    pub base_dir: PathBuf,
+    pub codename: String,
+}
+
+impl Publisher for Reprepro {
+    fn publish(&self, deb_path: &Path, listener: &dyn Listener) -> CDResult<()> {
+        let mut cmd = Command::new("reprepro");
+        cmd.arg("-b").arg(&self.base_dir).args(["includedeb", &self.codename]).arg(deb_path);
+        run_repo_tool(&mut cmd, "reprepro", &self.codename, listener)
+    }
+}
+
+/// Parses `aptly:<repo>` or `reprepro:<basedir>:<codename>` of `--add-to-repo`
+pub(crate) fn repo_publisher(spec: &str) -> CDResult<Box<dyn Publisher>> {
+    match spec.split_once(':') {
+        Some(("aptly", repo)) if !repo.is_empty() => Ok(Box::new(Aptly { repo: repo.to_owned() })),
+        Some(("reprepro", rest)) => match rest.rsplit_once(':') {
+            Some((base_dir, codename)) if !base_dir.is_empty() && !codename.is_empty() => Ok(Box::new(Reprepro {
+                base_dir: base_dir.into(),
+                codename: codename.to_owned(),
+            })),
+            _ => Err(CargoDebError::InvalidRepoSpec(spec.to_owned())),
+        },
+        _ => Err(CargoDebError::InvalidRepoSpec(spec.to_owned())),
+    }
+}
+
+/// Their output is reported as info, since it says what has been added or replaced
+fn run_repo_tool(cmd: &mut Command, tool: &'static str, repo: &str, listener: &dyn Listener) -> CDResult<()> {
+    let output = offline::audit(cmd)?.output()
+        .map_err(|e| CargoDebError::CommandFailed(e, tool))?;
+    if !output.status.success() {
+        let mut reason = output.stdout;
+        reason.extend_from_slice(&output.stderr);
+        return Err(CargoDebError::CommandError(tool, repo.to_owned(), reason));
+    }
+    for line in String::from_utf8_lossy(&output.stdout).lines().chain(String::from_utf8_lossy(&output.stderr).lines()) {
+        if !line.trim().is_empty() {
+            listener.info(format!("{tool}: {line}"));
+        }
+    }
+    Ok(())
+}
+
+/// `url` from `--publish=<url>` overrides the one in the config
+pub(crate) fn publisher_from_config(url: Option<&str>, config: Option<&PublishConfig>) -> CDResult<Box<dyn Publisher>> {
+    let default_config = PublishConfig::default();
+    let config = config.unwrap_or(&default_config);
+    let url = url.or(config.url.as_deref())
+        .ok_or(CargoDebError::Str("--publish needs a URL, either as --publish=<url> or `url` in [package.metadata.deb.publish]"))?;
+
+    if url.starts_with("s3://") {
+        return Ok(Box::new(S3 {
+            url: url.to_owned(),
+            region: config.region.clone(),
+            endpoint: config.endpoint.clone(),
+        }));
+    }
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(CargoDebError::InvalidPublishUrl(url.to_owned()));
+    }
+    let password = match &config.password_env {
+        Some(var) => Some(std::env::var(var).map_err(|_| CargoDebError::PublishPasswordEnv(var.clone()))?),
+        None => None,
+    };
+    Ok(Box::new(HttpPut {
+        url: url.to_owned(),
+        username: config.username.clone(),
+        password,
+        headers: config.headers.clone().unwrap_or_default(),
+    }))
+}
+
+fn target_url(url: &str, deb_path: &Path) -> String {
+    if !url.ends_with('/') {
+        return url.to_owned();
+    }
+    let file_name = deb_path.file_name().unwrap_or_default().to_string_lossy();
+    format!("{url}{file_name}")
+}
+
+/// `name = "value"` in curl's config file syntax
+fn curl_config_line(config: &mut String, name: &str, value: &str) {
+    config.push_str(name);
+    config.push_str(" = \"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => { config.push('\\'); config.push(c); },
+            '\n' => config.push_str("\\n"),
+            c => config.push(c),
+        }
+    }
+    config.push_str("\"\n");
+}
+
+#[test]
+fn publish_urls() {
+    let deb = Path::new("target/debian/foo_1.0-1_amd64.deb");
+    assert_eq!(target_url("https://repo.example.com/debian/", deb), "https://repo.example.com/debian/foo_1.0-1_amd64.deb");
+    assert_eq!(target_url("s3://bucket/latest.deb", deb), "s3://bucket/latest.deb");
+
+    let mut config = String::new();
+    curl_config_line(&mut config, "user", r#"me:p"a\ss"#);
+    assert_eq!(config, "user = \"me:p\\\"a\\\\ss\"\n");
+
+    assert!(publisher_from_config(None, None).is_err());
+    assert!(publisher_from_config(Some("ftp://example.com/"), None).is_err());
+    assert!(publisher_from_config(Some("s3://bucket/"), None).is_ok());
+}
+
+#[test]
+fn repo_specs() {
+    assert!(repo_publisher("aptly:stable").is_ok());
+    assert!(repo_publisher("reprepro:/srv/repo:bookworm").is_ok());
+    assert!(repo_publisher("reprepro:bookworm").is_err());
+    assert!(repo_publisher("aptly:").is_err());
+    assert!(repo_publisher("dput:ppa").is_err());
+}
@@ -0,0 +1,420 @@
+//! Parsing and normalization of package relationship fields like `Depends`
+//!
+//! <https://www.debian.org/doc/debian-policy/ch-relationships.html>
+
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum VersionOp {
+    Earlier,
+    EarlierOrEqual,
+    Equal,
+    LaterOrEqual,
+    Later,
+}
+
+impl VersionOp {
+    fn parse(op: &str) -> Option<Self> {
+        Some(match op {
+            "<<" => Self::Earlier,
+            "<=" => Self::EarlierOrEqual,
+            "=" => Self::Equal,
+            ">=" => Self::LaterOrEqual,
+            ">>" => Self::Later,
+            _ => return None,
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Earlier => "<<",
+            Self::EarlierOrEqual => "<=",
+            Self::Equal => "=",
+            Self::LaterOrEqual => ">=",
+            Self::Later => ">>",
+        }
+    }
+
+    fn is_lower_bound(self) -> bool {
+        matches!(self, Self::LaterOrEqual | Self::Later)
+    }
+}
+
+/// A single package in a relationship field, e.g. `libc6:any (>= 2.34) [amd64]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Relation {
+    pub name: String,
+    /// `:any` or `:native`
+    pub arch_qualifier: Option<String>,
+    pub version: Option<(VersionOp, String)>,
+    /// Architecture restriction, without the brackets
+    pub arch_spec: Option<String>,
+}
+
+impl Relation {
+    pub fn parse(s: &str) -> CDResult<Self> {
+        let invalid = || CargoDebError::InvalidRelation(s.trim().to_owned());
+        let s = s.trim();
+        let name_end = s.find([' ', '(', '[', '<']).unwrap_or(s.len());
+        let (name, arch_qualifier) = match s[..name_end].split_once(':') {
+            Some((name, qual)) => (name, Some(qual.to_owned())),
+            None => (&s[..name_end], None),
+        };
+        if name.is_empty() || !name.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'+' | b'-' | b'.')) {
+            return Err(invalid());
+        }
+        let mut rest = s[name_end..].trim_start();
+
+        let mut version = None;
+        if let Some(v) = rest.strip_prefix('(') {
+            let (v, after) = v.split_once(')').ok_or_else(invalid)?;
+            let v = v.trim();
+            let op_len = v.find(|c: char| !matches!(c, '<' | '>' | '=')).ok_or_else(invalid)?;
+            let op = VersionOp::parse(&v[..op_len]).ok_or_else(invalid)?;
+            let ver = v[op_len..].trim();
+            if ver.is_empty() {
+                return Err(invalid());
+            }
+            version = Some((op, ver.to_owned()));
+            rest = after.trim_start();
+        }
+
+        let mut arch_spec = None;
+        if let Some(a) = rest.strip_prefix('[') {
+            let (a, after) = a.split_once(']').ok_or_else(invalid)?;
+            arch_spec = Some(a.trim().to_owned());
+            rest = after.trim_start();
+        }
+        // build profiles are irrelevant for binary packages
+        if !rest.is_empty() && !rest.starts_with('<') {
+            return Err(invalid());
+        }
+
+        Ok(Self { name: name.to_owned(), arch_qualifier, version, arch_spec })
+    }
+
+    /// From a structured table in `Cargo.toml`, e.g. `libssl3`, `>= 3.0`, `["amd64"]`
+    pub fn from_parts(name: &str, version: Option<&str>, arch: &[String]) -> CDResult<Self> {
+        let invalid = |what: &str| CargoDebError::InvalidRelation(format!("{name} ({what})"));
+        if name.is_empty() || !name.bytes().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, b'+' | b'-' | b'.')) {
+            return Err(invalid("package names can only have lowercase letters, digits, and +-."));
+        }
+        let version = version.map(str::trim).filter(|v| !v.is_empty()).map(|v| {
+            let op_len = v.find(|c: char| !matches!(c, '<' | '>' | '=')).unwrap_or(v.len());
+            // a version without an operator means exactly that version
+            let op = if op_len == 0 { Some(VersionOp::Equal) } else { VersionOp::parse(&v[..op_len]) }
+                .ok_or_else(|| invalid("version operator must be one of <<, <=, =, >=, >>"))?;
+            let ver = v[op_len..].trim();
+            if ver.is_empty() || !ver.starts_with(|c: char| c.is_ascii_digit())
+                || !ver.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'+' | b'-' | b'~' | b':')) {
+                return Err(invalid("invalid version"));
+            }
+            Ok((op, ver.to_owned()))
+        }).transpose()?;
+        if arch.iter().any(|a| a.is_empty() || !a.bytes().enumerate().all(|(i, c)| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-' || (i == 0 && c == b'!'))) {
+            return Err(invalid("invalid architecture name"));
+        }
+        if arch.iter().any(|a| a.starts_with('!')) && !arch.iter().all(|a| a.starts_with('!')) {
+            return Err(invalid("architectures must be either all negated with ! or none"));
+        }
+        let arch_spec = (!arch.is_empty()).then(|| arch.join(" "));
+        Ok(Self { name: name.to_owned(), arch_qualifier: None, version, arch_spec })
+    }
+
+    /// Same package, possibly with a different version constraint
+    fn same_package(&self, other: &Self) -> bool {
+        self.name == other.name && self.arch_qualifier == other.arch_qualifier && self.arch_spec == other.arch_spec
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(q) = &self.arch_qualifier {
+            write!(f, ":{q}")?;
+        }
+        if let Some((op, ver)) = &self.version {
+            write!(f, " ({} {ver})", op.as_str())?;
+        }
+        if let Some(a) = &self.arch_spec {
+            write!(f, " [{a}]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Comma-separated list of alternatives, like the `Depends` field
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RelationList {
+    pub groups: Vec<Vec<Relation>>,
+}
+
+impl RelationList {
+    pub fn parse(s: &str) -> CDResult<Self> {
+        let groups = s.split(',')
+            .filter(|g| !g.trim().is_empty())
+            .map(|g| g.split('|').map(Relation::parse).collect())
+            .collect::<CDResult<_>>()?;
+        Ok(Self { groups })
+    }
+
+    /// Normalizes the field, or keeps it unchanged with a warning if it can't be parsed,
+    /// since dpkg may understand syntax that this parser doesn't
+    pub fn normalize_str(s: &str, field: &str, listener: &dyn Listener) -> String {
+        match Self::parse(s) {
+            Ok(list) => list.normalized().to_string(),
+            Err(e) => {
+                listener.warning(format!("{e}. {field} has been left unchanged"));
+                s.to_owned()
+            },
+        }
+    }
+
+    /// Names of all packages in the field, skipping relations that can't be parsed
+    pub fn package_names(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split([',', '|']).filter_map(|rel| Relation::parse(rel).ok()).map(|rel| rel.name)
+    }
+
+    /// Deduplicates packages, keeping only the strictest version constraints,
+    /// removes redundant alternatives, and sorts the list
+    #[must_use]
+    pub fn normalized(self) -> Self {
+        let mut singles: Vec<Relation> = Vec::new();
+        let mut alternations: Vec<Vec<Relation>> = Vec::new();
+        for mut group in self.groups {
+            let mut seen = Vec::with_capacity(group.len());
+            group.retain(|r| if seen.contains(r) { false } else { seen.push(r.clone()); true });
+            if group.len() == 1 {
+                singles.extend(group);
+            } else if !alternations.contains(&group) {
+                alternations.push(group);
+            }
+        }
+
+        let mut merged: Vec<Relation> = Vec::with_capacity(singles.len());
+        for rel in singles {
+            merge_strictest(&mut merged, rel);
+        }
+
+        // `a | b` is redundant if `a` is required anyway
+        alternations.retain(|group| !group.iter().any(|alt| merged.contains(alt) || (alt.version.is_none() && merged.iter().any(|m| m.same_package(alt)))));
+
+        let mut groups: Vec<Vec<Relation>> = merged.into_iter().map(|r| vec![r]).chain(alternations).collect();
+        groups.sort_by(|a, b| {
+            a[0].name.cmp(&b[0].name)
+                .then_with(|| a.len().cmp(&b.len()))
+                .then_with(|| a[0].to_string().cmp(&b[0].to_string()))
+        });
+        Self { groups }
+    }
+}
+
+impl RelationList {
+    /// Removes groups that have any of these packages. Removing only one alternative
+    /// would turn `foo | bar` into a hard dependency on the other one.
+    /// Returns names that weren't found.
+    pub fn remove_packages<'a>(&mut self, names: &'a [String]) -> Vec<&'a str> {
+        let mut found = vec![false; names.len()];
+        self.groups.retain(|group| {
+            let mut keep = true;
+            for rel in group {
+                if let Some(i) = names.iter().position(|n| *n == rel.name) {
+                    found[i] = true;
+                    keep = false;
+                }
+            }
+            keep
+        });
+        names.iter().zip(found).filter(|&(_, found)| !found).map(|(n, _)| n.as_str()).collect()
+    }
+
+    /// Replaces version constraints of these packages, e.g. `>= 3.0.0`. An empty constraint removes the version.
+    /// Returns names that weren't found.
+    pub fn pin_versions<'a>(&mut self, pins: &'a BTreeMap<String, String>) -> CDResult<Vec<&'a str>> {
+        let mut not_found = Vec::new();
+        for (name, constraint) in pins {
+            let constraint = constraint.trim().trim_start_matches('(').trim_end_matches(')');
+            let version = if constraint.is_empty() { None } else { Relation::parse(&format!("{name} ({constraint})"))?.version };
+            let mut found = false;
+            for rel in self.groups.iter_mut().flatten().filter(|rel| rel.name == *name) {
+                rel.version.clone_from(&version);
+                found = true;
+            }
+            if !found {
+                not_found.push(name.as_str());
+            }
+        }
+        Ok(not_found)
+    }
+}
+
+/// Adds the relation, or replaces a less strict constraint on the same package
+fn merge_strictest(merged: &mut Vec<Relation>, rel: Relation) {
+    let same_package = merged.iter().enumerate().filter(|(_, m)| m.same_package(&rel)).map(|(i, _)| i).collect::<Vec<_>>();
+    let Some((op, ver)) = &rel.version else {
+        if same_package.is_empty() {
+            merged.push(rel);
+        }
+        return;
+    };
+    for i in same_package {
+        let Some((old_op, old_ver)) = &merged[i].version else {
+            merged[i] = rel;
+            return;
+        };
+        if *old_op == VersionOp::Equal {
+            return;
+        }
+        if *op == VersionOp::Equal {
+            merged[i] = rel;
+            return;
+        }
+        // a lower and an upper bound are both needed
+        if old_op.is_lower_bound() == op.is_lower_bound() {
+            let ord = compare_debian_versions(ver, old_ver);
+            let is_stricter = if op.is_lower_bound() {
+                ord == Ordering::Greater || (ord == Ordering::Equal && *op == VersionOp::Later)
+            } else {
+                ord == Ordering::Less || (ord == Ordering::Equal && *op == VersionOp::Earlier)
+            };
+            if is_stricter {
+                merged[i] = rel;
+            }
+            return;
+        }
+    }
+    merged.push(rel);
+}
+
+impl fmt::Display for RelationList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, group) in self.groups.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            for (j, rel) in group.iter().enumerate() {
+                if j > 0 {
+                    f.write_str(" | ")?;
+                }
+                write!(f, "{rel}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares versions like `dpkg --compare-versions`
+pub(crate) fn compare_debian_versions(a: &str, b: &str) -> Ordering {
+    fn split(v: &str) -> (u64, &str, &str) {
+        let (epoch, rest) = match v.split_once(':') {
+            Some((e, rest)) => (e.parse().unwrap_or(0), rest),
+            None => (0, v),
+        };
+        let (upstream, revision) = rest.rsplit_once('-').unwrap_or((rest, ""));
+        (epoch, upstream, revision)
+    }
+
+    /// Letters sort before non-letters, and `~` sorts before everything, even the end
+    fn char_order(c: Option<u8>) -> i32 {
+        match c {
+            Some(b'~') => -1,
+            None => 0,
+            Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+            Some(c) => i32::from(c) + 256,
+        }
+    }
+
+    fn compare_part(mut a: &[u8], mut b: &[u8]) -> Ordering {
+        while !a.is_empty() || !b.is_empty() {
+            loop {
+                let ca = a.first().copied().filter(|c| !c.is_ascii_digit());
+                let cb = b.first().copied().filter(|c| !c.is_ascii_digit());
+                if ca.is_none() && cb.is_none() {
+                    break;
+                }
+                let ord = char_order(ca).cmp(&char_order(cb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+            let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+            let (da, db) = (digits(a), digits(b));
+            let na = a[..da].iter().skip_while(|&&c| c == b'0').copied().collect::<Vec<_>>();
+            let nb = b[..db].iter().skip_while(|&&c| c == b'0').copied().collect::<Vec<_>>();
+            let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(&nb));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            a = &a[da..];
+            b = &b[db..];
+        }
+        Ordering::Equal
+    }
+
+    let (ea, ua, ra) = split(a.trim());
+    let (eb, ub, rb) = split(b.trim());
+    ea.cmp(&eb)
+        .then_with(|| compare_part(ua.as_bytes(), ub.as_bytes()))
+        .then_with(|| compare_part(ra.as_bytes(), rb.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_comparison() {
+        use Ordering::*;
+        for (a, b, ord) in [
+            ("1.0", "1.0", Equal),
+            ("1.0", "1.00", Equal),
+            ("1.10", "1.9", Greater),
+            ("1.0~rc1", "1.0", Less),
+            ("1.0", "1.0+b1", Less),
+            ("1:0.1", "2.0", Greater),
+            ("1.0-2", "1.0-10", Less),
+            ("1.0a", "1.0+", Less),
+            ("2.34", "2.34-0ubuntu3", Less),
+        ] {
+            assert_eq!(compare_debian_versions(a, b), ord, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn parse_and_normalize() {
+        let list = RelationList::parse("libc6 (>= 2.34), libssl3 (>= 3.0.0), libc6 (>= 2.17),libc6 (>=2.36), \
+            foo | bar, foo|bar, libssl3 (<< 4), libssl3, baz:any [amd64], libc6 | musl").unwrap();
+        assert_eq!(list.normalized().to_string(), "baz:any [amd64], foo | bar, libc6 (>= 2.36), libssl3 (<< 4), libssl3 (>= 3.0.0)");
+        assert!(RelationList::parse("libc6 (~ 2)").is_err());
+        assert!(RelationList::parse("lib c6").is_err());
+        assert_eq!(RelationList::parse("a | a (= 1)").unwrap().normalized().to_string(), "a | a (= 1)");
+    }
+
+    #[test]
+    fn unparsable_lists_are_kept() {
+        let listener = crate::listener::CapturingListener::new();
+        assert_eq!(RelationList::normalize_str("b, a, a", "Depends", &listener), "a, b");
+        assert!(listener.warnings().is_empty());
+        assert_eq!(RelationList::normalize_str("b, a (~ 2), a", "Depends", &listener), "b, a (~ 2), a");
+        assert!(listener.has_warning("Depends has been left unchanged"));
+        assert_eq!(RelationList::package_names("a (~ 2), b | c:any").collect::<Vec<_>>(), ["b", "c"]);
+    }
+
+    #[test]
+    fn remove_and_pin() {
+        let mut list = RelationList::parse("libc6 (>= 2.34), libgcc-s1 (>= 4.2), libssl3 (>= 3.0.13), foo | libgcc-s1, bar (>= 1)").unwrap();
+        let remove = vec!["libgcc-s1".to_string(), "nope".to_string()];
+        assert_eq!(list.remove_packages(&remove), ["nope"]);
+        let pins = [("libssl3", ">= 3.0.0"), ("bar", ""), ("missing", "(= 1)")].into_iter().map(|(a, b)| (a.to_string(), b.to_string())).collect();
+        assert_eq!(list.pin_versions(&pins).unwrap(), ["missing"]);
+        assert_eq!(list.to_string(), "libc6 (>= 2.34), libssl3 (>= 3.0.0), bar");
+        let bad = [("libssl3".to_string(), "~ 3".to_string())].into_iter().collect();
+        assert!(list.pin_versions(&bad).is_err());
+    }
+}
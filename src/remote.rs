@@ -0,0 +1,125 @@
+//! `--crate <name>@<version>` packages a crate from crates.io, like `cargo install` would build it.
+//!
+//! Cargo downloads the crate as a dependency of a throwaway manifest, and then its source is copied
+//! to a temporary directory, so that the build doesn't write to Cargo's registry cache.
+
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::offline;
+use crate::CargoLockingFlags;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// Source of the crate. The temporary directory is deleted on drop.
+pub(crate) struct RemoteCrate {
+    dir: TempDir,
+    pub manifest_path: PathBuf,
+}
+
+impl RemoteCrate {
+    /// Leaves the crate's source and build directory on disk
+    pub fn keep(self) -> PathBuf {
+        self.dir.into_path()
+    }
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+    manifest_path: PathBuf,
+}
+
+/// `name@version` or `name`. Returns the name and a version requirement for Cargo.
+fn parse_crate_spec(spec: &str) -> CDResult<(&str, String)> {
+    let (name, version) = spec.split_once('@').unwrap_or((spec, ""));
+    if name.is_empty() || !name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_') {
+        return Err(CargoDebError::InvalidCrateSpec(spec.into()));
+    }
+    let version = version.trim();
+    if !version.bytes().all(|c| c.is_ascii_alphanumeric() || b".-+*^~=<>, ".contains(&c)) {
+        return Err(CargoDebError::InvalidCrateSpec(spec.into()));
+    }
+    // Like `cargo install`, a complete version means exactly that version
+    let is_exact = version.split('.').count() == 3 && version.starts_with(|c: char| c.is_ascii_digit());
+    let req = if version.is_empty() { "*".into() } else if is_exact { format!("={version}") } else { version.into() };
+    Ok((name, req))
+}
+
+/// Downloads the crate from crates.io (or Cargo's configured registry) into a temporary directory
+pub(crate) fn fetch_crate(spec: &str, cargo_locking_flags: CargoLockingFlags, listener: &dyn Listener) -> CDResult<RemoteCrate> {
+    let (name, req) = parse_crate_spec(spec)?;
+    let dir = tempfile::Builder::new().prefix("cargo-deb-crate").tempdir()?;
+
+    // Crate's own manifest can't be inside the throwaway one's workspace
+    let fetch_dir = dir.path().join("fetch");
+    fs::create_dir_all(fetch_dir.join("src"))?;
+    fs::write(fetch_dir.join("src/lib.rs"), "")?;
+    fs::write(fetch_dir.join("Cargo.toml"), format!("[package]\nname = \"cargo-deb-fetch\"\nversion = \"0.0.0\"\npublish = false\n\n\
+        [dependencies]\n{name} = \"{req}\"\n\n[workspace]\n"))?;
+
+    listener.info(format!("Downloading {name} {req}"));
+    let mut cmd = Command::new("cargo");
+    cmd.args(["metadata", "--format-version=1"]);
+    // The throwaway manifest has no lockfile to respect
+    if cargo_locking_flags.offline || cargo_locking_flags.frozen {
+        cmd.arg("--offline");
+    }
+    cmd.current_dir(&fetch_dir);
+    let output = offline::audit(&mut cmd)?.output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cargo (is it in your PATH?)"))?;
+    if !output.status.success() {
+        if String::from_utf8_lossy(&output.stderr).contains("no matching package named") {
+            return Err(CargoDebError::CrateNotFound(spec.into()));
+        }
+        return Err(CargoDebError::CommandError("cargo", format!("metadata (downloading {spec})"), output.stderr));
+    }
+    let metadata: Metadata = serde_json::from_slice(&output.stdout)?;
+    let package = metadata.packages.into_iter()
+        .find(|p| p.source.is_some() && p.name.replace('_', "-") == name.replace('_', "-"))
+        .ok_or_else(|| CargoDebError::CrateNotFound(spec.into()))?;
+    let source_dir = package.manifest_path.parent().ok_or("bad manifest path")?;
+
+    let crate_dir = dir.path().join(format!("{}-{}", package.name, package.version));
+    copy_dir(source_dir, &crate_dir)?;
+    listener.info(format!("Building {} {} in {}", package.name, package.version, crate_dir.display()));
+    Ok(RemoteCrate {
+        manifest_path: crate_dir.join("Cargo.toml"),
+        dir,
+    })
+}
+
+fn copy_dir(from: &Path, to: &Path) -> CDResult<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from).map_err(|e| CargoDebError::IoFile("unable to read crate source", e, from.into()))? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest).map_err(|e| CargoDebError::IoFile("unable to copy crate source", e, entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn crate_specs() {
+    assert_eq!(parse_crate_spec("ripgrep").unwrap(), ("ripgrep", "*".into()));
+    assert_eq!(parse_crate_spec("ripgrep@14.1.0").unwrap(), ("ripgrep", "=14.1.0".into()));
+    assert_eq!(parse_crate_spec("fd-find@10").unwrap(), ("fd-find", "10".into()));
+    assert_eq!(parse_crate_spec("fd-find@^10.1").unwrap(), ("fd-find", "^10.1".into()));
+    assert!(parse_crate_spec("").is_err());
+    assert!(parse_crate_spec("a = { path = \"/\" }").is_err());
+    assert!(parse_crate_spec("a@1\"\n").is_err());
+    assert!(parse_crate_spec("a b").unwrap_err().to_string().contains("not a valid crate name"));
+}
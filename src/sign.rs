@@ -0,0 +1,148 @@
+//! Signing of finished packages with `--sign`, configured in `[package.metadata.deb.sign]`
+//!
+//! [Sigstore](https://www.sigstore.dev) signatures are made with `cosign sign-blob`, so that its keyless (OIDC) login,
+//! KMS and hardware keys work as usual.
+
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::parse::manifest::{SignConfig, SigstoreConfig};
+use crate::util::offline;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Files written next to the `.deb`
+#[derive(Debug, PartialEq)]
+pub(crate) struct SigstoreFiles {
+    /// `.deb.sig`, base64-encoded signature
+    pub(crate) signature: PathBuf,
+    /// `.deb.pem`, the short-lived certificate of keyless signing
+    pub(crate) certificate: Option<PathBuf>,
+    /// `.deb.sigstore.json`, which `cosign verify-blob --bundle` can check on its own
+    pub(crate) bundle: PathBuf,
+}
+
+impl SigstoreFiles {
+    fn new(deb_path: &Path, keyless: bool) -> Self {
+        let with_suffix = |suffix: &str| {
+            let mut path = OsString::from(deb_path);
+            path.push(suffix);
+            PathBuf::from(path)
+        };
+        Self {
+            signature: with_suffix(".sig"),
+            certificate: keyless.then(|| with_suffix(".pem")),
+            bundle: with_suffix(".sigstore.json"),
+        }
+    }
+}
+
+/// Signs the package with every signing method that is configured
+pub(crate) fn sign_package(deb_path: &Path, config: Option<&SignConfig>, listener: &dyn Listener) -> CDResult<()> {
+    let sigstore = config.and_then(|c| c.sigstore.as_ref())
+        .ok_or(CargoDebError::Str("--sign needs a signing method, e.g. [package.metadata.deb.sign.sigstore]"))?;
+    let files = sign_with_sigstore(deb_path, sigstore, listener)?;
+    listener.info(format!("Sigstore signature written to {}", files.signature.display()));
+    if let Some(certificate) = &files.certificate {
+        listener.info(format!("Sigstore certificate written to {}", certificate.display()));
+    }
+    listener.info(format!("Sigstore bundle written to {}", files.bundle.display()));
+    Ok(())
+}
+
+/// Runs `cosign sign-blob`. Without a `key` it's keyless, and the identity comes from the OIDC token or a browser login.
+pub(crate) fn sign_with_sigstore(deb_path: &Path, config: &SigstoreConfig, listener: &dyn Listener) -> CDResult<SigstoreFiles> {
+    let (mut cmd, files) = cosign_command(deb_path, config)?;
+    listener.info(format!("Signing {} with cosign", deb_path.display()));
+    // Keyless signing may print a login URL and wait for the browser, so it needs the terminal.
+    // stdout only repeats the signature, and must not mix with --message-format=json.
+    let status = offline::audit(&mut cmd)?.stdout(Stdio::null()).status()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cosign (is it installed?)"))?;
+    if !status.success() {
+        return Err(CargoDebError::CommandError("Unable to sign", deb_path.display().to_string(), status.to_string().into_bytes()));
+    }
+    Ok(files)
+}
+
+fn cosign_command(deb_path: &Path, config: &SigstoreConfig) -> CDResult<(Command, SigstoreFiles)> {
+    let files = SigstoreFiles::new(deb_path, config.key.is_none());
+    let mut cmd = Command::new("cosign");
+    // --yes skips the interactive confirmation of uploading to the public transparency log
+    cmd.args(["sign-blob", "--yes"]);
+    if let Some(key) = &config.key {
+        cmd.args(["--key", key]);
+    }
+    // Secrets are passed via env, because arguments are visible to other users
+    if let Some(var) = &config.identity_token_env {
+        let token = std::env::var(var).map_err(|_| CargoDebError::SignTokenEnv(var.clone()))?;
+        cmd.env("SIGSTORE_ID_TOKEN", token);
+    }
+    cmd.arg("--output-signature").arg(&files.signature);
+    if let Some(certificate) = &files.certificate {
+        cmd.arg("--output-certificate").arg(certificate);
+    }
+    cmd.arg("--bundle").arg(&files.bundle);
+    cmd.arg(deb_path);
+    Ok((cmd, files))
+}
+
+/// Checks the `.sigstore.json` bundle written by `sign_with_sigstore` with `cosign verify-blob`.
+/// Keyless signatures are checked against any identity, because only the signer's CI knows which one is expected.
+pub(crate) fn verify_sigstore(deb_path: &Path, config: &SigstoreConfig) -> CDResult<()> {
+    let mut cmd = cosign_verify_command(deb_path, config);
+    let output = offline::audit(&mut cmd)?.output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cosign (is it installed?)"))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError("Sigstore bundle doesn't match", deb_path.display().to_string(), output.stderr));
+    }
+    Ok(())
+}
+
+fn cosign_verify_command(deb_path: &Path, config: &SigstoreConfig) -> Command {
+    let files = SigstoreFiles::new(deb_path, config.key.is_none());
+    let mut cmd = Command::new("cosign");
+    cmd.arg("verify-blob").arg("--bundle").arg(&files.bundle);
+    if let Some(key) = &config.key {
+        // verify-blob needs the public half, which `cosign generate-key-pair` writes next to the private one
+        let public_key = key.strip_suffix(".key").filter(|_| !key.contains("://")).map(|k| format!("{k}.pub"));
+        cmd.args(["--key", public_key.as_deref().unwrap_or(key)]);
+    } else {
+        cmd.args(["--certificate-identity-regexp", ".*", "--certificate-oidc-issuer-regexp", ".*"]);
+    }
+    cmd.arg(deb_path);
+    cmd
+}
+
+#[test]
+fn cosign_args() {
+    let deb = Path::new("target/debian/foo_1.0-1_amd64.deb");
+    let args = |cmd: &Command| cmd.get_args().map(|a| a.to_str().unwrap().to_owned()).collect::<Vec<_>>();
+
+    let (cmd, files) = cosign_command(deb, &SigstoreConfig::default()).unwrap();
+    assert_eq!(args(&cmd), [
+        "sign-blob", "--yes",
+        "--output-signature", "target/debian/foo_1.0-1_amd64.deb.sig",
+        "--output-certificate", "target/debian/foo_1.0-1_amd64.deb.pem",
+        "--bundle", "target/debian/foo_1.0-1_amd64.deb.sigstore.json",
+        "target/debian/foo_1.0-1_amd64.deb",
+    ]);
+    assert_eq!(files.certificate.as_deref(), Some(Path::new("target/debian/foo_1.0-1_amd64.deb.pem")));
+
+    let (cmd, files) = cosign_command(deb, &SigstoreConfig { key: Some("cosign.key".into()), ..Default::default() }).unwrap();
+    assert_eq!(args(&cmd)[..4], ["sign-blob", "--yes", "--key", "cosign.key"]);
+    assert!(!args(&cmd).iter().any(|a| a == "--output-certificate"));
+    assert_eq!(files.certificate, None);
+
+    let verify = cosign_verify_command(deb, &SigstoreConfig { key: Some("cosign.key".into()), ..Default::default() });
+    assert_eq!(args(&verify), [
+        "verify-blob", "--bundle", "target/debian/foo_1.0-1_amd64.deb.sigstore.json",
+        "--key", "cosign.pub",
+        "target/debian/foo_1.0-1_amd64.deb",
+    ]);
+
+    let verify = cosign_verify_command(deb, &SigstoreConfig { key: Some("awskms:///alias/cosign.key".into()), ..Default::default() });
+    assert_eq!(args(&verify)[3..5], ["--key", "awskms:///alias/cosign.key"]);
+
+    assert!(cosign_command(deb, &SigstoreConfig { identity_token_env: Some("CARGO_DEB_TEST_UNSET_TOKEN".into()), ..Default::default() }).is_err());
+    assert!(sign_package(deb, None, &crate::listener::NoOpListener).is_err());
+}
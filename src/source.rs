@@ -0,0 +1,334 @@
+//! `--source` makes a Debian source package (`.dsc`, `.orig.tar.xz` and `.debian.tar.xz`) for PPAs and distro archives.
+//!
+//! The upstream tarball is the `.crate` made by `cargo package`, with dependencies vendored, because source packages
+//! are built without network access. cargo-deb isn't available there either, so `debian/rules` runs Cargo
+//! and installs the assets itself, and debhelper does the rest (stripping, compression, `${shlibs:Depends}`).
+
+use crate::assets::AssetGlob;
+use crate::config::{Config, PackageConfig};
+use crate::deb::read::read_tar_entries;
+use crate::deb::tar::Tarball;
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::compress::{select_compressor, Format};
+use crate::util::offline;
+use crate::{debhelper, librust, upstream, CargoLockingFlags};
+use flate2::read::GzDecoder;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use tar::EntryType;
+
+// not dh-cargo, because it builds with crates packaged in Debian instead of the vendored ones
+const BUILD_DEPENDS: &str = "debhelper-compat (= 13), cargo:native, rustc:native";
+
+/// Files written to the output dir
+#[derive(Debug)]
+pub(crate) struct SourcePackage {
+    pub(crate) dsc: PathBuf,
+    pub(crate) orig_tarball: PathBuf,
+    pub(crate) debian_tarball: PathBuf,
+}
+
+/// Writes the source package next to where the `.deb` would be
+pub(crate) fn make_source_package(config: &Config, package_deb: &mut PackageConfig, cargo_locking_flags: CargoLockingFlags, listener: &dyn Listener) -> CDResult<SourcePackage> {
+    package_deb.assets = config.configured_assets(package_deb, listener)?;
+    let package_deb = &*package_deb;
+    let (upstream_version, version) = source_versions(&package_deb.deb_version)
+        .ok_or_else(|| CargoDebError::InvalidSourceVersion(package_deb.deb_version.clone()))?;
+
+    // The crate is unpacked outside of the project, because Cargo would think it's a member of the project's workspace
+    let work_dir = tempfile::tempdir()?;
+    let source_name = &package_deb.deb_name;
+    let dir_name = format!("{source_name}-{upstream_version}");
+    let source_dir = work_dir.path().join(&dir_name);
+
+    listener.info(format!("Running cargo package for {}", package_deb.name));
+    let crate_file = librust::run_cargo_package(config, package_deb, cargo_locking_flags)?;
+    unpack_crate(&crate_file, &source_dir)?;
+    vendor_dependencies(&source_dir, cargo_locking_flags, listener)?;
+
+    let orig_name = format!("{source_name}_{upstream_version}.orig.tar.xz");
+    fs::write(work_dir.path().join(&orig_name), orig_tarball(&source_dir, &dir_name, package_deb.default_timestamp)?)?;
+
+    write_debian_dir(config, package_deb, &source_dir, listener)?;
+    run_dpkg_source(work_dir.path(), &dir_name)?;
+
+    let deb_path = config.deb_output_path(package_deb);
+    let output_dir = deb_path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(output_dir)?;
+    let copy_to_output = |file_name: &str| -> CDResult<PathBuf> {
+        let dest = output_dir.join(file_name);
+        let src = work_dir.path().join(file_name);
+        fs::copy(&src, &dest).map_err(|e| CargoDebError::IoFile("dpkg-source didn't make the file", e, src))?;
+        Ok(dest)
+    };
+    Ok(SourcePackage {
+        orig_tarball: copy_to_output(&orig_name)?,
+        debian_tarball: copy_to_output(&format!("{source_name}_{version}.debian.tar.xz"))?,
+        dsc: copy_to_output(&format!("{source_name}_{version}.dsc"))?,
+    })
+}
+
+/// Upstream version and the version without the epoch, which are used in file names.
+/// `None` if there's no Debian revision, because the `3.0 (quilt)` format needs one.
+fn source_versions(deb_version: &str) -> Option<(&str, &str)> {
+    let version = deb_version.split_once(':').map_or(deb_version, |(_, v)| v);
+    let (upstream_version, revision) = version.rsplit_once('-')?;
+    (!upstream_version.is_empty() && !revision.is_empty()).then_some((upstream_version, version))
+}
+
+fn unpack_crate(crate_file: &[u8], source_dir: &Path) -> CDResult<()> {
+    let mut tar = Vec::new();
+    GzDecoder::new(crate_file).read_to_end(&mut tar)?;
+    for entry in read_tar_entries(&tar)? {
+        if entry.entry_type != EntryType::Regular {
+            continue;
+        }
+        // the crate's files are in a `name-version/` dir
+        let rel_path: PathBuf = entry.path.components().skip(1).collect();
+        if rel_path.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(CargoDebError::InvalidAssetPath(entry.path, "the .crate file has an unsafe path".into()));
+        }
+        let path = source_dir.join(rel_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &entry.data)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(if entry.mode & 0o111 != 0 { 0o755 } else { 0o644 }))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `cargo vendor`, and makes Cargo use the `vendor/` dir via `.cargo/config.toml`
+fn vendor_dependencies(source_dir: &Path, cargo_locking_flags: CargoLockingFlags, listener: &dyn Listener) -> CDResult<()> {
+    listener.info("Running cargo vendor".into());
+    let mut cmd = Command::new("cargo");
+    cmd.current_dir(source_dir)
+        .args(["vendor", "--versioned-dirs", "--manifest-path", "Cargo.toml", "vendor"])
+        .args(cargo_locking_flags.flags());
+    let output = offline::audit(&mut cmd)?.output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError("cargo", "vendor".into(), output.stderr));
+    }
+    // nothing is printed when there are no dependencies
+    if output.stdout.iter().any(|c| !c.is_ascii_whitespace()) {
+        fs::create_dir_all(source_dir.join(".cargo"))?;
+        fs::write(source_dir.join(".cargo/config.toml"), &output.stdout)?;
+    }
+    Ok(())
+}
+
+/// Reproducible tarball of the dir, before `debian/` is added
+fn orig_tarball(source_dir: &Path, dir_name: &str, time: u64) -> CDResult<Vec<u8>> {
+    let mut tarball = Tarball::new(select_compressor(false, Format::Xz, false)?, time);
+    add_dir_to_tarball(&mut tarball, source_dir, Path::new(dir_name))?;
+    Ok(tarball.into_inner()?.finish()?.to_vec())
+}
+
+fn add_dir_to_tarball<W: std::io::Write>(tarball: &mut Tarball<W>, dir: &Path, tar_dir: &Path) -> CDResult<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let tar_path = tar_dir.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            add_dir_to_tarball(tarball, &path, &tar_path)?;
+        } else if file_type.is_symlink() {
+            tarball.symlink(&tar_path, &fs::read_link(&path)?)?;
+        } else {
+            tarball.file(&tar_path, &fs::read(&path)?, if is_executable(&path)? { 0o755 } else { 0o644 })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> CDResult<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::metadata(path)?.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &Path) -> CDResult<bool> {
+    Ok(false)
+}
+
+fn write_debian_dir(config: &Config, package_deb: &PackageConfig, source_dir: &Path, listener: &dyn Listener) -> CDResult<()> {
+    let debian_dir = source_dir.join("debian");
+    fs::create_dir_all(debian_dir.join("source"))?;
+    fs::write(debian_dir.join("source/format"), "3.0 (quilt)\n")?;
+    fs::write(debian_dir.join("control"), debhelper::control(package_deb, BUILD_DEPENDS, &binary_relations(package_deb)))?;
+    fs::write(debian_dir.join("changelog"), debhelper::changelog(package_deb))?;
+    fs::write(debian_dir.join("copyright"), config.generate_copyright_asset(package_deb)?.1)?;
+    upstream::write_metadata_files(&debian_dir, package_deb, listener)?;
+
+    let rules_path = debian_dir.join("rules");
+    fs::write(&rules_path, rules(&cargo_build_flags(config, package_deb, source_dir), &install_commands(config, package_deb, source_dir, listener)?))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&rules_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    if let Some(dir) = &package_deb.maintainer_scripts_rel_path {
+        for name in ["preinst", "postinst", "prerm", "postrm", "config", "templates"] {
+            let path = config.path_in_package(dir).join(name);
+            if path.exists() {
+                fs::copy(&path, debian_dir.join(name)).map_err(|e| CargoDebError::IoFile("unable to copy maintainer script", e, path))?;
+            }
+        }
+    }
+    if package_deb.systemd_units.is_some() {
+        listener.warning("systemd units aren't added to the source package. Add them to its debian/ dir for dh_installsystemd".into());
+    }
+    Ok(())
+}
+
+/// dh's `${shlibs:Depends}` does what `$auto` does
+fn binary_relations(package_deb: &PackageConfig) -> Vec<(&'static str, String)> {
+    let mut depends: Vec<_> = package_deb.wildcard_depends.split(',').map(str::trim)
+        .filter(|dep| !dep.is_empty())
+        .map(|dep| if dep == "$auto" { "${shlibs:Depends}" } else { dep })
+        .collect();
+    depends.push("${misc:Depends}");
+    depends.dedup();
+    let mut relations = vec![("Depends", depends.join(", "))];
+    for (field, value) in [
+        ("Pre-Depends", &package_deb.pre_depends),
+        ("Recommends", &package_deb.recommends),
+        ("Suggests", &package_deb.suggests),
+        ("Enhances", &package_deb.enhances),
+        ("Conflicts", &package_deb.conflicts),
+        ("Breaks", &package_deb.breaks),
+        ("Replaces", &package_deb.replaces),
+        ("Provides", &package_deb.provides),
+    ] {
+        if let Some(value) = value {
+            relations.push((field, value.clone()));
+        }
+    }
+    relations
+}
+
+/// The same flags as for building the `.deb`, but always offline
+fn cargo_build_flags(config: &Config, package_deb: &PackageConfig, source_dir: &Path) -> Vec<String> {
+    let mut flags = Vec::new();
+    config.set_cargo_build_flags_for_package(package_deb, &mut flags);
+    flags.retain(|f| !matches!(f.as_str(), "--offline" | "--locked" | "--frozen" | "--workspace"));
+    flags.push(if source_dir.join("Cargo.lock").exists() { "--frozen" } else { "--offline" }.into());
+    if !config.default_features {
+        flags.push("--no-default-features".into());
+    }
+    if !config.features.is_empty() {
+        flags.push(format!("--features={}", config.features.join(",")));
+    }
+    flags
+}
+
+/// `install` commands for `debian/rules`. Files that aren't built are looked up now, built ones when it runs.
+fn install_commands(config: &Config, package_deb: &PackageConfig, source_dir: &Path, listener: &dyn Listener) -> CDResult<Vec<String>> {
+    let package_dir = Path::new("debian").join(&package_deb.deb_name);
+    let profile_dir = config.profile_dir();
+    let build_dir = Path::new("target").join(profile_dir.file_name().unwrap_or("release".as_ref()));
+    let mut commands = Vec::new();
+    for asset in &package_deb.assets.unresolved {
+        let target_path = asset.c.target_path.strip_prefix("/").unwrap_or(&asset.c.target_path);
+        let is_dir = asset.c.target_path.to_string_lossy().ends_with('/');
+        if asset.c.is_built() {
+            let Ok(rel_path) = asset.source_path.strip_prefix(&profile_dir) else {
+                listener.warning(format!("{} isn't built by this package, and won't be in the source package", asset.source_path.display()));
+                continue;
+            };
+            let source = build_dir.join(rel_path);
+            // globs of built files can't be matched yet. Unlike cargo-deb, `install` puts files from subdirectories in the same dir.
+            if AssetGlob::from(asset.source_path.as_path()).is_glob() {
+                commands.push(format!("install -d {}", quoted(&package_dir.join(target_path))));
+                commands.push(format!("install -m{:o} -t {} {}", asset.c.chmod, quoted(&package_dir.join(target_path)), source.display().to_string().replace('$', "$$")));
+                continue;
+            }
+            let dest = match source.file_name() {
+                Some(file_name) if is_dir => target_path.join(file_name),
+                _ => target_path.to_owned(),
+            };
+            commands.push(format!("install -D -m{:o} {} {}", asset.c.chmod, quoted(&source), quoted(&package_dir.join(dest))));
+            continue;
+        }
+        for resolved in asset.clone().resolve(package_deb.preserve_symlinks)? {
+            let Some(rel_path) = resolved.source.path().and_then(|p| p.strip_prefix(&config.package_manifest_dir).ok()) else {
+                continue;
+            };
+            if !source_dir.join(rel_path).exists() {
+                listener.warning(format!("{} isn't in the crate made by cargo package, and won't be in the source package", rel_path.display()));
+                continue;
+            }
+            commands.push(format!("install -D -m{:o} {} {}", resolved.c.chmod, quoted(rel_path), quoted(&package_dir.join(&resolved.c.target_path))));
+        }
+    }
+    Ok(commands)
+}
+
+/// Single-quoted for the shell, with `$` escaped for make
+fn quoted(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', r"'\''").replace('$', "$$"))
+}
+
+fn rules(cargo_build_flags: &[String], install_commands: &[String]) -> String {
+    let mut install = String::new();
+    for command in install_commands {
+        let _ = writeln!(install, "\t{command}");
+    }
+    format!("#!/usr/bin/make -f
+# Generated by `cargo deb --source`. Dependencies are vendored, so Cargo builds offline.
+
+export CARGO_HOME = $(CURDIR)/debian/cargo-home
+
+%:
+\tdh $@
+
+override_dh_auto_configure override_dh_auto_test:
+
+override_dh_auto_build:
+\tcargo build {}
+
+override_dh_auto_install:
+{install}
+override_dh_auto_clean:
+\trm -rf target debian/cargo-home
+", cargo_build_flags.join(" "))
+}
+
+fn run_dpkg_source(work_dir: &Path, dir_name: &str) -> CDResult<()> {
+    let mut cmd = Command::new("dpkg-source");
+    cmd.current_dir(work_dir).args(["--build", dir_name]);
+    let output = offline::audit(&mut cmd)?.output()
+        .map_err(|e| CargoDebError::CommandFailed(e, "dpkg-source (is dpkg-dev installed?)"))?;
+    if !output.status.success() {
+        return Err(CargoDebError::CommandError("dpkg-source", dir_name.into(), output.stderr));
+    }
+    Ok(())
+}
+
+#[test]
+fn source_package_versions() {
+    assert_eq!(source_versions("1.0-1"), Some(("1.0", "1.0-1")));
+    assert_eq!(source_versions("2:1.0-beta-3"), Some(("1.0-beta", "1.0-beta-3")));
+    assert_eq!(source_versions("1.0"), None);
+    assert_eq!(source_versions("1.0-"), None);
+}
+
+#[test]
+fn source_rules_file() {
+    let rules = rules(&["--release".into(), "--frozen".into()], &["install -D -m755 'target/release/foo' 'debian/foo/usr/bin/foo'".into()]);
+    assert!(rules.contains("\n\tcargo build --release --frozen\n"));
+    assert!(rules.contains("override_dh_auto_install:\n\tinstall -D -m755 'target/release/foo' 'debian/foo/usr/bin/foo'\n"));
+    assert_eq!(quoted(Path::new("it's $HOME")), r"'it'\''s $$HOME'");
+}
@@ -0,0 +1,147 @@
+//! Harness for testing integrations with cargo-deb, enabled with the `testing` feature.
+//!
+//! [`Fixture`] runs the whole packaging pipeline for a fixture project, and returns the contents of the `.deb`.
+//! Files added with [`Fixture::file`] exist only in memory, and are visible to cargo-deb as if they were on disk
+//! (as non-glob assets and maintainer scripts). The fixture's `Cargo.toml` itself must be a real file, because it's read by Cargo.
+
+use crate::deb::read::{control_field, read_deb_members, read_tar_entries};
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::CapturingListener;
+use crate::{CargoDeb, CargoDebOptions};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Shared by all threads, because assets are read on a thread pool. Paths are absolute, so fixtures in different dirs don't clash.
+static FAKE_FS: Mutex<BTreeMap<PathBuf, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+pub(crate) fn fake_file(path: &Path) -> Option<Vec<u8>> {
+    FAKE_FS.lock().unwrap().get(path).cloned()
+}
+
+/// Removes the fixture's files from the fake filesystem
+struct FakeFiles(Vec<PathBuf>);
+
+impl Drop for FakeFiles {
+    fn drop(&mut self) {
+        let mut fs = FAKE_FS.lock().unwrap();
+        for path in &self.0 {
+            fs.remove(path);
+        }
+    }
+}
+
+/// A project to package
+pub struct Fixture {
+    manifest_dir: PathBuf,
+    files: Vec<(PathBuf, Vec<u8>)>,
+    options: CargoDebOptions,
+}
+
+impl Fixture {
+    /// Doesn't build the project by default, because fixtures usually contain only assets. See [`Fixture::options`].
+    pub fn new(manifest_path: impl AsRef<Path>) -> CDResult<Self> {
+        let manifest_path = manifest_path.as_ref();
+        let manifest_path = manifest_path.canonicalize().map_err(|e| CargoDebError::IoFile("bad manifest path", e, manifest_path.into()))?;
+        let manifest_dir = manifest_path.parent().ok_or("bad manifest path")?.to_owned();
+        Ok(Self {
+            options: CargoDebOptions {
+                manifest_path: Some(manifest_path.display().to_string()),
+                no_build: true,
+                fast: true,
+                ..CargoDebOptions::default()
+            },
+            manifest_dir,
+            files: Vec::new(),
+        })
+    }
+
+    /// Adds a file that exists only in memory. The path is relative to the fixture's `Cargo.toml`.
+    #[must_use]
+    pub fn file(mut self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.push((self.manifest_dir.join(path), contents.into()));
+        self
+    }
+
+    /// Options for `cargo deb`. `manifest_path` and `output_path` are set by the fixture.
+    pub fn options(&mut self) -> &mut CargoDebOptions {
+        &mut self.options
+    }
+
+    /// Runs the pipeline, and reads back the `.deb` it made
+    pub fn build(mut self) -> CDResult<BuiltDeb> {
+        let output_dir = tempfile::tempdir()?;
+        self.options.output_path = Some(format!("{}/", output_dir.path().display()));
+
+        let _fake_files = {
+            let mut fs = FAKE_FS.lock().unwrap();
+            FakeFiles(self.files.into_iter().map(|(path, contents)| {
+                fs.insert(path.clone(), contents);
+                path
+            }).collect())
+        };
+        let listener = CapturingListener::new();
+        CargoDeb::new(self.options).process(&listener)?;
+        let deb_path = listener.generated_archives().pop().ok_or("no deb generated")?;
+        BuiltDeb::read(&deb_path, listener)
+    }
+}
+
+/// Contents of a generated `.deb`
+pub struct BuiltDeb {
+    /// The `control` file
+    pub control: String,
+    /// Maintainer scripts, `md5sums`, `conffiles`, etc. (except `control`)
+    pub control_files: BTreeMap<String, Vec<u8>>,
+    /// Files and symlinks in the data archive, by absolute path
+    pub files: BTreeMap<PathBuf, DataFile>,
+    /// Warnings and other messages from the build
+    pub listener: CapturingListener,
+}
+
+pub struct DataFile {
+    pub mode: u32,
+    pub data: Vec<u8>,
+    pub link_name: Option<PathBuf>,
+}
+
+impl BuiltDeb {
+    fn read(deb_path: &Path, listener: CapturingListener) -> CDResult<Self> {
+        let mut control = String::new();
+        let mut control_files = BTreeMap::new();
+        let mut files = BTreeMap::new();
+        for member in read_deb_members(deb_path)? {
+            if member.is_control() {
+                for entry in read_tar_entries(&member.decompressed()?)? {
+                    let Some(name) = entry.path.file_name().and_then(|n| n.to_str()) else { continue };
+                    if name == "control" {
+                        control = String::from_utf8(entry.data).map_err(|_| "control isn't UTF-8")?;
+                    } else if entry.entry_type.is_file() {
+                        control_files.insert(name.to_owned(), entry.data);
+                    }
+                }
+            } else if member.is_data() {
+                for entry in read_tar_entries(&member.decompressed()?)? {
+                    if entry.entry_type.is_dir() {
+                        continue;
+                    }
+                    let path = Path::new("/").join(entry.path.strip_prefix(".").unwrap_or(&entry.path));
+                    files.insert(path, DataFile { mode: entry.mode, data: entry.data, link_name: entry.link_name });
+                }
+            }
+        }
+        Ok(Self { control, control_files, files, listener })
+    }
+
+    /// Value of the control field, e.g. `field("Depends")`
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&str> {
+        control_field(&self.control, name)
+    }
+
+    /// File in the data archive, by its absolute installation path
+    #[must_use]
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<&DataFile> {
+        self.files.get(path.as_ref())
+    }
+}
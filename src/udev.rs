@@ -0,0 +1,47 @@
+//! udev rules, which `udevd` only notices after `udevadm control --reload`, like `dh_installudev` did.
+
+use crate::assets::Asset;
+use crate::config::PackageConfig;
+use crate::error::CDResult;
+use crate::listener::Listener;
+
+/// `/lib/udev/rules.d` is the pre-usrmerge location, which is still supported
+pub(crate) const RULES_DIRS: [&str; 2] = ["usr/lib/udev/rules.d", "lib/udev/rules.d"];
+
+pub(crate) fn is_rules_file(asset: &Asset) -> bool {
+    RULES_DIRS.iter().any(|dir| asset.c.target_path.starts_with(dir)) &&
+        asset.c.target_path.extension().is_some_and(|e| e == "rules")
+}
+
+pub(crate) fn has_rules(assets: &[Asset]) -> bool {
+    assets.iter().any(is_rules_file)
+}
+
+/// The rules do nothing without udev, but containers and chroots don't have it, so it's only recommended.
+pub(crate) fn add_udev_recommendation(package_deb: &mut PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    if has_rules(&package_deb.assets.resolved) && package_deb.add_recommends("udev", listener) {
+        listener.info("Adding 'udev' to Recommends, because the package has udev rules".into());
+    }
+    Ok(())
+}
+
+#[test]
+fn udev_rules_assets() {
+    use crate::assets::{AssetSource, IsBuilt};
+
+    let asset = |path: &str| Asset::new(AssetSource::Data(Vec::new()), path.into(), 0o644, IsBuilt::No, false);
+    assert!(has_rules(&[asset("usr/lib/udev/rules.d/60-foo.rules")]));
+    assert!(has_rules(&[asset("lib/udev/rules.d/60-foo.rules")]));
+    assert!(!has_rules(&[asset("usr/lib/udev/hwdb.d/60-foo.hwdb"), asset("etc/udev/rules.d/60-foo.rules")]));
+
+    let mut package_deb = crate::config::tests::example_package_config(Default::default(), Default::default()).unwrap();
+    package_deb.assets.resolved = vec![asset("usr/lib/udev/rules.d/60-foo.rules")];
+    package_deb.recommends = Some("foo".into());
+    add_udev_recommendation(&mut package_deb, &crate::listener::NoOpListener).unwrap();
+    assert_eq!(package_deb.recommends.as_deref(), Some("foo, udev"));
+
+    package_deb.recommends = None;
+    package_deb.resolved_depends = Some("udev (>= 240)".into());
+    add_udev_recommendation(&mut package_deb, &crate::listener::NoOpListener).unwrap();
+    assert_eq!(package_deb.recommends, None);
+}
@@ -0,0 +1,81 @@
+//! `debian/watch` and `debian/upstream/metadata` ([DEP-12](https://dep-team.pages.debian.net/deps/dep12/)) files,
+//! for maintainers of source packages. `--source` adds them to the source packages it makes,
+//! and `--upstream-metadata` writes them to `target/debian/<name>-source/debian/` for other source packages.
+
+use crate::config::{Config, PackageConfig};
+use crate::error::CDResult;
+use crate::listener::Listener;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the `debian` dir the files have been written to
+pub(crate) fn write_source_metadata(config: &Config, package_deb: &PackageConfig, listener: &dyn Listener) -> CDResult<PathBuf> {
+    let debian_dir = config.default_deb_output_dir().join(format!("{}-source", package_deb.deb_name)).join("debian");
+    write_metadata_files(&debian_dir, package_deb, listener)?;
+    Ok(debian_dir)
+}
+
+/// Writes `watch` and `upstream/metadata` into the given `debian` dir
+pub(crate) fn write_metadata_files(debian_dir: &Path, package_deb: &PackageConfig, listener: &dyn Listener) -> CDResult<()> {
+    fs::create_dir_all(debian_dir.join("upstream"))?;
+    match watch_file(package_deb) {
+        Some(watch) => fs::write(debian_dir.join("watch"), watch)?,
+        None => listener.warning(format!("No debian/watch for {}, because it's not published on crates.io, and its repository isn't on GitHub", package_deb.name)),
+    }
+    fs::write(debian_dir.join("upstream/metadata"), upstream_metadata(package_deb))?;
+    Ok(())
+}
+
+/// Tracks crates.io releases like debcargo does, or GitHub tags of crates that aren't published
+fn watch_file(package_deb: &PackageConfig) -> Option<String> {
+    let name = &package_deb.name;
+    if package_deb.published_on_crates_io {
+        return Some(format!("version=4\n\
+            opts=filenamemangle=s/.*\\/(.*)\\/download/{name}-$1\\.tar\\.gz/g,\\\n\
+            uversionmangle=s/(\\d)[_\\.\\-\\+]?((RC|rc|pre|dev|beta|alpha)\\d*)$/$1~$2/ \\\n\
+            https://qa.debian.org/cgi-bin/fakeupstream.cgi?upstream=crates.io/{name} .*/crates/{name}/@ANY_VERSION@/download\n"));
+    }
+    let repo = github_repository(package_deb.repository.as_deref()?)?;
+    Some(format!("version=4\n\
+        opts=\"filenamemangle=s%(?:.*?)?v?@ANY_VERSION@(@ARCHIVE_EXT@)%@PACKAGE@-$1$2%\" \\\n  \
+        {repo}/tags .*/archive/refs/tags/v?@ANY_VERSION@@ARCHIVE_EXT@\n"))
+}
+
+fn upstream_metadata(package_deb: &PackageConfig) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "---\nName: {}", package_deb.name);
+    if let Some(repo) = package_deb.repository.as_deref() {
+        let browse = repo.trim_end_matches('/').trim_end_matches(".git");
+        let _ = writeln!(out, "Repository: {browse}.git\nRepository-Browse: {browse}");
+        if let Some(github) = github_repository(repo) {
+            let _ = writeln!(out, "Bug-Database: {github}/issues\nBug-Submit: {github}/issues/new");
+        }
+    }
+    if let Some(docs) = package_deb.documentation.as_deref() {
+        let _ = writeln!(out, "Documentation: {docs}");
+    }
+    if package_deb.published_on_crates_io {
+        let _ = writeln!(out, "Archive: crates.io");
+    }
+    out
+}
+
+/// `https://github.com/owner/repo` without `.git`
+fn github_repository(url: &str) -> Option<String> {
+    let path = url.strip_prefix("https://github.com/").or_else(|| url.strip_prefix("http://github.com/"))?;
+    let path = path.trim_end_matches('/').trim_end_matches(".git");
+    let (owner, repo) = path.split_once('/')?;
+    if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+        return None;
+    }
+    Some(format!("https://github.com/{owner}/{repo}"))
+}
+
+#[test]
+fn github_urls() {
+    assert_eq!(github_repository("https://github.com/kornelski/cargo-deb.git").as_deref(), Some("https://github.com/kornelski/cargo-deb"));
+    assert_eq!(github_repository("https://github.com/kornelski/cargo-deb/").as_deref(), Some("https://github.com/kornelski/cargo-deb"));
+    assert_eq!(github_repository("https://github.com/kornelski/cargo-deb/tree/main"), None);
+    assert_eq!(github_repository("https://gitlab.com/kornelski/cargo-deb"), None);
+}
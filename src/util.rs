@@ -1,8 +1,13 @@
 use std::collections::BTreeSet;
 use std::path::Path;
 
+pub(crate) mod build_progress;
+pub(crate) mod lock;
+pub(crate) mod offline;
 pub(crate) mod ok_or;
 pub(crate) mod pathbytes;
+pub(crate) mod sandbox;
+pub(crate) mod sha;
 pub(crate) mod wordsplit;
 
 pub mod compress;
@@ -11,7 +16,7 @@ pub mod compress;
 /// Note: Due to the way the Path type works the final component is returned
 /// even if it looks like a directory, e.g. "/some/dir/" will return "dir"...
 pub(crate) fn fname_from_path(path: &Path) -> Option<String> {
-    if path.to_bytes().ends_with(&[b'/']) {
+    if path.to_bytes().ends_with(b"/") {
         return None;
     }
     let path = path.file_name()?.to_string_lossy();
@@ -24,6 +29,10 @@ pub(crate) use tests::is_path_file;
 
 #[cfg(not(test))]
 pub(crate) fn is_path_file(path: &Path) -> bool {
+    #[cfg(feature = "testing")]
+    if crate::testing::fake_file(path).is_some() {
+        return true;
+    }
     path.is_file()
 }
 
@@ -32,6 +41,10 @@ pub(crate) use tests::read_file_to_string;
 
 #[cfg(not(test))]
 pub(crate) fn read_file_to_string(path: &Path) -> std::io::Result<String> {
+    #[cfg(feature = "testing")]
+    if let Some(data) = crate::testing::fake_file(path) {
+        return String::from_utf8(data).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+    }
     std::fs::read_to_string(path)
 }
 
@@ -40,6 +53,10 @@ pub(crate) use tests::read_file_to_bytes;
 
 #[cfg(not(test))]
 pub(crate) fn read_file_to_bytes(path: &Path) -> std::io::Result<Vec<u8>> {
+    #[cfg(feature = "testing")]
+    if let Some(data) = crate::testing::fake_file(path) {
+        return Ok(data);
+    }
     std::fs::read(path)
 }
 
@@ -103,6 +120,42 @@ impl MyJoin for BTreeSet<String> {
     }
 }
 
+/// Formats the unix timestamp as RFC 2822 date in UTC, as used in Debian changelogs
+pub(crate) fn rfc2822_date(timestamp: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = timestamp / 86400;
+    let secs = timestamp % 86400;
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{}, {day:02} {} {year} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[(days % 7) as usize], MONTHS[(month - 1) as usize], secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Unix timestamp of midnight UTC of the given date. `None` if the month or day is out of range
+pub(crate) fn timestamp_from_date(year: i64, month: i64, day: i64) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    u64::try_from((era * 146_097 + doe - 719_468) * 86400).ok()
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use lazy_static::lazy_static;
@@ -272,6 +325,16 @@ pub(crate) mod tests {
         assert_eq!(None, fname_from_path(Path::new("/a/")));
     }
 
+    #[test]
+    fn rfc2822_dates() {
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 +0000", rfc2822_date(0));
+        assert_eq!("Tue, 29 Feb 2000 12:34:56 +0000", rfc2822_date(951_827_696));
+        assert_eq!("Wed, 18 Dec 2024 00:00:00 +0000", rfc2822_date(1_734_480_000));
+        assert_eq!(Some(1_734_480_000), timestamp_from_date(2024, 12, 18));
+        assert_eq!(Some(951_782_400), timestamp_from_date(2000, 2, 29));
+        assert_eq!(None, timestamp_from_date(2000, 13, 1));
+    }
+
     #[test]
     fn map_macro() {
         let mut one = HashMap::new();
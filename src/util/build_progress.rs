@@ -0,0 +1,114 @@
+//! Concise progress of `cargo build` for logs, where Cargo's own output is mostly noise.
+//!
+//! Cargo's JSON messages are parsed to count the built crates, and diagnostics are collected
+//! to be shown once at the end, instead of being interleaved with the build.
+
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use crate::util::offline;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+
+#[derive(Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum CargoMessage {
+    CompilerArtifact { package_id: String },
+    CompilerMessage { message: Diagnostic },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    level: String,
+    rendered: Option<String>,
+}
+
+#[derive(Default)]
+struct BuildProgress {
+    built: HashSet<String>,
+    /// Tenths of the total reported so far
+    reported_step: usize,
+    warnings: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl BuildProgress {
+    fn message(&mut self, line: &str, total: usize, listener: &dyn Listener) {
+        match serde_json::from_str(line) {
+            Ok(CargoMessage::CompilerArtifact { package_id }) => {
+                if !self.built.insert(package_id) {
+                    return;
+                }
+                let done = self.built.len();
+                let total = total.max(done);
+                let step = done * 10 / total;
+                if step > self.reported_step {
+                    self.reported_step = step;
+                    listener.progress("Building", done, total);
+                }
+            },
+            Ok(CargoMessage::CompilerMessage { message: Diagnostic { level, rendered: Some(rendered) } }) => {
+                let list = if level == "error" { &mut self.errors } else if level == "warning" { &mut self.warnings } else { return };
+                if !list.contains(&rendered) {
+                    list.push(rendered);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Runs `cargo build` with `--message-format=json`, reporting progress every 10% of `total` crates.
+/// Warnings are reported once the build is done, and errors are returned.
+pub(crate) fn run(cmd: &mut Command, total: usize, listener: &dyn Listener) -> CDResult<()> {
+    cmd.arg("--message-format=json").stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = offline::audit(cmd)?.spawn().map_err(|e| CargoDebError::CommandFailed(e, "cargo"))?;
+    let mut stderr = child.stderr.take().ok_or("stderr")?;
+    let stderr_thread = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stderr.read_to_end(&mut out);
+        out
+    });
+
+    let mut progress = BuildProgress::default();
+    for line in BufReader::new(child.stdout.take().ok_or("stdout")?).lines() {
+        progress.message(&line?, total, listener);
+    }
+    let status = child.wait()?;
+    let stderr = stderr_thread.join().unwrap_or_default();
+
+    if !status.success() {
+        // Build scripts and linkers fail without compiler messages
+        let output = if progress.errors.is_empty() { String::from_utf8_lossy(&stderr).into_owned() } else { progress.errors.concat() };
+        return Err(CargoDebError::BuildFailedWithOutput(output.trim_end().to_owned()));
+    }
+    if !progress.warnings.is_empty() {
+        listener.warning(format!("cargo build reported {} warning(s):\n{}", progress.warnings.len(), progress.warnings.concat().trim_end()));
+    }
+    Ok(())
+}
+
+#[test]
+fn parses_messages() {
+    use crate::listener::{CapturingListener, Level};
+
+    let listener = CapturingListener::new();
+    let mut progress = BuildProgress::default();
+    for i in 0..5 {
+        let line = format!(r#"{{"reason":"compiler-artifact","package_id":"crate{i} 1.0.0","target":{{}}}}"#);
+        progress.message(&line, 4, &listener);
+        progress.message(&line, 4, &listener);
+    }
+    progress.message(r#"{"reason":"build-script-executed","package_id":"crate0 1.0.0"}"#, 4, &listener);
+    progress.message(r#"{"reason":"compiler-message","message":{"level":"warning","rendered":"warning: unused\n"}}"#, 4, &listener);
+    progress.message(r#"{"reason":"compiler-message","message":{"level":"warning","rendered":"warning: unused\n"}}"#, 4, &listener);
+    progress.message(r#"{"reason":"compiler-message","message":{"level":"note","rendered":"note: hi\n"}}"#, 4, &listener);
+    progress.message("not json", 4, &listener);
+
+    assert_eq!(listener.messages(Level::Progress), ["Building (1/4)", "Building (2/4)", "Building (3/4)", "Building (4/4)"]);
+    assert_eq!(progress.warnings, ["warning: unused\n"]);
+    assert!(progress.errors.is_empty());
+}
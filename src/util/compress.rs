@@ -1,17 +1,62 @@
 use crate::error::{CDResult, CargoDebError};
+use crate::util::sandbox;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io::{BufWriter, Read};
 use std::num::NonZeroU64;
 #[cfg(feature = "lzma")]
 use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::{Child, ChildStdin, Command, Stdio};
 use std::{io, ops};
 use zopfli::{BlockType, GzipEncoder, Options};
 
 pub struct CompressConfig {
     pub fast: bool,
+    /// Maximum compression. `fast` takes precedence
+    pub best: bool,
     pub compress_type: Format,
+    /// Usually the same as `compress_type`
+    pub control_compress_type: Format,
     pub compress_system: bool,
     pub rsyncable: bool,
+    /// Compress the same way as `dpkg-deb`
+    pub dpkg_compat: bool,
+}
+
+impl CompressConfig {
+    #[must_use]
+    pub fn level(&self) -> CompressionLevel {
+        CompressionLevel::new(self.fast, self.best)
+    }
+}
+
+/// How much time to spend on making the package smaller
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// `--fast`
+    Fast,
+    #[default]
+    Default,
+    /// `--best`, for release artifacts: `xz -9e`, and Zopfli with more iterations
+    Best,
+}
+
+impl CompressionLevel {
+    #[must_use]
+    pub fn new(fast: bool, best: bool) -> Self {
+        match (fast, best) {
+            (true, _) => Self::Fast,
+            (false, true) => Self::Best,
+            (false, false) => Self::Default,
+        }
+    }
+
+    fn zopfli_iterations(self) -> NonZeroU64 {
+        // Zopfli's default is 15, but it's too slow for little gain
+        NonZeroU64::new(if self == Self::Best { 15 } else { 7 }).unwrap()
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -21,6 +66,16 @@ pub enum Format {
 }
 
 impl Format {
+    /// For file extensions of deb archive members
+    #[must_use]
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "xz" => Some(Self::Xz),
+            "gz" => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+
     #[must_use]
     pub fn extension(self) -> &'static str {
         match self {
@@ -36,12 +91,20 @@ impl Format {
         }
     }
 
-    const fn level(self, fast: bool) -> u32 {
-        match self {
-            Self::Xz => if fast { 1 } else { 6 },
-            Self::Gzip => if fast { 1 } else { 9 },
+    const fn level(self, level: CompressionLevel) -> u32 {
+        match (self, level) {
+            (_, CompressionLevel::Fast) => 1,
+            (Self::Xz, CompressionLevel::Default) => 6,
+            (Self::Xz, CompressionLevel::Best) => 9,
+            (Self::Gzip, _) => 9,
         }
     }
+
+    /// Arg of the command-line compressor, e.g. `-9e` for xz's extreme mode
+    fn level_arg(self, level: CompressionLevel) -> String {
+        let extreme = matches!((self, level), (Self::Xz, CompressionLevel::Best));
+        format!("-{}{}", self.level(level), if extreme { "e" } else { "" })
+    }
 }
 
 enum Writer {
@@ -149,9 +212,9 @@ impl ops::Deref for Compressed {
     }
 }
 
-fn system_compressor(compress_format: Format, fast: bool) -> CDResult<Compressor> {
-    let mut child = Command::new(compress_format.program())
-        .arg(format!("-{}", compress_format.level(fast)))
+fn system_compressor(compress_format: Format, level: CompressionLevel) -> CDResult<Compressor> {
+    let mut child = sandbox::apply(&mut Command::new(compress_format.program()))?
+        .arg(compress_format.level_arg(level))
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
@@ -169,37 +232,31 @@ fn system_compressor(compress_format: Format, fast: bool) -> CDResult<Compressor
 }
 
 pub fn select_compressor(fast: bool, compress_format: Format, use_system: bool) -> CDResult<Compressor> {
+    select_compressor_with_level(CompressionLevel::new(fast, false), compress_format, use_system)
+}
+
+pub fn select_compressor_with_level(level: CompressionLevel, compress_format: Format, use_system: bool) -> CDResult<Compressor> {
     if use_system {
-        return system_compressor(compress_format, fast);
+        return system_compressor(compress_format, level);
     }
 
     match compress_format {
         #[cfg(feature = "lzma")]
-        Format::Xz => {
-            // Compression level 6 is a good trade off between size and [ridiculously] long compression time
-            let encoder = xz2::stream::MtStreamBuilder::new()
-                .threads(std::thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap()).get() as u32)
-                .preset(compress_format.level(fast))
-                .encoder()
-                .map_err(CargoDebError::LzmaCompressionError)?;
-
-            let writer = xz2::write::XzEncoder::new_stream(Vec::new(), encoder);
-            Ok(Compressor::new(Writer::Xz(writer)))
-        },
+        Format::Xz => xz_compressor(level, xz2::stream::Check::None),
         #[cfg(not(feature = "lzma"))]
-        Format::Xz => system_compressor(compress_format, fast),
+        Format::Xz => system_compressor(compress_format, level),
         Format::Gzip => {
             use flate2::write::GzEncoder;
             use flate2::Compression;
 
-            let writer = if !fast {
+            let writer = if level != CompressionLevel::Fast {
                 let inner_writer = GzipEncoder::new_buffered(Options {
-                    iteration_count: NonZeroU64::new(7).unwrap(),
+                    iteration_count: level.zopfli_iterations(),
                     ..Options::default()
                 }, BlockType::Dynamic, Vec::new()).unwrap();
                 Writer::ZopfliGz(inner_writer)
             } else {
-                let inner_writer = GzEncoder::new(Vec::new(), Compression::new(compress_format.level(fast)));
+                let inner_writer = GzEncoder::new(Vec::new(), Compression::new(compress_format.level(level)));
                 Writer::Gz(inner_writer)
             };
             Ok(Compressor::new(writer))
@@ -207,8 +264,107 @@ pub fn select_compressor(fast: bool, compress_format: Format, use_system: bool)
     }
 }
 
-pub(crate) fn gzipped(mut content: &[u8]) -> io::Result<Vec<u8>> {
+/// Flag for xz presets, like `xz -e`. From liblzma's `container.h`
+#[cfg(feature = "lzma")]
+const LZMA_PRESET_EXTREME: u32 = 1 << 31;
+
+#[cfg(feature = "lzma")]
+fn xz_compressor(level: CompressionLevel, check: xz2::stream::Check) -> CDResult<Compressor> {
+    // Compression level 6 is a good trade off between size and [ridiculously] long compression time.
+    // Level 9 has a 64MB dictionary instead of 8MB, and the extreme flag (LZMA_PRESET_EXTREME) searches for matches harder.
+    let extreme = if level == CompressionLevel::Best { LZMA_PRESET_EXTREME } else { 0 };
+    let encoder = xz2::stream::MtStreamBuilder::new()
+        .threads(std::thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap()).get() as u32)
+        .preset(Format::Xz.level(level) | extreme)
+        .check(check)
+        .encoder()
+        .map_err(CargoDebError::LzmaCompressionError)?;
+
+    let writer = xz2::write::XzEncoder::new_stream(Vec::new(), encoder);
+    Ok(Compressor::new(Writer::Xz(writer)))
+}
+
+/// `dpkg-deb` uses zlib for gzip, so it's closer to its output than Zopfli. Its xz has CRC64 checksums.
+pub fn select_dpkg_compat_compressor(fast: bool, compress_format: Format, use_system: bool) -> CDResult<Compressor> {
+    select_dpkg_compat_compressor_with_level(CompressionLevel::new(fast, false), compress_format, use_system)
+}
+
+/// `dpkg-deb` uses zlib for gzip, so it's closer to its output than Zopfli. Its xz has CRC64 checksums.
+pub fn select_dpkg_compat_compressor_with_level(level: CompressionLevel, compress_format: Format, use_system: bool) -> CDResult<Compressor> {
+    #[cfg(feature = "lzma")]
+    if let (Format::Xz, false) = (compress_format, use_system) {
+        return xz_compressor(level, xz2::stream::Check::Crc64);
+    }
+    if let (Format::Gzip, false) = (compress_format, use_system) {
+        // zlib's header has no timestamp, and Unix as the OS
+        let writer = flate2::GzBuilder::new().operating_system(3)
+            .write(Vec::new(), flate2::Compression::new(compress_format.level(level)));
+        return Ok(Compressor::new(Writer::Gz(writer)));
+    }
+    select_compressor_with_level(level, compress_format, use_system)
+}
+
+/// Decompresses a `*.tar.{ext}` member of a deb archive
+pub(crate) fn decompress(extension: &str, data: &[u8]) -> CDResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3);
+    match extension {
+        "" | "tar" => return Ok(data.to_vec()),
+        "gz" => { flate2::read::GzDecoder::new(data).read_to_end(&mut out)?; },
+        #[cfg(feature = "lzma")]
+        "xz" => { xz2::read::XzDecoder::new_multi_decoder(data).read_to_end(&mut out)?; },
+        #[cfg(not(feature = "lzma"))]
+        "xz" => system_filter("xz", "-dc", data, &mut out)?,
+        "zst" => system_filter("zstd", "-dc", data, &mut out)?,
+        "bz2" => system_filter("bzip2", "-dc", data, &mut out)?,
+        _ => return Err(CargoDebError::Str("unsupported compression format of a deb archive member")),
+    }
+    Ok(out)
+}
+
+/// Compresses a `*.tar.{ext}` member of a deb archive, in any format that `decompress` supports
+pub(crate) fn compress(extension: &str, data: &[u8]) -> CDResult<Vec<u8>> {
+    if let Some(format) = Format::from_extension(extension) {
+        let mut compressor = select_compressor(false, format, false)?;
+        io::Write::write_all(&mut compressor, data)?;
+        return Ok(compressor.finish()?.to_vec());
+    }
+    let mut out = Vec::with_capacity(data.len() / 2);
+    match extension {
+        "" | "tar" => return Ok(data.to_vec()),
+        "zst" => system_filter("zstd", "-c", data, &mut out)?,
+        "bz2" => system_filter("bzip2", "-c", data, &mut out)?,
+        _ => return Err(CargoDebError::Str("unsupported compression format of a deb archive member")),
+    }
+    Ok(out)
+}
+
+/// Pipes the data through `program` with `-c` or `-dc`
+fn system_filter(program: &'static str, mode: &str, data: &[u8], out: &mut Vec<u8>) -> CDResult<()> {
+    let mut child = sandbox::apply(&mut Command::new(program))?
+        .arg(mode)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| CargoDebError::CommandFailed(e, program))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let data = data.to_vec();
+    let writer = std::thread::spawn(move || io::Write::write_all(&mut stdin, &data));
+    child.stdout.take().unwrap().read_to_end(out)?;
+    writer.join().unwrap()?;
+    if !child.wait()?.success() {
+        return Err(CargoDebError::CommandError(if mode == "-c" { "compression failed" } else { "decompression failed" }, program.into(), vec![]));
+    }
+    Ok(())
+}
+
+/// Zopfli makes smaller files, but it's very slow. `fast` uses flate2 instead.
+pub(crate) fn gzipped(mut content: &[u8], fast: bool) -> io::Result<Vec<u8>> {
     let mut compressed = Vec::with_capacity(content.len() * 2 / 3);
+    if fast {
+        let mut encoder = flate2::write::GzEncoder::new(compressed, flate2::Compression::fast());
+        io::copy(&mut content, &mut encoder)?;
+        return encoder.finish();
+    }
     let mut encoder = GzipEncoder::new(
         Options {
             iteration_count: NonZeroU64::new(7).unwrap(),
@@ -221,3 +377,102 @@ pub(crate) fn gzipped(mut content: &[u8]) -> io::Result<Vec<u8>> {
     encoder.finish()?;
     Ok(compressed)
 }
+
+/// Settings for gzipping man pages, changelogs and other docs
+#[derive(Debug, Clone, Default)]
+pub struct GzipConfig {
+    /// Use flate2 instead of slow Zopfli
+    pub fast: bool,
+    /// Zopfli's output is reused from here, keyed by a hash of the content
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl GzipConfig {
+    /// Returns compressed data, and whether it has been reused from the cache
+    pub(crate) fn gzipped(&self, content: &[u8]) -> io::Result<(Vec<u8>, bool)> {
+        let cache_path = self.cache_dir.as_ref().filter(|_| !self.fast).map(|dir| {
+            let mut hasher = DefaultHasher::new();
+            content.hash(&mut hasher);
+            dir.join(format!("{:016x}-{}.gz", hasher.finish(), content.len()))
+        });
+        if let Some(cached) = cache_path.as_deref().and_then(|path| load_cached_gzip(path, content)) {
+            return Ok((cached, true));
+        }
+        let compressed = gzipped(content, self.fast)?;
+        if let Some(path) = &cache_path {
+            store_cached_gzip(path, &compressed);
+        }
+        Ok((compressed, false))
+    }
+}
+
+/// Decompressing is much faster than Zopfli, and verifies that the file is for this content
+fn load_cached_gzip(path: &Path, content: &[u8]) -> Option<Vec<u8>> {
+    let compressed = fs::read(path).ok()?;
+    let mut decompressed = Vec::with_capacity(content.len());
+    flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).ok()?;
+    (decompressed == content).then_some(compressed)
+}
+
+/// The cache is not essential, so errors are ignored
+fn store_cached_gzip(path: &Path, compressed: &[u8]) {
+    let Some(dir) = path.parent() else { return };
+    let _ = fs::create_dir_all(dir);
+    // written to a temp file, so that parallel runs don't read incomplete files
+    let Ok(mut tmp) = tempfile::NamedTempFile::new_in(dir) else { return };
+    if io::Write::write_all(&mut tmp, compressed).is_ok() {
+        let _ = tmp.persist(path);
+    }
+}
+
+#[test]
+#[cfg(feature = "lzma")]
+fn compression_levels() {
+    use std::io::Write;
+    assert_eq!(CompressionLevel::new(true, true), CompressionLevel::Fast);
+    assert_eq!(CompressionLevel::new(false, true), CompressionLevel::Best);
+    assert_eq!(Format::Xz.level_arg(CompressionLevel::Best), "-9e");
+    assert_eq!(Format::Xz.level_arg(CompressionLevel::Default), "-6");
+    assert_eq!(Format::Gzip.level_arg(CompressionLevel::Best), "-9");
+
+    let content = (0..2000).map(|i| format!("line {}\n", i % 777)).collect::<String>();
+    for format in [Format::Xz, Format::Gzip] {
+        let compressed = [CompressionLevel::Fast, CompressionLevel::Default, CompressionLevel::Best].map(|level| {
+            let mut compressor = select_compressor_with_level(level, format, false).unwrap();
+            compressor.write_all(content.as_bytes()).unwrap();
+            let compressed = compressor.finish().unwrap();
+            assert_eq!(decompress(compressed.extension(), &compressed).unwrap(), content.as_bytes());
+            compressed.len()
+        });
+        assert!(compressed[2] <= compressed[1] && compressed[1] < compressed[0], "{compressed:?}");
+    }
+}
+
+#[test]
+fn gzipped_fast_and_slow() {
+    use std::io::Read;
+    let content = "changelog ".repeat(1000);
+    for fast in [false, true] {
+        let compressed = gzipped(content.as_bytes(), fast).unwrap();
+        assert!(compressed.len() < content.len() / 10);
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(content, decompressed);
+    }
+}
+
+#[test]
+fn gzip_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let gzip = GzipConfig { fast: false, cache_dir: Some(dir.path().into()) };
+    let (compressed, reused) = gzip.gzipped(b"man page").unwrap();
+    assert!(!reused);
+    assert_eq!((compressed.clone(), true), gzip.gzipped(b"man page").unwrap());
+    assert!(!gzip.gzipped(b"other man page").unwrap().1);
+
+    // corrupted cache is ignored
+    for entry in fs::read_dir(dir.path()).unwrap() {
+        fs::write(entry.unwrap().path(), b"bad").unwrap();
+    }
+    assert_eq!((compressed, false), gzip.gzipped(b"man page").unwrap());
+}
@@ -0,0 +1,62 @@
+//! Advisory lock of the `target/debian` dir, so that concurrent runs of cargo-deb don't delete each other's files
+
+use crate::error::{CDResult, CargoDebError};
+use crate::listener::Listener;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// The lock is released when this is dropped
+pub(crate) struct DirLock {
+    _file: File,
+}
+
+/// Blocks until no other cargo-deb is packaging in the same dir
+pub(crate) fn lock_dir(dir: &Path, listener: &dyn Listener) -> CDResult<DirLock> {
+    fs::create_dir_all(dir).map_err(|e| CargoDebError::IoFile("unable to create dir", e, dir.to_owned()))?;
+    let path = dir.join(".cargo-deb-lock");
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)
+        .map_err(|e| CargoDebError::IoFile("unable to create lock file", e, path.clone()))?;
+    if !lock(&file, false)? {
+        listener.warning(format!("Waiting for another cargo-deb to finish (the lock file is {})", path.display()));
+        lock(&file, true)?;
+    }
+    Ok(DirLock { _file: file })
+}
+
+/// Returns `false` if it's locked by someone else, and it wasn't asked to wait
+#[cfg(unix)]
+fn lock(file: &File, wait: bool) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let flags = if wait { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), flags) } == 0 {
+            return Ok(true);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EWOULDBLOCK) if !wait => return Ok(false),
+            // Some network filesystems don't support locking, and then it's better to carry on without it
+            Some(libc::ENOLCK | libc::ENOTSUP) => return Ok(true),
+            _ => return Err(err),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_: &File, _wait: bool) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[test]
+#[cfg(unix)]
+fn lock_is_exclusive() {
+    let dir = tempfile::tempdir().unwrap();
+    let listener = crate::listener::NoOpListener;
+    let _first = lock_dir(dir.path(), &listener).unwrap();
+    // flock locks belong to the open file, so another open file conflicts even in the same process
+    let other = File::open(dir.path().join(".cargo-deb-lock")).unwrap();
+    assert!(!lock(&other, false).unwrap());
+}
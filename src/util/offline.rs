@@ -0,0 +1,67 @@
+//! `--offline-strict` mode for air-gapped builds.
+//!
+//! Cargo is forced to run offline, and every subprocess is checked against a list of tools that are known
+//! not to use the network, and recorded, so that the build can be audited afterwards.
+
+use crate::error::{CDResult, CargoDebError};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+static INVOCATIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Programs that cargo-deb runs, which don't access the network (Cargo does, unless it's offline)
+const LOCAL_PROGRAMS: &[&str] = &["cargo", "xz", "gzip", "zstd", "bzip2", "git", "sudo"];
+
+pub(crate) fn enable_strict() {
+    STRICT.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Call before running any subprocess. In the strict mode it's recorded, and unknown programs are refused.
+///
+/// External tools go through [`sandbox::apply`](super::sandbox::apply), which calls this before their arguments are set,
+/// so only Cargo invocations are recorded with all arguments.
+pub(crate) fn audit(cmd: &mut Command) -> CDResult<&mut Command> {
+    if !is_strict() {
+        return Ok(cmd);
+    }
+    let program = PathBuf::from(cmd.get_program());
+    let name = program.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    if !is_local_program(name) {
+        return Err(CargoDebError::OfflineStrict(program.display().to_string()));
+    }
+    // Cargo also respects this in its own subprocesses, e.g. build scripts running `cargo metadata`
+    cmd.env("CARGO_NET_OFFLINE", "true");
+
+    let mut invocation = program.display().to_string();
+    for arg in cmd.get_args() {
+        invocation.push(' ');
+        invocation.push_str(&arg.to_string_lossy());
+    }
+    INVOCATIONS.lock().unwrap().push(invocation);
+    Ok(cmd)
+}
+
+fn is_local_program(name: &str) -> bool {
+    // cross-compilation tools have target prefixes, like aarch64-linux-gnu-strip
+    LOCAL_PROGRAMS.contains(&name) || name.starts_with("dpkg-") || name == "dpkg"
+        || name.ends_with("strip") || name.ends_with("objcopy")
+}
+
+/// Commands run so far, in the order they were started
+pub(crate) fn invocations() -> Vec<String> {
+    INVOCATIONS.lock().unwrap().clone()
+}
+
+#[test]
+fn local_programs() {
+    assert!(is_local_program("aarch64-linux-gnu-strip"));
+    assert!(is_local_program("dpkg-shlibdeps"));
+    assert!(!is_local_program("curl"));
+}
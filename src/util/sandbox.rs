@@ -0,0 +1,152 @@
+//! Optional [Landlock](https://docs.kernel.org/userspace-api/landlock.html) sandbox for external tools
+//! (`strip`, `objcopy`, `xz`, `dpkg-shlibdeps`) that cargo-deb runs.
+//!
+//! When enabled, the tools can only write to the target dir and the temp dir,
+//! and can only read system dirs and the project's dirs. `cargo build` and `dpkg -i` are not sandboxed.
+
+use crate::error::{CDResult, CargoDebError};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::RwLock;
+
+/// Read-only system dirs the tools need to run
+const SYSTEM_DIRS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/var/lib/dpkg", "/proc", "/opt"];
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SandboxDirs {
+    pub readable: Vec<PathBuf>,
+    pub writable: Vec<PathBuf>,
+}
+
+static SANDBOX: RwLock<Option<SandboxDirs>> = RwLock::new(None);
+
+/// Subsequently spawned tools will be restricted to these dirs (in addition to system dirs)
+pub(crate) fn enable(mut dirs: SandboxDirs) -> CDResult<()> {
+    if !cfg!(all(feature = "sandbox", target_os = "linux")) {
+        return Err(CargoDebError::Str("--sandbox requires cargo-deb built with the 'sandbox' feature on Linux"));
+    }
+    dirs.writable.push(std::env::temp_dir());
+    dirs.writable.push("/dev".into());
+    dirs.readable.extend(SYSTEM_DIRS.iter().map(PathBuf::from));
+    imp::check_supported()?;
+    *SANDBOX.write().unwrap() = Some(dirs);
+    Ok(())
+}
+
+/// Makes the command run in the sandbox, if it has been enabled
+pub(crate) fn apply(cmd: &mut Command) -> CDResult<&mut Command> {
+    super::offline::audit(cmd)?;
+    if let Some(dirs) = &*SANDBOX.read().unwrap() {
+        imp::restrict(cmd, dirs)?;
+    }
+    Ok(cmd)
+}
+
+#[cfg(not(all(feature = "sandbox", target_os = "linux")))]
+mod imp {
+    use super::SandboxDirs;
+    use crate::error::CDResult;
+    use std::process::Command;
+
+    pub(super) fn check_supported() -> CDResult<()> {
+        Ok(())
+    }
+
+    pub(super) fn restrict(_: &mut Command, _: &SandboxDirs) -> CDResult<()> {
+        unreachable!()
+    }
+}
+
+#[cfg(all(feature = "sandbox", target_os = "linux"))]
+mod imp {
+    use super::SandboxDirs;
+    use crate::error::{CDResult, CargoDebError};
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::Command;
+
+    // The syscall numbers are the same on all architectures
+    const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+    const ACCESS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_READ_DIR: u64 = 1 << 3;
+    /// All of the ABI v1 rights, including removing and creating files
+    const ACCESS_ALL: u64 = (1 << 13) - 1;
+    const ACCESS_READ: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    pub(super) fn check_supported() -> CDResult<()> {
+        let abi = unsafe { libc::syscall(SYS_LANDLOCK_CREATE_RULESET, std::ptr::null::<RulesetAttr>(), 0usize, LANDLOCK_CREATE_RULESET_VERSION) };
+        if abi < 1 {
+            return Err(CargoDebError::Io(io::Error::new(io::ErrorKind::Unsupported,
+                format!("Landlock is not available in this kernel: {}", io::Error::last_os_error()))));
+        }
+        Ok(())
+    }
+
+    /// The ruleset is prepared in the parent process, because allocating after `fork` is not safe
+    pub(super) fn restrict(cmd: &mut Command, dirs: &SandboxDirs) -> CDResult<()> {
+        let attr = RulesetAttr { handled_access_fs: ACCESS_ALL };
+        let fd = unsafe { libc::syscall(SYS_LANDLOCK_CREATE_RULESET, &attr as *const RulesetAttr, std::mem::size_of::<RulesetAttr>(), 0u32) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        for (paths, access) in [(&dirs.readable, ACCESS_READ), (&dirs.writable, ACCESS_ALL)] {
+            for path in paths {
+                add_path_rule(&ruleset, path, access)?;
+            }
+        }
+
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset.as_raw_fd(), 0u32) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    fn add_path_rule(ruleset: &OwnedFd, path: &Path, mut access: u64) -> CDResult<()> {
+        let Ok(file) = OpenOptions::new().read(true).custom_flags(libc::O_PATH | libc::O_CLOEXEC).open(path) else {
+            log::debug!("sandbox: skipping {}", path.display());
+            return Ok(());
+        };
+        if !file.metadata()?.is_dir() {
+            // rights that apply only to dirs are rejected for files
+            access &= ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE;
+        }
+        let attr = PathBeneathAttr { allowed_access: access, parent_fd: file.as_raw_fd() };
+        let res = unsafe { libc::syscall(SYS_LANDLOCK_ADD_RULE, ruleset.as_raw_fd(), LANDLOCK_RULE_PATH_BENEATH, &attr as *const PathBeneathAttr, 0u32) };
+        if res != 0 {
+            return Err(CargoDebError::IoFile("unable to add a sandbox rule", io::Error::last_os_error(), path.to_owned()));
+        }
+        Ok(())
+    }
+}
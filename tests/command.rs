@@ -19,6 +19,7 @@ fn build_workspaces() {
     assert!(control.contains("Version: 1.0.0-ws-1\n"));
     assert!(control.contains("Package: test1-crate-name\n"));
     assert!(control.contains("Maintainer: ws\n"));
+    assert!(control.contains("Section: games\n"));
 
     let (_, ddir) = extract_built_package_from_manifest("tests/test-workspace/test-ws2/Cargo.toml", DEFAULT_COMPRESSION_EXT, &["--no-strip"]);
     assert!(ddir.path().join("usr/bin/renamed2").exists());
@@ -193,7 +194,7 @@ fn cargo_deb(manifest_path: &str, args: &[&str]) -> (TempDir, PathBuf) {
     );
 
     // prints deb path on the last line
-    let last_line = output.stdout[..output.stdout.len() - 1].split(|&c| c == b'\n').last().unwrap();
+    let last_line = output.stdout[..output.stdout.len() - 1].split(|&c| c == b'\n').next_back().unwrap();
     let printed_deb_path = Path::new(::std::str::from_utf8(last_line).unwrap());
     assert_eq!(printed_deb_path, deb_path);
     assert!(deb_path.exists());
@@ -224,6 +225,13 @@ fn run_cargo_deb_command_on_example_dir() {
         &[0x1F, 0x8B],
         &fs::read(ddir.path().join("usr/share/doc/example/changelog.Debian.gz")).unwrap()[..2]
     );
+
+    let md5sums = fs::read_to_string(cdir.path().join("md5sums")).unwrap();
+    assert!(md5sums.contains("  usr/bin/example\n"));
+    assert!(md5sums.contains("  var/lib/example/3.txt\n"));
+    let checked = Command::new("md5sum").arg("--check").arg("--quiet").arg(cdir.path().join("md5sums"))
+        .current_dir(ddir.path()).status().unwrap();
+    assert!(checked.success());
 }
 
 #[test]
@@ -252,6 +260,129 @@ fn run_cargo_deb_command_on_example_dir_with_separate_debug_symbols() {
     );
 }
 
+#[test]
+fn check_config_reports_all_problems_without_building() {
+    let target_dir = tempfile::tempdir().unwrap();
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    let check = |variants: &str| Command::new(env!("CARGO_BIN_EXE_cargo-deb"))
+        .env("CARGO_TARGET_DIR", target_dir.path())
+        .arg(format!("--manifest-path={}", root.join("example/Cargo.toml").display()))
+        .arg(format!("--variant={variants}"))
+        // the problems have been explicitly asked for
+        .arg("--quiet")
+        .arg("--check-config")
+        .output().unwrap();
+
+    let output = check("debug");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!target_dir.path().join("release").exists());
+
+    let output = check("nope1,debug,nope2");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("variants.nope1]") && stderr.contains("variants.nope2]"), "{stderr}");
+    assert!(stderr.contains("Found 2 problem(s)"), "{stderr}");
+}
+
+#[test]
+#[cfg(all(feature = "lzma", target_family = "unix"))]
+fn source_package_with_vendored_dependencies() {
+    if !Command::new("dpkg-source").arg("--version").output().is_ok_and(|o| o.status.success()) {
+        eprintln!("dpkg-source not available, skipping");
+        return;
+    }
+    let target_dir = tempfile::tempdir().unwrap();
+    let output_dir = tempfile::tempdir().unwrap();
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-deb"))
+        .env("CARGO_TARGET_DIR", target_dir.path())
+        .arg(format!("--manifest-path={}", root.join("example/Cargo.toml").display()))
+        .arg(format!("--output={}/", output_dir.path().display()))
+        .args(["--source", "--offline"])
+        .output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let dsc_path = output_dir.path().join("example_0.1.0-1.dsc");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), dsc_path.to_str().unwrap());
+    assert!(output_dir.path().join("example_0.1.0.orig.tar.xz").exists());
+    assert!(!target_dir.path().join("release").exists());
+
+    let unpacked = tempfile::tempdir().unwrap();
+    assert!(Command::new("dpkg-source").current_dir(unpacked.path()).arg("-x").arg(&dsc_path).output().unwrap().status.success());
+    let source_dir = unpacked.path().join("example-0.1.0");
+    assert!(source_dir.join("Cargo.lock").exists());
+    let control = fs::read_to_string(source_dir.join("debian/control")).unwrap();
+    assert!(control.contains("\nDepends: ${shlibs:Depends}, ${misc:Depends}\n"), "{control}");
+    let rules = fs::read_to_string(source_dir.join("debian/rules")).unwrap();
+    assert!(rules.contains("\n\tcargo build --release --frozen --no-default-features --features=example_debian_build\n"), "{rules}");
+    assert!(rules.contains("\n\tinstall -D -m755 'target/release/example' 'debian/example/usr/bin/example'\n"), "{rules}");
+    assert!(rules.contains("\n\tinstall -D -m644 '3.txt' 'debian/example/var/lib/example/merged.txt'\n"), "{rules}");
+}
+
+#[test]
+#[cfg(feature = "lzma")]
+fn apt_repo_dir() {
+    let repo = tempfile::tempdir().unwrap();
+    let (_bdir, deb_path) = cargo_deb("example/Cargo.toml", &["--no-strip", &format!("--repo={}", repo.path().display())]);
+    let arch = fs::read_dir(repo.path().join("dists/stable/main")).unwrap().next().unwrap().unwrap().file_name();
+    let arch = arch.to_str().unwrap().strip_prefix("binary-").unwrap().to_owned();
+    let pooled = format!("pool/main/e/example/example_0.1.0-1_{arch}.deb");
+    assert_eq!(fs::read(repo.path().join(&pooled)).unwrap(), fs::read(&deb_path).unwrap());
+
+    let packages = fs::read_to_string(repo.path().join(format!("dists/stable/main/binary-{arch}/Packages"))).unwrap();
+    assert!(packages.starts_with("Package: example\nVersion: 0.1.0-1\n"), "{packages}");
+    assert!(packages.contains(&format!("\nFilename: {pooled}\nSize: {}\n", fs::metadata(&deb_path).unwrap().len())), "{packages}");
+    assert!(repo.path().join(format!("dists/stable/main/binary-{arch}/Packages.gz")).exists());
+    let release = fs::read_to_string(repo.path().join("dists/stable/Release")).unwrap();
+    assert!(release.contains(&format!("\nArchitectures: {arch}\n")), "{release}");
+    assert!(release.contains(&format!(" main/binary-{arch}/Packages.gz\n")), "{release}");
+}
+
+#[test]
+#[cfg(feature = "lzma")]
+fn target_distros_keep_each_others_debs() {
+    let target_dir = tempfile::tempdir().unwrap();
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-deb"))
+        .env("CARGO_TARGET_DIR", target_dir.path())
+        .arg(format!("--manifest-path={}", root.join("example/Cargo.toml").display()))
+        .args(["--no-strip", "--target-distro=debian12,ubuntu24.04"])
+        .output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let debs: Vec<_> = stdout.lines().map(Path::new).collect();
+    assert_eq!(debs.len(), 2, "{stdout}");
+    assert!(debs[0].to_str().unwrap().contains("+deb12u1_"), "{stdout}");
+    assert!(debs[1].to_str().unwrap().contains("+ubuntu24.04_"), "{stdout}");
+    for deb in debs {
+        assert!(deb.exists(), "{}", deb.display());
+    }
+}
+
+#[test]
+fn print_paths_without_building() {
+    let target_dir = tempfile::tempdir().unwrap();
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-deb"))
+        .env("CARGO_TARGET_DIR", target_dir.path())
+        .arg(format!("--manifest-path={}", root.join("example/Cargo.toml").display()))
+        .args(["--print=paths", "--target=aarch64-unknown-linux-gnu", "--deb-version=1.0", "--target-distro=debian12,ubuntu24.04"])
+        .output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let paths: Vec<_> = stdout.lines().map(|l| l.split_once('=').unwrap()).collect();
+    let target = target_dir.path().join("aarch64-unknown-linux-gnu");
+    assert_eq!(paths[..5], [
+        ("example:target-dir", target_dir.path().to_str().unwrap()),
+        ("example:profile-dir", target.join("release").to_str().unwrap()),
+        ("example:deb-temp-dir", target.join("debian/example").to_str().unwrap()),
+        ("example:deb-output-dir", target.join("debian").to_str().unwrap()),
+        ("example:deb-path", target.join("debian/example_1.0+deb12u1_arm64.deb").to_str().unwrap()),
+    ]);
+    assert_eq!(paths.len(), 10);
+    assert_eq!(paths[9], ("example:deb-path", target.join("debian/example_1.0+ubuntu24.04_arm64.deb").to_str().unwrap()));
+    assert!(!target.exists());
+}
+
 #[test]
 #[cfg(feature = "lzma")]
 fn run_cargo_deb_command_on_example_dir_with_variant() {
@@ -393,3 +524,29 @@ fn cwd_dir2() {
 fn cwd_dir3() {
     dir_test_run_in_subdir("tests/dir-confusion/src");
 }
+
+#[test]
+#[cfg(all(feature = "lzma", target_family = "unix", not(target_os = "macos")))]
+fn dpkg_compat_matches_dpkg_deb() {
+    let (_bdir, deb_path) = cargo_deb("example/Cargo.toml", &["--no-strip", "--dpkg-compat"]);
+    let ours = fs::read(&deb_path).unwrap();
+
+    // dpkg-deb uses SOURCE_DATE_EPOCH for the ar headers, so take it from the debian-binary member
+    let mtime = std::str::from_utf8(&ours[8 + 16..8 + 28]).unwrap().trim();
+    let tree = tempfile::tempdir().unwrap();
+    let theirs_path = tree.path().join("theirs.deb");
+    let root = tree.path().join("root");
+    let extracted = Command::new("dpkg-deb").arg("-R").arg(&deb_path).arg(&root).status();
+    if !extracted.is_ok_and(|s| s.success()) {
+        eprintln!("dpkg-deb not available, skipping");
+        return;
+    }
+    assert!(Command::new("dpkg-deb")
+        .env("SOURCE_DATE_EPOCH", mtime)
+        .arg("--root-owner-group").arg("--build")
+        .arg(&root).arg(&theirs_path)
+        .status().unwrap().success());
+    let theirs = fs::read(&theirs_path).unwrap();
+    assert_eq!(ours.len(), theirs.len());
+    assert!(ours == theirs, "deb differs from dpkg-deb's");
+}
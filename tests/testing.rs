@@ -0,0 +1,43 @@
+#![cfg(feature = "testing")]
+
+use cargo_deb::testing::Fixture;
+use std::fs;
+
+#[test]
+fn fixture_with_fake_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(dir.path().join("Cargo.toml"), r#"
+        [package]
+        name = "fixture"
+        version = "1.2.3"
+        authors = ["Me <me@example.com>"]
+        description = "A fixture"
+        license = "MIT"
+
+        [package.metadata.deb]
+        depends = "libfoo1"
+        maintainer-scripts = "debian"
+        assets = [["notes.txt", "usr/share/fixture/", "644"]]
+
+        [workspace]
+    "#).unwrap();
+
+    let deb = Fixture::new(dir.path().join("Cargo.toml")).unwrap()
+        .file("notes.txt", "only in memory")
+        .file("debian/postinst", "#!/bin/sh\necho hi\n")
+        .build().unwrap();
+
+    assert_eq!(deb.field("Package"), Some("fixture"));
+    assert_eq!(deb.field("Version"), Some("1.2.3-1"));
+    assert_eq!(deb.field("Depends"), Some("libfoo1"));
+    assert_eq!(deb.control_files["postinst"], b"#!/bin/sh\necho hi\n");
+    let notes = deb.file("/usr/share/fixture/notes.txt").unwrap();
+    assert_eq!(notes.data, b"only in memory");
+    assert_eq!(notes.mode & 0o777, 0o644);
+    assert!(deb.listener.warnings().is_empty(), "{:?}", deb.listener.warnings());
+
+    // the fake files are gone afterwards
+    assert!(Fixture::new(dir.path().join("Cargo.toml")).unwrap().build().is_err());
+}